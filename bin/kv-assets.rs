@@ -0,0 +1,277 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use clap::{Clap, ValueHint};
+use kv_assets::{
+    encode_index, index_dir, sync_assets, CancelToken, DeployReport, Error, KVAssets,
+    NamespaceTarget, RateLimiter, SyncConfig,
+};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Manage static assets in Workers KV.
+/// Account, namespace, and api token are read from the environment variables
+/// CLOUDFLARE_ACCOUNT_ID, CLOUDFLARE_KV_NAMESPACE_ID (or CLOUDFLARE_KV_PREVIEW_NAMESPACE_ID
+/// with --preview), and CLOUDFLARE_API_TOKEN.
+#[derive(Clap, Debug)]
+struct Opt {
+    /// Use the preview namespace
+    #[clap(long)]
+    preview: bool,
+
+    /// Stop after this many seconds, leaving the deploy to be resumed by running it again
+    #[clap(long)]
+    timeout: Option<u64>,
+
+    /// Write a JSON deploy report to this file (publish and sync)
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    report: Option<PathBuf>,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Clap, Debug)]
+enum Command {
+    /// Build the asset index from the asset directory, without uploading
+    Index(DirOpt),
+    /// Upload assets not already in KV, check that they are all in KV,
+    /// then write the asset index
+    Publish(DirOpt),
+    /// Upload changed assets using settings from wrangler.toml (same as kv-sync)
+    Sync(SyncOpt),
+    /// List the keys in the namespace
+    List,
+    /// Check that every key referenced by the index is in KV
+    Verify(IndexOpt),
+    /// Delete keys in KV that aren't referenced by the index.
+    /// Run only after the index is deployed
+    Gc(IndexOpt),
+    /// Download assets to a local directory
+    Mirror(MirrorOpt),
+    /// Make a published index version current again. Without a version,
+    /// lists the published versions
+    Rollback(RollbackOpt),
+}
+
+#[derive(Clap, Debug)]
+struct DirOpt {
+    /// Path to assets dir
+    #[clap(short, long, value_hint = ValueHint::DirPath, default_value = "public")]
+    assets: PathBuf,
+
+    /// Path for generated asset index
+    #[clap(short, long, value_hint = ValueHint::FilePath, default_value = "data/assets.bin")]
+    output: PathBuf,
+}
+
+#[derive(Clap, Debug)]
+struct SyncOpt {
+    /// Path to configuration file
+    #[clap(short, long, value_hint = ValueHint::FilePath, default_value = "wrangler.toml")]
+    wrangler: PathBuf,
+
+    /// Path to assets dir
+    #[clap(short, long, value_hint = ValueHint::DirPath, default_value = "public")]
+    assets: PathBuf,
+
+    /// Path for generated asset index
+    #[clap(short, long, value_hint = ValueHint::FilePath, default_value = "data/assets.bin")]
+    output: PathBuf,
+
+    /// Remove KV assets not referenced by the new index
+    #[clap(long)]
+    prune: bool,
+}
+
+#[derive(Clap, Debug)]
+struct IndexOpt {
+    /// Path to asset index
+    #[clap(short, long, value_hint = ValueHint::FilePath, default_value = "data/assets.bin")]
+    index: PathBuf,
+}
+
+#[derive(Clap, Debug)]
+struct MirrorOpt {
+    /// Path to asset index. If it doesn't exist, all keys are downloaded
+    #[clap(short, long, value_hint = ValueHint::FilePath, default_value = "data/assets.bin")]
+    index: PathBuf,
+
+    /// Destination directory
+    #[clap(short, long, value_hint = ValueHint::DirPath)]
+    dest: PathBuf,
+}
+
+#[derive(Clap, Debug)]
+struct RollbackOpt {
+    /// Published index version
+    version: Option<u32>,
+
+    /// Also write the index of the version to this file, for embedding in the worker
+    #[clap(short, long, value_hint = ValueHint::FilePath)]
+    output: Option<PathBuf>,
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let opt = Opt::parse();
+    match run(opt).await {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(2);
+        }
+    }
+}
+
+// Returns false if verification failed
+async fn run(opt: Opt) -> Result<bool, Error> {
+    let target = match opt.preview {
+        true => NamespaceTarget::Preview,
+        false => NamespaceTarget::Production,
+    };
+    let cancel = match opt.timeout {
+        Some(secs) => CancelToken::new().with_timeout(std::time::Duration::from_secs(secs)),
+        None => CancelToken::new(),
+    };
+    match opt.command {
+        Command::Index(dir) => {
+            let index = index_dir(&dir.assets)?;
+            write_file(&dir.output, &encode_index(&index)?)?;
+            println!("Indexed {} assets", index.len());
+        }
+        Command::Publish(dir) => {
+            let kv = handler(&[], target, &cancel)?;
+            let staged = kv.stage_dir(&dir.assets).await?;
+            let report = kv.commit_staged_to_file(staged, &dir.output).await?;
+            for path in report.added.iter().chain(report.updated.iter()) {
+                println!("uploaded {}", path);
+            }
+            finish_report(&report, opt.report.as_deref())?;
+        }
+        Command::Sync(sync) => {
+            // sync_assets uses blocking requests, which can't run on the async runtime
+            let sync_cancel = cancel.clone();
+            let report = tokio::task::spawn_blocking(move || {
+                sync_assets(SyncConfig {
+                    output_path: &sync.output,
+                    wrangler_path: &sync.wrangler,
+                    asset_dir: &sync.assets,
+                    prune: sync.prune,
+                    cancel: Some(&sync_cancel),
+                    ..Default::default()
+                })
+            })
+            .await
+            .map_err(|e| Error::Message(e.to_string()))??;
+            finish_report(&report, opt.report.as_deref())?;
+        }
+        Command::List => {
+            for key in handler(&[], target, &cancel)?.list_keys().await? {
+                println!("{}", key);
+            }
+        }
+        Command::Verify(opt) => {
+            let blob = read_file(&opt.index)?;
+            let report = handler(&blob, target, &cancel)?.verify().await?;
+            for key in report.missing.iter() {
+                println!("missing {}", key);
+            }
+            for key in report.extra.iter() {
+                println!("unreferenced {}", key);
+            }
+            return Ok(report.is_ok());
+        }
+        Command::Gc(opt) => {
+            let blob = read_file(&opt.index)?;
+            if blob.is_empty() {
+                return Err(Error::Message(format!(
+                    "{} is empty: refusing to delete every key",
+                    opt.index.display()
+                )));
+            }
+            let deleted = handler(&blob, target, &cancel)?.prune().await?;
+            for key in deleted.iter() {
+                println!("deleted {}", key);
+            }
+            println!("Deleted {} keys", deleted.len());
+        }
+        Command::Mirror(opt) => {
+            let blob = match opt.index.exists() {
+                true => read_file(&opt.index)?,
+                false => Vec::new(),
+            };
+            let count = handler(&blob, target, &cancel)?
+                .mirror_to_dir(&opt.dest)
+                .await?;
+            println!("Wrote {} files to {}", count, opt.dest.display());
+        }
+        Command::Rollback(opt) => {
+            let kv = handler(&[], target, &cancel)?;
+            let version = match opt.version {
+                Some(version) => version,
+                None => {
+                    let current = kv.current_index_version().await?;
+                    for version in kv.index_versions().await? {
+                        match Some(version) == current {
+                            true => println!("{} (current)", version),
+                            false => println!("{}", version),
+                        }
+                    }
+                    return Ok(true);
+                }
+            };
+            let index = kv.rollback_to(version).await?;
+            if let Some(output) = opt.output {
+                write_file(&output, &encode_index(&index)?)?;
+            }
+            println!(
+                "Rolled back to version {} ({} assets)",
+                version,
+                index.len()
+            );
+        }
+    }
+    Ok(true)
+}
+
+// Print report summary, and write it as JSON if requested
+fn finish_report(report: &DeployReport, path: Option<&Path>) -> Result<(), Error> {
+    for warning in report.warnings.iter() {
+        eprintln!("warning: {}", warning);
+    }
+    println!("{}", report);
+    match path {
+        Some(path) => write_file(path, report.to_json()?.as_bytes()),
+        None => Ok(()),
+    }
+}
+
+fn handler<'a>(
+    index: &'a [u8],
+    target: NamespaceTarget,
+    cancel: &CancelToken,
+) -> Result<KVAssets<'a>, Error> {
+    KVAssets::builder()
+        .index(index)
+        .target(target)
+        .from_env()
+        .proxy_from_env()
+        .build()
+        .map(|kv| {
+            kv.with_rate_limiter(Arc::new(RateLimiter::cloudflare()))
+                .with_cancel_token(cancel.clone())
+        })
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, Error> {
+    std::fs::read(path).map_err(|e| Error::IO(format!("reading {}: {}", path.display(), e)))
+}
+
+fn write_file(path: &Path, data: &[u8]) -> Result<(), Error> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| Error::IO(format!("creating {}: {}", parent.display(), e)))?;
+    }
+    std::fs::write(path, data).map_err(|e| Error::IO(format!("writing {}: {}", path.display(), e)))
+}
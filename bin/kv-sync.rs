@@ -1,7 +1,7 @@
 #![cfg(not(target_arch = "wasm32"))]
 
 use clap::{Clap, ValueHint};
-use kv_assets::{sync_assets, SyncConfig};
+use kv_assets::{sync_assets, AliasTarget, SyncConfig};
 use std::path::PathBuf;
 
 #[derive(Clap, Debug)]
@@ -22,6 +22,34 @@ struct Opt {
     #[clap(long, value_hint=ValueHint::FilePath)]
     dump: Option<PathBuf>,
 
+    /// Generate the asset index (--output) from a JSON index, without syncing
+    #[clap(long, value_hint=ValueHint::FilePath)]
+    import_json: Option<PathBuf>,
+
+    /// Generate the asset index (--output) from a Workers Sites asset manifest, without syncing.
+    /// Assets are served from the keys wrangler uploaded them to
+    #[clap(long, value_hint=ValueHint::FilePath)]
+    import_sites_manifest: Option<PathBuf>,
+
+    /// Add an alias to the index, as FROM=TO. If TO is an http(s) url, FROM redirects to it;
+    /// otherwise, TO is an asset path. May be repeated
+    #[clap(long, multiple_occurrences = true, number_of_values = 1)]
+    alias: Vec<String>,
+
+    /// Write a manifest of the index for javascript workers to this file: a TypeScript
+    /// module if it ends with .ts, otherwise JSON
+    #[clap(long, value_hint=ValueHint::FilePath)]
+    js_manifest: Option<PathBuf>,
+
+    /// Write a JSON deploy report to this file
+    #[clap(long, value_hint=ValueHint::FilePath)]
+    report: Option<PathBuf>,
+
+    /// Keep assets removed from the assets dir in the index as tombstones for this many days,
+    /// so a rollback can restore them; --prune doesn't delete their values until then
+    #[clap(long)]
+    keep_removed: Option<u64>,
+
     /// Remove obsolete/unreferenced KV assets in the namespace. Use this flag only after successful publish
     #[clap(long)]
     prune: bool,
@@ -30,7 +58,7 @@ struct Opt {
 fn main() {
     let opt = Opt::parse();
     if let Err(e) = run(opt) {
-        eprintln!("Error: {}", e.to_string());
+        eprintln!("Error: {}", e);
         std::process::exit(2);
     }
 }
@@ -39,30 +67,105 @@ fn run(opt: Opt) -> Result<(), kv_assets::Error> {
     if let Some(dump_file) = opt.dump {
         return dump(&dump_file);
     }
+    if let Some(json_file) = opt.import_json {
+        return import_json(&json_file, &opt.output, kv_assets::index_from_json);
+    }
+    if let Some(manifest_file) = opt.import_sites_manifest {
+        return import_json(
+            &manifest_file,
+            &opt.output,
+            kv_assets::index_from_sites_manifest,
+        );
+    }
+    let aliases = opt
+        .alias
+        .iter()
+        .map(|alias| parse_alias(alias))
+        .collect::<Result<Vec<_>, _>>()?;
     let args = SyncConfig {
         output_path: &opt.output,
         wrangler_path: &opt.wrangler,
         asset_dir: &opt.assets,
         prune: opt.prune,
+        aliases: &aliases,
+        tombstone_retention: opt
+            .keep_removed
+            .map(|days| std::time::Duration::from_secs(days * 24 * 3600)),
         ..Default::default()
     };
-    sync_assets(args)?;
+    let report = sync_assets(args)?;
+    if let Some(path) = opt.report {
+        write_file(&path, report.to_json()?)?;
+    }
+    if let Some(path) = opt.js_manifest {
+        write_js_manifest(&opt.output, &path)?;
+    }
     Ok(())
 }
 
+fn write_file(path: &std::path::Path, contents: String) -> Result<(), kv_assets::Error> {
+    std::fs::write(path, contents)
+        .map_err(|e| kv_assets::Error::Message(format!("Error writing {}: {}", path.display(), e)))
+}
+
+// Write manifest of the generated index, as TypeScript or JSON depending on the extension
+fn write_js_manifest(
+    index_file: &std::path::Path,
+    path: &std::path::Path,
+) -> Result<(), kv_assets::Error> {
+    use kv_assets::{decode_index, AssetIndexExt, Error};
+
+    let blob = std::fs::read(index_file)
+        .map_err(|e| Error::Message(format!("Error reading {}: {}", index_file.display(), e)))?;
+    let index = decode_index(&blob)?;
+    let manifest = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("ts") => index.to_ts_module()?,
+        _ => index.to_js_manifest()?,
+    };
+    write_file(path, manifest)
+}
+
+fn parse_alias(alias: &str) -> Result<(String, AliasTarget), kv_assets::Error> {
+    match alias.split_once('=') {
+        Some((from, to)) if !from.is_empty() && !to.is_empty() => {
+            let target = match to.starts_with("http://") || to.starts_with("https://") {
+                true => AliasTarget::Redirect(to.to_string()),
+                false => AliasTarget::Path(to.to_string()),
+            };
+            Ok((from.to_string(), target))
+        }
+        _ => Err(kv_assets::Error::Message(format!(
+            "Invalid alias '{}'. Expected FROM=TO",
+            alias
+        ))),
+    }
+}
+
 fn dump(path: &std::path::Path) -> Result<(), kv_assets::Error> {
-    use kv_assets::{AssetIndex, Error};
+    use kv_assets::{index_to_json, Error};
 
     let blob = std::fs::read(path).map_err(|e| {
         Error::Message(format!(
             "Error reading asset file {} for dump: {}",
             path.display(),
-            e.to_string()
+            e
         ))
     })?;
-    let map: AssetIndex = bincode::deserialize(&blob).map_err(Error::DeserializeAssets)?;
-    let json = serde_json::to_string_pretty(&map)
-        .map_err(|e| Error::Message(format!("json serialization error: {}", e.to_string())))?;
-    println!("{}", json);
+    println!("{}", index_to_json(&blob)?);
+    Ok(())
+}
+
+fn import_json(
+    path: &std::path::Path,
+    output: &std::path::Path,
+    convert: fn(&str) -> Result<Vec<u8>, kv_assets::Error>,
+) -> Result<(), kv_assets::Error> {
+    use kv_assets::Error;
+
+    let json = std::fs::read_to_string(path)
+        .map_err(|e| Error::Message(format!("Error reading {}: {}", path.display(), e)))?;
+    let blob = convert(&json)?;
+    std::fs::write(output, blob)
+        .map_err(|e| Error::Message(format!("Error writing {}: {}", output.display(), e)))?;
     Ok(())
 }
@@ -0,0 +1,220 @@
+use crate::{AssetMetadata, Error, KVAssets, ResolvedAsset, ServeOutcome};
+use bytes::Bytes;
+use http::{HeaderMap, Request, Response};
+use serde_json::Value;
+
+/// Key in `AssetMetadata::extra` marking an asset as protected. Values are
+/// "authenticated" (any verified claims), or "claim=value" (see `AccessRules::require_claim`)
+pub const ACCESS_KEY: &str = "access";
+
+/// Access rule: (asset path, verified claims, if any) -> allowed
+pub type AccessFn = dyn Fn(&str, Option<&Value>) -> bool + Send + Sync;
+
+/// Authorization rules by path prefix, for members-only assets hosted in the same
+/// namespace as public ones. The worker verifies the request's credentials
+/// (e.g., a JWT) and passes the verified claims to `KVAssets::serve_authorized`;
+/// paths under a protected prefix are forbidden unless the rule allows the claims.
+/// Prefixes match whole path segments, and the longest matching prefix applies.
+///
+/// Assets can also be protected in the index, with `extra["access"]`.
+/// An asset must satisfy both its prefix rule and its index entry.
+#[derive(Default)]
+pub struct AccessRules {
+    // sorted by prefix length, longest first
+    rules: Vec<(String, Box<AccessFn>)>,
+}
+
+impl AccessRules {
+    /// No protected paths
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Paths under prefix require verified claims (any claims)
+    pub fn protect(self, prefix: &str) -> Self {
+        self.rule(prefix, |_, claims| claims.is_some())
+    }
+
+    /// Paths under prefix require the claim to equal value, or, if the claim
+    /// is an array, to contain it (e.g., `require_claim("/members", "roles", "member")`)
+    pub fn require_claim(self, prefix: &str, claim: &str, value: &str) -> Self {
+        let (claim, value) = (claim.to_string(), value.to_string());
+        self.rule(prefix, move |_, claims| has_claim(claims, &claim, &value))
+    }
+
+    /// Paths under prefix are allowed if `allow` returns true for the path and claims
+    pub fn rule(
+        mut self,
+        prefix: &str,
+        allow: impl Fn(&str, Option<&Value>) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        let prefix = format!("/{}", prefix.trim_matches('/'));
+        self.rules.retain(|(p, _)| *p != prefix);
+        self.rules.push((prefix, Box::new(allow)));
+        self.rules.sort_by_key(|(p, _)| std::cmp::Reverse(p.len()));
+        self
+    }
+
+    /// Returns true if the claims allow access to the path, by the prefix rules only
+    pub fn is_allowed(&self, path: &str, claims: Option<&Value>) -> bool {
+        let path = format!("/{}", path.trim_start_matches('/'));
+        let rule = self.rules.iter().find(|(prefix, _)| {
+            prefix == "/"
+                || path
+                    .strip_prefix(prefix.as_str())
+                    .map(|rest| rest.is_empty() || rest.starts_with('/'))
+                    .unwrap_or(false)
+        });
+        match rule {
+            Some((_, allow)) => allow(&path, claims),
+            None => true,
+        }
+    }
+
+    /// Returns true if the claims allow access to the asset, by the prefix rules
+    /// and the asset's index entry
+    pub fn is_allowed_asset(&self, path: &str, md: &AssetMetadata, claims: Option<&Value>) -> bool {
+        let by_index = match md.extra.get(ACCESS_KEY).map(String::as_str) {
+            None | Some("") | Some("public") => true,
+            Some("authenticated") => claims.is_some(),
+            Some(requirement) => match requirement.split_once('=') {
+                Some((claim, value)) => has_claim(claims, claim.trim(), value.trim()),
+                None => false,
+            },
+        };
+        by_index && self.is_allowed(path, claims)
+    }
+}
+
+// Claim equals value, or is an array containing it
+fn has_claim(claims: Option<&Value>, claim: &str, value: &str) -> bool {
+    match claims.and_then(|c| c.get(claim)) {
+        Some(Value::String(s)) => s == value,
+        Some(Value::Array(items)) => items.iter().any(|item| item.as_str() == Some(value)),
+        Some(other) => serde_json::from_str::<Value>(value).is_ok_and(|v| v == *other),
+        None => false,
+    }
+}
+
+impl<'ah> KVAssets<'ah> {
+    /// Same as `serve_outcome`, but returns `ServeOutcome::Forbidden` if the
+    /// verified claims don't allow access to the path, or, for aliases, to the
+    /// target path. Claims are None for unauthenticated requests
+    pub async fn serve_outcome_authorized(
+        &self,
+        path: &str,
+        request_headers: &HeaderMap,
+        rules: &AccessRules,
+        claims: Option<&Value>,
+    ) -> Result<ServeOutcome, Error> {
+        if !self.is_authorized(path, rules, claims)? {
+            return Ok(ServeOutcome::Forbidden);
+        }
+        self.serve_outcome(path, request_headers).await
+    }
+
+    /// Serve the request (see `serve`) if the verified claims allow access to the path,
+    /// and, for aliases, to the target path, otherwise respond with 403 Forbidden
+    pub async fn serve_authorized(
+        &self,
+        req: &Request<()>,
+        rules: &AccessRules,
+        claims: Option<&Value>,
+    ) -> Response<Bytes> {
+        let path = req.uri().path();
+        match self.is_authorized(path, rules, claims) {
+            Ok(true) => self.serve(req).await,
+            Ok(false) => ServeOutcome::Forbidden.into_response(path),
+            Err(e) => crate::serve::error_response(&e),
+        }
+    }
+
+    // Prefix rules of the path and, for aliases, of the target path, and the
+    // index entry of the asset served
+    fn is_authorized(
+        &self,
+        path: &str,
+        rules: &AccessRules,
+        claims: Option<&Value>,
+    ) -> Result<bool, Error> {
+        if !rules.is_allowed(path, claims) {
+            return Ok(false);
+        }
+        Ok(match self.resolve_key(path)? {
+            Some(ResolvedAsset::Direct(md)) => rules.is_allowed_asset(path, &md, claims),
+            Some(ResolvedAsset::Alias { target, metadata }) => {
+                rules.is_allowed_asset(&target, &metadata, claims)
+            }
+            Some(ResolvedAsset::Redirect(_)) | None => true,
+        })
+    }
+}
+
+/// Tests access rules by prefix and index entry
+#[test]
+fn test_access_rules() {
+    let rules =
+        AccessRules::new()
+            .protect("/members/")
+            .require_claim("/members/admin", "roles", "admin");
+    let member = serde_json::json!({ "sub": "u1", "roles": ["member"] });
+    let admin = serde_json::json!({ "sub": "u2", "roles": ["member", "admin"] });
+
+    assert!(rules.is_allowed("/index.html", None));
+    assert!(rules.is_allowed("/membership.html", None));
+    assert!(!rules.is_allowed("/members/a.pdf", None));
+    assert!(rules.is_allowed("members/a.pdf", Some(&member)));
+    assert!(!rules.is_allowed("/members/admin/b.pdf", Some(&member)));
+    assert!(rules.is_allowed("/members/admin/b.pdf", Some(&admin)));
+
+    let mut md = AssetMetadata::default();
+    md.extra
+        .insert(ACCESS_KEY.to_string(), "roles=admin".to_string());
+    assert!(!rules.is_allowed_asset("/report.pdf", &md, Some(&member)));
+    assert!(rules.is_allowed_asset("/report.pdf", &md, Some(&admin)));
+    md.extra
+        .insert(ACCESS_KEY.to_string(), "authenticated".to_string());
+    assert!(!rules.is_allowed_asset("/report.pdf", &md, None));
+
+    assert_eq!(
+        ServeOutcome::Forbidden.into_response("/a").status(),
+        http::StatusCode::FORBIDDEN
+    );
+}
+
+/// Tests that aliases don't bypass the rules of their target (does not invoke cloudflare api)
+#[cfg(test)]
+#[tokio::test]
+async fn test_serve_authorized_alias() {
+    let mut index = crate::AssetIndex::new();
+    index.insert(
+        "members/secret.pdf".to_string(),
+        AssetMetadata {
+            path: "members/secret.1.pdf".to_string(),
+            size: 5,
+            ..Default::default()
+        },
+    );
+    index.insert(
+        "public/x.pdf".to_string(),
+        AssetMetadata::alias("members/secret.pdf"),
+    );
+    let blob = crate::encode_index(&index).unwrap();
+    // unreachable credentials: any KV access would fail
+    let kv = KVAssets::init(&blob, "acct", "ns", "token");
+    let rules = AccessRules::new().protect("/members");
+
+    let req = Request::get("/public/x.pdf").body(()).unwrap();
+    let resp = kv.serve_authorized(&req, &rules, None).await;
+    assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+    let outcome = kv
+        .serve_outcome_authorized("/public/x.pdf", &HeaderMap::new(), &rules, None)
+        .await
+        .unwrap();
+    assert!(matches!(outcome, ServeOutcome::Forbidden));
+
+    // index errors aren't ignored
+    let kv = KVAssets::init(b"not an index", "acct", "ns", "token");
+    let resp = kv.serve_authorized(&req, &rules, None).await;
+    assert_eq!(resp.status(), http::StatusCode::INTERNAL_SERVER_ERROR);
+}
@@ -0,0 +1,65 @@
+//! actix-web integration (feature "actix")
+//!
+//! ```ignore
+//! App::new()
+//!     .service(web::resource("/api/status").to(status))
+//!     .service(kv_assets::actix::KvAssetsFiles::new("/static", kv))
+//! ```
+
+use crate::KVAssets;
+use actix_web::{
+    dev::{AppService, HttpServiceFactory},
+    web, HttpRequest, HttpResponse,
+};
+use std::sync::Arc;
+
+/// Service factory that serves assets from KV under a mount path,
+/// similar to `actix_files::Files`. Handles GET and HEAD; other methods get 405.
+pub struct KvAssetsFiles {
+    mount_path: String,
+    kv: Arc<KVAssets<'static>>,
+}
+
+impl KvAssetsFiles {
+    /// Serve assets under mount_path. The remainder of the request path,
+    /// after the mount path, is the key looked up in the asset index.
+    pub fn new(mount_path: &str, kv: impl Into<Arc<KVAssets<'static>>>) -> Self {
+        Self {
+            mount_path: mount_path.trim_end_matches('/').to_string(),
+            kv: kv.into(),
+        }
+    }
+}
+
+impl HttpServiceFactory for KvAssetsFiles {
+    fn register(self, config: &mut AppService) {
+        let kv = self.kv;
+        let handler = move |req: HttpRequest| handle(kv.clone(), req);
+        web::resource(format!("{}/{{tail:.*}}", self.mount_path))
+            .route(web::get().to(handler.clone()))
+            .route(web::head().to(handler))
+            .register(config)
+    }
+}
+
+/// Convert actix request to request head, serve it, and convert the response
+async fn handle(kv: Arc<KVAssets<'static>>, req: HttpRequest) -> HttpResponse {
+    let tail = req.match_info().query("tail");
+    let uri = match format!("/{}", tail).parse() {
+        Ok(uri) => uri,
+        Err(_) => return HttpResponse::NotFound().finish(),
+    };
+    let mut head = http::Request::new(());
+    *head.method_mut() = req.method().clone();
+    *head.uri_mut() = uri;
+    for (name, val) in req.headers() {
+        head.headers_mut().append(name.clone(), val.clone());
+    }
+
+    let (parts, body) = kv.serve(&head).await.into_parts();
+    let mut response = HttpResponse::build(parts.status);
+    for (name, val) in parts.headers.iter() {
+        response.insert_header((name.clone(), val.clone()));
+    }
+    response.body(body)
+}
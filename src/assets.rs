@@ -1,69 +1,842 @@
-use crate::Error;
+use crate::{Error, HttpClient, KvPutOptions, Metrics};
+use bytes::Bytes;
+use http::Method;
 use serde::{Deserialize, Serialize};
-use std::cell::RefCell;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, RwLock,
+};
 
 const CLOUDFLARE_KV_ENDPOINT: &str = "https://api.cloudflare.com/client/v4";
 
+/// Maximum size of a single value in Workers KV (25 MiB)
+pub const MAX_VALUE_SIZE: usize = 25 * 1024 * 1024;
+
+// Maximum number of aliases followed by a lookup
+const MAX_ALIAS_DEPTH: usize = 8;
+
+// Number of chunks of a large value fetched in parallel
+const DEFAULT_CHUNK_CONCURRENCY: usize = 4;
+
 /// Hashmap of asset paths to metadata
 /// Path strings have leading / removed
 pub type AssetIndex = std::collections::HashMap<String, AssetMetadata>;
 
+/// Result of `resolve_key`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResolvedAsset {
+    /// The path is an asset
+    Direct(AssetMetadata),
+    /// The path is an alias of the target asset path
+    Alias {
+        /// Asset path the alias resolved to
+        target: String,
+        /// Metadata of the target asset
+        metadata: AssetMetadata,
+    },
+    /// The path is an alias that redirects to an external url
+    Redirect(String),
+}
+
+/// Which of a paired production and preview namespace KV operations use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NamespaceTarget {
+    /// The namespace id passed to `init` (`id` in wrangler.toml)
+    Production,
+    /// The namespace set with `with_preview_namespace` (`preview_id` in wrangler.toml)
+    Preview,
+}
+
 /// Asset metadata
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Ord, PartialOrd)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq, Ord, PartialOrd)]
 pub struct AssetMetadata {
-    /// Path to file within the namespace
+    /// Path to file within the namespace (its KV key). If empty, the key is derived
+    /// from the index path and hash with the handler's `KeyStrategy`
     pub path: String,
     /// Last modified time of file, in UTC seconds since EPOCH
     pub modified: u64,
     /// Size of file
     pub size: u64,
+    /// Hash of file contents, if recorded when the index was built: hex-encoded,
+    /// prefixed with its algorithm (`blake3:…`, `xxh3:…`), or without prefix for
+    /// SHA-256 (see `HashAlgorithm::hash`)
+    #[serde(default)]
+    pub hash: Option<String>,
+    /// If set, this entry is an alias, and path, modified, and size are unused
+    #[serde(default)]
+    pub alias: Option<AliasTarget>,
+    /// Response headers added by `serve`, such as `x-robots-tag` or `content-language`
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Application-defined fields set when the index is built, such as build ids
+    #[serde(default)]
+    pub extra: BTreeMap<String, String>,
+    /// If set, the asset was removed at this time, in UTC seconds since EPOCH.
+    /// Removed entries (tombstones) aren't served, but their KV values are kept so the
+    /// asset can be restored; `KVAssets::gc_tombstones` deletes them after a retention window
+    #[serde(default)]
+    pub deleted: Option<u64>,
+}
+
+/// Target of an alias entry in the index
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Ord, PartialOrd)]
+pub enum AliasTarget {
+    /// Another asset path in the index, such as the new location of a moved page
+    Path(String),
+    /// External url, which should be returned as a redirect
+    Redirect(String),
+}
+
+impl AssetMetadata {
+    /// Alias entry for another asset path in the index
+    pub fn alias(target: impl Into<String>) -> Self {
+        Self::alias_entry(AliasTarget::Path(target.into()))
+    }
+
+    /// Alias entry that redirects to an external url
+    pub fn redirect(url: impl Into<String>) -> Self {
+        Self::alias_entry(AliasTarget::Redirect(url.into()))
+    }
+
+    /// True if the entry is a tombstone for a removed asset
+    pub fn is_deleted(&self) -> bool {
+        self.deleted.is_some()
+    }
+
+    fn alias_entry(target: AliasTarget) -> Self {
+        Self {
+            alias: Some(target),
+            ..Default::default()
+        }
+    }
+}
+
+// Index entry found by resolving a path, borrowed from the index
+enum Found<'i> {
+    Direct(&'i AssetMetadata),
+    // target path, and its metadata
+    Alias(&'i str, &'i AssetMetadata),
+    Redirect(&'i str),
 }
 
 /// Serves static assets out of Worker KV storage.
 pub struct KVAssets<'ah> {
     index: &'ah [u8],
-    account_id: &'ah str,
-    namespace_id: &'ah str,
-    auth_token: &'ah str,
-    map: RefCell<Option<AssetIndex>>,
+    account_id: Cow<'ah, str>,
+    namespace_id: Cow<'ah, str>,
+    preview_namespace_id: Option<Cow<'ah, str>>,
+    use_preview: AtomicBool,
+    auth_token: Cow<'ah, str>,
+    api_endpoint: Cow<'ah, str>,
+    default_headers: http::HeaderMap,
+    decode_limits: crate::DecodeLimits,
+    map: RwLock<Option<AssetIndex>>,
+    // etag, hash, or version of the index loaded at runtime (see refresh.rs)
+    index_tag: std::sync::Mutex<Option<String>>,
+    client: Option<Arc<dyn HttpClient>>,
+    metrics: Option<Arc<dyn Metrics>>,
+    usage: crate::usage::Usage,
+    breaker: Option<Arc<crate::CircuitBreaker>>,
+    overlay: Option<crate::WriteOverlay>,
+    cache: Option<Arc<crate::ValueCache>>,
+    hash_algorithm: crate::HashAlgorithm,
+    key_strategy: Arc<dyn crate::KeyStrategy>,
+    edge_cache: Option<(Arc<dyn crate::EdgeCache>, std::time::Duration)>,
+    rate_limiter: Option<Arc<crate::RateLimiter>>,
+    observer: Option<Arc<dyn crate::DeployObserver>>,
+    ttl_rules: Option<Arc<crate::TtlRules>>,
+    cancel: Option<crate::CancelToken>,
+    #[cfg(not(target_arch = "wasm32"))]
+    checkpoint: Option<crate::CheckpointStore>,
+    #[cfg(not(target_arch = "wasm32"))]
+    transforms: Option<Arc<crate::Transforms>>,
+    verify_checksums: bool,
+    evict_stale: bool,
+    auto_index: bool,
+    path_mapping: Option<crate::PathMapping>,
+    // active deploy version read from the pointer key (see bluegreen.rs)
+    active_version: std::sync::Mutex<Option<crate::bluegreen::ActiveVersion>>,
+    active_version_ttl: std::time::Duration,
+    chunk_size: Option<usize>,
+    chunk_concurrency: usize,
+    max_buffered_size: Option<u64>,
+    image_formats: Vec<String>,
+    security_headers: Option<crate::SecurityHeaders>,
+    preload_rules: Option<crate::PreloadRules>,
+    embedded: Option<crate::EmbeddedAssets>,
+    #[cfg(feature = "encryption")]
+    encryption: Option<Arc<crate::Encryption>>,
+    #[cfg(feature = "signing")]
+    index_key: Option<&'ah [u8]>,
+    #[cfg(feature = "compression")]
+    compression: Option<crate::Compression>,
 }
 
 impl<'ah> KVAssets<'ah> {
     /// Initialize handler
-    /// - index: binary serialized index (created by cf_assets)
+    /// - index: binary serialized index (created by `kv-assets index` or `kv-sync`)
     /// - account_id: cloudflare account id
-    /// - namespace_id: cloudflare namespace (printed by cf_assets)
+    /// - namespace_id: cloudflare namespace id
     /// - auth_token: cloudflare OAuth token
     pub fn init(
         index: &'ah [u8],
         account_id: &'ah str,
         namespace_id: &'ah str,
         auth_token: &'ah str,
+    ) -> Self {
+        Self::from_parts(
+            index,
+            Cow::Borrowed(account_id),
+            Cow::Borrowed(namespace_id),
+            Cow::Borrowed(auth_token),
+        )
+    }
+
+    pub(crate) fn from_parts(
+        index: &'ah [u8],
+        account_id: Cow<'ah, str>,
+        namespace_id: Cow<'ah, str>,
+        auth_token: Cow<'ah, str>,
     ) -> Self {
         Self {
             index,
             account_id,
             namespace_id,
+            preview_namespace_id: None,
+            use_preview: AtomicBool::new(false),
             auth_token,
-            map: RefCell::new(None),
+            api_endpoint: Cow::Borrowed(CLOUDFLARE_KV_ENDPOINT),
+            default_headers: http::HeaderMap::new(),
+            decode_limits: crate::DecodeLimits::default(),
+            map: RwLock::new(None),
+            index_tag: std::sync::Mutex::new(None),
+            #[cfg(feature = "reqwest")]
+            client: Some(Arc::new(crate::ReqwestClient::default())),
+            #[cfg(not(feature = "reqwest"))]
+            client: None,
+            metrics: None,
+            usage: crate::usage::Usage::default(),
+            breaker: None,
+            overlay: None,
+            cache: None,
+            hash_algorithm: crate::HashAlgorithm::default(),
+            key_strategy: Arc::new(crate::ContentHashKeys),
+            edge_cache: None,
+            rate_limiter: None,
+            observer: None,
+            ttl_rules: None,
+            cancel: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            checkpoint: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            transforms: None,
+            verify_checksums: false,
+            evict_stale: false,
+            auto_index: false,
+            path_mapping: None,
+            active_version: std::sync::Mutex::new(None),
+            active_version_ttl: crate::bluegreen::DEFAULT_ACTIVE_VERSION_TTL,
+            chunk_size: None,
+            chunk_concurrency: DEFAULT_CHUNK_CONCURRENCY,
+            max_buffered_size: None,
+            image_formats: vec!["avif".to_string(), "webp".to_string()],
+            security_headers: None,
+            preload_rules: None,
+            embedded: None,
+            #[cfg(feature = "encryption")]
+            encryption: None,
+            #[cfg(feature = "signing")]
+            index_key: None,
+            #[cfg(feature = "compression")]
+            compression: None,
+        }
+    }
+
+    /// Initialize handler with account, namespace, and token from environment variables
+    /// `CLOUDFLARE_ACCOUNT_ID`, `CLOUDFLARE_KV_NAMESPACE_ID`, and `CLOUDFLARE_API_TOKEN`.
+    /// If any are missing, returns `Error::MissingConfig` listing all missing variables.
+    pub fn from_env(index: &'ah [u8]) -> Result<Self, Error> {
+        crate::KVAssetsBuilder::new()
+            .index(index)
+            .from_env()
+            .build()
+    }
+
+    /// Returns a builder, for configuring the handler from multiple sources
+    pub fn builder() -> crate::KVAssetsBuilder<'ah> {
+        crate::KVAssetsBuilder::new()
+    }
+
+    /// Set the preview namespace, paired with the production namespace
+    /// (like `preview_id` and `id` in wrangler.toml). Select it with `set_namespace_target`.
+    pub fn with_preview_namespace(mut self, namespace_id: impl Into<Cow<'ah, str>>) -> Self {
+        self.preview_namespace_id = Some(namespace_id.into());
+        self
+    }
+
+    /// Select the namespace used by all KV operations. Can be changed at runtime,
+    /// for example to run smoke tests against preview. Selecting Preview without
+    /// a preview namespace causes KV operations to fail, rather than use production.
+    pub fn set_namespace_target(&self, target: NamespaceTarget) {
+        self.use_preview
+            .store(target == NamespaceTarget::Preview, Ordering::SeqCst);
+    }
+
+    /// Returns the selected namespace
+    pub fn namespace_target(&self) -> NamespaceTarget {
+        match self.use_preview.load(Ordering::SeqCst) {
+            true => NamespaceTarget::Preview,
+            false => NamespaceTarget::Production,
+        }
+    }
+
+    /// Returns the id of the selected namespace
+    pub fn namespace_id(&self) -> Result<&str, Error> {
+        match self.namespace_target() {
+            NamespaceTarget::Production => Ok(&self.namespace_id),
+            NamespaceTarget::Preview => self
+                .preview_namespace_id
+                .as_deref()
+                .ok_or_else(|| Error::MissingConfig(vec!["preview_namespace_id".into()])),
+        }
+    }
+
+    /// Send Cloudflare api requests with this client, instead of the default
+    /// reqwest client. Required if the "reqwest" feature is disabled.
+    pub fn with_http_client(mut self, client: Arc<dyn HttpClient>) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Verify the index signature (created by `sign_index`) with this key before loading it.
+    /// If the signature doesn't match, lookups fail with `Error::IndexTampered`.
+    #[cfg(feature = "signing")]
+    pub fn with_index_key(mut self, key: &'ah [u8]) -> Self {
+        self.index_key = Some(key);
+        self
+    }
+
+    /// Returns the index signing key, if set
+    #[cfg(feature = "signing")]
+    pub(crate) fn index_key(&self) -> Option<&[u8]> {
+        self.index_key
+    }
+
+    /// Encrypt values in put_kv_value, and decrypt them in get_kv_value
+    #[cfg(feature = "encryption")]
+    pub fn with_encryption(mut self, encryption: crate::Encryption) -> Self {
+        self.encryption = Some(Arc::new(encryption));
+        self
+    }
+
+    /// Compress responses from `serve` on the fly (see `Compression`)
+    #[cfg(feature = "compression")]
+    pub fn with_compression(mut self, compression: crate::Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Returns the response compression settings, if set
+    #[cfg(feature = "compression")]
+    pub(crate) fn compression(&self) -> Option<&crate::Compression> {
+        self.compression.as_ref()
+    }
+
+    /// Limits on the index decoded by this handler, embedded or loaded at runtime
+    /// (see `DecodeLimits`). default: `DecodeLimits::default()`
+    pub fn with_decode_limits(mut self, limits: crate::DecodeLimits) -> Self {
+        self.decode_limits = limits;
+        self
+    }
+
+    pub(crate) fn decode_limits(&self) -> &crate::DecodeLimits {
+        &self.decode_limits
+    }
+
+    /// Soft limits on this handler's api usage: exceeding one is reported to
+    /// `Metrics::usage_budget_exceeded`, without failing requests (see `usage`)
+    pub fn with_usage_budget(mut self, budget: crate::UsageBudget) -> Self {
+        self.usage.set_budget(budget);
+        self
+    }
+
+    pub(crate) fn usage_counters(&self) -> &crate::usage::Usage {
+        &self.usage
+    }
+
+    /// Send api requests to endpoint instead of `https://api.cloudflare.com/client/v4`,
+    /// such as a mock api in tests (see `testspace`), or a proxy
+    pub fn with_api_endpoint(mut self, endpoint: &str) -> Self {
+        self.api_endpoint = Cow::Owned(endpoint.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Add a header to every Cloudflare api request, such as `User-Agent`, or tracing
+    /// headers for an egress proxy. Replaces an earlier value of the same header.
+    /// The `Authorization` header is always the api token
+    pub fn with_default_header(
+        mut self,
+        name: http::header::HeaderName,
+        value: http::HeaderValue,
+    ) -> Self {
+        self.default_headers.insert(name, value);
+        self
+    }
+
+    /// Add security headers to responses from `serve` (see `SecurityHeaders`)
+    pub fn with_security_headers(mut self, headers: crate::SecurityHeaders) -> Self {
+        self.security_headers = Some(headers);
+        self
+    }
+
+    /// Returns the security headers policy, if set
+    pub(crate) fn security_headers(&self) -> Option<&crate::SecurityHeaders> {
+        self.security_headers.as_ref()
+    }
+
+    /// Preload relationships of HTML pages, added to responses from `serve` as
+    /// `Link` headers (see `PreloadRules`). default: none
+    pub fn with_preload_rules(mut self, rules: crate::PreloadRules) -> Self {
+        self.preload_rules = Some(rules);
+        self
+    }
+
+    pub(crate) fn preload_rules(&self) -> Option<&crate::PreloadRules> {
+        self.preload_rules.as_ref()
+    }
+
+    /// Serve assets compiled into the worker when reading KV or the api fails
+    /// (see `EmbeddedAssets`). default: none
+    pub fn with_embedded_fallback(mut self, assets: crate::EmbeddedAssets) -> Self {
+        self.embedded = Some(assets);
+        self
+    }
+
+    pub(crate) fn embedded_fallback(&self) -> Option<&crate::EmbeddedAssets> {
+        self.embedded.as_ref()
+    }
+
+    /// Answer requests for directories that have assets but no `index.html` with a
+    /// listing of the index entries under them (see `list_dir`): HTML, or JSON if the
+    /// request accepts `application/json` but not `text/html`. Useful for file dumps
+    /// and artifact hosting. default: false
+    pub fn with_auto_index(mut self, enabled: bool) -> Self {
+        self.auto_index = enabled;
+        self
+    }
+
+    pub(crate) fn auto_index(&self) -> bool {
+        self.auto_index
+    }
+
+    /// Map request paths to index paths, such as `/static/app.js` to `v42/app.js`
+    /// (see `PathMapping`). Applies to lookups and `serve`. default: none
+    pub fn with_path_mapping(mut self, mapping: crate::PathMapping) -> Self {
+        self.path_mapping = Some(mapping);
+        self
+    }
+
+    // Index path of the request path, or None if the path mapping excludes it.
+    // The active deploy version, if any, is added after the mapping
+    pub(crate) fn index_path<'p>(&self, path: &'p str) -> Option<std::borrow::Cow<'p, str>> {
+        let path = path.strip_prefix('/').unwrap_or(path);
+        let mapped = match &self.path_mapping {
+            Some(mapping) => mapping.map(path)?,
+            None => std::borrow::Cow::Borrowed(path),
+        };
+        match self.active_version() {
+            Some(version) => Some(std::borrow::Cow::Owned(format!("{}/{}", version, mapped))),
+            None => Some(mapped),
+        }
+    }
+
+    /// How long the active deploy version read by `load_active_version` is used before
+    /// the pointer key is read again. default: 60 seconds
+    pub fn with_active_version_ttl(mut self, ttl: std::time::Duration) -> Self {
+        self.active_version_ttl = ttl;
+        self
+    }
+
+    pub(crate) fn active_version_state(&self) -> Option<crate::bluegreen::ActiveVersion> {
+        self.active_version.lock().unwrap().clone()
+    }
+
+    pub(crate) fn set_active_version_state(&self, state: crate::bluegreen::ActiveVersion) {
+        *self.active_version.lock().unwrap() = Some(state);
+    }
+
+    pub(crate) fn active_version_ttl(&self) -> std::time::Duration {
+        self.active_version_ttl
+    }
+
+    /// Serve values written or deleted through this handler from memory for a
+    /// while after the write, since KV is eventually consistent (see `WriteOverlay`)
+    pub fn with_write_overlay(mut self, overlay: crate::WriteOverlay) -> Self {
+        self.overlay = Some(overlay);
+        self
+    }
+
+    /// Serve recently read values from memory (see `ValueCache`)
+    pub fn with_value_cache(mut self, cache: Arc<crate::ValueCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Returns the value cache, if installed
+    pub(crate) fn value_cache(&self) -> Option<&crate::ValueCache> {
+        self.cache.as_deref()
+    }
+
+    /// Record a successful write (or delete, if value is None) in the
+    /// value cache and write overlay
+    pub(crate) fn record_write(&self, key: &str, value: Option<Bytes>) {
+        if let Some(cache) = &self.cache {
+            cache.remove(key);
+        }
+        if let Some(overlay) = &self.overlay {
+            overlay.record(key, value);
+        }
+    }
+
+    /// Algorithm for content hashes computed by `put_if_changed`, `publish_dir`,
+    /// and `OriginPull`. Hashes of any supported algorithm are verified. default: `HashAlgorithm::default()`
+    pub fn with_hash_algorithm(mut self, algorithm: crate::HashAlgorithm) -> Self {
+        self.hash_algorithm = algorithm;
+        self
+    }
+
+    /// Returns the content hash algorithm
+    pub(crate) fn hash_algorithm(&self) -> crate::HashAlgorithm {
+        self.hash_algorithm
+    }
+
+    /// How asset paths map to KV keys, when publishing assets and reading index
+    /// entries without a key (see `KeyStrategy`). default: `ContentHashKeys`
+    pub fn with_key_strategy(mut self, strategy: Arc<dyn crate::KeyStrategy>) -> Self {
+        self.key_strategy = strategy;
+        self
+    }
+
+    pub(crate) fn key_strategy(&self) -> &dyn crate::KeyStrategy {
+        self.key_strategy.as_ref()
+    }
+
+    /// Check a shared http cache, such as the Workers Cache API, before reading
+    /// asset values from KV, and store values read from KV in it with max-age ttl
+    /// (see `EdgeCache`, and `DEFAULT_EDGE_CACHE_TTL`)
+    pub fn with_edge_cache(
+        mut self,
+        cache: Arc<dyn crate::EdgeCache>,
+        ttl: std::time::Duration,
+    ) -> Self {
+        self.edge_cache = Some((cache, ttl));
+        self
+    }
+
+    /// Returns the edge cache and its ttl, if installed
+    pub(crate) fn edge_cache(&self) -> Option<(&dyn crate::EdgeCache, std::time::Duration)> {
+        self.edge_cache
+            .as_ref()
+            .map(|(cache, ttl)| (cache.as_ref(), *ttl))
+    }
+
+    /// Limit the rate of api requests made by writes, deletes, and key listing
+    /// (see `RateLimiter`). Reads are not limited
+    pub fn with_rate_limiter(mut self, limiter: Arc<crate::RateLimiter>) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    /// Report uploads and deletes made by `publish_dir` and `prune` to the observer
+    pub fn with_deploy_observer(mut self, observer: Arc<dyn crate::DeployObserver>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Upload assets matching the rules with expiration TTLs in `publish_dir` (see `TtlRules`)
+    pub fn with_ttl_rules(mut self, rules: Arc<crate::TtlRules>) -> Self {
+        self.ttl_rules = Some(rules);
+        self
+    }
+
+    /// TTL in seconds for an asset uploaded by `publish_dir`, or None if it is permanent
+    pub(crate) fn ttl_for(&self, path: &str) -> Option<u64> {
+        self.ttl_rules
+            .as_ref()
+            .and_then(|rules| rules.ttl_for(path))
+    }
+
+    /// Stop long-running operations (publishing, bulk writes, mirroring, copying,
+    /// and garbage collection) with `Error::Cancelled` when the token is cancelled
+    pub fn with_cancel_token(mut self, token: crate::CancelToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Save the progress of `publish_dir` and `stage_dir`, so an interrupted
+    /// deploy of the same content resumes where it left off
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_checkpoint(mut self, store: crate::CheckpointStore) -> Self {
+        self.checkpoint = Some(store);
+        self
+    }
+
+    /// Transform asset content before `publish_dir`, `stage_dir`, `deploy_dir`, and
+    /// `stage_version` upload it, such as to minify CSS and JS. KV keys, sizes,
+    /// hashes, and integrity hashes are computed from the transformed content, so
+    /// changing a transform's output uploads new values
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_transforms(mut self, transforms: crate::Transforms) -> Self {
+        self.transforms = Some(Arc::new(transforms));
+        self
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn transforms(&self) -> Option<&crate::Transforms> {
+        self.transforms.as_deref()
+    }
+
+    // Tag of the index loaded at runtime: "v{n}" for published versions,
+    // "etag:{etag}" or "sha256:{hash}" for indexes loaded from a url
+    pub(crate) fn index_tag(&self) -> Option<String> {
+        self.index_tag.lock().unwrap().clone()
+    }
+
+    pub(crate) fn set_index_tag(&self, tag: Option<String>) {
+        *self.index_tag.lock().unwrap() = tag;
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn checkpoint_store(&self) -> Option<&crate::CheckpointStore> {
+        self.checkpoint.as_ref()
+    }
+
+    // Returns Error::Cancelled if the cancel token, if any, is cancelled
+    pub(crate) fn check_cancelled(&self, operation: &str) -> Result<(), Error> {
+        match &self.cancel {
+            Some(token) => token.check(operation),
+            None => Ok(()),
+        }
+    }
+
+    /// Send the event to the deploy observer, if installed
+    pub(crate) fn observe(&self, event: impl FnOnce() -> crate::DeployEvent) {
+        if let Some(observer) = &self.observer {
+            observer.on_event(&event());
+        }
+    }
+
+    // Wait for the rate limiter, if installed
+    pub(crate) async fn rate_limit(&self) {
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.acquire().await;
+        }
+    }
+
+    /// Report lookups and KV operations to a metrics backend
+    pub fn with_metrics(mut self, metrics: Arc<dyn Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Verify downloaded assets against the content hash in the index.
+    /// If the content doesn't match, `get_asset` and `serve` return `Error::ChecksumMismatch`
+    /// instead of serving a truncated or out-of-date file.
+    /// Assets without a hash in the index are not checked.
+    pub fn verify_checksums(mut self, verify: bool) -> Self {
+        self.verify_checksums = verify;
+        self
+    }
+
+    /// When an indexed asset's KV value doesn't exist (`Error::IndexOutOfDate`),
+    /// remove its entries from the in-memory index, so later requests for the path
+    /// are not found without a KV read (and a `FallbackChain` tries the next source).
+    pub fn evict_stale_entries(mut self, evict: bool) -> Self {
+        self.evict_stale = evict;
+        self
+    }
+
+    /// Store values larger than chunk_size as multiple KV values, to get around
+    /// the KV value size limit (see `MAX_VALUE_SIZE`). `put_kv_value` writes the parts as
+    /// `{key}.part0` .. `{key}.partN`, with a small manifest at `key`, and `get_kv_value`
+    /// reassembles them. This changes the storage layout, so readers of the namespace
+    /// must also enable chunking.
+    pub fn with_chunking(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = Some(chunk_size.max(1));
+        self
+    }
+
+    /// Maximum number of parts of a chunked value fetched in parallel. default: 4
+    pub fn with_chunk_concurrency(mut self, concurrency: usize) -> Self {
+        self.chunk_concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Maximum size of a value read into memory by `get_asset`, `serve`, and
+    /// `get_kv_value`, in bytes. Larger assets fail with `Error::TooLargeToBuffer`
+    /// before they are read, so one large asset can't exhaust the worker's memory
+    /// (128MB on Cloudflare Workers): serve them by streaming from the worker's KV
+    /// binding instead. Entries of unknown size (0) are checked after they are read,
+    /// and chunked values before their parts are read. default: no limit
+    pub fn with_max_buffered_size(mut self, bytes: u64) -> Self {
+        self.max_buffered_size = Some(bytes);
+        self
+    }
+
+    // Fail with TooLargeToBuffer if a value of size exceeds the buffer limit
+    pub(crate) fn check_buffered_size(&self, key: &str, size: u64) -> Result<(), Error> {
+        match self.max_buffered_size {
+            Some(limit) if size > limit => Err(Error::TooLargeToBuffer {
+                key: key.to_string(),
+                size,
+                limit,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Alternative image formats (file extensions) tried by `lookup_image`,
+    /// in order of preference. default: avif, webp
+    pub fn with_image_formats(mut self, formats: &[&str]) -> Self {
+        self.image_formats = formats.iter().map(|f| f.to_ascii_lowercase()).collect();
+        self
+    }
+
+    pub(crate) fn image_formats(&self) -> &[String] {
+        &self.image_formats
+    }
+
+    /// Returns the number of parts to fetch in parallel
+    pub(crate) fn chunk_concurrency(&self) -> usize {
+        self.chunk_concurrency
+    }
+
+    /// Lightweight handler for another namespace, sharing this handler's http client,
+    /// api endpoint, account, api token, rate limiter, circuit breaker, metrics, chunking, and encryption
+    /// (see `NamespaceHandle`)
+    pub fn namespace<'s>(&'s self, namespace_id: &'s str) -> crate::NamespaceHandle<'s> {
+        let mut kv = KVAssets::from_parts(
+            &[],
+            Cow::Borrowed(self.account_id.as_ref()),
+            Cow::Borrowed(namespace_id),
+            Cow::Borrowed(self.auth_token.as_ref()),
+        );
+        kv.client = self.client.clone();
+        kv.api_endpoint = self.api_endpoint.clone();
+        kv.metrics = self.metrics.clone();
+        kv.breaker = self.breaker.clone();
+        kv.rate_limiter = self.rate_limiter.clone();
+        kv.chunk_size = self.chunk_size;
+        kv.chunk_concurrency = self.chunk_concurrency;
+        #[cfg(feature = "encryption")]
+        {
+            kv.encryption = self.encryption.clone();
+        }
+        crate::NamespaceHandle::new(kv)
+    }
+
+    // Replace the api token and account id, for NamespaceHandle
+    pub(crate) fn set_credentials(
+        &mut self,
+        account_id: Option<Cow<'ah, str>>,
+        auth_token: Option<Cow<'ah, str>>,
+    ) {
+        if let Some(account_id) = account_id {
+            self.account_id = account_id;
+        }
+        if let Some(auth_token) = auth_token {
+            self.auth_token = auth_token;
+        }
+    }
+
+    /// Returns the chunk size, if chunking is enabled
+    pub(crate) fn chunk_size(&self) -> Option<usize> {
+        self.chunk_size
+    }
+
+    /// Fail KV reads fast with `Error::CircuitOpen` while the api is failing
+    pub fn with_circuit_breaker(mut self, breaker: Arc<crate::CircuitBreaker>) -> Self {
+        self.breaker = Some(breaker);
+        self
+    }
+
+    /// Returns the installed metrics hooks, if any
+    pub(crate) fn metrics(&self) -> Option<&dyn Metrics> {
+        self.metrics.as_deref()
+    }
+
+    // Report a cache lookup to the metrics hooks, if any
+    pub(crate) fn record_cache_lookup(&self, cache: &str, hit: bool) {
+        match (self.metrics(), hit) {
+            (Some(metrics), true) => metrics.cache_hit(cache),
+            (Some(metrics), false) => metrics.cache_miss(cache),
+            (None, _) => {}
         }
     }
 
     // Lazily deserialize map, so we don't bother doing so
     // when handling urls that aren't for static assets
     fn ensure_map(&self) -> Result<(), Error> {
-        let mut map = self.map.borrow_mut();
+        if self.map.read().unwrap().is_some() {
+            return Ok(());
+        }
+        let mut map = self.map.write().unwrap();
         if (*map).is_none() {
-            *map = Some(bincode::deserialize(self.index).map_err(Error::DeserializeAssets)?);
+            #[cfg(feature = "tracing")]
+            let _span =
+                tracing::debug_span!("deserialize_index", bytes = self.index.len()).entered();
+            #[cfg(feature = "signing")]
+            let blob = match self.index_key {
+                Some(key) => crate::signing::verify_index(self.index, key)?,
+                None => self.index,
+            };
+            #[cfg(not(feature = "signing"))]
+            let blob = self.index;
+            let mut index = crate::decode_index_with_limits(blob, &self.decode_limits)?;
+            self.fill_keys(&mut index);
+            #[cfg(feature = "tracing")]
+            tracing::debug!(entries = index.len(), "index loaded");
+            *map = Some(index);
         }
         Ok(())
     }
 
+    /// Deserialize the index now, rather than on first use, and return its number of entries.
+    /// Indexes with thousands of entries take a while to decode: call this at startup,
+    /// or from a background task (such as a worker's `waitUntil`, or `spawn_warm_index`),
+    /// so the first request doesn't wait for it. Does nothing if the index is already loaded.
+    pub fn warm_index(&self) -> Result<usize, Error> {
+        self.with_index(|index| index.len())
+    }
+
+    /// Replace the index used by this handler, such as with an index loaded from KV
+    /// (see `load_published_index`). Lookups in progress finish with the previous index
+    pub fn replace_index(&self, index: AssetIndex) {
+        *self.map.write().unwrap() = Some(index);
+        self.set_index_tag(None);
+    }
+
     /// all-in-one method to get the asset from KV
     pub async fn get_asset(&self, key: &str) -> Result<Option<bytes::Bytes>, Error> {
+        self.get_asset_with(key, &crate::CacheOptions::default())
+            .await
+    }
+
+    /// Get the asset as `get_asset` does, with per-request cache behavior,
+    /// such as bypassing caches for admin requests (see `CacheOptions`)
+    pub async fn get_asset_with(
+        &self,
+        key: &str,
+        options: &crate::CacheOptions,
+    ) -> Result<Option<bytes::Bytes>, Error> {
         match self.lookup_key(key) {
             Ok(Some(md)) => {
-                let doc = self.get_kv_value(&md.path).await?;
+                let doc = self.fetch_asset_traced(&md, options).await?.0;
+                if let Some(metrics) = self.metrics() {
+                    metrics.bytes_served(doc.len() as u64);
+                }
                 Ok(Some(doc))
             }
             Ok(None) => Ok(None),
@@ -71,21 +844,157 @@ impl<'ah> KVAssets<'ah> {
         }
     }
 
+    /// Fetches the asset value from the edge cache, if any, or KV, and verifies the
+    /// checksum if enabled. Returns `Error::IndexOutOfDate` if the value doesn't exist
+    pub(crate) async fn fetch_asset(&self, md: &AssetMetadata) -> Result<bytes::Bytes, Error> {
+        let options = crate::CacheOptions::default();
+        Ok(self.fetch_asset_traced(md, &options).await?.0)
+    }
+
+    // fetch_asset with per-request cache options, also returning true if the value
+    // was served from a cache without reading KV
+    pub(crate) async fn fetch_asset_traced(
+        &self,
+        md: &AssetMetadata,
+        options: &crate::CacheOptions,
+    ) -> Result<(bytes::Bytes, bool), Error> {
+        self.check_buffered_size(&md.path, md.size)?;
+        if options.uses_edge_cache() {
+            if let Some(doc) = self.edge_cache_get(md).await {
+                return Ok((self.verify_asset(md, doc)?, true));
+            }
+        }
+        self.apply_cache_priority(md);
+        let (doc, cached) = match self.get_kv_value_traced(&md.path, options).await {
+            Ok(found) => found,
+            Err(Error::KVKeyNotFound(key, 404)) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(key = key.as_str(), "index out of date");
+                if self.evict_stale {
+                    if let Some(index) = self.map.write().unwrap().as_mut() {
+                        index.retain(|_, entry| entry.path != key);
+                    }
+                }
+                return Err(Error::IndexOutOfDate { key });
+            }
+            Err(e) => return Err(e),
+        };
+        if md.size == 0 {
+            self.check_buffered_size(&md.path, doc.len() as u64)?;
+        }
+        let doc = self.verify_asset(md, doc)?;
+        self.edge_cache_put(md, doc.clone()).await;
+        Ok((doc, cached))
+    }
+
+    // Verify the checksum of the asset value, if enabled
+    fn verify_asset(&self, md: &AssetMetadata, doc: Bytes) -> Result<Bytes, Error> {
+        if self.verify_checksums {
+            if let Some(hash) = &md.hash {
+                if !crate::checksum::matches(&doc, hash) {
+                    return Err(Error::ChecksumMismatch(md.path.clone()));
+                }
+            }
+        }
+        Ok(doc)
+    }
+
+    /// Calls f with the asset index, deserializing it first if necessary
+    pub(crate) fn with_index<R>(&self, f: impl FnOnce(&AssetIndex) -> R) -> Result<R, Error> {
+        self.ensure_map()?;
+        let map = self.map.read().unwrap();
+        Ok(f(map.as_ref().unwrap()))
+    }
+
     /// Finds the path in the map, returning the "key"
     /// This lookup should reliably and quickly determine whether asset is in KV,
     /// as it doesn't require querying KV yet.
     /// Removes leading / if present
     /// Returns Ok(None) if Not found
+    ///
+    /// Aliases are resolved, returning the metadata of the target asset.
+    /// For an alias that redirects to an external url, returns the alias entry.
+    /// Use `resolve_key` to find out whether the path was an alias.
     pub fn lookup_key(&self, path: &'_ str) -> Result<Option<AssetMetadata>, Error> {
-        // remove leading '/' if present
-        let path = path.strip_prefix('/').unwrap_or(path);
-        if path.is_empty() {
-            return Err(Error::EmptyKey);
+        Ok(self.resolve_key(path)?.map(|resolved| match resolved {
+            ResolvedAsset::Direct(md) | ResolvedAsset::Alias { metadata: md, .. } => md,
+            ResolvedAsset::Redirect(url) => AssetMetadata::redirect(url),
+        }))
+    }
+
+    /// Finds the path in the map, resolving aliases, and reports whether the
+    /// asset was found directly or through an alias, so the caller can choose to
+    /// redirect to the canonical path. Returns `Error::AliasLoop` if aliases are
+    /// nested more than 8 deep.
+    pub fn resolve_key(&self, path: &'_ str) -> Result<Option<ResolvedAsset>, Error> {
+        self.with_resolved(path, |found| match found {
+            Found::Direct(md) => ResolvedAsset::Direct(md.clone()),
+            Found::Alias(target, md) => ResolvedAsset::Alias {
+                target: target.to_string(),
+                metadata: md.clone(),
+            },
+            Found::Redirect(url) => ResolvedAsset::Redirect(url.to_string()),
+        })
+    }
+
+    /// Calls f with the metadata of the asset at path, without cloning it, and returns
+    /// its result, or Ok(None) if the path isn't in the index. Aliases are resolved as in
+    /// `lookup_key`. Use this on hot paths that only need a few fields, such as the
+    /// KV key or content type, to avoid allocating a copy of the metadata per request.
+    /// The index is locked for reading while f runs.
+    pub fn with_metadata<R>(
+        &self,
+        path: &str,
+        f: impl FnOnce(&AssetMetadata) -> R,
+    ) -> Result<Option<R>, Error> {
+        self.with_resolved(path, |found| match found {
+            Found::Direct(md) | Found::Alias(_, md) => f(md),
+            Found::Redirect(url) => f(&AssetMetadata::redirect(url.to_string())),
+        })
+    }
+
+    // Resolve path (and any aliases) in the index, and call f with the result
+    fn with_resolved<R>(
+        &self,
+        path: &str,
+        f: impl FnOnce(Found<'_>) -> R,
+    ) -> Result<Option<R>, Error> {
+        // remove leading '/' if present, and apply the path mapping
+        let mapped = match self.index_path(path) {
+            Some(mapped) => mapped,
+            None => {
+                if let Some(metrics) = self.metrics() {
+                    metrics.lookup(false);
+                }
+                return Ok(None);
+            }
+        };
+        let path = mapped.as_ref();
+        crate::AssetKey::new(path)?;
+        let resolved = self.with_index(|index| {
+            let mut key = path;
+            for depth in 0..=MAX_ALIAS_DEPTH {
+                let md = match index.get(key) {
+                    Some(md) if !md.is_deleted() => md,
+                    _ => return Ok(None),
+                };
+                match &md.alias {
+                    None if depth == 0 => return Ok(Some(f(Found::Direct(md)))),
+                    None => return Ok(Some(f(Found::Alias(key, md)))),
+                    Some(AliasTarget::Redirect(url)) => return Ok(Some(f(Found::Redirect(url)))),
+                    Some(AliasTarget::Path(target)) => {
+                        key = target.strip_prefix('/').unwrap_or(target)
+                    }
+                }
+            }
+            Err(Error::AliasLoop(path.to_string()))
+        })??;
+        #[cfg(feature = "tracing")]
+        tracing::trace!(path, found = resolved.is_some(), "lookup");
+        if let Some(metrics) = self.metrics() {
+            metrics.lookup(resolved.is_some());
         }
-        self.ensure_map()?;
-        let map = self.map.borrow();
-        let md = map.as_ref().unwrap().get(path).cloned();
-        Ok(md)
+        Ok(resolved)
     }
 
     /// Lookup asset in worker kV storage.
@@ -94,80 +1003,459 @@ impl<'ah> KVAssets<'ah> {
     /// - the asset was deleted from KV
     /// - the value timed out via TTL
     /// - the index is out of date
+    ///
+    /// Returns `Error::CircuitOpen` without a request if a circuit breaker is installed and open.
+    /// Keys recently written through this handler are served from its write overlay, if any,
+    /// and recently read keys from its value cache, if any.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip(self), err)
+    )]
     pub async fn get_kv_value(&self, key: &str) -> Result<bytes::Bytes, Error> {
-        let url = format!(
-            "{}/accounts/{}/storage/kv/namespaces/{}/values/{}",
-            CLOUDFLARE_KV_ENDPOINT, &self.account_id, &self.namespace_id, key
-        );
-        let client = reqwest::Client::new();
-        let response = client
-            .get(&url)
-            .header("Authorization", format!("Bearer {}", self.auth_token))
-            .send()
+        let options = crate::CacheOptions::default();
+        Ok(self.get_kv_value_traced(key, &options).await?.0)
+    }
+
+    // get_kv_value with per-request cache options, also returning true if the value
+    // was served from the write overlay or value cache
+    async fn get_kv_value_traced(
+        &self,
+        key: &str,
+        options: &crate::CacheOptions,
+    ) -> Result<(bytes::Bytes, bool), Error> {
+        if let Some(result) = self.overlay.as_ref().and_then(|o| o.get(key)) {
+            return Ok((result?, true));
+        }
+        if !options.bypass && !options.revalidate {
+            if let Some(cache) = &self.cache {
+                let value = cache.get(key, options.max_stale);
+                self.record_cache_lookup("value", value.is_some());
+                if let Some(value) = value {
+                    return Ok((value, true));
+                }
+            }
+        }
+        if !options.bypass {
+            if let Some(value) = self.revalidate(key, options.revalidate).await {
+                return Ok((value, false));
+            }
+        }
+        let result = match &self.breaker {
+            Some(breaker) => {
+                breaker.allow()?;
+                let result = self.read_kv_value(key).await;
+                breaker.record(&result);
+                result
+            }
+            None => self.read_kv_value(key).await,
+        };
+        if let (Ok(value), Some(cache)) = (&result, &self.cache) {
+            cache.insert(key, value.clone());
+        }
+        result.map(|value| (value, false))
+    }
+
+    // Fetch value, reassembling it if it was stored in chunks
+    pub(crate) async fn read_kv_value(&self, key: &str) -> Result<bytes::Bytes, Error> {
+        let body = self.get_value(key).await?;
+        if self.chunk_size.is_some() {
+            if let Some(manifest) = crate::chunked::ChunkManifest::decode(&body) {
+                self.check_buffered_size(key, manifest.size)?;
+                return self.get_chunks(key, &manifest).await;
+            }
+        }
+        Ok(body)
+    }
+
+    // Fetch a single value, and decrypt it if encryption is enabled
+    pub(crate) async fn get_value(&self, key: &str) -> Result<bytes::Bytes, Error> {
+        Ok(self.get_value_with_headers(key).await?.1)
+    }
+
+    // Fetch a single value and the response headers, and decrypt the value if encryption is enabled
+    pub(crate) async fn get_value_with_headers(
+        &self,
+        key: &str,
+    ) -> Result<(http::HeaderMap, Bytes), Error> {
+        let url = self.key_url("values", key)?;
+        let response = self
+            .send(self.request(Method::GET, &url, Bytes::new()))
             .await
-            .map_err(Error::KVHttp)?;
+            .inspect_err(|_| {
+                if let Some(metrics) = self.metrics() {
+                    metrics.kv_get(0);
+                }
+            })?;
+        #[cfg(feature = "tracing")]
+        tracing::debug!(status = response.status().as_u16(), "kv get");
+        if let Some(metrics) = self.metrics() {
+            metrics.kv_get(response.status().as_u16());
+        }
         match response.status().is_success() {
             false => Err(Error::KVKeyNotFound(
                 key.to_string(),
                 response.status().as_u16(),
             )),
-            true => Ok(response.bytes().await.map_err(Error::KVHttp)?),
+            true => {
+                let (parts, body) = response.into_parts();
+                #[cfg(feature = "encryption")]
+                let body = match &self.encryption {
+                    Some(enc) => bytes::Bytes::from(enc.decrypt(&body)?),
+                    None => body,
+                };
+                #[cfg(feature = "tracing")]
+                tracing::debug!(bytes = body.len(), "kv get body");
+                Ok((parts.headers, body))
+            }
         }
     }
 
+    /// List all keys in the KV namespace. Results are paged by the api,
+    /// so this makes one request per 1000 keys. To process keys as pages arrive,
+    /// or to read their metadata, use `list_all_keys`.
+    pub async fn list_keys(&self) -> Result<Vec<String>, Error> {
+        let mut keys = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let (page, next) = self.list_page(cursor.as_deref()).await?;
+            keys.extend(page.into_iter().map(|k| k.name));
+            cursor = next;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        Ok(keys)
+    }
+
     /// Store a value in KV. Optionally, set expiration TTL, number of seconds in future
     /// when content should be automatically deleted. TTL must be at least 60.
-    pub async fn put_kv_value<T: Into<reqwest::Body>>(
+    /// If the api rejects the value as too large (413), fails with `Error::ValueTooLarge`
+    /// or, if chunking is enabled (see `with_chunking`), stores the value in smaller parts instead.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip(self, val), err)
+    )]
+    pub async fn put_kv_value<T: Into<Bytes>>(
         &self,
         key: &str,
         val: T,
         expiration_ttl: Option<u64>,
     ) -> Result<(), Error> {
-        let url = format!(
-            "{}/accounts/{}/storage/kv/namespaces/{}/values/{}{}",
-            CLOUDFLARE_KV_ENDPOINT,
-            &self.account_id,
-            &self.namespace_id,
-            key,
-            match expiration_ttl {
-                Some(ttl) => {
-                    if ttl < 60 {
-                        return Err(Error::TTLTooShort);
-                    }
-                    format!("?expiration_ttl={}", ttl)
-                }
-                None => String::from(""),
+        let opts = KvPutOptions {
+            expiration_ttl,
+            ..Default::default()
+        };
+        self.put_kv_value_opts(key, val, &opts).await
+    }
+
+    /// Store a value in KV with options for expiration, metadata, and content type
+    /// (see `KvPutOptions`). Large values are handled as in `put_kv_value`;
+    /// when a value is chunked, its metadata is stored with the first key.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip(self, val, opts), err)
+    )]
+    pub async fn put_kv_value_opts<T: Into<Bytes>>(
+        &self,
+        key: &str,
+        val: T,
+        opts: &KvPutOptions,
+    ) -> Result<(), Error> {
+        let body: Bytes = val.into();
+        let result = match self.chunk_size {
+            Some(chunk_size) if body.len() > chunk_size => {
+                self.put_chunks(key, body.clone(), opts, chunk_size).await
             }
-        );
+            _ => self.put(key, body.clone(), opts).await,
+        };
+        // the api limit was lower than the chunk size (for example, after encryption
+        // overhead): retry with parts half the size of the value
+        let result = match (result, self.chunk_size) {
+            (Err(Error::ValueTooLarge { .. }), Some(chunk_size)) if body.len() > 1 => {
+                let part_size = body.len().div_ceil(2).min(chunk_size);
+                self.put_chunks(key, body.clone(), opts, part_size).await
+            }
+            (result, _) => result,
+        };
+        if let Some(cache) = &self.cache {
+            cache.remove(key);
+        }
+        if let (Ok(()), Some(overlay)) = (&result, &self.overlay) {
+            overlay.record(key, Some(body));
+        }
+        result
+    }
 
-        let client = reqwest::Client::new();
-        let response = client
-            .put(&url)
-            .header("Authorization", format!("Bearer {}", self.auth_token))
-            .body(val)
-            .send()
-            .await
-            .map_err(Error::KVHttp)?
-            .json::<WriteKVResponse>()
-            .await
-            .map_err(Error::KVHttp)?;
+    /// Store a value in KV, unless it is unchanged. Returns true if the value was written.
+    /// The content hash is compared with the hash recorded for the key in the index
+    /// or, if the index doesn't reference the key, with the hash in the key's KV metadata.
+    /// Values written by this method store their hash in KV metadata,
+    /// so re-running a deploy doesn't rewrite every key.
+    /// Values are written with `put_kv_value_opts`, so large values are chunked if enabled.
+    pub async fn put_if_changed(&self, key: &str, val: Bytes) -> Result<bool, Error> {
+        let indexed = self.with_index(|index| {
+            index
+                .values()
+                .find(|md| md.path == key)
+                .and_then(|md| md.hash.clone())
+        })?;
+        let existing = match indexed {
+            Some(hash) => Some(hash),
+            None => self
+                .get_kv_metadata(key)
+                .await?
+                .and_then(|md| md.get("hash").and_then(|h| h.as_str().map(String::from))),
+        };
+        // compared with the existing hash's algorithm, so changing algorithms doesn't rewrite values
+        if existing.map(|h| crate::checksum::matches(&val, &h)) == Some(true) {
+            return Ok(false);
+        }
+        let opts = KvPutOptions::new()
+            .with_metadata(serde_json::json!({ "hash": self.hash_algorithm.hash(&val) }));
+        self.put_kv_value_opts(key, val, &opts).await?;
+        Ok(true)
+    }
+
+    /// Returns the metadata stored with a KV key, or None if the key
+    /// doesn't exist or has no metadata.
+    pub async fn get_kv_metadata(&self, key: &str) -> Result<Option<serde_json::Value>, Error> {
+        let url = self.key_url("metadata", key)?;
+        let response = self
+            .send(self.request(Method::GET, &url, Bytes::new()))
+            .await?;
+        match response.status().as_u16() {
+            404 => Ok(None),
+            status if !response.status().is_success() => {
+                Err(Error::KVKeyNotFound(key.to_string(), status))
+            }
+            _ => {
+                let response: MetadataResponse = parse_json(response.body())?;
+                Ok(response.result.filter(|md| !md.is_null()))
+            }
+        }
+    }
+
+    /// Returns true if the key exists in KV, without downloading its value: only the
+    /// key's metadata is fetched, so this is cheap even for large values.
+    /// Keys recently written or deleted through this handler are answered from its
+    /// write overlay, if any. Unlike `lookup_key`, this checks KV rather than the index.
+    pub async fn exists(&self, key: &str) -> Result<bool, Error> {
+        if let Some(result) = self.overlay.as_ref().and_then(|o| o.get(key)) {
+            return Ok(result.is_ok());
+        }
+        let url = self.key_url("metadata", key)?;
+        let response = self
+            .send(self.request(Method::GET, &url, Bytes::new()))
+            .await?;
+        match response.status().as_u16() {
+            404 => Ok(false),
+            status if !response.status().is_success() => {
+                Err(Error::KVKeyNotFound(key.to_string(), status))
+            }
+            _ => Ok(true),
+        }
+    }
+
+    /// Delete a value from KV. Deleting a key that doesn't exist is not an error.
+    pub async fn delete_kv_value(&self, key: &str) -> Result<(), Error> {
+        let url = self.key_url("values", key)?;
+        self.rate_limit().await;
+        let response = self
+            .send(self.request(Method::DELETE, &url, Bytes::new()))
+            .await?;
+        let response: WriteKVResponse = parse_json(response.body())?;
+        if let Some(cache) = &self.cache {
+            cache.remove(key);
+        }
+        if response.success {
+            if let Some(overlay) = &self.overlay {
+                overlay.record(key, None);
+            }
+            Ok(())
+        } else {
+            Err(crate::error_kind::api_error(
+                &format!("deleting key {}", key),
+                &response.errors,
+            ))
+        }
+    }
+
+    // Write value, with optional expiration, metadata, and content type.
+    // Values with metadata are sent as multipart form
+    pub(crate) async fn put(
+        &self,
+        key: &str,
+        body: Bytes,
+        opts: &KvPutOptions,
+    ) -> Result<(), Error> {
+        let url = format!("{}{}", self.key_url("values", key)?, opts.query()?);
+
+        let body = self.encrypt(body)?;
+        #[cfg(feature = "tracing")]
+        tracing::debug!(bytes = body.len(), "kv put");
+        let body_len = body.len();
+        self.rate_limit().await;
+        let (content_type, body) = match &opts.metadata {
+            Some(metadata) => {
+                let (content_type, form) = multipart_form(&body, metadata, opts.content_type());
+                (Some(content_type), form)
+            }
+            None => (opts.content_type.clone(), body),
+        };
+        let mut request = self.request(Method::PUT, &url, body);
+        if let Some(val) = content_type.and_then(|ct| http::HeaderValue::from_str(&ct).ok()) {
+            request
+                .headers_mut()
+                .insert(http::header::CONTENT_TYPE, val);
+        }
+        let response = self.send(request).await.inspect_err(|_| {
+            if let Some(metrics) = self.metrics() {
+                metrics.kv_put(0);
+            }
+        })?;
+        #[cfg(feature = "tracing")]
+        tracing::debug!(status = response.status().as_u16(), "kv put response");
+        if let Some(metrics) = self.metrics() {
+            metrics.kv_put(response.status().as_u16());
+        }
+        if response.status() == http::StatusCode::PAYLOAD_TOO_LARGE {
+            return Err(Error::ValueTooLarge {
+                key: key.to_string(),
+                size: body_len,
+                limit: MAX_VALUE_SIZE,
+            });
+        }
+        let response: WriteKVResponse = parse_json(response.body())?;
 
         if response.success {
             Ok(())
         } else {
-            Err(Error::Message(format!(
-                "writing key {}: errors:{:?} messages:{:?}",
-                key, response.errors, response.messages
-            )))
+            Err(crate::error_kind::api_error(
+                &format!("writing key {}", key),
+                &response.errors,
+            ))
+        }
+    }
+
+    // Encrypt value before it is written, if encryption is enabled
+    pub(crate) fn encrypt(&self, body: Bytes) -> Result<Bytes, Error> {
+        #[cfg(feature = "encryption")]
+        if let Some(enc) = &self.encryption {
+            return Ok(Bytes::from(enc.encrypt(&body)?));
+        }
+        Ok(body)
+    }
+
+    // Url of the key in the KV namespace api endpoint ("values" or "metadata").
+    // Fails with InvalidKey before any request if the key is invalid
+    fn key_url(&self, endpoint: &str, key: &str) -> Result<String, Error> {
+        let key = crate::AssetKey::new(key)?;
+        self.namespace_url(&format!("{}/{}", endpoint, key.url_encoded()))
+    }
+
+    // Url of selected KV namespace api, with path appended
+    pub(crate) fn namespace_url(&self, path: &str) -> Result<String, Error> {
+        Ok(format!(
+            "{}/{}",
+            self.account_url(&format!("storage/kv/namespaces/{}", self.namespace_id()?)),
+            path
+        ))
+    }
+
+    // Url of account api, with path appended
+    pub(crate) fn account_url(&self, path: &str) -> String {
+        self.api_url(&format!("accounts/{}/{}", &self.account_id, path))
+    }
+
+    // Url of cloudflare api, with path appended
+    pub(crate) fn api_url(&self, path: &str) -> String {
+        format!("{}/{}", self.api_endpoint, path)
+    }
+
+    // Build authorized api request
+    pub(crate) fn request(&self, method: Method, url: &str, body: Bytes) -> http::Request<Bytes> {
+        let mut request = http::Request::new(body);
+        *request.method_mut() = method;
+        if let Ok(uri) = url.parse() {
+            *request.uri_mut() = uri;
+        }
+        for (name, value) in self.default_headers.iter() {
+            request.headers_mut().insert(name, value.clone());
         }
+        if let Ok(val) = http::HeaderValue::from_str(&format!("Bearer {}", self.auth_token)) {
+            request
+                .headers_mut()
+                .insert(http::header::AUTHORIZATION, val);
+        }
+        request
+    }
+
+    // Send request with the configured http client
+    pub(crate) async fn send(
+        &self,
+        request: http::Request<Bytes>,
+    ) -> Result<http::Response<Bytes>, Error> {
+        let client = match &self.client {
+            Some(client) => client,
+            None => return Err(Error::Message("No http client configured".into())),
+        };
+        let op = crate::usage::classify(request.method(), request.uri().path());
+        let sent = request.body().len() as u64;
+        let response = client.send(request).await;
+        let received = response.as_ref().map_or(0, |r| r.body().len() as u64);
+        self.usage.record(op, sent, received, self.metrics());
+        response
     }
 }
 
+// Parse api response
+pub(crate) fn parse_json<T: serde::de::DeserializeOwned>(body: &[u8]) -> Result<T, Error> {
+    serde_json::from_slice(body).map_err(|e| Error::KVHttp(Box::new(e)))
+}
+
+// Encode value and metadata as multipart/form-data, returning content type and body.
+// The boundary is derived from the content hash, so it won't occur in the value.
+fn multipart_form(
+    value: &[u8],
+    metadata: &serde_json::Value,
+    content_type: Option<&str>,
+) -> (String, Bytes) {
+    let boundary = format!("kv-assets-{}", crate::checksum::content_hash(value));
+    let mut form = Vec::with_capacity(value.len() + 512);
+    form.extend_from_slice(
+        format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"value\"\r\n{}\r\n",
+            boundary,
+            content_type
+                .map(|ct| format!("Content-Type: {}\r\n", ct))
+                .unwrap_or_default()
+        )
+        .as_bytes(),
+    );
+    form.extend_from_slice(value);
+    form.extend_from_slice(
+        format!(
+            "\r\n--{}\r\nContent-Disposition: form-data; name=\"metadata\"\r\n\r\n{}\r\n--{}--\r\n",
+            boundary, metadata, boundary
+        )
+        .as_bytes(),
+    );
+    (
+        format!("multipart/form-data; boundary={}", boundary),
+        Bytes::from(form),
+    )
+}
+
+#[derive(Deserialize)]
+pub(crate) struct WriteKVResponse {
+    pub success: bool,
+    #[serde(default)]
+    pub errors: Vec<serde_json::Value>,
+}
+
 #[derive(Deserialize)]
-struct WriteKVResponse {
-    success: bool,
-    errors: Vec<String>,
-    messages: Vec<String>,
+struct MetadataResponse {
+    result: Option<serde_json::Value>,
 }
 
 /// Tests manifest lookup function (does not invoke cloudflare api)
@@ -177,22 +1465,28 @@ fn test_lookup() {
         path: "a/b.txt".to_string(),
         modified: 10000,
         size: 10,
+        hash: None,
+        ..Default::default()
     };
     let md_b = AssetMetadata {
         path: "b".to_string(),
         modified: 20000,
         size: 20,
+        hash: None,
+        ..Default::default()
     };
     let md_c = AssetMetadata {
         path: "c.json".to_string(),
         modified: 30000,
         size: 30,
+        hash: None,
+        ..Default::default()
     };
     let mut index = AssetIndex::new();
     index.insert("a/b".to_string(), md_ab.clone());
     index.insert("b".to_string(), md_b.clone());
     index.insert("c.json".to_string(), md_c.clone());
-    let blob = bincode::serialize(&index).expect("serialize-index");
+    let blob = crate::encode_index(&index).expect("serialize-index");
 
     let kv = KVAssets::init(&blob, "123", "namespace", "token");
 
@@ -209,4 +1503,318 @@ fn test_lookup() {
 
     // ensure_map
     assert!(kv.ensure_map().is_ok());
+
+    // borrowed lookup
+    assert_eq!(kv.with_metadata("/a/b", |md| md.size).unwrap(), Some(10));
+    assert_eq!(kv.with_metadata("xyz", |md| md.size).unwrap(), None);
+}
+
+/// Tests selection of production and preview namespaces
+#[test]
+fn test_namespace_target() {
+    let kv = KVAssets::init(&[], "123", "prod", "token");
+    assert_eq!(kv.namespace_id().unwrap(), "prod");
+    // preview selected but not configured: must not fall back to production
+    kv.set_namespace_target(NamespaceTarget::Preview);
+    assert!(kv.namespace_id().is_err());
+
+    let kv = KVAssets::init(&[], "123", "prod", "token").with_preview_namespace("preview");
+    assert_eq!(kv.namespace_target(), NamespaceTarget::Production);
+    kv.set_namespace_target(NamespaceTarget::Preview);
+    assert_eq!(kv.namespace_id().unwrap(), "preview");
+    kv.set_namespace_target(NamespaceTarget::Production);
+    assert_eq!(kv.namespace_id().unwrap(), "prod");
+}
+
+/// Tests alias resolution
+#[test]
+fn test_resolve_alias() {
+    let md = AssetMetadata {
+        path: "new.1.html".to_string(),
+        modified: 10000,
+        size: 10,
+        hash: None,
+        ..Default::default()
+    };
+    let mut index = AssetIndex::new();
+    index.insert("new.html".to_string(), md.clone());
+    index.insert("old.html".to_string(), AssetMetadata::alias("new.html"));
+    index.insert("older.html".to_string(), AssetMetadata::alias("/old.html"));
+    index.insert(
+        "ext".to_string(),
+        AssetMetadata::redirect("https://example.com"),
+    );
+    index.insert("loop".to_string(), AssetMetadata::alias("loop"));
+    let blob = crate::encode_index(&index).unwrap();
+    let kv = KVAssets::init(&blob, "123", "namespace", "token");
+
+    assert_eq!(
+        kv.resolve_key("new.html").unwrap(),
+        Some(ResolvedAsset::Direct(md.clone()))
+    );
+    assert_eq!(
+        kv.resolve_key("/older.html").unwrap(),
+        Some(ResolvedAsset::Alias {
+            target: "new.html".to_string(),
+            metadata: md.clone()
+        })
+    );
+    assert_eq!(
+        kv.with_metadata("old.html", |md| md.path.len()).unwrap(),
+        Some(md.path.len())
+    );
+    assert_eq!(kv.lookup_key("old.html").unwrap(), Some(md));
+    assert_eq!(
+        kv.resolve_key("ext").unwrap(),
+        Some(ResolvedAsset::Redirect("https://example.com".to_string()))
+    );
+    assert!(matches!(kv.resolve_key("loop"), Err(Error::AliasLoop(_))));
+}
+
+/// Tests that a missing KV value is reported as index drift (does not invoke cloudflare api)
+#[cfg(test)]
+#[tokio::test]
+async fn test_index_out_of_date() {
+    use crate::{HttpClient, HttpFuture};
+
+    struct Missing;
+    impl HttpClient for Missing {
+        fn send(&self, _: http::Request<Bytes>) -> HttpFuture<'_> {
+            Box::pin(async {
+                let mut response = http::Response::new(Bytes::new());
+                *response.status_mut() = http::StatusCode::NOT_FOUND;
+                Ok(response)
+            })
+        }
+    }
+
+    let mut index = AssetIndex::new();
+    index.insert(
+        "a.txt".to_string(),
+        AssetMetadata {
+            path: "a.1.txt".to_string(),
+            size: 5,
+            ..Default::default()
+        },
+    );
+    index.insert("b.txt".to_string(), AssetMetadata::alias("a.txt"));
+    let blob = crate::encode_index(&index).unwrap();
+    let kv = KVAssets::init(&blob, "acct", "ns", "token")
+        .with_http_client(Arc::new(Missing))
+        .evict_stale_entries(true);
+
+    match kv.get_asset("/a.txt").await {
+        Err(Error::IndexOutOfDate { key }) => assert_eq!(key, "a.1.txt"),
+        other => panic!("expected IndexOutOfDate, got {:?}", other),
+    }
+    // evicted: now not found without a KV read
+    assert!(kv.lookup_key("/a.txt").unwrap().is_none());
+    assert!(kv.get_asset("/a.txt").await.unwrap().is_none());
+}
+
+/// Tests refusing to buffer values over the size limit (does not invoke cloudflare api)
+#[cfg(test)]
+#[tokio::test]
+async fn test_max_buffered_size() {
+    use crate::{HttpClient, HttpFuture};
+
+    // every value is 10 bytes
+    struct Stub;
+    impl HttpClient for Stub {
+        fn send(&self, _: http::Request<Bytes>) -> HttpFuture<'_> {
+            Box::pin(async { Ok(http::Response::new(Bytes::from("0123456789"))) })
+        }
+    }
+
+    let mut index = AssetIndex::new();
+    for (path, size) in [("small.txt", 10), ("large.bin", 1 << 30), ("unknown", 0)] {
+        index.insert(
+            path.to_string(),
+            AssetMetadata {
+                path: path.to_string(),
+                size,
+                ..Default::default()
+            },
+        );
+    }
+    let blob = crate::encode_index(&index).unwrap();
+    let kv = KVAssets::init(&blob, "acct", "ns", "token")
+        .with_http_client(Arc::new(Stub))
+        .with_max_buffered_size(8 << 20);
+    assert!(kv.get_asset("small.txt").await.unwrap().is_some());
+    match kv.get_asset("large.bin").await {
+        Err(Error::TooLargeToBuffer { key, size, limit }) => {
+            assert_eq!((key.as_str(), size, limit), ("large.bin", 1 << 30, 8 << 20))
+        }
+        other => panic!("expected TooLargeToBuffer, got {:?}", other),
+    }
+    // HEAD doesn't read the value
+    let head = http::Request::head("/large.bin").body(()).unwrap();
+    assert_eq!(kv.serve(&head).await.status(), http::StatusCode::OK);
+
+    let kv = kv.with_max_buffered_size(5);
+    assert!(matches!(
+        kv.get_asset("unknown").await,
+        Err(Error::TooLargeToBuffer { size: 10, .. })
+    ));
+}
+
+/// Tests that exists checks the metadata endpoint only (does not invoke cloudflare api)
+#[cfg(test)]
+#[tokio::test]
+async fn test_exists() {
+    use crate::{HttpClient, HttpFuture};
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Stub {
+        paths: Mutex<Vec<String>>,
+    }
+    impl HttpClient for Stub {
+        fn send(&self, request: http::Request<Bytes>) -> HttpFuture<'_> {
+            let path = request.uri().path().to_string();
+            let found = path.ends_with("/metadata/big.bin");
+            self.paths.lock().unwrap().push(path);
+            Box::pin(async move {
+                let mut response = http::Response::new(Bytes::from(
+                    r#"{"success":true,"errors":[],"messages":[],"result":null}"#,
+                ));
+                if !found {
+                    *response.status_mut() = http::StatusCode::NOT_FOUND;
+                }
+                Ok(response)
+            })
+        }
+    }
+
+    let stub = Arc::new(Stub::default());
+    let kv = KVAssets::init(&[], "acct", "ns", "token").with_http_client(stub.clone());
+    assert!(kv.exists("big.bin").await.unwrap());
+    assert!(!kv.exists("missing.bin").await.unwrap());
+    assert!(stub
+        .paths
+        .lock()
+        .unwrap()
+        .iter()
+        .all(|p| p.contains("/metadata/")));
+}
+
+/// Tests 413 responses, and the fallback to chunked storage (does not invoke cloudflare api)
+#[cfg(test)]
+#[tokio::test]
+async fn test_value_too_large() {
+    use crate::{HttpClient, HttpFuture};
+    use std::sync::Mutex;
+
+    // rejects values larger than 60 bytes
+    #[derive(Default)]
+    struct Stub {
+        puts: Mutex<Vec<(String, usize)>>,
+    }
+    impl HttpClient for Stub {
+        fn send(&self, request: http::Request<Bytes>) -> HttpFuture<'_> {
+            let key = request.uri().path().rsplit('/').next().unwrap().to_string();
+            let size = request.body().len();
+            self.puts.lock().unwrap().push((key, size));
+            Box::pin(async move {
+                let mut response = http::Response::new(Bytes::from(
+                    r#"{"success":true,"errors":[],"messages":[]}"#,
+                ));
+                if size > 60 {
+                    *response.status_mut() = http::StatusCode::PAYLOAD_TOO_LARGE;
+                }
+                Ok(response)
+            })
+        }
+    }
+
+    let kv = KVAssets::init(&[], "acct", "ns", "token").with_http_client(Arc::new(Stub::default()));
+    let value = Bytes::from(vec![b'x'; 100]);
+    match kv.put_kv_value("big.bin", value.clone(), None).await {
+        Err(Error::ValueTooLarge { key, size, limit }) => {
+            assert_eq!(
+                (key.as_str(), size, limit),
+                ("big.bin", 100, MAX_VALUE_SIZE)
+            );
+        }
+        other => panic!("expected ValueTooLarge, got {:?}", other),
+    }
+
+    let stub = Arc::new(Stub::default());
+    let kv = KVAssets::init(&[], "acct", "ns", "token")
+        .with_http_client(stub.clone())
+        .with_chunking(128);
+    kv.put_kv_value("big.bin", value, None).await.unwrap();
+    let puts = stub.puts.lock().unwrap();
+    assert_eq!(puts[0], ("big.bin".to_string(), 100));
+    assert_eq!(puts[1], ("big.bin.part0".to_string(), 50));
+    assert_eq!(puts[2], ("big.bin.part1".to_string(), 50));
+    assert_eq!(puts[3].0, "big.bin");
+}
+
+/// Tests expiration, metadata, and content type in put_kv_value_opts (does not invoke cloudflare api)
+#[cfg(test)]
+#[tokio::test]
+async fn test_put_kv_value_opts() {
+    use crate::{HttpClient, HttpFuture};
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Stub {
+        requests: Mutex<Vec<http::Request<Bytes>>>,
+    }
+    impl HttpClient for Stub {
+        fn send(&self, request: http::Request<Bytes>) -> HttpFuture<'_> {
+            self.requests.lock().unwrap().push(request);
+            Box::pin(async move {
+                Ok(http::Response::new(Bytes::from(
+                    r#"{"success":true,"errors":[],"messages":[]}"#,
+                )))
+            })
+        }
+    }
+
+    let stub = Arc::new(Stub::default());
+    let kv = KVAssets::init(&[], "acct", "ns", "token").with_http_client(stub.clone());
+    let opts = KvPutOptions::new()
+        .with_expiration(1_900_000_000)
+        .with_content_type("image/png");
+    kv.put_kv_value_opts("a.png", "png", &opts).await.unwrap();
+    let opts = opts.with_metadata(serde_json::json!({ "v": 1 }));
+    kv.put_kv_value_opts("b.png", "png", &opts).await.unwrap();
+
+    let requests = stub.requests.lock().unwrap();
+    assert_eq!(requests[0].uri().query(), Some("expiration=1900000000"));
+    assert_eq!(
+        requests[0].headers()[http::header::CONTENT_TYPE],
+        "image/png"
+    );
+    assert_eq!(requests[0].body(), "png");
+    let content_type = requests[1].headers()[http::header::CONTENT_TYPE]
+        .to_str()
+        .unwrap();
+    assert!(content_type.starts_with("multipart/form-data"));
+    let form = String::from_utf8_lossy(requests[1].body());
+    assert!(form.contains("name=\"value\"\r\nContent-Type: image/png\r\n\r\npng\r\n"));
+    assert!(form.contains(r#"{"v":1}"#));
+}
+
+/// Tests that put_if_changed skips unchanged values, and chunks large ones, against the mock api
+#[cfg(all(test, feature = "reqwest"))]
+#[tokio::test]
+async fn test_put_if_changed() {
+    let mock = crate::testspace::MockCloudflare::start().unwrap();
+    let kv = mock.handler(&[], "ns").with_chunking(64);
+    let value = Bytes::from(vec![b'x'; 100]);
+    assert!(kv.put_if_changed("big.bin", value.clone()).await.unwrap());
+    assert_eq!(
+        mock.keys("ns"),
+        vec!["big.bin", "big.bin.part0", "big.bin.part1"]
+    );
+    assert_eq!(kv.get_kv_value("big.bin").await.unwrap(), value);
+    assert!(!kv.put_if_changed("big.bin", value).await.unwrap());
+    assert!(kv
+        .put_if_changed("big.bin", Bytes::from("small"))
+        .await
+        .unwrap());
 }
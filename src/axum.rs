@@ -0,0 +1,21 @@
+//! axum integration (feature "axum")
+//!
+//! ```ignore
+//! let app = Router::new()
+//!     .route("/api/status", get(status))
+//!     .fallback_service(kv_assets::axum::serve_dir(kv));
+//! ```
+
+use crate::{KVAssets, KvAssetsService};
+use ::axum::routing::{get_service, MethodRouter};
+use std::sync::Arc;
+
+/// Returns a router for GET and HEAD requests that serves assets from KV,
+/// suitable for `Router::fallback_service` or `Router::nest_service`.
+/// Conditional requests get 304 responses, and paths not in the index get 404.
+pub fn serve_dir<S>(kv: impl Into<Arc<KVAssets<'static>>>) -> MethodRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    get_service(KvAssetsService::from_arc(kv.into()))
+}
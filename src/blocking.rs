@@ -0,0 +1,104 @@
+#![cfg(all(feature = "blocking", not(target_arch = "wasm32")))]
+
+use crate::{AssetMetadata, Error, HttpClient, HttpFuture, KVAssets, SyncConfig};
+use bytes::Bytes;
+use http::{Request, Response};
+use std::sync::Arc;
+
+/// Synchronous version of `KVAssets`, for deploy scripts and build.rs steps
+/// that don't have an async runtime. Requests are made with `reqwest::blocking`,
+/// so, like that client, this must not be used from within an async runtime.
+pub struct KVAssetsBlocking<'ah> {
+    inner: KVAssets<'ah>,
+}
+
+impl<'ah> KVAssetsBlocking<'ah> {
+    /// Initialize handler. Parameters are the same as `KVAssets::init`
+    pub fn init(
+        index: &'ah [u8],
+        account_id: &'ah str,
+        namespace_id: &'ah str,
+        auth_token: &'ah str,
+    ) -> Self {
+        Self {
+            inner: KVAssets::init(index, account_id, namespace_id, auth_token)
+                .with_http_client(Arc::new(BlockingClient::default())),
+        }
+    }
+
+    /// Returns the async handler, which shares the index and configuration
+    pub fn inner(&self) -> &KVAssets<'ah> {
+        &self.inner
+    }
+
+    /// Finds the path in the index. See `KVAssets::lookup_key`
+    pub fn lookup_key(&self, path: &str) -> Result<Option<AssetMetadata>, Error> {
+        self.inner.lookup_key(path)
+    }
+
+    /// all-in-one method to get the asset from KV
+    pub fn get_asset(&self, key: &str) -> Result<Option<Bytes>, Error> {
+        futures_executor::block_on(self.inner.get_asset(key))
+    }
+
+    /// Lookup asset in worker KV storage. See `KVAssets::get_kv_value`
+    pub fn get_kv_value(&self, key: &str) -> Result<Bytes, Error> {
+        futures_executor::block_on(self.inner.get_kv_value(key))
+    }
+
+    /// Store a value in KV. See `KVAssets::put_kv_value`
+    pub fn put_kv_value<T: Into<Bytes>>(
+        &self,
+        key: &str,
+        val: T,
+        expiration_ttl: Option<u64>,
+    ) -> Result<(), Error> {
+        futures_executor::block_on(self.inner.put_kv_value(key, val, expiration_ttl))
+    }
+
+    /// Store a value in KV with options. See `KVAssets::put_kv_value_opts`
+    pub fn put_kv_value_opts<T: Into<Bytes>>(
+        &self,
+        key: &str,
+        val: T,
+        opts: &crate::KvPutOptions,
+    ) -> Result<(), Error> {
+        futures_executor::block_on(self.inner.put_kv_value_opts(key, val, opts))
+    }
+
+    /// Sync an asset folder to KV and generate the index. See `sync_assets`
+    pub fn sync(args: SyncConfig) -> Result<crate::DeployReport, Error> {
+        crate::sync_assets(args)
+    }
+}
+
+/// HttpClient that completes each request synchronously, with reqwest::blocking
+#[derive(Default)]
+struct BlockingClient {
+    client: reqwest::blocking::Client,
+}
+
+impl BlockingClient {
+    fn send_blocking(&self, request: Request<Bytes>) -> Result<Response<Bytes>, Error> {
+        let (parts, body) = request.into_parts();
+        let response = self
+            .client
+            .request(parts.method, parts.uri.to_string())
+            .headers(parts.headers)
+            .body(body.to_vec())
+            .send()
+            .map_err(|e| Error::KVHttp(Box::new(e)))?;
+        let mut builder = Response::builder().status(response.status());
+        if let Some(headers) = builder.headers_mut() {
+            *headers = response.headers().clone();
+        }
+        let body = response.bytes().map_err(|e| Error::KVHttp(Box::new(e)))?;
+        builder.body(body).map_err(|e| Error::KVHttp(Box::new(e)))
+    }
+}
+
+impl HttpClient for BlockingClient {
+    fn send(&self, request: Request<Bytes>) -> HttpFuture<'_> {
+        Box::pin(std::future::ready(self.send_blocking(request)))
+    }
+}
@@ -0,0 +1,177 @@
+use crate::{Error, KVAssets, INDEX_KEY_PREFIX};
+use bytes::Bytes;
+use std::time::Duration;
+
+/// KV key holding the active deploy version, for blue/green deploys
+/// (see `KVAssets::set_active_version`)
+pub const ACTIVE_VERSION_KEY: &str = "__index_active";
+
+/// Default time the active version is used before the pointer key is read again
+pub const DEFAULT_ACTIVE_VERSION_TTL: Duration = Duration::from_secs(60);
+
+// Active version read from the pointer key, and when it was read
+#[derive(Debug, Clone)]
+pub(crate) struct ActiveVersion {
+    pub(crate) version: Option<String>,
+    pub(crate) read_at: u64,
+}
+
+// Versions are the first segment of index paths and KV keys
+fn check_version(version: &str) -> Result<(), Error> {
+    if version.is_empty() || version.contains('/') || version.starts_with(INDEX_KEY_PREFIX) {
+        return Err(Error::InvalidKey(format!(
+            "invalid deploy version '{}'",
+            version
+        )));
+    }
+    Ok(())
+}
+
+impl<'ah> KVAssets<'ah> {
+    /// Upload asset_dir as deploy version (such as "v42"), as `stage_dir` does, with
+    /// index paths and KV keys under "{version}/". Versions can be staged side by side:
+    /// merge their indexes (with `extend`) into the index workers use, then switch
+    /// between them with `set_active_version`, without touching individual assets
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn stage_version(
+        &self,
+        asset_dir: &std::path::Path,
+        version: &str,
+    ) -> Result<crate::StagedDeploy, Error> {
+        check_version(version)?;
+        self.stage(asset_dir, Some(version)).await
+    }
+
+    /// Make version the active deploy: writes it to the pointer key
+    /// (`ACTIVE_VERSION_KEY`), and uses it in this handler. Workers pick it up
+    /// the next time they call `load_active_version` after their cached version
+    /// expires, so flipping the pointer deploys or rolls back every asset at once
+    pub async fn set_active_version(&self, version: &str) -> Result<(), Error> {
+        check_version(version)?;
+        self.put_kv_value(ACTIVE_VERSION_KEY, Bytes::from(version.to_string()), None)
+            .await?;
+        self.set_active_version_state(ActiveVersion {
+            version: Some(version.to_string()),
+            read_at: crate::breaker::system_millis(),
+        });
+        Ok(())
+    }
+
+    /// Active deploy version, read from the pointer key if it hasn't been read within
+    /// the ttl (`with_active_version_ttl`). Once loaded, lookups and `serve` prefix
+    /// index paths with "{version}/". Returns None if no version is active.
+    /// Workers have no system clock, so on wasm the pointer is read once per handler:
+    /// call `reload_active_version` to read it again
+    pub async fn load_active_version(&self) -> Result<Option<String>, Error> {
+        if let Some(state) = self.active_version_state() {
+            let age = crate::breaker::system_millis().saturating_sub(state.read_at);
+            if u128::from(age) < self.active_version_ttl().as_millis() {
+                return Ok(state.version);
+            }
+        }
+        self.reload_active_version().await
+    }
+
+    /// Read the active deploy version from the pointer key, and use it in this handler
+    pub async fn reload_active_version(&self) -> Result<Option<String>, Error> {
+        let version = match self.get_value(ACTIVE_VERSION_KEY).await {
+            Ok(value) => {
+                let version = std::str::from_utf8(&value)
+                    .map(|v| v.trim().to_string())
+                    .unwrap_or_default();
+                check_version(&version)
+                    .map_err(|_| Error::Message(format!("invalid {} value", ACTIVE_VERSION_KEY)))?;
+                Some(version)
+            }
+            Err(Error::KVKeyNotFound(_, 404)) => None,
+            Err(e) => return Err(e),
+        };
+        self.set_active_version_state(ActiveVersion {
+            version: version.clone(),
+            read_at: crate::breaker::system_millis(),
+        });
+        Ok(version)
+    }
+
+    /// Active deploy version last loaded by this handler, without reading KV
+    pub fn active_version(&self) -> Option<String> {
+        self.active_version_state().and_then(|state| state.version)
+    }
+}
+
+/// Tests staging versions and switching between them, against the mock api
+#[cfg(all(test, feature = "reqwest"))]
+#[tokio::test]
+async fn test_blue_green() {
+    use crate::AssetIndex;
+
+    let dir = std::env::temp_dir().join(format!("kv-assets-bluegreen-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mock = crate::testspace::MockCloudflare::start().unwrap();
+    let deployer = mock.handler(&[], "ns");
+    let mut index = AssetIndex::new();
+    for version in ["blue", "green"] {
+        std::fs::write(dir.join("index.html"), version).unwrap();
+        let staged = deployer.stage_version(&dir, version).await.unwrap();
+        index.extend(staged.into_parts().0);
+    }
+    assert!(index.contains_key("blue/index.html"));
+    assert!(mock
+        .keys("ns")
+        .iter()
+        .any(|key| key.starts_with("green/index.")));
+    // the version prefix counts toward the key length limit
+    let long = format!("{}/{}", "x".repeat(250), "y".repeat(250));
+    std::fs::create_dir_all(dir.join("x".repeat(250))).unwrap();
+    std::fs::write(dir.join(&long), "long").unwrap();
+    let (staged, _) = deployer
+        .stage_version(&dir, "blue")
+        .await
+        .unwrap()
+        .into_parts();
+    let key = &staged[&format!("blue/{}", long)].path;
+    assert!(key.starts_with("blue/") && key.len() <= crate::MAX_KEY_LENGTH);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(deployer.stage_version(&dir, "a/b").await.is_err());
+    assert!(deployer
+        .set_active_version(ACTIVE_VERSION_KEY)
+        .await
+        .is_err());
+
+    let bytes = crate::encode_index(&index).unwrap();
+    let worker = mock
+        .handler(&bytes, "ns")
+        .with_active_version_ttl(Duration::ZERO);
+    assert_eq!(worker.load_active_version().await.unwrap(), None);
+    assert!(worker.lookup_key("/blue/index.html").unwrap().is_some());
+
+    let get = || http::Request::get("/index.html").body(()).unwrap();
+    for version in ["blue", "green", "blue"] {
+        deployer.set_active_version(version).await.unwrap();
+        assert_eq!(
+            worker.load_active_version().await.unwrap().as_deref(),
+            Some(version)
+        );
+        assert_eq!(
+            worker.serve(&get()).await.body().as_ref(),
+            version.as_bytes()
+        );
+    }
+    assert_eq!(worker.active_version().as_deref(), Some("blue"));
+
+    // cached until the ttl expires
+    let cached = mock.handler(&bytes, "ns");
+    assert_eq!(
+        cached.load_active_version().await.unwrap().as_deref(),
+        Some("blue")
+    );
+    deployer.set_active_version("green").await.unwrap();
+    assert_eq!(
+        cached.load_active_version().await.unwrap().as_deref(),
+        Some("blue")
+    );
+    assert_eq!(
+        cached.reload_active_version().await.unwrap().as_deref(),
+        Some("green")
+    );
+}
@@ -0,0 +1,161 @@
+use crate::Error;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// State of a `CircuitBreaker`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    /// Requests are sent to KV
+    Closed,
+    /// Requests fail immediately with `Error::CircuitOpen`
+    Open,
+    /// Cooldown has elapsed: the next request is sent as a probe.
+    /// If it succeeds the breaker closes, otherwise it re-opens.
+    HalfOpen,
+}
+
+/// Circuit breaker for KV reads. After `threshold` consecutive api failures
+/// (transport errors, 429, and 5xx responses), `get_kv_value` fails immediately with
+/// `Error::CircuitOpen` for the cooldown period, so callers can serve a fallback
+/// instead of waiting on a degraded api. After the cooldown, one request is let
+/// through as a probe. A key that doesn't exist (404) is not a failure.
+///
+/// The clock defaults to the system clock. On wasm, where it isn't available,
+/// supply one with `with_clock` (for example, `|| js_sys::Date::now() as u64`).
+/// Install with `KVAssets::with_circuit_breaker`; one breaker can be shared by handlers.
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown_ms: u64,
+    clock: fn() -> u64,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    failures: u32,
+    opened_at: Option<u64>,
+    // start time of the outstanding probe. A probe that doesn't complete
+    // (e.g., its future was dropped) is replaced after another cooldown
+    probe_started: Option<u64>,
+}
+
+impl CircuitBreaker {
+    /// Open after `threshold` consecutive failures, and probe again after `cooldown`
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            cooldown_ms: cooldown.as_millis() as u64,
+            clock: system_millis,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    /// Use a clock returning the current time in milliseconds
+    pub fn with_clock(mut self, clock: fn() -> u64) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Current state
+    pub fn state(&self) -> BreakerState {
+        let inner = self.inner.lock().unwrap();
+        match inner.opened_at {
+            None => BreakerState::Closed,
+            Some(_) if self.can_probe(&inner) => BreakerState::HalfOpen,
+            Some(_) => BreakerState::Open,
+        }
+    }
+
+    /// Returns Err(CircuitOpen) if the request should not be sent.
+    /// In the half-open state, allows one probe request at a time.
+    pub(crate) fn allow(&self) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.opened_at {
+            None => Ok(()),
+            Some(_) if self.can_probe(&inner) => {
+                inner.probe_started = Some((self.clock)());
+                Ok(())
+            }
+            Some(_) => Err(Error::CircuitOpen),
+        }
+    }
+
+    // Open, cooldown elapsed, and no probe in progress
+    fn can_probe(&self, inner: &Inner) -> bool {
+        let since = inner.probe_started.or(inner.opened_at).unwrap_or_default();
+        (self.clock)().saturating_sub(since) >= self.cooldown_ms
+    }
+
+    /// Record the result of a request that was allowed
+    pub(crate) fn record<T>(&self, result: &Result<T, Error>) {
+        let mut inner = self.inner.lock().unwrap();
+        match result {
+            Err(e) if is_api_failure(e) => {
+                inner.failures += 1;
+                if inner.probe_started.is_some() || inner.failures >= self.threshold {
+                    inner.opened_at = Some((self.clock)());
+                }
+                inner.probe_started = None;
+            }
+            _ => *inner = Inner::default(),
+        }
+    }
+}
+
+// Errors that indicate the api is unavailable, rather than a problem with the request
+fn is_api_failure(e: &Error) -> bool {
+    match e {
+        Error::KVHttp(_) => true,
+        Error::KVKeyNotFound(_, status) => *status == 0 || *status == 429 || *status >= 500,
+        _ => false,
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn system_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+// No system clock on wasm: breaker never leaves the open state unless a clock is supplied
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn system_millis() -> u64 {
+    0
+}
+
+/// Tests breaker state transitions
+#[test]
+fn test_circuit_breaker() {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    static NOW: AtomicU64 = AtomicU64::new(1000);
+    fn clock() -> u64 {
+        NOW.load(Ordering::Relaxed)
+    }
+    let failed: Result<(), Error> = Err(Error::KVKeyNotFound("a".into(), 503));
+    let missing: Result<(), Error> = Err(Error::KVKeyNotFound("a".into(), 404));
+
+    let breaker = CircuitBreaker::new(2, Duration::from_secs(10)).with_clock(clock);
+    breaker.record(&failed);
+    breaker.record(&missing); // not a failure: resets count
+    breaker.record(&failed);
+    assert_eq!(breaker.state(), BreakerState::Closed);
+    breaker.record(&failed);
+    assert_eq!(breaker.state(), BreakerState::Open);
+    assert!(matches!(breaker.allow(), Err(Error::CircuitOpen)));
+
+    // after cooldown, one probe; failed probe re-opens
+    NOW.fetch_add(10_000, Ordering::Relaxed);
+    assert_eq!(breaker.state(), BreakerState::HalfOpen);
+    assert!(breaker.allow().is_ok());
+    assert!(breaker.allow().is_err());
+    breaker.record(&failed);
+    assert_eq!(breaker.state(), BreakerState::Open);
+
+    // successful probe closes
+    NOW.fetch_add(10_000, Ordering::Relaxed);
+    assert!(breaker.allow().is_ok());
+    breaker.record(&Ok(()));
+    assert_eq!(breaker.state(), BreakerState::Closed);
+}
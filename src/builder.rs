@@ -0,0 +1,303 @@
+use crate::{Error, KVAssets, NamespaceTarget};
+use std::borrow::Cow;
+
+/// Environment variable for the Cloudflare account id
+pub const ENV_ACCOUNT_ID: &str = "CLOUDFLARE_ACCOUNT_ID";
+/// Environment variable for the KV namespace id
+pub const ENV_NAMESPACE_ID: &str = "CLOUDFLARE_KV_NAMESPACE_ID";
+/// Environment variable for the preview KV namespace id
+pub const ENV_PREVIEW_NAMESPACE_ID: &str = "CLOUDFLARE_KV_PREVIEW_NAMESPACE_ID";
+/// Environment variable for the Cloudflare api token
+pub const ENV_API_TOKEN: &str = "CLOUDFLARE_API_TOKEN";
+// Environment variables for the proxy, used by `proxy_from_env`
+#[cfg(all(feature = "reqwest", not(target_arch = "wasm32")))]
+const ENV_PROXY: [&str; 2] = ["HTTPS_PROXY", "https_proxy"];
+
+/// Builder for `KVAssets`. Settings can be set explicitly, or read from
+/// environment variables with `from_env`. `build` reports every missing setting at once.
+#[derive(Default)]
+pub struct KVAssetsBuilder<'ah> {
+    index: Option<&'ah [u8]>,
+    account_id: Option<Cow<'ah, str>>,
+    namespace_id: Option<Cow<'ah, str>>,
+    preview_namespace_id: Option<Cow<'ah, str>>,
+    preview: bool,
+    auth_token: Option<Cow<'ah, str>>,
+    headers: Vec<(String, String)>,
+    #[cfg(all(feature = "reqwest", not(target_arch = "wasm32")))]
+    proxy: Option<String>,
+    #[cfg(all(feature = "reqwest", not(target_arch = "wasm32")))]
+    connection_options: Option<crate::ConnectionOptions>,
+    from_env: bool,
+}
+
+impl<'ah> KVAssetsBuilder<'ah> {
+    /// Create builder with no settings
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Binary serialized index (created by kv-sync). default: empty index
+    pub fn index(mut self, index: &'ah [u8]) -> Self {
+        self.index = Some(index);
+        self
+    }
+
+    /// Cloudflare account id
+    pub fn account_id(mut self, account_id: impl Into<Cow<'ah, str>>) -> Self {
+        self.account_id = Some(account_id.into());
+        self
+    }
+
+    /// KV namespace id
+    pub fn namespace_id(mut self, namespace_id: impl Into<Cow<'ah, str>>) -> Self {
+        self.namespace_id = Some(namespace_id.into());
+        self
+    }
+
+    /// Preview KV namespace id, paired with the production namespace_id
+    pub fn preview_namespace_id(mut self, namespace_id: impl Into<Cow<'ah, str>>) -> Self {
+        self.preview_namespace_id = Some(namespace_id.into());
+        self
+    }
+
+    /// Initially selected namespace. default: Production
+    pub fn target(mut self, target: NamespaceTarget) -> Self {
+        self.preview = target == NamespaceTarget::Preview;
+        self
+    }
+
+    /// Cloudflare api token
+    pub fn auth_token(mut self, auth_token: impl Into<Cow<'ah, str>>) -> Self {
+        self.auth_token = Some(auth_token.into());
+        self
+    }
+
+    /// `User-Agent` of Cloudflare api requests
+    pub fn user_agent(self, user_agent: impl Into<String>) -> Self {
+        self.default_header("user-agent", user_agent)
+    }
+
+    /// Header added to every Cloudflare api request, such as tracing propagation
+    /// headers for an egress proxy (see `KVAssets::with_default_header`)
+    pub fn default_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Send all Cloudflare api requests through the HTTP(S) proxy at the url
+    #[cfg(all(feature = "reqwest", not(target_arch = "wasm32")))]
+    pub fn proxy(mut self, proxy_url: impl Into<String>) -> Self {
+        self.proxy = Some(proxy_url.into());
+        self
+    }
+
+    /// Use the proxy in `HTTPS_PROXY` (or `https_proxy`), if set and no proxy
+    /// was set explicitly
+    #[cfg(all(feature = "reqwest", not(target_arch = "wasm32")))]
+    pub fn proxy_from_env(mut self) -> Self {
+        self.proxy = self.proxy.or_else(|| {
+            ENV_PROXY
+                .iter()
+                .filter_map(|name| std::env::var(name).ok())
+                .find(|v| !v.is_empty())
+        });
+        self
+    }
+
+    /// Connection pool and keepalive settings of the api client (see `ConnectionOptions`)
+    #[cfg(all(feature = "reqwest", not(target_arch = "wasm32")))]
+    pub fn connection_options(mut self, options: crate::ConnectionOptions) -> Self {
+        self.connection_options = Some(options);
+        self
+    }
+
+    /// Take the account id and namespace id from wrangler.toml, for the namespace
+    /// binding in the environment (None for top-level). If preview is true,
+    /// selects the binding's preview_id, so staging deploys can't write to production.
+    #[cfg(feature = "wrangler-config")]
+    pub fn wrangler_config(
+        mut self,
+        config: &crate::wrangler_config::WranglerConfig,
+        binding: &str,
+        env: Option<&str>,
+        preview: bool,
+    ) -> Self {
+        if let Some(account_id) = config.account_id(env) {
+            self.account_id = Some(Cow::Owned(account_id.to_string()));
+        }
+        if let Some(namespace_id) = config.namespace_id(binding, env, false) {
+            self.namespace_id = Some(Cow::Owned(namespace_id.to_string()));
+        }
+        if let Some(namespace_id) = config.namespace_id(binding, env, true) {
+            self.preview_namespace_id = Some(Cow::Owned(namespace_id.to_string()));
+        }
+        self.preview = preview;
+        self
+    }
+
+    /// Read settings that haven't been set explicitly from environment variables
+    /// `CLOUDFLARE_ACCOUNT_ID`, `CLOUDFLARE_KV_NAMESPACE_ID`, `CLOUDFLARE_API_TOKEN`,
+    /// and, optionally, `CLOUDFLARE_KV_PREVIEW_NAMESPACE_ID`
+    pub fn from_env(mut self) -> Self {
+        fn var(name: &str) -> Option<Cow<'static, str>> {
+            std::env::var(name)
+                .ok()
+                .filter(|v| !v.is_empty())
+                .map(Cow::Owned)
+        }
+        self.account_id = self.account_id.or_else(|| var(ENV_ACCOUNT_ID));
+        self.namespace_id = self.namespace_id.or_else(|| var(ENV_NAMESPACE_ID));
+        self.preview_namespace_id = self
+            .preview_namespace_id
+            .or_else(|| var(ENV_PREVIEW_NAMESPACE_ID));
+        self.auth_token = self.auth_token.or_else(|| var(ENV_API_TOKEN));
+        self.from_env = true;
+        self
+    }
+
+    /// Create the handler. If any required settings are missing, returns
+    /// `Error::MissingConfig` with their names (environment variable names, if `from_env` was used).
+    /// Invalid default headers are reported as `Error::Message`
+    pub fn build(self) -> Result<KVAssets<'ah>, Error> {
+        let name = |setting: &str, env: &str| match self.from_env {
+            true => env.to_string(),
+            false => setting.to_string(),
+        };
+        let mut missing = Vec::new();
+        if self.account_id.is_none() {
+            missing.push(name("account_id", ENV_ACCOUNT_ID));
+        }
+        if self.namespace_id.is_none() {
+            missing.push(name("namespace_id", ENV_NAMESPACE_ID));
+        }
+        if self.auth_token.is_none() {
+            missing.push(name("auth_token", ENV_API_TOKEN));
+        }
+        match (self.account_id, self.namespace_id, self.auth_token) {
+            (Some(account_id), Some(namespace_id), Some(auth_token)) => {
+                let mut kv = KVAssets::from_parts(
+                    self.index.unwrap_or_default(),
+                    account_id,
+                    namespace_id,
+                    auth_token,
+                );
+                if let Some(preview_namespace_id) = self.preview_namespace_id {
+                    kv = kv.with_preview_namespace(preview_namespace_id);
+                }
+                if self.preview {
+                    kv.set_namespace_target(NamespaceTarget::Preview);
+                }
+                for (name, value) in self.headers.iter() {
+                    let invalid = || Error::Message(format!("invalid header {}: {}", name, value));
+                    let name = http::header::HeaderName::from_bytes(name.as_bytes())
+                        .map_err(|_| invalid())?;
+                    let value = http::HeaderValue::from_str(value).map_err(|_| invalid())?;
+                    kv = kv.with_default_header(name, value);
+                }
+                #[cfg(all(feature = "reqwest", not(target_arch = "wasm32")))]
+                if self.proxy.is_some() || self.connection_options.is_some() {
+                    let options = self.connection_options.unwrap_or_default();
+                    kv = kv.with_http_client(std::sync::Arc::new(
+                        crate::ReqwestClient::configured(self.proxy.as_deref(), &options)?,
+                    ));
+                }
+                Ok(kv)
+            }
+            _ => Err(Error::MissingConfig(missing)),
+        }
+    }
+}
+
+/// Tests reporting of missing settings
+#[test]
+fn test_builder_missing() {
+    match KVAssetsBuilder::new().account_id("123").build() {
+        Err(Error::MissingConfig(missing)) => {
+            assert_eq!(missing, vec!["namespace_id", "auth_token"])
+        }
+        _ => panic!("expected MissingConfig"),
+    }
+    let token = String::from("token");
+    let kv = KVAssets::builder()
+        .account_id("123")
+        .namespace_id("ns")
+        .auth_token(token)
+        .build();
+    assert!(kv.is_ok());
+}
+
+/// Tests default headers on api requests (does not invoke cloudflare api)
+#[cfg(test)]
+#[tokio::test]
+async fn test_builder_default_headers() {
+    use crate::{HttpClient, HttpFuture};
+    use bytes::Bytes;
+
+    struct Stub;
+    impl HttpClient for Stub {
+        fn send(&self, request: http::Request<Bytes>) -> HttpFuture<'_> {
+            let headers = request.headers();
+            assert_eq!(headers["user-agent"], "my-proxy/1.0");
+            assert_eq!(headers["traceparent"], "00-abc-def-01");
+            assert_eq!(headers["authorization"], "Bearer token");
+            Box::pin(async { Ok(http::Response::new(Bytes::from("hello"))) })
+        }
+    }
+
+    let builder = || {
+        KVAssets::builder()
+            .account_id("123")
+            .namespace_id("ns")
+            .auth_token("token")
+    };
+    let kv = builder()
+        .user_agent("my-proxy/1.0")
+        .default_header("traceparent", "00-abc-def-01")
+        .default_header("Authorization", "Bearer other")
+        .build()
+        .unwrap()
+        .with_http_client(std::sync::Arc::new(Stub));
+    assert_eq!(kv.get_kv_value("a.txt").await.unwrap(), "hello");
+    assert!(builder().default_header("bad header", "x").build().is_err());
+}
+
+/// Tests proxy configuration
+#[cfg(all(feature = "reqwest", not(target_arch = "wasm32")))]
+#[test]
+fn test_builder_proxy() {
+    let builder = || {
+        KVAssets::builder()
+            .account_id("123")
+            .namespace_id("ns")
+            .auth_token("token")
+    };
+    assert!(builder()
+        .proxy("http://proxy.internal:3128")
+        .build()
+        .is_ok());
+    assert!(builder().proxy("not a url").build().is_err());
+}
+
+/// Tests connection tuning configuration
+#[cfg(all(feature = "reqwest", not(target_arch = "wasm32")))]
+#[test]
+fn test_builder_connection_options() {
+    use std::time::Duration;
+
+    let options = crate::ConnectionOptions::new()
+        .with_http2_keep_alive(Duration::from_secs(30))
+        .with_http2_adaptive_window(true)
+        .with_pool_idle_timeout(Duration::from_secs(300))
+        .with_pool_max_idle_per_host(16)
+        .with_tcp_keepalive(Duration::from_secs(60));
+    assert_ne!(options, crate::ConnectionOptions::default());
+    let kv = KVAssets::builder()
+        .account_id("123")
+        .namespace_id("ns")
+        .auth_token("token")
+        .proxy("http://proxy.internal:3128")
+        .connection_options(options)
+        .build();
+    assert!(kv.is_ok());
+}
@@ -0,0 +1,171 @@
+use crate::assets::{parse_json, WriteKVResponse};
+use crate::{Error, KVAssets, KvPutOptions};
+use bytes::Bytes;
+use http::Method;
+use serde::Serialize;
+use std::borrow::Cow;
+
+/// Maximum number of keys in one bulk write request
+pub const BULK_KEY_MAX: usize = 10_000;
+
+// Maximum size of a bulk write request body
+const BULK_BYTES_MAX: usize = 100 * 1024 * 1024;
+
+// Key-value pair in the body of a bulk write request
+#[derive(Serialize)]
+struct BulkPair<'a> {
+    key: &'a str,
+    value: Cow<'a, str>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    base64: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expiration: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expiration_ttl: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<&'a serde_json::Value>,
+}
+
+impl<'ah> KVAssets<'ah> {
+    /// Store many values with the bulk write api, up to `BULK_KEY_MAX` keys per request.
+    /// The bulk api takes values as json strings: text values are sent as is, and
+    /// binary values (fonts, images, and encrypted values) are base64-encoded and marked
+    /// `base64`, so they are stored unchanged. Expiration and metadata from `opts` apply
+    /// to every value; the content type is ignored. Values are not chunked, so each
+    /// must fit in a KV value (see `MAX_VALUE_SIZE`).
+    pub async fn put_kv_values_bulk(
+        &self,
+        values: &[(String, Bytes)],
+        opts: &KvPutOptions,
+    ) -> Result<(), Error> {
+        opts.query()?;
+        let encrypted = values
+            .iter()
+            .map(|(key, value)| Ok((key, self.encrypt(value.clone())?)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let pairs = encrypted
+            .iter()
+            .map(|(key, value)| {
+                let (value, base64) = bulk_value(value);
+                BulkPair {
+                    key,
+                    value,
+                    base64,
+                    expiration: opts.expiration,
+                    expiration_ttl: opts.expiration_ttl,
+                    metadata: opts.metadata.as_ref(),
+                }
+            })
+            .collect::<Vec<_>>();
+        for batch in batches(&pairs) {
+            self.check_cancelled("bulk write")?;
+            self.put_batch(batch).await?;
+        }
+        for (key, value) in values {
+            self.record_write(key, Some(value.clone()));
+        }
+        Ok(())
+    }
+
+    async fn put_batch(&self, pairs: &[BulkPair<'_>]) -> Result<(), Error> {
+        let body = serde_json::to_vec(pairs).map_err(|e| Error::Message(e.to_string()))?;
+        let url = self.namespace_url("bulk")?;
+        self.rate_limit().await;
+        let mut request = self.request(Method::PUT, &url, Bytes::from(body));
+        request.headers_mut().insert(
+            http::header::CONTENT_TYPE,
+            http::HeaderValue::from_static("application/json"),
+        );
+        let response = self.send(request).await?;
+        if let Some(metrics) = self.metrics() {
+            metrics.kv_put(response.status().as_u16());
+        }
+        let response: WriteKVResponse = parse_json(response.body())?;
+        if response.success {
+            Ok(())
+        } else {
+            Err(crate::error_kind::api_error(
+                &format!("bulk write of {} keys", pairs.len()),
+                &response.errors,
+            ))
+        }
+    }
+}
+
+// Value as a json string: utf-8 text as is, anything else base64-encoded.
+// Returns the string and whether it was encoded
+fn bulk_value(value: &[u8]) -> (Cow<'_, str>, bool) {
+    match std::str::from_utf8(value) {
+        Ok(text) => (Cow::Borrowed(text), false),
+        Err(_) => (Cow::Owned(base64::encode(value)), true),
+    }
+}
+
+// Split pairs into requests within the api limits on key count and body size
+fn batches<'p, 'a>(pairs: &'p [BulkPair<'a>]) -> Vec<&'p [BulkPair<'a>]> {
+    let mut batches = Vec::new();
+    let (mut start, mut size) = (0, 0);
+    for (n, pair) in pairs.iter().enumerate() {
+        // allow for the json syntax around key and value
+        let pair_size = pair.key.len() + pair.value.len() + 64;
+        if n > start && (n - start == BULK_KEY_MAX || size + pair_size > BULK_BYTES_MAX) {
+            batches.push(&pairs[start..n]);
+            start = n;
+            size = 0;
+        }
+        size += pair_size;
+    }
+    if start < pairs.len() {
+        batches.push(&pairs[start..]);
+    }
+    batches
+}
+
+/// Tests bulk writes of text and binary values, against the mock api
+#[cfg(all(test, feature = "reqwest"))]
+#[tokio::test]
+async fn test_put_kv_values_bulk() {
+    let mock = crate::testspace::MockCloudflare::start().unwrap();
+    let kv = mock.handler(&[], "ns");
+    let font = Bytes::from_static(&[0x77, 0x4f, 0x46, 0x32, 0x00, 0xff, 0xfe]);
+    let values = vec![
+        ("a.css".to_string(), Bytes::from("body{}")),
+        ("a.woff2".to_string(), font.clone()),
+    ];
+    let opts = KvPutOptions::new()
+        .with_expiration_ttl(3600)
+        .with_metadata(serde_json::json!({ "build": 7 }));
+    kv.put_kv_values_bulk(&values, &opts).await.unwrap();
+
+    assert_eq!(mock.get("ns", "a.css").unwrap(), "body{}");
+    // binary values are stored unchanged
+    assert_eq!(mock.get("ns", "a.woff2").unwrap(), font);
+    assert_eq!(
+        mock.metadata("ns", "a.woff2"),
+        Some(serde_json::json!({ "build": 7 }))
+    );
+    let info = kv.get_kv_value_with_info("a.css").await.unwrap();
+    assert!(info.expiration.is_some());
+}
+
+/// Tests splitting bulk writes by key count
+#[test]
+fn test_bulk_batches() {
+    let keys = (0..BULK_KEY_MAX + 1)
+        .map(|n| n.to_string())
+        .collect::<Vec<_>>();
+    let pairs = keys
+        .iter()
+        .map(|key| BulkPair {
+            key,
+            value: Cow::Borrowed("x"),
+            base64: false,
+            expiration: None,
+            expiration_ttl: None,
+            metadata: None,
+        })
+        .collect::<Vec<_>>();
+    let sizes = batches(&pairs).iter().map(|b| b.len()).collect::<Vec<_>>();
+    assert_eq!(sizes, vec![BULK_KEY_MAX, 1]);
+    assert!(batches(&[]).is_empty());
+}
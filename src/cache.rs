@@ -0,0 +1,615 @@
+use crate::{AssetMetadata, Error, KVAssets};
+use bytes::Bytes;
+use futures_util::stream::{self, StreamExt};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Default time a cached value is served before it is read from KV again
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Per-request cache behavior for `get_asset_with` and `serve_with`, so admin or
+/// preview traffic can see fresh content while other requests are served from caches.
+/// The edge cache (`with_edge_cache`) is skipped when any option is set, since the age
+/// of its entries isn't known. Values written or deleted through the handler are still
+/// served from its write overlay, which is never staler than KV
+/// ```
+/// # use kv_assets::CacheOptions;
+/// # use std::time::Duration;
+/// let admin = CacheOptions::new().with_bypass(true);
+/// let preview = CacheOptions::new().with_max_stale(Duration::from_secs(5));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheOptions {
+    pub(crate) bypass: bool,
+    pub(crate) revalidate: bool,
+    pub(crate) max_stale: Option<Duration>,
+}
+
+impl CacheOptions {
+    /// Use the handler's caches as configured
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the value from KV, without checking caches. The value read
+    /// is stored in the caches, refreshing them for other requests
+    pub fn with_bypass(mut self, bypass: bool) -> Self {
+        self.bypass = bypass;
+        self
+    }
+
+    /// Serve a cached value only if its hash matches the `hash` in KV metadata,
+    /// even if it hasn't expired (see `ValueCache::with_revalidation`)
+    pub fn with_revalidate(mut self, revalidate: bool) -> Self {
+        self.revalidate = revalidate;
+        self
+    }
+
+    /// Serve a cached value only if it was read from KV within max_stale,
+    /// rather than within the cache's ttl
+    pub fn with_max_stale(mut self, max_stale: Duration) -> Self {
+        self.max_stale = Some(max_stale);
+        self
+    }
+
+    // True if the edge cache may be used
+    pub(crate) fn uses_edge_cache(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Key in `AssetMetadata::extra` setting the `CachePriority` of an asset's value
+/// in the value cache: "high" or "pinned"
+pub const CACHE_PRIORITY_KEY: &str = "cache";
+
+/// Eviction priority of a value in `ValueCache`. When the cache is full, least
+/// recently used `Normal` values are evicted first, then `High` values;
+/// `Pinned` values are never evicted (they still expire after the ttl)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CachePriority {
+    /// Evicted in LRU order
+    #[default]
+    Normal,
+    /// Evicted only when there are no `Normal` values left
+    High,
+    /// Never evicted
+    Pinned,
+}
+
+impl CachePriority {
+    /// Priority named in `extra["cache"]` ("high" or "pinned"), or None
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "normal" => Some(Self::Normal),
+            "high" => Some(Self::High),
+            "pinned" | "pin" => Some(Self::Pinned),
+            _ => None,
+        }
+    }
+}
+
+/// In-memory cache of KV values, so repeated requests for the same asset in a
+/// worker isolate (or server process) don't each pay the KV round-trip.
+/// Install with `KVAssets::with_value_cache`; one cache can be shared by handlers
+/// for the same namespace. `get_kv_value` serves cached values for the ttl
+/// (default 60 seconds), and writes and deletes through the handler remove the key.
+///
+/// The cache holds at most `max_bytes` of values, evicting the least recently
+/// used; values larger than that are not cached. Keys can be given a higher
+/// `CachePriority` (with `set_priority`, `KVAssets::set_cache_priority`, or
+/// `extra["cache"]` in the index) so critical assets such as `index.html` stay
+/// cached under memory pressure; pinned values can exceed `max_bytes`. With `with_revalidation`, expired
+/// values are revalidated against the content hash in KV metadata before
+/// they are downloaded again.
+/// The clock defaults to the system clock; on wasm, supply one with `with_clock`.
+pub struct ValueCache {
+    max_bytes: usize,
+    ttl_ms: u64,
+    revalidate: bool,
+    clock: fn() -> u64,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<String, Entry>,
+    bytes: usize,
+    // incremented on each access, for LRU order
+    tick: u64,
+    // keys with priority other than Normal, whether cached or not
+    priorities: HashMap<String, CachePriority>,
+}
+
+struct Entry {
+    value: Bytes,
+    stored: u64,
+    used: u64,
+    // content hash, computed when the entry is first revalidated (with the algorithm of KV's hash)
+    hash: Option<String>,
+}
+
+impl ValueCache {
+    /// Cache up to max_bytes of values
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            ttl_ms: DEFAULT_CACHE_TTL.as_millis() as u64,
+            revalidate: false,
+            clock: crate::breaker::system_millis,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    /// Serve cached values for ttl after they are read from KV. default: 60 seconds
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl_ms = ttl.as_millis() as u64;
+        self
+    }
+
+    /// Use a clock returning the current time in milliseconds
+    pub fn with_clock(mut self, clock: fn() -> u64) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// When a cached value expires, read the key's KV metadata, and if its `hash`
+    /// matches the cached value, keep serving it for another ttl instead of downloading
+    /// the value again. This turns refreshes of unchanged large values into a
+    /// metadata read. Only useful for values whose metadata records their hash,
+    /// such as those written by `put_if_changed` or pulled by `OriginPull`:
+    /// for other values, each refresh costs an extra request. default: false
+    pub fn with_revalidation(mut self, revalidate: bool) -> Self {
+        self.revalidate = revalidate;
+        self
+    }
+
+    /// Number of cached values
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    /// Returns true if no values are cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Total size of cached values, in bytes
+    pub fn size_bytes(&self) -> usize {
+        self.inner.lock().unwrap().bytes
+    }
+
+    /// Remove all values. Priorities are kept
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        let priorities = std::mem::take(&mut inner.priorities);
+        *inner = Inner {
+            priorities,
+            ..Default::default()
+        };
+    }
+
+    /// Set the eviction priority of the KV key's value
+    pub fn set_priority(&self, key: &str, priority: CachePriority) {
+        let mut inner = self.inner.lock().unwrap();
+        match priority {
+            CachePriority::Normal => inner.priorities.remove(key),
+            _ => inner.priorities.insert(key.to_string(), priority),
+        };
+    }
+
+    /// Eviction priority of the KV key's value
+    pub fn priority(&self, key: &str) -> CachePriority {
+        let inner = self.inner.lock().unwrap();
+        inner.priorities.get(key).copied().unwrap_or_default()
+    }
+
+    /// Cached value of the key, if stored within the ttl, and within max_stale if set
+    pub(crate) fn get(&self, key: &str, max_stale: Option<Duration>) -> Option<Bytes> {
+        let max_age = match max_stale {
+            Some(max_stale) => self.ttl_ms.min(max_stale.as_millis() as u64),
+            None => self.ttl_ms,
+        };
+        let now = (self.clock)();
+        let mut inner = self.inner.lock().unwrap();
+        inner.tick += 1;
+        let tick = inner.tick;
+        match inner.entries.get_mut(key) {
+            Some(entry) if now.saturating_sub(entry.stored) < max_age => {
+                entry.used = tick;
+                Some(entry.value.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Store the value, evicting least recently used values of the lowest priority
+    /// to make room. Pinned values are stored even if that exceeds max_bytes
+    pub(crate) fn insert(&self, key: &str, value: Bytes) {
+        if value.len() > self.max_bytes && self.priority(key) != CachePriority::Pinned {
+            self.remove(key);
+            return;
+        }
+        let now = (self.clock)();
+        let mut inner = self.inner.lock().unwrap();
+        inner.tick += 1;
+        let entry = Entry {
+            stored: now,
+            used: inner.tick,
+            value,
+            hash: None,
+        };
+        inner.bytes += entry.value.len();
+        if let Some(old) = inner.entries.insert(key.to_string(), entry) {
+            inner.bytes -= old.value.len();
+        }
+        while inner.bytes > self.max_bytes {
+            let priority = |k: &String| inner.priorities.get(k).copied().unwrap_or_default();
+            // the new value is the most recently used, so it is evicted only if
+            // everything else has a higher priority
+            let lru = inner
+                .entries
+                .iter()
+                .map(|(k, e)| (priority(k), e.used, k))
+                .filter(|(p, _, _)| *p != CachePriority::Pinned)
+                .min();
+            let lru = match lru {
+                Some((_, _, k)) => k.clone(),
+                None => break,
+            };
+            if let Some(old) = inner.entries.remove(&lru) {
+                inner.bytes -= old.value.len();
+            }
+        }
+    }
+
+    /// Returns true if revalidation is enabled (or forced) and the cache holds a value for the key
+    pub(crate) fn has_stale(&self, key: &str, force: bool) -> bool {
+        (self.revalidate || force) && self.inner.lock().unwrap().entries.contains_key(key)
+    }
+
+    /// Cached value of the key, if its content hash matches hash. The value is then
+    /// served for another ttl. The hash of the value is computed once per algorithm
+    pub(crate) fn revalidated(&self, key: &str, hash: &str) -> Option<Bytes> {
+        let algorithm = crate::HashAlgorithm::of(hash)?;
+        let now = (self.clock)();
+        let mut inner = self.inner.lock().unwrap();
+        inner.tick += 1;
+        let tick = inner.tick;
+        let entry = inner.entries.get_mut(key)?;
+        let cached = match &entry.hash {
+            Some(h) if crate::HashAlgorithm::of(h) == Some(algorithm) => h.clone(),
+            _ => {
+                let h = algorithm.hash(&entry.value);
+                entry.hash = Some(h.clone());
+                h
+            }
+        };
+        if !cached.eq_ignore_ascii_case(hash) {
+            return None;
+        }
+        entry.stored = now;
+        entry.used = tick;
+        Some(entry.value.clone())
+    }
+
+    /// Remove the key
+    pub(crate) fn remove(&self, key: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(old) = inner.entries.remove(key) {
+            inner.bytes -= old.value.len();
+        }
+    }
+}
+
+impl<'ah> KVAssets<'ah> {
+    /// Expired cached value of the key, if its hash matches the hash in KV metadata.
+    /// Returns None if revalidation is disabled (and not forced), or the value changed
+    /// or can't be compared
+    pub(crate) async fn revalidate(&self, key: &str, force: bool) -> Option<Bytes> {
+        let cache = self.value_cache()?;
+        if !cache.has_stale(key, force) {
+            return None;
+        }
+        let current = self.get_kv_metadata(key).await.ok()??;
+        cache.revalidated(key, current.get("hash")?.as_str()?)
+    }
+
+    /// Set the value cache priority of the asset at path (see `CachePriority`), such as
+    /// pinning `index.html` and critical CSS. Returns false if no value cache is installed,
+    /// or the path isn't in the index. Priorities follow the KV key, so set them again
+    /// after the index changes
+    pub fn set_cache_priority(&self, path: &str, priority: CachePriority) -> Result<bool, Error> {
+        let cache = match self.value_cache() {
+            Some(cache) => cache,
+            None => return Ok(false),
+        };
+        match self.lookup_key(path)? {
+            Some(md) if md.alias.is_none() => {
+                cache.set_priority(&md.path, priority);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    // Apply the priority in the asset's `extra["cache"]`, if any, before its value is cached
+    pub(crate) fn apply_cache_priority(&self, md: &AssetMetadata) {
+        let priority = md
+            .extra
+            .get(CACHE_PRIORITY_KEY)
+            .and_then(|name| CachePriority::from_name(name));
+        if let (Some(cache), Some(priority)) = (self.value_cache(), priority) {
+            cache.set_priority(&md.path, priority);
+        }
+    }
+
+    /// Load assets into the value cache (see `with_value_cache`), so the first
+    /// request for critical assets (e.g., the main CSS and JS) doesn't wait on KV.
+    /// Call at startup or on a schedule. Paths are looked up in the index, and values
+    /// are fetched in parallel (see `with_chunk_concurrency`). Best-effort: paths
+    /// that aren't in the index or fail to load are skipped.
+    /// Returns the number of assets cached, which is 0 if no cache is installed.
+    pub async fn warm(&self, paths: &[&str]) -> usize {
+        if self.value_cache().is_none() {
+            return 0;
+        }
+        let keys = paths
+            .iter()
+            .filter_map(|path| match self.lookup_key(path) {
+                Ok(Some(md)) if md.alias.is_none() => {
+                    self.apply_cache_priority(&md);
+                    Some(md.path)
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        stream::iter(keys)
+            .map(|key| async move { self.get_kv_value(&key).await.is_ok() })
+            .buffer_unordered(self.chunk_concurrency())
+            .filter(|cached| futures_util::future::ready(*cached))
+            .count()
+            .await
+    }
+}
+
+/// Tests LRU eviction and ttl
+#[test]
+fn test_value_cache() {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    static NOW: AtomicU64 = AtomicU64::new(1000);
+    fn clock() -> u64 {
+        NOW.load(Ordering::Relaxed)
+    }
+
+    let cache = ValueCache::new(10)
+        .with_ttl(Duration::from_secs(60))
+        .with_clock(clock);
+    cache.insert("a", Bytes::from("aaaa"));
+    cache.insert("b", Bytes::from("bbbb"));
+    assert!(cache.get("a", None).is_some());
+    cache.insert("c", Bytes::from("cccc"));
+    // b was least recently used
+    assert!(cache.get("b", None).is_none());
+    assert_eq!(cache.get("a", None).unwrap(), "aaaa");
+    assert_eq!(cache.size_bytes(), 8);
+    cache.insert("big", Bytes::from("0123456789a"));
+    assert_eq!(cache.len(), 2);
+
+    NOW.fetch_add(60_000, Ordering::Relaxed);
+    assert!(cache.get("a", None).is_none());
+}
+
+/// Tests that high-priority and pinned values outlast normal ones
+#[test]
+fn test_cache_priority() {
+    let cache = ValueCache::new(8);
+    cache.set_priority("index", CachePriority::Pinned);
+    cache.set_priority("css", CachePriority::High);
+    cache.insert("index", Bytes::from("iiii"));
+    cache.insert("css", Bytes::from("cc"));
+    cache.insert("a", Bytes::from("aa"));
+    // b evicts a, the only normal value
+    cache.insert("b", Bytes::from("bb"));
+    assert!(cache.get("a", None).is_none());
+    assert!(cache.get("css", None).is_some());
+    // a normal value doesn't evict a high one: it isn't kept
+    cache.insert("c", Bytes::from("cccc"));
+    assert!(cache.get("c", None).is_none());
+    assert!(cache.get("css", None).is_some());
+    // high values are evicted for pinned ones, and pinned values can exceed max_bytes
+    cache.set_priority("logo", CachePriority::Pinned);
+    cache.insert("logo", Bytes::from("llllllll"));
+    assert!(cache.get("css", None).is_none());
+    assert_eq!(cache.size_bytes(), 12);
+    cache.clear();
+    assert_eq!(cache.priority("logo"), CachePriority::Pinned);
+    assert_eq!(CachePriority::from_name("High"), Some(CachePriority::High));
+}
+
+/// Tests warming the cache (does not invoke cloudflare api)
+#[cfg(test)]
+#[tokio::test]
+async fn test_warm() {
+    use crate::{AssetIndex, AssetMetadata, HttpClient, HttpFuture};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[derive(Default)]
+    struct Stub {
+        gets: AtomicUsize,
+    }
+    impl HttpClient for Stub {
+        fn send(&self, _: http::Request<Bytes>) -> HttpFuture<'_> {
+            self.gets.fetch_add(1, Ordering::Relaxed);
+            Box::pin(async { Ok(http::Response::new(Bytes::from("value"))) })
+        }
+    }
+
+    let mut index = AssetIndex::new();
+    for (path, key) in [("app.css", "app.1.css"), ("app.js", "app.2.js")] {
+        index.insert(
+            path.to_string(),
+            AssetMetadata {
+                path: key.to_string(),
+                size: 5,
+                ..Default::default()
+            },
+        );
+    }
+    let blob = crate::encode_index(&index).unwrap();
+    let stub = Arc::new(Stub::default());
+    let kv = KVAssets::init(&blob, "acct", "ns", "token").with_http_client(stub.clone());
+    assert_eq!(kv.warm(&["/app.css"]).await, 0);
+
+    let kv = kv.with_value_cache(Arc::new(ValueCache::new(1024)));
+    stub.gets.store(0, Ordering::Relaxed);
+    assert_eq!(kv.warm(&["/app.css", "/app.js", "/missing.js"]).await, 2);
+    assert_eq!(stub.gets.load(Ordering::Relaxed), 2);
+    assert_eq!(kv.get_asset("/app.css").await.unwrap().unwrap(), "value");
+    assert_eq!(stub.gets.load(Ordering::Relaxed), 2);
+}
+
+/// Tests revalidating expired values against the KV metadata hash
+/// (does not invoke cloudflare api)
+#[cfg(test)]
+#[tokio::test]
+async fn test_revalidate() {
+    use crate::{HttpClient, HttpFuture};
+    use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    static NOW: AtomicU64 = AtomicU64::new(1000);
+    fn clock() -> u64 {
+        NOW.load(Ordering::Relaxed)
+    }
+
+    struct Stub {
+        value: Mutex<&'static str>,
+        downloads: AtomicUsize,
+    }
+    impl HttpClient for Stub {
+        fn send(&self, request: http::Request<Bytes>) -> HttpFuture<'_> {
+            let value = *self.value.lock().unwrap();
+            let body = match request.uri().path().contains("/metadata/") {
+                true => serde_json::json!({
+                    "result": { "hash": crate::checksum::content_hash(value.as_bytes()) }
+                })
+                .to_string(),
+                false => {
+                    self.downloads.fetch_add(1, Ordering::Relaxed);
+                    value.to_string()
+                }
+            };
+            Box::pin(async move { Ok(http::Response::new(Bytes::from(body))) })
+        }
+    }
+
+    let stub = Arc::new(Stub {
+        value: Mutex::new("large value"),
+        downloads: AtomicUsize::new(0),
+    });
+    let cache = ValueCache::new(1024)
+        .with_clock(clock)
+        .with_revalidation(true);
+    let kv = KVAssets::init(&[], "acct", "ns", "token")
+        .with_http_client(stub.clone())
+        .with_value_cache(Arc::new(cache));
+
+    assert_eq!(kv.get_kv_value("big").await.unwrap(), "large value");
+    // expired but unchanged: metadata read only
+    NOW.fetch_add(60_000, Ordering::Relaxed);
+    assert_eq!(kv.get_kv_value("big").await.unwrap(), "large value");
+    assert_eq!(stub.downloads.load(Ordering::Relaxed), 1);
+    // changed: downloaded again
+    NOW.fetch_add(60_000, Ordering::Relaxed);
+    *stub.value.lock().unwrap() = "new value";
+    assert_eq!(kv.get_kv_value("big").await.unwrap(), "new value");
+    assert_eq!(stub.downloads.load(Ordering::Relaxed), 2);
+}
+
+/// Tests per-request cache options (does not invoke cloudflare api)
+#[cfg(test)]
+#[tokio::test]
+async fn test_cache_options() {
+    use crate::{AssetIndex, AssetMetadata, HttpClient, HttpFuture};
+    use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    static NOW: AtomicU64 = AtomicU64::new(1000);
+    fn clock() -> u64 {
+        NOW.load(Ordering::Relaxed)
+    }
+
+    struct Stub {
+        value: Mutex<&'static str>,
+        downloads: AtomicUsize,
+    }
+    impl HttpClient for Stub {
+        fn send(&self, request: http::Request<Bytes>) -> HttpFuture<'_> {
+            let value = *self.value.lock().unwrap();
+            let body = match request.uri().path().contains("/metadata/") {
+                true => serde_json::json!({
+                    "result": { "hash": crate::checksum::content_hash(value.as_bytes()) }
+                })
+                .to_string(),
+                false => {
+                    self.downloads.fetch_add(1, Ordering::Relaxed);
+                    value.to_string()
+                }
+            };
+            Box::pin(async move { Ok(http::Response::new(Bytes::from(body))) })
+        }
+    }
+
+    let mut index = AssetIndex::new();
+    index.insert(
+        "a.txt".to_string(),
+        AssetMetadata {
+            path: "a.1.txt".to_string(),
+            ..Default::default()
+        },
+    );
+    let bytes = crate::encode_index(&index).unwrap();
+    let stub = Arc::new(Stub {
+        value: Mutex::new("old"),
+        downloads: AtomicUsize::new(0),
+    });
+    let kv = KVAssets::init(&bytes, "acct", "ns", "token")
+        .with_http_client(stub.clone())
+        .with_value_cache(Arc::new(ValueCache::new(1024).with_clock(clock)));
+    let downloads = || stub.downloads.load(Ordering::Relaxed);
+
+    assert_eq!(kv.get_asset("a.txt").await.unwrap().unwrap(), "old");
+    *stub.value.lock().unwrap() = "new";
+    // cached for normal requests
+    assert_eq!(kv.get_asset("a.txt").await.unwrap().unwrap(), "old");
+    // revalidation finds the changed hash
+    let revalidate = CacheOptions::new().with_revalidate(true);
+    assert_eq!(
+        kv.get_asset_with("a.txt", &revalidate)
+            .await
+            .unwrap()
+            .unwrap(),
+        "new"
+    );
+    assert_eq!(downloads(), 2);
+    // unchanged: revalidated without downloading
+    assert_eq!(
+        kv.get_asset_with("a.txt", &revalidate)
+            .await
+            .unwrap()
+            .unwrap(),
+        "new"
+    );
+    assert_eq!(downloads(), 2);
+
+    let bypass = CacheOptions::new().with_bypass(true);
+    kv.get_asset_with("a.txt", &bypass).await.unwrap();
+    assert_eq!(downloads(), 3);
+
+    NOW.fetch_add(5_000, Ordering::Relaxed);
+    let fresh = CacheOptions::new().with_max_stale(Duration::from_secs(1));
+    kv.get_asset_with("a.txt", &fresh).await.unwrap();
+    assert_eq!(downloads(), 4);
+    kv.get_asset("a.txt").await.unwrap();
+    assert_eq!(downloads(), 4);
+}
@@ -0,0 +1,121 @@
+use crate::Error;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Cancellation flag and optional deadline for long-running operations: `publish_dir`,
+/// `put_kv_values_bulk`, `mirror_to_dir`, `copy_to`, `prune`, and `gc_tombstones`
+/// (install with `KVAssets::with_cancel_token`), and `sync_assets`
+/// (see `SyncConfig::cancel`). Operations check the token between values,
+/// and stop with `Error::Cancelled` once it is cancelled or the deadline passes.
+/// Values already written stay in KV, and the index isn't written, so running
+/// the operation again resumes it: uploads skip values that are already in KV.
+///
+/// Clones share the flag, so a Ctrl-C handler can cancel a clone.
+/// Deadlines use the system clock, so they don't expire on wasm.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+    // deadline in milliseconds since EPOCH, or 0 for none
+    deadline: Arc<AtomicU64>,
+}
+
+impl CancelToken {
+    /// Token that isn't cancelled, without a deadline
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel operations using this token (or a clone)
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Cancel operations once timeout has passed from now, such as before a CI job's time limit
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        let deadline = crate::breaker::system_millis().saturating_add(timeout.as_millis() as u64);
+        self.deadline.store(deadline.max(1), Ordering::Relaxed);
+        self
+    }
+
+    /// Returns true if cancelled, or the deadline has passed
+    pub fn is_cancelled(&self) -> bool {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return true;
+        }
+        let deadline = self.deadline.load(Ordering::Relaxed);
+        deadline != 0 && crate::breaker::system_millis() >= deadline
+    }
+
+    /// Returns `Error::Cancelled` with the description of the operation, if cancelled
+    pub fn check(&self, operation: &str) -> Result<(), Error> {
+        match self.is_cancelled() {
+            true => Err(Error::Cancelled(operation.to_string())),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Tests cancelling and deadlines
+#[test]
+fn test_cancel_token() {
+    let token = CancelToken::new();
+    assert!(token.check("a").is_ok());
+    let clone = token.clone();
+    clone.cancel();
+    assert!(token.is_cancelled());
+    assert_eq!(
+        token.check("uploading").unwrap_err().to_string(),
+        "Cancelled: uploading"
+    );
+
+    let token = CancelToken::new().with_timeout(Duration::from_secs(60));
+    assert!(!token.is_cancelled());
+    let token = CancelToken::new().with_timeout(Duration::ZERO);
+    assert!(token.is_cancelled());
+}
+
+/// Tests that a cancelled publish stops before uploading (does not invoke cloudflare api)
+#[cfg(all(test, not(target_arch = "wasm32")))]
+#[tokio::test]
+async fn test_cancel_publish() {
+    use crate::{DeployEvent, HttpClient, HttpFuture, KVAssets};
+    use bytes::Bytes;
+    use std::sync::Mutex;
+
+    struct Stub;
+    impl HttpClient for Stub {
+        fn send(&self, request: http::Request<Bytes>) -> HttpFuture<'_> {
+            assert_eq!(request.method(), http::Method::GET, "no uploads");
+            let body = r#"{"success":true,"errors":[],"messages":[],"result":[]}"#;
+            Box::pin(async move { Ok(http::Response::new(Bytes::from(body))) })
+        }
+    }
+
+    let dir = std::env::temp_dir().join(format!("kv-assets-cancel-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("a.txt"), "a").unwrap();
+    std::fs::write(dir.join("b.txt"), "b").unwrap();
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let log = events.clone();
+    let token = CancelToken::new();
+    let kv = KVAssets::init(&[], "acct", "ns", "token")
+        .with_http_client(Arc::new(Stub))
+        .with_cancel_token(token.clone())
+        .with_deploy_observer(Arc::new(move |e: &DeployEvent| {
+            log.lock().unwrap().push(e.clone())
+        }));
+    token.cancel();
+    let result = kv.publish_dir(&dir).await;
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(matches!(result, Err(Error::Cancelled(_))));
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec![DeployEvent::Cancelled {
+            completed: 0,
+            remaining: 2
+        }]
+    );
+}
@@ -0,0 +1,169 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use crate::{AssetIndex, AssetIndexExt, Error, HashAlgorithm, KVAssets};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+/// KV key of the checkpoint saved with `CheckpointStore::Kv`. It has the published
+/// index prefix, so `verify` and `prune` ignore it
+pub const CHECKPOINT_KEY: &str = "__index_checkpoint";
+
+/// Number of uploads between saves of the checkpoint
+pub const CHECKPOINT_INTERVAL: usize = 50;
+
+/// Where `publish_dir` saves its progress (see `KVAssets::with_checkpoint`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckpointStore {
+    /// Local JSON file, such as `.kv-assets-checkpoint.json`
+    File(PathBuf),
+    /// KV key `CHECKPOINT_KEY` in the namespace, for CI jobs without a persistent disk
+    Kv,
+}
+
+/// Keys uploaded by an interrupted deploy of the index with this hash
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Checkpoint {
+    index_hash: String,
+    uploaded: BTreeSet<String>,
+    #[serde(skip)]
+    unsaved: usize,
+}
+
+impl Checkpoint {
+    /// True if the key was uploaded by the interrupted deploy
+    pub(crate) fn contains(&self, key: &str) -> bool {
+        self.uploaded.contains(key)
+    }
+
+    /// Record an upload. Returns true if the checkpoint should be saved
+    pub(crate) fn record(&mut self, key: &str) -> bool {
+        self.uploaded.insert(key.to_string());
+        self.unsaved += 1;
+        self.unsaved >= CHECKPOINT_INTERVAL
+    }
+}
+
+// Hash of the index entries, independent of map order
+fn index_hash(index: &AssetIndex) -> Result<String, Error> {
+    Ok(HashAlgorithm::Sha256.hash(index.to_json()?.as_bytes()))
+}
+
+impl<'ah> KVAssets<'ah> {
+    /// Checkpoint for deploying index: the saved one if it is for the same index,
+    /// otherwise an empty one. None if checkpoints aren't enabled
+    pub(crate) async fn load_checkpoint(
+        &self,
+        index: &AssetIndex,
+    ) -> Result<Option<Checkpoint>, Error> {
+        let store = match self.checkpoint_store() {
+            Some(store) => store,
+            None => return Ok(None),
+        };
+        let index_hash = index_hash(index)?;
+        let saved = match store {
+            CheckpointStore::File(path) => std::fs::read(path).ok().map(Bytes::from),
+            CheckpointStore::Kv => match self.get_value(CHECKPOINT_KEY).await {
+                Ok(value) => Some(value),
+                Err(Error::KVKeyNotFound(_, 404)) => None,
+                Err(e) => return Err(e),
+            },
+        };
+        let checkpoint = saved
+            .and_then(|bytes| serde_json::from_slice::<Checkpoint>(&bytes).ok())
+            .filter(|checkpoint| checkpoint.index_hash == index_hash)
+            .unwrap_or(Checkpoint {
+                index_hash,
+                ..Default::default()
+            });
+        Ok(Some(checkpoint))
+    }
+
+    pub(crate) async fn save_checkpoint(&self, checkpoint: &mut Checkpoint) -> Result<(), Error> {
+        let json = serde_json::to_vec(checkpoint)
+            .map_err(|e| Error::Message(format!("checkpoint json: {}", e)))?;
+        match self.checkpoint_store() {
+            Some(CheckpointStore::File(path)) => std::fs::write(path, json)
+                .map_err(|e| Error::IO(format!("writing {}: {}", path.display(), e)))?,
+            Some(CheckpointStore::Kv) => {
+                self.put_kv_value(CHECKPOINT_KEY, json, None).await?;
+            }
+            None => {}
+        }
+        checkpoint.unsaved = 0;
+        Ok(())
+    }
+
+    /// Remove the checkpoint after a completed deploy
+    pub(crate) async fn clear_checkpoint(&self) -> Result<(), Error> {
+        match self.checkpoint_store() {
+            Some(CheckpointStore::File(path)) if path.exists() => std::fs::remove_file(path)
+                .map_err(|e| Error::IO(format!("removing {}: {}", path.display(), e))),
+            Some(CheckpointStore::Kv) => match self.delete_kv_value(CHECKPOINT_KEY).await {
+                Ok(()) | Err(Error::KVKeyNotFound(_, 404)) => Ok(()),
+                Err(e) => Err(e),
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Tests resuming an interrupted publish from a checkpoint file
+/// (does not invoke cloudflare api)
+#[cfg(test)]
+#[tokio::test]
+async fn test_checkpoint() {
+    use crate::{HttpClient, HttpFuture};
+    use std::sync::{Arc, Mutex};
+
+    // fails uploads after the first until `fail` is cleared; key listings are always
+    // empty, as when recent writes haven't reached the listing yet
+    struct Stub {
+        fail: Mutex<bool>,
+        puts: Mutex<Vec<String>>,
+    }
+    impl HttpClient for Stub {
+        fn send(&self, request: http::Request<Bytes>) -> HttpFuture<'_> {
+            let path = request.uri().path().to_string();
+            let body = match path.split_once("/values/") {
+                Some(_) if *self.fail.lock().unwrap() && !self.puts.lock().unwrap().is_empty() => {
+                    r#"{"success":false,"errors":[],"messages":[]}"#
+                }
+                Some((_, key)) => {
+                    self.puts.lock().unwrap().push(key.to_string());
+                    r#"{"success":true,"errors":[],"messages":[]}"#
+                }
+                None => r#"{"success":true,"errors":[],"messages":[],"result":[]}"#,
+            };
+            Box::pin(async move { Ok(http::Response::new(Bytes::from(body))) })
+        }
+    }
+
+    let dir = std::env::temp_dir().join(format!("kv-assets-checkpoint-{}", std::process::id()));
+    let assets = dir.join("public");
+    std::fs::create_dir_all(&assets).unwrap();
+    std::fs::write(assets.join("a.txt"), "a").unwrap();
+    std::fs::write(assets.join("b.txt"), "b").unwrap();
+    let state = dir.join("checkpoint.json");
+
+    let stub = Arc::new(Stub {
+        fail: Mutex::new(true),
+        puts: Mutex::new(Vec::new()),
+    });
+    let kv = KVAssets::init(&[], "acct", "ns", "token")
+        .with_http_client(stub.clone())
+        .with_checkpoint(CheckpointStore::File(state.clone()));
+    assert!(kv.publish_dir(&assets).await.is_err());
+    assert!(state.exists());
+    assert_eq!(stub.puts.lock().unwrap().len(), 1);
+
+    *stub.fail.lock().unwrap() = false;
+    let (_, report) = kv.publish_dir(&assets).await.unwrap();
+    let exists = state.exists();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(report.skipped.len(), 1);
+    assert_eq!(report.added.len(), 1);
+    assert_eq!(stub.puts.lock().unwrap().len(), 2);
+    assert!(!exists);
+}
@@ -0,0 +1,112 @@
+use sha2::{Digest, Sha256};
+
+/// Algorithm of content hashes in the index (`AssetMetadata::hash`) and in KV metadata.
+/// Each hash records its algorithm as a prefix (`blake3:…`, `xxh3:…`); SHA-256 hashes
+/// have no prefix, so indexes built before the algorithm was configurable remain readable.
+/// Choose speed (xxh3, not cryptographic) or integrity (sha256, blake3).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm {
+    /// SHA-256
+    Sha256,
+    /// BLAKE3: cryptographic, and faster than SHA-256 (feature "blake3", a default feature)
+    #[cfg(feature = "blake3")]
+    Blake3,
+    /// 128-bit XXH3: fastest, but not cryptographic (feature "xxhash")
+    #[cfg(feature = "xxhash")]
+    Xxh3,
+}
+
+impl Default for HashAlgorithm {
+    /// Blake3 if the "blake3" feature is enabled, otherwise Sha256
+    fn default() -> Self {
+        #[cfg(feature = "blake3")]
+        return HashAlgorithm::Blake3;
+        #[cfg(not(feature = "blake3"))]
+        return HashAlgorithm::Sha256;
+    }
+}
+
+impl HashAlgorithm {
+    /// Hex-encoded hash of content, prefixed with the algorithm name (except SHA-256)
+    pub fn hash(&self, data: &[u8]) -> String {
+        match self {
+            HashAlgorithm::Sha256 => format!("{:x}", Sha256::digest(data)),
+            #[cfg(feature = "blake3")]
+            HashAlgorithm::Blake3 => format!("blake3:{}", blake3::hash(data).to_hex()),
+            #[cfg(feature = "xxhash")]
+            HashAlgorithm::Xxh3 => format!("xxh3:{:032x}", xxhash_rust::xxh3::xxh3_128(data)),
+        }
+    }
+
+    /// Algorithm of a hash created by `hash`. Returns None if the algorithm
+    /// is unknown, or its feature isn't enabled
+    pub fn of(hash: &str) -> Option<Self> {
+        match hash.split_once(':') {
+            None => Some(HashAlgorithm::Sha256),
+            #[cfg(feature = "blake3")]
+            Some(("blake3", _)) => Some(HashAlgorithm::Blake3),
+            #[cfg(feature = "xxhash")]
+            Some(("xxh3", _)) => Some(HashAlgorithm::Xxh3),
+            Some(_) => None,
+        }
+    }
+}
+
+/// Hash of content with the default algorithm, as stored in `AssetMetadata::hash`
+pub(crate) fn content_hash(data: &[u8]) -> String {
+    HashAlgorithm::default().hash(data)
+}
+
+/// Returns true if the content hash matches, computing it with the hash's algorithm.
+/// Hashes with an unsupported algorithm don't match
+pub(crate) fn matches(data: &[u8], hash: &str) -> bool {
+    match HashAlgorithm::of(hash) {
+        Some(algorithm) => algorithm.hash(data).eq_ignore_ascii_case(hash),
+        None => false,
+    }
+}
+
+/// KV key for a version of an asset: the first 10 hex digits of the
+/// content hash are inserted before the extension, as wrangler does
+/// (`css/site.css` -> `css/site.2cf24dba5f.css`)
+pub(crate) fn versioned_key(path: &str, hash: &str) -> String {
+    let hash = hash.rsplit(':').next().unwrap_or(hash);
+    let hash = &hash[..hash.len().min(10)];
+    let name_start = path.rfind('/').map(|p| p + 1).unwrap_or(0);
+    match path[name_start..].rfind('.') {
+        Some(dot) if dot > 0 => {
+            let dot = name_start + dot;
+            format!("{}.{}{}", &path[..dot], hash, &path[dot..])
+        }
+        _ => format!("{}.{}", path, hash),
+    }
+}
+
+/// Tests content hash format
+#[test]
+fn test_content_hash() {
+    let hash = HashAlgorithm::Sha256.hash(b"hello");
+    assert_eq!(
+        hash,
+        "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+    );
+    assert!(matches(b"hello", &hash.to_uppercase()));
+    assert!(!matches(b"hell", &hash));
+    assert!(!matches(b"hello", "md5:5d41402abc4b2a76b9719d911017c592"));
+    assert_eq!(
+        versioned_key("css/site.css", &hash),
+        "css/site.2cf24dba5f.css"
+    );
+    assert_eq!(versioned_key("LICENSE", &hash), "LICENSE.2cf24dba5f");
+}
+
+/// Tests prefixed hashes
+#[cfg(feature = "blake3")]
+#[test]
+fn test_blake3_hash() {
+    let hash = HashAlgorithm::Blake3.hash(b"hello");
+    assert!(hash.starts_with("blake3:ea8f163db3"));
+    assert_eq!(HashAlgorithm::of(&hash), Some(HashAlgorithm::Blake3));
+    assert!(matches(b"hello", &hash));
+    assert_eq!(versioned_key("a.css", &hash), "a.ea8f163db3.css");
+}
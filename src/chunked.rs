@@ -0,0 +1,146 @@
+use crate::{Error, KVAssets};
+use bytes::{Bytes, BytesMut};
+use futures_util::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+
+// Prefix of the manifest value stored at the key of a chunked value
+const MANIFEST_MAGIC: &[u8] = b"kv-assets-chunks:";
+
+/// Describes a value stored as multiple parts
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub(crate) struct ChunkManifest {
+    /// Number of parts
+    pub parts: usize,
+    /// Total size of value
+    pub size: u64,
+}
+
+impl ChunkManifest {
+    /// Serialized manifest, stored in place of the value
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut bytes = MANIFEST_MAGIC.to_vec();
+        bytes.extend_from_slice(&serde_json::to_vec(self).expect("serialize manifest"));
+        bytes
+    }
+
+    /// Parse manifest. Returns None if the value is not a chunk manifest
+    pub(crate) fn decode(bytes: &[u8]) -> Option<Self> {
+        bytes
+            .strip_prefix(MANIFEST_MAGIC)
+            .and_then(|json| serde_json::from_slice(json).ok())
+    }
+}
+
+/// KV key of a part of a chunked value
+pub(crate) fn part_key(key: &str, part: usize) -> String {
+    format!("{}.part{}", key, part)
+}
+
+/// Key of the chunked value, if key is the key of one of its parts (see `part_key`)
+pub(crate) fn chunked_key(key: &str) -> Option<&str> {
+    let (value_key, part) = key.rsplit_once(".part")?;
+    match !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()) {
+        true => Some(value_key),
+        false => None,
+    }
+}
+
+impl<'ah> KVAssets<'ah> {
+    // Write value as parts of at most chunk_size bytes, then write the manifest,
+    // so the value never references parts that haven't been uploaded
+    pub(crate) async fn put_chunks(
+        &self,
+        key: &str,
+        value: Bytes,
+        opts: &crate::KvPutOptions,
+        chunk_size: usize,
+    ) -> Result<(), Error> {
+        let mut parts = 0;
+        for (n, start) in (0..value.len()).step_by(chunk_size).enumerate() {
+            let end = (start + chunk_size).min(value.len());
+            self.put(
+                &part_key(key, n),
+                value.slice(start..end),
+                &opts.expiration_only(),
+            )
+            .await?;
+            parts += 1;
+        }
+        let manifest = ChunkManifest {
+            parts,
+            size: value.len() as u64,
+        };
+        let opts = crate::KvPutOptions {
+            content_type: None,
+            ..opts.clone()
+        };
+        self.put(key, manifest.encode().into(), &opts).await
+    }
+
+    // Fetch parts concurrently (up to chunk_concurrency at a time),
+    // and reassemble them in order. Parts that don't add up to the manifest's size
+    // are a checksum mismatch
+    pub(crate) async fn get_chunks(
+        &self,
+        key: &str,
+        manifest: &ChunkManifest,
+    ) -> Result<Bytes, Error> {
+        let mut parts = stream::iter(0..manifest.parts)
+            .map(|n| async move { self.get_value(&part_key(key, n)).await })
+            .buffered(self.chunk_concurrency());
+        // the stored size isn't trusted: parts can't be larger than a KV value
+        let max_size = (manifest.parts as u64).saturating_mul(crate::MAX_VALUE_SIZE as u64);
+        let mut value = BytesMut::with_capacity(manifest.size.min(max_size) as usize);
+        while let Some(part) = parts.next().await {
+            value.extend_from_slice(&part?);
+            if value.len() as u64 > manifest.size {
+                return Err(Error::ChecksumMismatch(key.to_string()));
+            }
+        }
+        if value.len() as u64 != manifest.size {
+            return Err(Error::ChecksumMismatch(key.to_string()));
+        }
+        Ok(value.freeze())
+    }
+}
+
+/// Tests chunk manifest encoding
+#[test]
+fn test_chunk_manifest() {
+    let manifest = ChunkManifest {
+        parts: 3,
+        size: 60_000_000,
+    };
+    let bytes = manifest.encode();
+    assert_eq!(ChunkManifest::decode(&bytes), Some(manifest));
+    assert_eq!(ChunkManifest::decode(b"<html></html>"), None);
+    assert_eq!(part_key("video/intro.mp4", 2), "video/intro.mp4.part2");
+    assert_eq!(
+        chunked_key("video/intro.mp4.part2"),
+        Some("video/intro.mp4")
+    );
+    assert_eq!(chunked_key("video/intro.part"), None);
+    assert_eq!(chunked_key("video/intro.part1a"), None);
+}
+
+/// Tests that parts are checked against the manifest size, against the mock api
+#[cfg(all(test, feature = "reqwest"))]
+#[tokio::test]
+async fn test_get_chunks_size() {
+    let mock = crate::testspace::MockCloudflare::start().unwrap();
+    let kv = mock.handler(&[], "ns").with_chunking(4);
+    mock.insert("ns", "a.part0", "abcd");
+    mock.insert("ns", "a.part1", "ef");
+    for (parts, size) in [(2, 5), (2, 7), (1, u64::MAX)].iter() {
+        let manifest = ChunkManifest {
+            parts: *parts,
+            size: *size,
+        };
+        assert!(matches!(
+            kv.get_chunks("a", &manifest).await,
+            Err(Error::ChecksumMismatch(_))
+        ));
+    }
+    let manifest = ChunkManifest { parts: 2, size: 6 };
+    assert_eq!(kv.get_chunks("a", &manifest).await.unwrap(), "abcdef");
+}
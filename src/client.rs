@@ -0,0 +1,256 @@
+use crate::Error;
+use bytes::Bytes;
+use http::{Request, Response};
+use std::{future::Future, pin::Pin};
+
+/// Future returned by `HttpClient::send`
+#[cfg(not(target_arch = "wasm32"))]
+pub type HttpFuture<'a> = Pin<Box<dyn Future<Output = Result<Response<Bytes>, Error>> + Send + 'a>>;
+
+/// Future returned by `HttpClient::send`
+#[cfg(target_arch = "wasm32")]
+pub type HttpFuture<'a> = Pin<Box<dyn Future<Output = Result<Response<Bytes>, Error>> + 'a>>;
+
+/// Http client used for all Cloudflare api requests.
+/// The default implementation uses reqwest (feature "reqwest"). Implement this
+/// to use the worker's `fetch`, hyper directly, or an instrumented client,
+/// and install it with `KVAssets::with_http_client`.
+///
+/// The rest of the crate doesn't depend on an async runtime: with a client for
+/// your runtime (e.g., one based on surf for async-std), `KVAssets` runs on any
+/// executor, including single-threaded ones such as `futures::executor::block_on`.
+pub trait HttpClient: Send + Sync {
+    /// Send request, and return the response with its complete body.
+    /// Only transport failures should be errors: non-success statuses are returned
+    /// as responses, so the caller can interpret them.
+    fn send(&self, request: Request<Bytes>) -> HttpFuture<'_>;
+}
+
+/// HttpClient implementation using reqwest.
+/// The underlying reqwest client, and its connection pool, is shared by all requests.
+/// Outside wasm, reqwest requires a Tokio runtime: requests sent from another
+/// executor panic. Disable the "reqwest" feature and install another client
+/// to use a different runtime.
+#[cfg(feature = "reqwest")]
+#[derive(Clone, Default)]
+pub struct ReqwestClient {
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "reqwest")]
+impl ReqwestClient {
+    /// Use a configured reqwest client
+    pub fn new(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+
+    /// Client that sends all requests through the HTTP(S) proxy at the url
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_proxy(proxy_url: &str) -> Result<Self, Error> {
+        Self::configured(Some(proxy_url), &ConnectionOptions::default())
+    }
+
+    /// Client with connection pool and keepalive settings (see `ConnectionOptions`)
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_connection_options(options: &ConnectionOptions) -> Result<Self, Error> {
+        Self::configured(None, options)
+    }
+
+    // Client with an optional proxy and connection settings
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn configured(
+        proxy_url: Option<&str>,
+        options: &ConnectionOptions,
+    ) -> Result<Self, Error> {
+        let mut builder = reqwest::Client::builder();
+        if let Some(proxy_url) = proxy_url {
+            let proxy = reqwest::Proxy::all(proxy_url)
+                .map_err(|e| Error::Message(format!("invalid proxy url {}: {}", proxy_url, e)))?;
+            builder = builder.proxy(proxy);
+        }
+        if let Some(interval) = options.http2_keep_alive {
+            builder = builder
+                .http2_keep_alive_interval(interval)
+                .http2_keep_alive_while_idle(true);
+        }
+        if let Some(timeout) = options.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        if let Some(max) = options.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        if let Some(interval) = options.tcp_keepalive {
+            builder = builder.tcp_keepalive(interval);
+        }
+        let client = builder
+            .http2_adaptive_window(options.http2_adaptive_window)
+            .build()
+            .map_err(|e| Error::KVHttp(Box::new(e)))?;
+        Ok(Self { client })
+    }
+}
+
+/// Connection tuning for the shared `ReqwestClient`, so high-traffic proxies keep warm
+/// connections to the Cloudflare api and avoid handshakes on bursty traffic.
+/// Set with `KVAssetsBuilder::connection_options`. Unset options use reqwest's defaults.
+///
+/// The api is reached over HTTP/2 when TLS negotiates it, so concurrent requests
+/// are multiplexed on one connection per host.
+#[cfg(all(feature = "reqwest", not(target_arch = "wasm32")))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConnectionOptions {
+    http2_keep_alive: Option<std::time::Duration>,
+    http2_adaptive_window: bool,
+    pool_idle_timeout: Option<std::time::Duration>,
+    pool_max_idle_per_host: Option<usize>,
+    tcp_keepalive: Option<std::time::Duration>,
+}
+
+#[cfg(all(feature = "reqwest", not(target_arch = "wasm32")))]
+impl ConnectionOptions {
+    /// Create options with reqwest's defaults
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send HTTP/2 pings at interval, including on idle connections,
+    /// so they aren't closed by the server or middleboxes. default: off
+    pub fn with_http2_keep_alive(mut self, interval: std::time::Duration) -> Self {
+        self.http2_keep_alive = Some(interval);
+        self
+    }
+
+    /// Size HTTP/2 flow control windows from the measured bandwidth-delay product,
+    /// for large uploads and downloads over high-latency links. default: false
+    pub fn with_http2_adaptive_window(mut self, enabled: bool) -> Self {
+        self.http2_adaptive_window = enabled;
+        self
+    }
+
+    /// How long idle connections stay in the pool. default: 90 seconds
+    pub fn with_pool_idle_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Maximum idle connections kept per host. default: unlimited
+    pub fn with_pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    /// Enable TCP keepalive on sockets, with the interval. default: off
+    pub fn with_tcp_keepalive(mut self, interval: std::time::Duration) -> Self {
+        self.tcp_keepalive = Some(interval);
+        self
+    }
+}
+
+#[cfg(feature = "reqwest")]
+impl HttpClient for ReqwestClient {
+    fn send(&self, request: Request<Bytes>) -> HttpFuture<'_> {
+        Box::pin(async move {
+            let (parts, body) = request.into_parts();
+            let response = self
+                .client
+                .request(parts.method, parts.uri.to_string())
+                .headers(parts.headers)
+                .body(body)
+                .send()
+                .await
+                .map_err(|e| Error::KVHttp(Box::new(e)))?;
+            let mut builder = Response::builder().status(response.status());
+            if let Some(headers) = builder.headers_mut() {
+                *headers = response.headers().clone();
+            }
+            let body = response
+                .bytes()
+                .await
+                .map_err(|e| Error::KVHttp(Box::new(e)))?;
+            builder.body(body).map_err(|e| Error::KVHttp(Box::new(e)))
+        })
+    }
+}
+
+/// Tests KV requests through a custom http client (does not invoke cloudflare api)
+#[cfg(test)]
+#[tokio::test]
+async fn test_custom_client() {
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Stub {
+        requests: Mutex<Vec<(http::Method, String)>>,
+    }
+    impl HttpClient for Stub {
+        fn send(&self, request: Request<Bytes>) -> HttpFuture<'_> {
+            self.requests
+                .lock()
+                .unwrap()
+                .push((request.method().clone(), request.uri().to_string()));
+            let body = match *request.method() {
+                http::Method::PUT => Bytes::from(r#"{"success":true,"errors":[],"messages":[]}"#),
+                _ => Bytes::from("hello"),
+            };
+            Box::pin(async move { Ok(Response::new(body)) })
+        }
+    }
+
+    let stub = Arc::new(Stub::default());
+    let kv = crate::KVAssets::init(&[], "acct", "ns", "token").with_http_client(stub.clone());
+    assert_eq!(kv.get_kv_value("a.txt").await.unwrap(), "hello");
+    kv.put_kv_value("b.txt", "data", None).await.unwrap();
+
+    let requests = stub.requests.lock().unwrap();
+    assert_eq!(
+        requests[0],
+        (
+            http::Method::GET,
+            "https://api.cloudflare.com/client/v4/accounts/acct/storage/kv/namespaces/ns/values/a.txt"
+                .to_string()
+        )
+    );
+    assert_eq!(requests[1].0, http::Method::PUT);
+}
+
+/// Tests KV requests, rate limiting, and publishing on an executor without Tokio
+/// (does not invoke cloudflare api)
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn test_without_tokio() {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    struct Stub;
+    impl HttpClient for Stub {
+        fn send(&self, request: Request<Bytes>) -> HttpFuture<'_> {
+            let body = match *request.method() {
+                http::Method::GET if request.uri().path().ends_with("/keys") => {
+                    r#"{"success":true,"errors":[],"messages":[],"result":[{"name":"a.txt"}]}"#
+                }
+                http::Method::GET => "hello",
+                _ => r#"{"success":true,"errors":[],"messages":[]}"#,
+            };
+            Box::pin(async move { Ok(Response::new(Bytes::from(body))) })
+        }
+    }
+
+    let dir = std::env::temp_dir().join(format!("kv-assets-no-tokio-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("b.txt"), "world").unwrap();
+    let kv = crate::KVAssets::init(&[], "acct", "ns", "token")
+        .with_http_client(Arc::new(Stub))
+        // second request waits for the default timer
+        .with_rate_limiter(Arc::new(crate::RateLimiter::new(
+            1,
+            Duration::from_millis(20),
+        )));
+    let (index, report) = futures_executor::block_on(async {
+        assert_eq!(kv.get_kv_value("a.txt").await.unwrap(), "hello");
+        assert_eq!(kv.list_keys().await.unwrap(), vec!["a.txt"]);
+        kv.publish_dir(&dir).await.unwrap()
+    });
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(index.contains_key("b.txt"));
+    assert_eq!(report.added, vec!["b.txt"]);
+}
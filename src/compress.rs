@@ -0,0 +1,279 @@
+#![cfg(feature = "compression")]
+
+use bytes::Bytes;
+use http::{header, HeaderMap, HeaderValue, Response};
+use std::io::Write;
+
+// Content types that are already compressed. Entries ending in '/' match the whole type
+const DEFAULT_SKIP: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "image/avif",
+    "audio/",
+    "video/",
+    "font/woff",
+    "font/woff2",
+    "application/zip",
+    "application/gzip",
+    "application/x-gzip",
+    "application/x-brotli",
+    "application/zstd",
+    "application/pdf",
+];
+
+/// Encoding applied by on-the-fly compression
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentEncoding {
+    /// `br`
+    Brotli,
+    /// `gzip`
+    Gzip,
+}
+
+impl ContentEncoding {
+    /// Content-Encoding header value
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentEncoding::Brotli => "br",
+            ContentEncoding::Gzip => "gzip",
+        }
+    }
+}
+
+/// On-the-fly compression of responses from `serve`, for deployments whose
+/// assets weren't precompressed. Responses at least `min_size` bytes long are
+/// compressed with brotli or gzip (as preferred by Accept-Encoding), unless their
+/// content type is already compressed (images other than svg, audio, video, fonts, archives).
+/// Assets with a Content-Encoding in their metadata headers, partial content (206),
+/// and HEAD responses are never compressed. The applied encoding is reported in
+/// the Content-Encoding header; the ETag becomes weak, since the bytes differ from the asset.
+/// Install with `KVAssets::with_compression`.
+#[derive(Debug, Clone)]
+pub struct Compression {
+    min_size: u64,
+    skip: Vec<String>,
+    brotli: bool,
+    gzip: bool,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self {
+            min_size: 1024,
+            skip: DEFAULT_SKIP.iter().map(|s| s.to_string()).collect(),
+            brotli: true,
+            gzip: true,
+        }
+    }
+}
+
+impl Compression {
+    /// Compress with brotli and gzip, for responses of 1KB or more
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Smallest response to compress, in bytes. default: 1024
+    pub fn with_min_size(mut self, min_size: u64) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// Don't compress these content types, in addition to the default list.
+    /// Entries ending in '/' (e.g., "model/") match all subtypes
+    pub fn with_skip(mut self, content_types: &[&str]) -> Self {
+        self.skip
+            .extend(content_types.iter().map(|s| s.to_ascii_lowercase()));
+        self
+    }
+
+    /// Enable or disable brotli. default: enabled
+    pub fn with_brotli(mut self, enabled: bool) -> Self {
+        self.brotli = enabled;
+        self
+    }
+
+    /// Enable or disable gzip. default: enabled
+    pub fn with_gzip(mut self, enabled: bool) -> Self {
+        self.gzip = enabled;
+        self
+    }
+
+    /// Returns true if responses with the content type can be compressed
+    pub fn is_compressible(&self, content_type: &str) -> bool {
+        let mime = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        !self.skip.iter().any(|skip| match skip.ends_with('/') {
+            true => mime.starts_with(skip.as_str()),
+            false => mime == *skip,
+        })
+    }
+
+    /// Encoding to use for the request, from its Accept-Encoding header.
+    /// Brotli is preferred when both are accepted with the same q value
+    pub fn negotiate(&self, request_headers: &HeaderMap) -> Option<ContentEncoding> {
+        let accept = request_headers
+            .get(header::ACCEPT_ENCODING)
+            .and_then(|v| v.to_str().ok())?;
+        let mut best: Option<(ContentEncoding, f32)> = None;
+        for item in accept.split(',') {
+            let mut parts = item.split(';');
+            let coding = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+            let q = parts
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            let encoding = match coding.as_str() {
+                "br" if self.brotli => ContentEncoding::Brotli,
+                "gzip" | "x-gzip" if self.gzip => ContentEncoding::Gzip,
+                _ => continue,
+            };
+            let better = match best {
+                None => true,
+                Some((current, best_q)) => {
+                    q > best_q
+                        || (q == best_q
+                            && encoding == ContentEncoding::Brotli
+                            && current != encoding)
+                }
+            };
+            if q > 0.0 && better {
+                best = Some((encoding, q));
+            }
+        }
+        best.map(|(encoding, _)| encoding)
+    }
+
+    /// Compress a 200 response, if its content type and size qualify and the request
+    /// accepts a supported encoding. Adds `Vary: Accept-Encoding` to compressible
+    /// responses, whether or not they were compressed
+    pub(crate) fn apply(
+        &self,
+        request_headers: &HeaderMap,
+        response: Response<Bytes>,
+    ) -> Response<Bytes> {
+        let headers = response.headers();
+        let content_type = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        if headers.contains_key(header::CONTENT_ENCODING)
+            || (response.body().len() as u64) < self.min_size
+            || !self.is_compressible(content_type)
+        {
+            return response;
+        }
+        let (mut parts, body) = response.into_parts();
+        parts
+            .headers
+            .append(header::VARY, HeaderValue::from_static("accept-encoding"));
+        let encoding = match self.negotiate(request_headers) {
+            Some(encoding) => encoding,
+            None => return Response::from_parts(parts, body),
+        };
+        let compressed = match compress(encoding, &body) {
+            Some(compressed) if compressed.len() < body.len() => compressed,
+            _ => return Response::from_parts(parts, body),
+        };
+        let headers = &mut parts.headers;
+        headers.insert(
+            header::CONTENT_ENCODING,
+            HeaderValue::from_static(encoding.as_str()),
+        );
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from(compressed.len()));
+        headers.remove(header::ACCEPT_RANGES);
+        if let Some(etag) = headers
+            .get(header::ETAG)
+            .and_then(|v| v.to_str().ok())
+            .filter(|etag| !etag.starts_with("W/"))
+            .and_then(|etag| HeaderValue::from_str(&format!("W/{}", etag)).ok())
+        {
+            headers.insert(header::ETAG, etag);
+        }
+        Response::from_parts(parts, Bytes::from(compressed))
+    }
+}
+
+fn compress(encoding: ContentEncoding, data: &[u8]) -> Option<Vec<u8>> {
+    match encoding {
+        ContentEncoding::Gzip => {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data).ok()?;
+            encoder.finish().ok()
+        }
+        ContentEncoding::Brotli => {
+            let mut out = Vec::new();
+            {
+                let mut encoder = brotli::CompressorWriter::new(&mut out, 4096, 5, 22);
+                encoder.write_all(data).ok()?;
+            }
+            Some(out)
+        }
+    }
+}
+
+/// Tests encoding negotiation and response compression
+#[test]
+fn test_compression() {
+    let compression = Compression::new().with_min_size(10);
+    let accept = |val: &'static str| {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT_ENCODING, HeaderValue::from_static(val));
+        headers
+    };
+    assert_eq!(
+        compression.negotiate(&accept("gzip, deflate, br")),
+        Some(ContentEncoding::Brotli)
+    );
+    assert_eq!(
+        compression.negotiate(&accept("br;q=0.5, gzip")),
+        Some(ContentEncoding::Gzip)
+    );
+    assert_eq!(compression.negotiate(&accept("identity, br;q=0")), None);
+    assert_eq!(compression.negotiate(&HeaderMap::new()), None);
+    assert!(compression.is_compressible("text/css; charset=utf-8"));
+    assert!(compression.is_compressible("image/svg+xml"));
+    assert!(!compression.is_compressible("image/png"));
+    assert!(!compression.is_compressible("video/mp4"));
+
+    let response = |content_type: &'static str| {
+        let mut response = Response::new(Bytes::from(vec![b'a'; 1000]));
+        response
+            .headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+        response
+            .headers_mut()
+            .insert(header::ETAG, HeaderValue::from_static("\"abc\""));
+        response
+    };
+    let resp = compression.apply(&accept("gzip"), response("text/plain"));
+    assert_eq!(resp.headers()[header::CONTENT_ENCODING], "gzip");
+    assert_eq!(resp.headers()[header::VARY], "accept-encoding");
+    assert_eq!(resp.headers()[header::ETAG], "W/\"abc\"");
+    assert_eq!(
+        resp.headers()[header::CONTENT_LENGTH],
+        resp.body().len().to_string().as_str()
+    );
+    let mut decoded = Vec::new();
+    std::io::Read::read_to_end(
+        &mut flate2::read::GzDecoder::new(resp.body().as_ref()),
+        &mut decoded,
+    )
+    .unwrap();
+    assert_eq!(decoded.len(), 1000);
+
+    let resp = compression.apply(&accept("br"), response("text/plain"));
+    assert_eq!(resp.headers()[header::CONTENT_ENCODING], "br");
+    let resp = compression.apply(&accept("gzip"), response("image/png"));
+    assert!(resp.headers().get(header::CONTENT_ENCODING).is_none());
+    let resp = compression.apply(&HeaderMap::new(), response("text/plain"));
+    assert!(resp.headers().get(header::CONTENT_ENCODING).is_none());
+    assert_eq!(resp.headers()[header::VARY], "accept-encoding");
+}
@@ -0,0 +1,101 @@
+use crate::{AssetIndex, Error, KVAssets};
+use futures_util::stream::{self, StreamExt};
+
+impl<'ah> KVAssets<'ah> {
+    /// Copy assets to another namespace, possibly in another account, for moving
+    /// a site between namespaces or accounts. Every asset in the index whose path
+    /// passes `filter` is read from this namespace and written to `target`
+    /// under the same KV key. If the index is empty, every KV key passing the
+    /// filter is copied instead. Values are copied in parallel
+    /// (see `with_chunk_concurrency`), and `progress` is called with
+    /// (path, number copied, total) as each completes.
+    ///
+    /// Returns the index of copied assets, including aliases passing the filter. Since KV keys are unchanged,
+    /// it can be encoded with `encode_index` and deployed with a worker reading from `target`.
+    pub async fn copy_to(
+        &self,
+        target: &KVAssets<'_>,
+        filter: impl Fn(&str) -> bool,
+        mut progress: impl FnMut(&str, usize, usize),
+    ) -> Result<AssetIndex, Error> {
+        let (indexed, index) = self.with_index(|index| {
+            let copied = index
+                .iter()
+                .filter(|(path, _)| filter(path))
+                .map(|(path, md)| (path.clone(), md.clone()))
+                .collect::<AssetIndex>();
+            (!index.is_empty(), copied)
+        })?;
+        let keys: Vec<(String, String)> = match indexed {
+            true => index
+                .iter()
+                .filter(|(_, md)| md.alias.is_none())
+                .map(|(path, md)| (path.clone(), md.path.clone()))
+                .collect(),
+            false => self
+                .list_keys()
+                .await?
+                .into_iter()
+                .filter(|key| filter(key))
+                .map(|key| (key.clone(), key))
+                .collect(),
+        };
+        let total = keys.len();
+        let mut copies = stream::iter(keys)
+            .map(|(path, key)| async move {
+                self.check_cancelled("copying assets")?;
+                let value = self.get_kv_value(&key).await?;
+                target.put_kv_value(&key, value, None).await?;
+                Ok::<_, Error>(path)
+            })
+            .buffer_unordered(self.chunk_concurrency());
+        let mut copied = 0;
+        while let Some(path) = copies.next().await {
+            let path = path?;
+            copied += 1;
+            progress(&path, copied, total);
+        }
+        Ok(index)
+    }
+}
+
+/// Tests copying filtered assets between namespaces, against the mock api
+#[cfg(all(test, feature = "reqwest"))]
+#[tokio::test]
+async fn test_copy_to() {
+    use crate::AssetMetadata;
+
+    let mock = crate::testspace::MockCloudflare::start().unwrap();
+    let mut index = AssetIndex::new();
+    for (path, key) in [("a.txt", "a.1.txt"), ("b.css", "b.2.css")] {
+        mock.insert("src", key, "value");
+        index.insert(
+            path.to_string(),
+            AssetMetadata {
+                path: key.to_string(),
+                modified: 10000,
+                size: 5,
+                hash: None,
+                ..Default::default()
+            },
+        );
+    }
+    let blob = crate::encode_index(&index).unwrap();
+    let source = mock.handler(&blob, "src");
+    let target = mock.handler(&[], "dst");
+
+    let mut reported = Vec::new();
+    let copied = source
+        .copy_to(
+            &target,
+            |path| path.ends_with(".txt"),
+            |path, n, total| reported.push((path.to_string(), n, total)),
+        )
+        .await
+        .unwrap();
+    assert_eq!(copied.len(), 1);
+    assert_eq!(copied["a.txt"].path, "a.1.txt");
+    assert_eq!(reported, vec![("a.txt".to_string(), 1, 1)]);
+    assert_eq!(mock.keys("dst"), vec!["a.1.txt"]);
+    assert_eq!(mock.get("dst", "a.1.txt").unwrap(), "value");
+}
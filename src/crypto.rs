@@ -0,0 +1,111 @@
+#![cfg(feature = "encryption")]
+
+use crate::Error;
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use std::collections::HashMap;
+
+// encrypted value format: version(1) | key_id(4, big-endian) | nonce(24) | ciphertext+tag
+const FORMAT_VERSION: u8 = 1;
+const HEADER_LEN: usize = 5;
+const NONCE_LEN: usize = 24;
+
+/// Client-side encryption of KV values with XChaCha20-Poly1305.
+/// Each value is tagged with the id of the key that encrypted it, so keys can be rotated:
+/// new values are written with the current key, and values written with
+/// older keys can still be read as long as those keys are added with `add_key`.
+pub struct Encryption {
+    keys: HashMap<u32, XChaCha20Poly1305>,
+    write_key: u32,
+}
+
+impl Encryption {
+    /// Encrypt new values with this key
+    pub fn new(key_id: u32, key: &[u8; 32]) -> Self {
+        let mut keys = HashMap::new();
+        keys.insert(key_id, XChaCha20Poly1305::new(key.into()));
+        Self {
+            keys,
+            write_key: key_id,
+        }
+    }
+
+    /// Add a key used only for decrypting values written before a key rotation
+    pub fn add_key(mut self, key_id: u32, key: &[u8; 32]) -> Self {
+        self.keys
+            .entry(key_id)
+            .or_insert_with(|| XChaCha20Poly1305::new(key.into()));
+        self
+    }
+
+    /// Encrypt value with the current key
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut out = Vec::with_capacity(HEADER_LEN + NONCE_LEN + plaintext.len() + 16);
+        out.push(FORMAT_VERSION);
+        out.extend_from_slice(&self.write_key.to_be_bytes());
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self.keys[&self.write_key]
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext,
+                    aad: &out[..HEADER_LEN],
+                },
+            )
+            .map_err(|_| Error::Encryption("encrypt failed".into()))?;
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&ciphertext);
+        Ok(out)
+    }
+
+    /// Decrypt value, using the key whose id is in the value header
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        if data.len() < HEADER_LEN + NONCE_LEN || data[0] != FORMAT_VERSION {
+            return Err(Error::Encryption("value is not encrypted".into()));
+        }
+        let mut key_id = [0u8; 4];
+        key_id.copy_from_slice(&data[1..HEADER_LEN]);
+        let key_id = u32::from_be_bytes(key_id);
+        let cipher = self
+            .keys
+            .get(&key_id)
+            .ok_or_else(|| Error::Encryption(format!("unknown key id {}", key_id)))?;
+        let nonce = XNonce::from_slice(&data[HEADER_LEN..HEADER_LEN + NONCE_LEN]);
+        cipher
+            .decrypt(
+                nonce,
+                Payload {
+                    msg: &data[HEADER_LEN + NONCE_LEN..],
+                    aad: &data[..HEADER_LEN],
+                },
+            )
+            .map_err(|_| Error::Encryption(format!("decrypt failed with key id {}", key_id)))
+    }
+}
+
+/// Tests encryption round trip and key rotation
+#[test]
+fn test_encryption() {
+    let old = Encryption::new(1, &[1u8; 32]);
+    let sealed_old = old.encrypt(b"draft").unwrap();
+    assert_ne!(&sealed_old[HEADER_LEN + NONCE_LEN..], b"draft");
+    assert_eq!(old.decrypt(&sealed_old).unwrap(), b"draft");
+
+    // rotated: new writes use key 2, old values still readable
+    let rotated = Encryption::new(2, &[2u8; 32]).add_key(1, &[1u8; 32]);
+    let sealed_new = rotated.encrypt(b"final").unwrap();
+    assert_eq!(&sealed_new[1..HEADER_LEN], &2u32.to_be_bytes());
+    assert_eq!(rotated.decrypt(&sealed_old).unwrap(), b"draft");
+    assert_eq!(rotated.decrypt(&sealed_new).unwrap(), b"final");
+
+    // key 2 not known to old keyring
+    assert!(old.decrypt(&sealed_new).is_err());
+
+    // tampering with header or ciphertext is detected
+    let mut tampered = sealed_new.clone();
+    tampered[HEADER_LEN + NONCE_LEN] ^= 1;
+    assert!(rotated.decrypt(&tampered).is_err());
+    assert!(rotated.decrypt(b"plaintext").is_err());
+}
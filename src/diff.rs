@@ -0,0 +1,118 @@
+use crate::{AssetIndex, AssetMetadata};
+use serde::Serialize;
+
+/// Differences between two indexes, returned by `AssetIndexExt::diff`.
+/// Paths are sorted. Serializable, for change logs in CI.
+#[derive(Debug, Default, Clone, Serialize, PartialEq, Eq)]
+pub struct IndexDiff {
+    /// Paths in the new index only
+    pub added: Vec<String>,
+    /// Paths in the old index only
+    pub removed: Vec<String>,
+    /// Paths in both indexes whose content or response settings changed
+    pub modified: Vec<String>,
+}
+
+impl IndexDiff {
+    /// Compare indexes. An entry is modified if its content changed (by hash, if
+    /// both entries have one, otherwise by size and modification time), its KV key
+    /// changed, or its alias target or response headers changed.
+    /// Tombstones are treated as absent
+    pub fn new(old: &AssetIndex, new: &AssetIndex) -> Self {
+        let mut diff = IndexDiff::default();
+        for (path, md) in new.iter().filter(|(_, md)| !md.is_deleted()) {
+            match live(old, path) {
+                None => diff.added.push(path.clone()),
+                Some(prev) if is_modified(prev, md) => diff.modified.push(path.clone()),
+                Some(_) => {}
+            }
+        }
+        diff.removed = old
+            .iter()
+            .filter(|(path, md)| !md.is_deleted() && live(new, path).is_none())
+            .map(|(path, _)| path.clone())
+            .collect();
+        diff.added.sort();
+        diff.removed.sort();
+        diff.modified.sort();
+        diff
+    }
+
+    /// Returns true if the indexes have the same entries
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+
+    /// Added, removed, and modified paths, sorted: the paths whose cached
+    /// copies should be invalidated after a deploy
+    pub fn changed_paths(&self) -> Vec<String> {
+        let mut paths: Vec<String> = self
+            .added
+            .iter()
+            .chain(self.removed.iter())
+            .chain(self.modified.iter())
+            .cloned()
+            .collect();
+        paths.sort();
+        paths
+    }
+}
+
+impl std::fmt::Display for IndexDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} added, {} removed, {} modified",
+            self.added.len(),
+            self.removed.len(),
+            self.modified.len()
+        )
+    }
+}
+
+// Entry at path, unless it is a tombstone
+fn live<'i>(index: &'i AssetIndex, path: &str) -> Option<&'i AssetMetadata> {
+    index.get(path).filter(|md| !md.is_deleted())
+}
+
+fn is_modified(old: &AssetMetadata, new: &AssetMetadata) -> bool {
+    let content = match (&old.hash, &new.hash) {
+        (Some(a), Some(b)) => !a.eq_ignore_ascii_case(b),
+        _ => old.size != new.size || old.modified != new.modified,
+    };
+    content || old.path != new.path || old.alias != new.alias || old.headers != new.headers
+}
+
+/// Tests comparing indexes
+#[test]
+fn test_index_diff() {
+    use crate::AssetIndexExt;
+
+    let entry = |key: &str, hash: Option<&str>, modified: u64| AssetMetadata {
+        path: key.to_string(),
+        modified,
+        size: 5,
+        hash: hash.map(String::from),
+        ..Default::default()
+    };
+    let mut old = AssetIndex::new();
+    old.insert("a.css".into(), entry("a.css", Some("aa"), 1));
+    old.insert("b.js".into(), entry("b.js", None, 1));
+    old.insert("c.html".into(), entry("c.html", Some("cc"), 1));
+    old.insert("gone.txt".into(), entry("gone.txt", None, 1));
+    let mut new = AssetIndex::new();
+    // same hash, newer mtime: unchanged
+    new.insert("a.css".into(), entry("a.css", Some("AA"), 2));
+    // no hash, newer mtime: modified
+    new.insert("b.js".into(), entry("b.js", None, 2));
+    new.insert("c.html".into(), entry("c.html", Some("c2"), 1));
+    new.insert("new.txt".into(), entry("new.txt", None, 1));
+
+    let diff = AssetIndex::diff(&old, &new);
+    assert_eq!(diff.added, vec!["new.txt"]);
+    assert_eq!(diff.removed, vec!["gone.txt"]);
+    assert_eq!(diff.modified, vec!["b.js", "c.html"]);
+    assert_eq!(diff.to_string(), "1 added, 1 removed, 2 modified");
+    assert_eq!(diff.changed_paths().len(), 4);
+    assert!(AssetIndex::diff(&new, &new).is_empty());
+}
@@ -0,0 +1,141 @@
+use crate::{AssetFuture, AssetMetadata, Error, KVAssets};
+use bytes::Bytes;
+use http::{header, HeaderValue, Response, StatusCode};
+use std::time::Duration;
+
+/// Default max-age of responses stored in the edge cache
+pub const DEFAULT_EDGE_CACHE_TTL: Duration = Duration::from_secs(3600);
+
+/// Shared http cache checked before KV, such as the Workers Cache API
+/// (`caches.default`). With a cache installed (`KVAssets::with_edge_cache`),
+/// asset values are looked up in the cache first, and stored in it after a KV read,
+/// as responses with headers derived from the asset metadata and
+/// `Cache-Control: public, max-age=<ttl>`. This is the Workers Sites pattern,
+/// and avoids most KV reads for popular assets.
+///
+/// Keys are `<namespace id>/<KV key>`. The Cache API requires a url, so implementations
+/// should map the key to one under a host of the zone (e.g.,
+/// `https://example.com/__kv-assets/<key>`). With versioned KV keys (as created by
+/// `kv-assets publish`), redeploys never serve stale cached content.
+///
+/// Errors in the cache should be treated as misses: `get` returns None and `put` does nothing.
+pub trait EdgeCache: Send + Sync {
+    /// Returns the cached response for the key, if any
+    fn get<'a>(&'a self, key: &'a str) -> AssetFuture<'a, Option<Response<Bytes>>>;
+
+    /// Stores the response for the key
+    fn put<'a>(&'a self, key: &'a str, response: Response<Bytes>) -> AssetFuture<'a, ()>;
+}
+
+impl<'ah> KVAssets<'ah> {
+    // Cache key of the asset
+    fn edge_cache_key(&self, md: &AssetMetadata) -> Result<String, Error> {
+        Ok(format!("{}/{}", self.namespace_id()?, md.path))
+    }
+
+    /// Asset value from the edge cache, if installed and cached
+    pub(crate) async fn edge_cache_get(&self, md: &AssetMetadata) -> Option<Bytes> {
+        let (cache, _) = self.edge_cache()?;
+        let value = match cache.get(&self.edge_cache_key(md).ok()?).await {
+            Some(response) if response.status() == StatusCode::OK => Some(response.into_body()),
+            _ => None,
+        };
+        self.record_cache_lookup("edge", value.is_some());
+        value
+    }
+
+    /// Store the asset value in the edge cache, if installed
+    pub(crate) async fn edge_cache_put(&self, md: &AssetMetadata, body: Bytes) {
+        let (cache, ttl) = match self.edge_cache() {
+            Some(found) => found,
+            None => return,
+        };
+        let key = match self.edge_cache_key(md) {
+            Ok(key) => key,
+            Err(_) => return,
+        };
+        let mut response = crate::serve::asset_response(&md.path, md, body);
+        response.headers_mut().insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_str(&format!("public, max-age={}", ttl.as_secs()))
+                .unwrap_or_else(|_| HeaderValue::from_static("public")),
+        );
+        response.headers_mut().remove(header::SET_COOKIE);
+        cache.put(&key, response).await;
+    }
+}
+
+/// Tests that values are served from the edge cache after the first KV read
+/// (does not invoke cloudflare api)
+#[cfg(test)]
+#[tokio::test]
+async fn test_edge_cache() {
+    use crate::{AssetIndex, HttpClient, HttpFuture};
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Stub {
+        gets: AtomicUsize,
+    }
+    impl HttpClient for Stub {
+        fn send(&self, _: http::Request<Bytes>) -> HttpFuture<'_> {
+            self.gets.fetch_add(1, Ordering::Relaxed);
+            Box::pin(async { Ok(Response::new(Bytes::from("body{}"))) })
+        }
+    }
+
+    #[derive(Default)]
+    struct MemoryCache {
+        entries: Mutex<HashMap<String, Response<Bytes>>>,
+    }
+    impl EdgeCache for MemoryCache {
+        fn get<'a>(&'a self, key: &'a str) -> AssetFuture<'a, Option<Response<Bytes>>> {
+            let found = self.entries.lock().unwrap().get(key).map(|r| {
+                let mut response = Response::new(r.body().clone());
+                *response.headers_mut() = r.headers().clone();
+                response
+            });
+            Box::pin(async move { found })
+        }
+        fn put<'a>(&'a self, key: &'a str, response: Response<Bytes>) -> AssetFuture<'a, ()> {
+            self.entries
+                .lock()
+                .unwrap()
+                .insert(key.to_string(), response);
+            Box::pin(async {})
+        }
+    }
+
+    let mut index = AssetIndex::new();
+    index.insert(
+        "app.css".to_string(),
+        AssetMetadata {
+            path: "app.1.css".to_string(),
+            size: 6,
+            ..Default::default()
+        },
+    );
+    let blob = crate::encode_index(&index).unwrap();
+    let stub = Arc::new(Stub::default());
+    let cache = Arc::new(MemoryCache::default());
+    let metrics = Arc::new(crate::metrics::RecordedMetrics::default());
+    let kv = KVAssets::init(&blob, "acct", "ns", "token")
+        .with_http_client(stub.clone())
+        .with_edge_cache(cache.clone(), Duration::from_secs(600))
+        .with_metrics(metrics.clone());
+
+    for _ in 0..2 {
+        assert_eq!(kv.get_asset("/app.css").await.unwrap().unwrap(), "body{}");
+    }
+    assert_eq!(stub.gets.load(Ordering::Relaxed), 1);
+    assert_eq!(metrics.events(), vec!["cache_miss:edge", "cache_hit:edge"]);
+    let entries = cache.entries.lock().unwrap();
+    let cached = entries.get("ns/app.1.css").unwrap();
+    assert_eq!(
+        cached.headers()[header::CACHE_CONTROL],
+        "public, max-age=600"
+    );
+    assert_eq!(cached.headers()[header::CONTENT_TYPE], "text/css");
+}
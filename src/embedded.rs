@@ -0,0 +1,170 @@
+use crate::ServedBy;
+use bytes::Bytes;
+use http::{header, HeaderValue, Method, Request, Response, StatusCode};
+use std::collections::BTreeMap;
+
+/// Tiny set of assets compiled into the worker (with `include_bytes!`), served by
+/// `KVAssets::serve` when reading KV or the api fails, so the site stays minimally
+/// responsive during Cloudflare incidents. Install with `KVAssets::with_embedded_fallback`.
+///
+/// A failed request (502 or 503) is answered with the embedded asset at its path, or
+/// else with the error page (such as an offline or maintenance page) and status 503.
+/// Embedded responses aren't cached (`Cache-Control: no-store`), and have the
+/// `ServedBy("embedded")` response extension.
+/// ```
+/// # use kv_assets::EmbeddedAssets;
+/// let embedded = EmbeddedAssets::new()
+///     .with("offline.html", b"<h1>Back soon</h1>")
+///     .with_error_page("offline.html");
+/// assert_eq!(embedded.len(), 1);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct EmbeddedAssets {
+    files: BTreeMap<String, Bytes>,
+    error_page: Option<String>,
+}
+
+impl EmbeddedAssets {
+    /// Create bundle with no assets
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an asset at path (with or without its leading '/')
+    pub fn with(mut self, path: &str, content: &'static [u8]) -> Self {
+        self.files
+            .insert(trim_path(path).to_string(), Bytes::from_static(content));
+        self
+    }
+
+    /// Embedded asset served for failed requests with no embedded asset at their path
+    pub fn with_error_page(mut self, path: &str) -> Self {
+        self.error_page = Some(trim_path(path).to_string());
+        self
+    }
+
+    /// Number of embedded assets
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Returns true if there are no embedded assets
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Content of the embedded asset at path
+    pub fn get(&self, path: &str) -> Option<&Bytes> {
+        self.files.get(trim_path(path))
+    }
+
+    // Response replacing a failed response to req, if the bundle has one
+    pub(crate) fn response(&self, req: &Request<()>) -> Option<Response<Bytes>> {
+        if !matches!(*req.method(), Method::GET | Method::HEAD) {
+            return None;
+        }
+        let path = trim_path(req.uri().path());
+        let (path, status) = match self.files.contains_key(path) {
+            true => (path, StatusCode::OK),
+            false => (self.error_page.as_deref()?, StatusCode::SERVICE_UNAVAILABLE),
+        };
+        let body = self.files.get(path)?.clone();
+        let mut response = Response::new(Bytes::new());
+        *response.status_mut() = status;
+        let headers = response.headers_mut();
+        let content_type = mime_guess::from_path(path)
+            .first()
+            .and_then(|mime| HeaderValue::from_str(mime.as_ref()).ok())
+            .unwrap_or_else(|| HeaderValue::from_static("application/octet-stream"));
+        headers.insert(header::CONTENT_TYPE, content_type);
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from(body.len()));
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        if req.method() == Method::GET {
+            *response.body_mut() = body;
+        }
+        response
+            .extensions_mut()
+            .insert(ServedBy("embedded".to_string()));
+        Some(response)
+    }
+}
+
+fn trim_path(path: &str) -> &str {
+    path.strip_prefix('/').unwrap_or(path)
+}
+
+// True for responses to requests that failed reading KV or the api
+pub(crate) fn is_failure(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE
+    )
+}
+
+/// Tests serving embedded assets when KV fails (does not invoke cloudflare api)
+#[cfg(test)]
+#[tokio::test]
+async fn test_embedded_fallback() {
+    use crate::{AssetIndex, AssetMetadata, Error, HttpClient, HttpFuture, KVAssets};
+    use std::sync::Arc;
+
+    struct Down;
+    impl HttpClient for Down {
+        fn send(&self, _: Request<Bytes>) -> HttpFuture<'_> {
+            Box::pin(async { Err(Error::KVHttp("connection refused".into())) })
+        }
+    }
+
+    let mut index = AssetIndex::new();
+    for path in ["index.html", "logo.svg"] {
+        index.insert(
+            path.to_string(),
+            AssetMetadata {
+                path: path.to_string(),
+                size: 3,
+                ..Default::default()
+            },
+        );
+    }
+    let bytes = crate::encode_index(&index).unwrap();
+    let get = |path: &str| Request::get(path).body(()).unwrap();
+
+    let kv = KVAssets::init(&bytes, "acct", "ns", "token").with_http_client(Arc::new(Down));
+    assert_eq!(
+        kv.serve(&get("/index.html")).await.status(),
+        StatusCode::BAD_GATEWAY
+    );
+
+    let kv = kv.with_embedded_fallback(
+        EmbeddedAssets::new()
+            .with("/logo.svg", b"<svg/>")
+            .with("offline.html", b"offline"),
+    );
+    let response = kv.serve(&get("/logo.svg")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "image/svg+xml");
+    assert_eq!(
+        response.extensions().get(),
+        Some(&ServedBy("embedded".to_string()))
+    );
+    // no error page
+    assert_eq!(
+        kv.serve(&get("/index.html")).await.status(),
+        StatusCode::BAD_GATEWAY
+    );
+    // not found isn't a failure
+    assert_eq!(
+        kv.serve(&get("/missing")).await.status(),
+        StatusCode::NOT_FOUND
+    );
+
+    let kv = kv.with_embedded_fallback(
+        EmbeddedAssets::new()
+            .with("offline.html", b"offline")
+            .with_error_page("/offline.html"),
+    );
+    let response = kv.serve(&get("/index.html")).await;
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.body().as_ref(), b"offline");
+    assert_eq!(response.headers()[header::CACHE_CONTROL], "no-store");
+}
@@ -0,0 +1,130 @@
+use crate::Error;
+
+/// Category of an `Error`, for retry layers and handlers that decide what to do
+/// with a failure without matching every variant. New variants of `Error` are
+/// added to one of these categories, so matching on the kind doesn't break
+/// when a new failure mode is added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Missing or invalid configuration: credentials, config files, options
+    Config,
+    /// The asset index couldn't be loaded, or doesn't match KV
+    Index,
+    /// Unsuccessful http response, or transport failure (status 0)
+    Http { status: u16 },
+    /// The Cloudflare api rejected the request with an error code
+    Api { code: u32 },
+    /// Local file system error
+    Io,
+    /// Invalid key, value, or url passed by the caller
+    Input,
+    /// The operation was cancelled, or its deadline passed (see `CancelToken`)
+    Cancelled,
+    /// Anything else
+    Other,
+}
+
+impl Error {
+    /// Category of the error
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::KVHttp(_) => ErrorKind::Http { status: 0 },
+            Error::KVKeyNotFound(_, status) | Error::OriginStatus(_, status) => {
+                ErrorKind::Http { status: *status }
+            }
+            Error::CircuitOpen => ErrorKind::Http { status: 503 },
+            Error::ValueTooLarge { .. } => ErrorKind::Http { status: 413 },
+            Error::Api { code, .. } => ErrorKind::Api { code: *code },
+            Error::DeserializeAssets(_)
+            | Error::IndexCorrupt { .. }
+            | Error::IndexOutOfDate { .. }
+            | Error::AliasLoop(_)
+            | Error::ChecksumMismatch(_)
+            | Error::MissingValues(_) => ErrorKind::Index,
+            #[cfg(feature = "signing")]
+            Error::IndexTampered => ErrorKind::Index,
+            Error::EmptyKey
+            | Error::InvalidKey(_)
+            | Error::OversizedAssets(_)
+            | Error::TooLargeToBuffer { .. }
+            | Error::InvalidMetadata(_) => ErrorKind::Input,
+            #[cfg(feature = "signing")]
+            Error::InvalidSignedUrl(_) => ErrorKind::Input,
+            Error::MissingWranglerFile(_)
+            | Error::WranglerConfig(_)
+            | Error::MissingConfig(_)
+            | Error::InvalidToken(_)
+            | Error::TTLTooShort => ErrorKind::Config,
+            #[cfg(feature = "profiles")]
+            Error::ProfileConfig(_) | Error::UnknownProfile(_) => ErrorKind::Config,
+            #[cfg(not(target_arch = "wasm32"))]
+            Error::InvalidAssetsBinPath(_) | Error::InvalidAssetPath(_) => ErrorKind::Config,
+            #[cfg(not(target_arch = "wasm32"))]
+            Error::IO(_) | Error::CreateDir(_) => ErrorKind::Io,
+            Error::Cancelled(_) => ErrorKind::Cancelled,
+            _ => ErrorKind::Other,
+        }
+    }
+
+    /// True if the request may succeed if tried again later: transport failures,
+    /// timeouts, rate limiting (429), server errors (5xx), and an open circuit breaker
+    pub fn is_retryable(&self) -> bool {
+        match self.kind() {
+            ErrorKind::Http { status } => {
+                matches!(status, 0 | 408 | 429) || (500..600).contains(&status)
+            }
+            _ => false,
+        }
+    }
+
+    /// True if the key or asset doesn't exist: a 404 from KV, or a KV value missing
+    /// for an indexed asset
+    pub fn is_not_found(&self) -> bool {
+        matches!(
+            self,
+            Error::KVKeyNotFound(_, 404) | Error::IndexOutOfDate { .. }
+        )
+    }
+}
+
+// Error for an unsuccessful api response: the code and message of the first error
+// in the response, if any
+pub(crate) fn api_error(context: &str, errors: &[serde_json::Value]) -> Error {
+    let first = errors.first().and_then(|e| {
+        let code = e.get("code")?.as_u64()?;
+        let message = e.get("message").and_then(|m| m.as_str()).unwrap_or("");
+        Some((code as u32, message))
+    });
+    match first {
+        Some((code, message)) => Error::Api {
+            code,
+            message: format!("{}: {}", context, message),
+        },
+        None => Error::Message(format!("{}: errors:{:?}", context, errors)),
+    }
+}
+
+/// Tests error categories
+#[test]
+fn test_error_kind() {
+    let err = Error::KVKeyNotFound("a".into(), 429);
+    assert_eq!(err.kind(), ErrorKind::Http { status: 429 });
+    assert!(err.is_retryable());
+    assert!(!err.is_not_found());
+    let err = Error::KVKeyNotFound("a".into(), 404);
+    assert!(!err.is_retryable());
+    assert!(err.is_not_found());
+    assert!(Error::CircuitOpen.is_retryable());
+    assert_eq!(Error::TTLTooShort.kind(), ErrorKind::Config);
+    assert_eq!(Error::EmptyKey.kind(), ErrorKind::Input);
+
+    let errors = vec![serde_json::json!({ "code": 10009, "message": "key not found" })];
+    let err = api_error("writing key a", &errors);
+    assert_eq!(err.kind(), ErrorKind::Api { code: 10009 });
+    assert_eq!(
+        err.to_string(),
+        "Api error 10009: writing key a: key not found"
+    );
+    assert!(matches!(api_error("x", &[]), Error::Message(_)));
+}
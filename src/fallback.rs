@@ -0,0 +1,212 @@
+use crate::{AssetFuture, AssetMetadata, AssetSource, Error, ResolvedAsset};
+use bytes::Bytes;
+use http::{Request, Response};
+use std::sync::Arc;
+
+/// Key in `AssetMetadata::extra` recording the name of the source that found the asset
+pub const SOURCE_KEY: &str = "source";
+
+/// Asset found by `FallbackChain::fetch`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FallbackHit {
+    /// Name of the source that served the asset
+    pub source: String,
+    /// Asset metadata
+    pub metadata: AssetMetadata,
+    /// Asset content
+    pub body: Bytes,
+}
+
+/// Response extension added by `FallbackChain::serve`: name of the source that answered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServedBy(pub String);
+
+/// Tries a list of asset sources in order, so a miss or failure in one tries the next
+/// (e.g., KV, then a local directory or an origin), for graceful degradation
+/// when KV is unavailable or the index lags a deploy.
+///
+/// A source is skipped if it doesn't have the asset, or fails. `serve` also falls
+/// through on 404 and 5xx responses, returning the last source's response if none succeed.
+/// Where the hit came from is recorded: `FallbackHit::source` for `fetch`,
+/// the `ServedBy` response extension for `serve`, and `extra["source"]`
+/// in metadata returned by `lookup_key`.
+#[derive(Default, Clone)]
+pub struct FallbackChain<'a> {
+    sources: Vec<(String, Arc<dyn AssetSource + 'a>)>,
+}
+
+impl<'a> FallbackChain<'a> {
+    /// Create chain with no sources
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a source, tried after the sources already added
+    pub fn with(mut self, name: &str, source: Arc<dyn AssetSource + 'a>) -> Self {
+        self.sources.push((name.to_string(), source));
+        self
+    }
+
+    /// Names of the sources, in order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.sources.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Looks up and reads the asset from the first source that has it.
+    /// Returns Ok(None) if no source has it, or the last error if any source failed
+    pub async fn fetch(&self, path: &str) -> Result<Option<FallbackHit>, Error> {
+        let mut last_err = None;
+        for (name, source) in self.sources.iter() {
+            let metadata = match source.lookup_key(path) {
+                Ok(Some(md)) => md,
+                Ok(None) => continue,
+                Err(e) => {
+                    last_err = Some(e);
+                    continue;
+                }
+            };
+            match source.fetch_asset(&metadata).await {
+                Ok(body) => {
+                    return Ok(Some(FallbackHit {
+                        source: name.clone(),
+                        metadata: tag(metadata, name),
+                        body,
+                    }))
+                }
+                Err(e) => last_err = Some(e),
+            }
+        }
+        last_err.map_or(Ok(None), Err)
+    }
+}
+
+// Record the source name in metadata
+fn tag(mut md: AssetMetadata, name: &str) -> AssetMetadata {
+    md.extra.insert(SOURCE_KEY.to_string(), name.to_string());
+    md
+}
+
+impl<'c> AssetSource for FallbackChain<'c> {
+    fn resolve_key(&self, path: &str) -> Result<Option<ResolvedAsset>, Error> {
+        let mut last_err = None;
+        for (name, source) in self.sources.iter() {
+            match source.resolve_key(path) {
+                Ok(Some(ResolvedAsset::Direct(md))) => {
+                    return Ok(Some(ResolvedAsset::Direct(tag(md, name))))
+                }
+                Ok(Some(ResolvedAsset::Alias { target, metadata })) => {
+                    return Ok(Some(ResolvedAsset::Alias {
+                        target,
+                        metadata: tag(metadata, name),
+                    }))
+                }
+                Ok(Some(redirect)) => return Ok(Some(redirect)),
+                Ok(None) => {}
+                Err(e) => last_err = Some(e),
+            }
+        }
+        last_err.map_or(Ok(None), Err)
+    }
+
+    /// Reads from the source recorded in the metadata by `resolve_key`.
+    /// Untagged metadata is tried with each source in order
+    fn fetch_asset<'a>(&'a self, md: &'a AssetMetadata) -> AssetFuture<'a, Result<Bytes, Error>> {
+        Box::pin(async move {
+            let mut last_err = None;
+            let tagged = md.extra.get(SOURCE_KEY);
+            for (name, source) in self.sources.iter() {
+                if tagged.map(|t| t != name).unwrap_or(false) {
+                    continue;
+                }
+                match source.fetch_asset(md).await {
+                    Ok(body) => return Ok(body),
+                    Err(e) => last_err = Some(e),
+                }
+            }
+            Err(last_err.unwrap_or_else(|| Error::KVKeyNotFound(md.path.clone(), 404)))
+        })
+    }
+
+    fn get_asset<'a>(&'a self, path: &'a str) -> AssetFuture<'a, Result<Option<Bytes>, Error>> {
+        Box::pin(async move { Ok(self.fetch(path).await?.map(|hit| hit.body)) })
+    }
+
+    fn serve<'a>(&'a self, req: &'a Request<()>) -> AssetFuture<'a, Response<Bytes>> {
+        Box::pin(async move {
+            let mut last = None;
+            for (name, source) in self.sources.iter() {
+                let mut response = source.serve(req).await;
+                response.extensions_mut().insert(ServedBy(name.clone()));
+                let status = response.status();
+                if status != http::StatusCode::NOT_FOUND && !status.is_server_error() {
+                    return response;
+                }
+                last = Some(response);
+            }
+            last.unwrap_or_else(|| crate::serve::status_response(http::StatusCode::NOT_FOUND))
+        })
+    }
+}
+
+/// Tests falling back from KV to a local directory (does not invoke cloudflare api)
+#[cfg(test)]
+#[tokio::test]
+async fn test_fallback_chain() {
+    use crate::{AssetIndex, HttpClient, HttpFuture, KVAssets, LocalAssets};
+
+    // KV api is down
+    struct Unavailable;
+    impl HttpClient for Unavailable {
+        fn send(&self, _: http::Request<Bytes>) -> HttpFuture<'_> {
+            Box::pin(async {
+                let mut response = http::Response::new(Bytes::new());
+                *response.status_mut() = http::StatusCode::SERVICE_UNAVAILABLE;
+                Ok(response)
+            })
+        }
+    }
+
+    let dir = std::env::temp_dir().join(format!("kv-assets-fallback-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("a.txt"), "local a").unwrap();
+    std::fs::write(dir.join("new.txt"), "local new").unwrap();
+
+    let mut index = AssetIndex::new();
+    index.insert(
+        "a.txt".to_string(),
+        AssetMetadata {
+            path: "a.1.txt".to_string(),
+            size: 7,
+            ..Default::default()
+        },
+    );
+    let blob = crate::encode_index(&index).unwrap();
+    let kv = KVAssets::init(&blob, "acct", "ns", "token").with_http_client(Arc::new(Unavailable));
+    let chain = FallbackChain::new()
+        .with("kv", Arc::new(kv))
+        .with("local", Arc::new(LocalAssets::new(&dir)));
+
+    // index lags deploy: not in KV index
+    let md = chain.lookup_key("/new.txt").unwrap().unwrap();
+    assert_eq!(md.extra[SOURCE_KEY], "local");
+    // in index, but KV unavailable
+    let hit = chain.fetch("/a.txt").await.unwrap().unwrap();
+    assert_eq!(hit.source, "local");
+    assert_eq!(hit.body, "local a");
+    assert!(chain.fetch("/missing.txt").await.unwrap().is_none());
+
+    let mut req = Request::new(());
+    *req.uri_mut() = "/a.txt".parse().unwrap();
+    let resp = chain.serve(&req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert_eq!(
+        resp.extensions().get(),
+        Some(&ServedBy("local".to_string()))
+    );
+    *req.uri_mut() = "/missing.txt".parse().unwrap();
+    assert_eq!(
+        chain.serve(&req).await.status(),
+        http::StatusCode::NOT_FOUND
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,147 @@
+use crate::{Error, KVAssets, KeyInfo, KvPutOptions, ValueInfo};
+use bytes::Bytes;
+use futures_util::stream::Stream;
+use std::borrow::Cow;
+
+/// KV operations on another namespace, created with `KVAssets::namespace`.
+/// One configured handler (http client and connection pool, rate limiter,
+/// circuit breaker) can address several namespaces without configuring a
+/// `KVAssets` for each, for tools that copy or audit across namespaces.
+/// Handles have no index, write overlay, or value cache: they read and write KV directly.
+pub struct NamespaceHandle<'a> {
+    kv: KVAssets<'a>,
+}
+
+impl<'a> NamespaceHandle<'a> {
+    pub(crate) fn new(kv: KVAssets<'a>) -> Self {
+        Self { kv }
+    }
+
+    /// Use a different api token for this namespace
+    pub fn with_auth_token(mut self, auth_token: impl Into<Cow<'a, str>>) -> Self {
+        self.kv.set_credentials(None, Some(auth_token.into()));
+        self
+    }
+
+    /// Use a namespace in a different account
+    pub fn with_account_id(mut self, account_id: impl Into<Cow<'a, str>>) -> Self {
+        self.kv.set_credentials(Some(account_id.into()), None);
+        self
+    }
+
+    /// Namespace id
+    pub fn namespace_id(&self) -> &str {
+        self.kv.namespace_id().unwrap_or_default()
+    }
+
+    /// Read a value (see `KVAssets::get_kv_value`)
+    pub async fn get_kv_value(&self, key: &str) -> Result<Bytes, Error> {
+        self.kv.get_kv_value(key).await
+    }
+
+    /// Read a value with its expiration and metadata (see `KVAssets::get_kv_value_with_info`)
+    pub async fn get_kv_value_with_info(&self, key: &str) -> Result<ValueInfo, Error> {
+        self.kv.get_kv_value_with_info(key).await
+    }
+
+    /// Returns the metadata stored with a key (see `KVAssets::get_kv_metadata`)
+    pub async fn get_kv_metadata(&self, key: &str) -> Result<Option<serde_json::Value>, Error> {
+        self.kv.get_kv_metadata(key).await
+    }
+
+    /// Returns true if the key exists (see `KVAssets::exists`)
+    pub async fn exists(&self, key: &str) -> Result<bool, Error> {
+        self.kv.exists(key).await
+    }
+
+    /// Store a value (see `KVAssets::put_kv_value`)
+    pub async fn put_kv_value<T: Into<Bytes>>(
+        &self,
+        key: &str,
+        val: T,
+        expiration_ttl: Option<u64>,
+    ) -> Result<(), Error> {
+        self.kv.put_kv_value(key, val, expiration_ttl).await
+    }
+
+    /// Store a value with options (see `KVAssets::put_kv_value_opts`)
+    pub async fn put_kv_value_opts<T: Into<Bytes>>(
+        &self,
+        key: &str,
+        val: T,
+        opts: &KvPutOptions,
+    ) -> Result<(), Error> {
+        self.kv.put_kv_value_opts(key, val, opts).await
+    }
+
+    /// Delete a value (see `KVAssets::delete_kv_value`)
+    pub async fn delete_kv_value(&self, key: &str) -> Result<(), Error> {
+        self.kv.delete_kv_value(key).await
+    }
+
+    /// List all keys (see `KVAssets::list_keys`)
+    pub async fn list_keys(&self) -> Result<Vec<String>, Error> {
+        self.kv.list_keys().await
+    }
+
+    /// Stream of all keys (see `KVAssets::list_all_keys`)
+    pub fn list_all_keys(&self) -> impl Stream<Item = Result<KeyInfo, Error>> + '_ {
+        self.kv.list_all_keys()
+    }
+}
+
+/// Tests that handles address their namespace with the shared client
+/// (does not invoke cloudflare api)
+#[cfg(test)]
+#[tokio::test]
+async fn test_namespace_handle() {
+    use crate::{HttpClient, HttpFuture};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Stub {
+        requests: Mutex<Vec<(String, String)>>,
+    }
+    impl HttpClient for Stub {
+        fn send(&self, request: http::Request<Bytes>) -> HttpFuture<'_> {
+            let auth = request.headers()[http::header::AUTHORIZATION]
+                .to_str()
+                .unwrap()
+                .to_string();
+            self.requests
+                .lock()
+                .unwrap()
+                .push((request.uri().path().to_string(), auth));
+            Box::pin(async { Ok(http::Response::new(Bytes::from("value"))) })
+        }
+    }
+
+    let stub = Arc::new(Stub::default());
+    let kv = KVAssets::init(&[], "acct", "ns", "token").with_http_client(stub.clone());
+    assert_eq!(
+        kv.namespace("other").get_kv_value("a").await.unwrap(),
+        "value"
+    );
+    let staging = kv
+        .namespace("staging")
+        .with_account_id("acct2")
+        .with_auth_token("token2");
+    assert_eq!(staging.namespace_id(), "staging");
+    staging.get_kv_value("b").await.unwrap();
+
+    let requests = stub.requests.lock().unwrap();
+    assert_eq!(
+        requests[0],
+        (
+            "/client/v4/accounts/acct/storage/kv/namespaces/other/values/a".to_string(),
+            "Bearer token".to_string()
+        )
+    );
+    assert_eq!(
+        requests[1],
+        (
+            "/client/v4/accounts/acct2/storage/kv/namespaces/staging/values/b".to_string(),
+            "Bearer token2".to_string()
+        )
+    );
+}
@@ -0,0 +1,136 @@
+use crate::{AssetMetadata, Error, KVAssets};
+
+/// Asset found by `lookup_localized`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalizedAsset {
+    /// Metadata of the selected variant
+    pub metadata: AssetMetadata,
+    /// Language tag of the selected variant, or None if the default was selected.
+    /// Use it for the Content-Language header, and add `Vary: Accept-Language` to the response.
+    pub language: Option<String>,
+}
+
+impl<'ah> KVAssets<'ah> {
+    /// Finds the localized variant of an asset that best matches an Accept-Language header.
+    /// Variants are stored with the language tag before the extension:
+    /// `index.de.html` and `index.pt-br.html` are variants of `index.html` (tags are lowercase).
+    /// Language ranges are tried in order of preference (q value), and each is
+    /// progressively truncated (`de-CH`, then `de`), as in the RFC 4647 lookup scheme.
+    /// If no variant matches, returns the default (un-tagged) asset, if it exists.
+    pub fn lookup_localized(
+        &self,
+        path: &str,
+        accept_language: Option<&str>,
+    ) -> Result<Option<LocalizedAsset>, Error> {
+        let path = path.strip_prefix('/').unwrap_or(path);
+        if path.is_empty() {
+            return Err(Error::EmptyKey);
+        }
+        let ranges = accept_language.map(language_ranges).unwrap_or_default();
+        let found = self.with_index(|index| {
+            for range in ranges.iter() {
+                let mut tag = range.as_str();
+                loop {
+                    if let Some(md) = index.get(&variant_path(path, tag)) {
+                        return Some((md.clone(), Some(tag.to_string())));
+                    }
+                    match tag.rfind('-') {
+                        Some(pos) => tag = &tag[..pos],
+                        None => break,
+                    }
+                    // a trailing single-character subtag is removed with the subtag after it
+                    if let Some(pos) = tag.rfind('-') {
+                        if tag.len() - pos == 2 {
+                            tag = &tag[..pos];
+                        }
+                    }
+                }
+            }
+            index.get(path).map(|md| (md.clone(), None))
+        })?;
+        if let Some(metrics) = self.metrics() {
+            metrics.lookup(found.is_some());
+        }
+        Ok(found.map(|(metadata, language)| LocalizedAsset { metadata, language }))
+    }
+}
+
+// Language ranges from Accept-Language, lowercase, in order of preference.
+// Ranges with q=0 and the wildcard are omitted: both fall back to the default
+fn language_ranges(header: &str) -> Vec<String> {
+    let mut ranges: Vec<(String, f32)> = header
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let range = parts.next()?.trim().to_ascii_lowercase();
+            let q = parts
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            match range.is_empty() || range == "*" || q <= 0.0 {
+                true => None,
+                false => Some((range, q)),
+            }
+        })
+        .collect();
+    // stable sort keeps header order for equal q
+    ranges.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    ranges.into_iter().map(|(range, _)| range).collect()
+}
+
+// Path of the variant for the language: tag inserted before the file extension
+pub(crate) fn variant_path(path: &str, tag: &str) -> String {
+    let name_start = path.rfind('/').map(|p| p + 1).unwrap_or(0);
+    match path[name_start..].rfind('.') {
+        Some(dot) if dot > 0 => {
+            let dot = name_start + dot;
+            format!("{}.{}{}", &path[..dot], tag, &path[dot..])
+        }
+        _ => format!("{}.{}", path, tag),
+    }
+}
+
+/// Tests Accept-Language negotiation
+#[test]
+fn test_lookup_localized() {
+    use crate::AssetIndex;
+
+    let mut index = AssetIndex::new();
+    for path in [
+        "docs/index.html",
+        "docs/index.de.html",
+        "docs/index.pt-br.html",
+    ] {
+        index.insert(
+            path.to_string(),
+            AssetMetadata {
+                path: path.to_string(),
+                modified: 10000,
+                size: 5,
+                hash: None,
+                ..Default::default()
+            },
+        );
+    }
+    let blob = crate::encode_index(&index).unwrap();
+    let kv = KVAssets::init(&blob, "acct", "ns", "token");
+    let language = |accept: Option<&str>| {
+        kv.lookup_localized("/docs/index.html", accept)
+            .unwrap()
+            .unwrap()
+            .language
+    };
+
+    assert_eq!(language(Some("de-CH, fr;q=0.9")), Some("de".to_string()));
+    assert_eq!(language(Some("fr, pt-BR;q=0.5")), Some("pt-br".to_string()));
+    assert_eq!(language(Some("de;q=0, *;q=0.5")), None);
+    assert_eq!(language(Some("fr;q=0.8, de;q=0.9")), Some("de".to_string()));
+    assert_eq!(language(None), None);
+    assert!(kv
+        .lookup_localized("/missing.html", Some("de"))
+        .unwrap()
+        .is_none());
+
+    assert_eq!(variant_path("a/b.min.js", "fr"), "a/b.min.fr.js");
+    assert_eq!(variant_path("a.d/README", "fr"), "a.d/README.fr");
+}
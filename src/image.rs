@@ -0,0 +1,150 @@
+use crate::{AssetMetadata, Error, KVAssets};
+
+/// Asset found by `lookup_image`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NegotiatedImage {
+    /// Path of the selected variant, or the requested path if no variant was selected
+    pub path: String,
+    /// Metadata of the selected variant
+    pub metadata: AssetMetadata,
+    /// Content type of the selected variant, for the Content-Type header.
+    /// Responses should also include `Vary: Accept`.
+    pub content_type: String,
+}
+
+impl<'ah> KVAssets<'ah> {
+    /// Finds the best encoding of an image for the request's Accept header.
+    /// Alternative formats are stored beside the original with a different extension
+    /// (`hero.avif` and `hero.webp` for `hero.png`). Each format registered with
+    /// `with_image_formats` is tried in order, if the client explicitly accepts its
+    /// content type and the variant is in the index. Otherwise returns the requested asset.
+    /// Paths that aren't images are looked up without negotiation.
+    pub fn lookup_image(
+        &self,
+        path: &str,
+        accept: Option<&str>,
+    ) -> Result<Option<NegotiatedImage>, Error> {
+        let path = path.strip_prefix('/').unwrap_or(path);
+        if path.is_empty() {
+            return Err(Error::EmptyKey);
+        }
+        let original_type = mime_guess::from_path(path).first_or_octet_stream();
+        let accepted: Vec<String> = match original_type.type_() == mime_guess::mime::IMAGE {
+            true => accept.map(accepted_types).unwrap_or_default(),
+            false => Vec::new(),
+        };
+        let found = self.with_index(|index| {
+            for format in self.image_formats() {
+                let variant = match replace_extension(path, format) {
+                    Some(variant) if variant != path => variant,
+                    _ => continue,
+                };
+                let content_type = mime_guess::from_ext(format).first_or_octet_stream();
+                if !accepted.iter().any(|t| t == content_type.essence_str()) {
+                    continue;
+                }
+                if let Some(md) = index.get(&variant) {
+                    return Some(NegotiatedImage {
+                        path: variant,
+                        metadata: md.clone(),
+                        content_type: content_type.to_string(),
+                    });
+                }
+            }
+            index.get(path).map(|md| NegotiatedImage {
+                path: path.to_string(),
+                metadata: md.clone(),
+                content_type: original_type.to_string(),
+            })
+        })?;
+        if let Some(metrics) = self.metrics() {
+            metrics.lookup(found.is_some());
+        }
+        Ok(found)
+    }
+}
+
+// Media types explicitly listed in an Accept header, lowercase, excluding those with q=0
+fn accepted_types(header: &str) -> Vec<String> {
+    header
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let media_type = parts.next()?.trim().to_ascii_lowercase();
+            let refused = parts
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .any(|q| q.trim().parse::<f32>().map(|q| q <= 0.0).unwrap_or(false));
+            match refused || media_type.is_empty() {
+                true => None,
+                false => Some(media_type),
+            }
+        })
+        .collect()
+}
+
+// Path with the file extension replaced, or None if the file name has no extension
+fn replace_extension(path: &str, ext: &str) -> Option<String> {
+    let name_start = path.rfind('/').map(|p| p + 1).unwrap_or(0);
+    match path[name_start..].rfind('.') {
+        Some(dot) if dot > 0 => Some(format!("{}.{}", &path[..name_start + dot], ext)),
+        _ => None,
+    }
+}
+
+/// Tests image format negotiation
+#[test]
+fn test_lookup_image() {
+    use crate::AssetIndex;
+
+    let mut index = AssetIndex::new();
+    for path in [
+        "img/hero.png",
+        "img/hero.webp",
+        "img/hero.avif",
+        "logo.png",
+        "app.js",
+    ] {
+        index.insert(
+            path.to_string(),
+            AssetMetadata {
+                path: path.to_string(),
+                modified: 10000,
+                size: 5,
+                hash: None,
+                ..Default::default()
+            },
+        );
+    }
+    let blob = crate::encode_index(&index).unwrap();
+    let kv = KVAssets::init(&blob, "acct", "ns", "token");
+    let chrome = Some("image/avif,image/webp,image/apng,image/*,*/*;q=0.8");
+
+    let found = kv.lookup_image("/img/hero.png", chrome).unwrap().unwrap();
+    assert_eq!(found.path, "img/hero.avif");
+    assert_eq!(found.content_type, "image/avif");
+
+    let found = kv
+        .lookup_image("/img/hero.png", Some("image/webp,image/avif;q=0"))
+        .unwrap()
+        .unwrap();
+    assert_eq!(found.content_type, "image/webp");
+
+    let found = kv
+        .lookup_image("/img/hero.png", Some("*/*"))
+        .unwrap()
+        .unwrap();
+    assert_eq!(found.path, "img/hero.png");
+    assert_eq!(found.content_type, "image/png");
+
+    let found = kv.lookup_image("/app.js", chrome).unwrap().unwrap();
+    assert_eq!(found.content_type, "text/javascript");
+
+    // no variants of logo.png
+    let found = kv.lookup_image("/logo.png", chrome).unwrap().unwrap();
+    assert_eq!(found.path, "logo.png");
+
+    let kv = KVAssets::init(&blob, "acct", "ns", "token").with_image_formats(&["webp"]);
+    let found = kv.lookup_image("/img/hero.png", chrome).unwrap().unwrap();
+    assert_eq!(found.path, "img/hero.webp");
+    assert!(kv.lookup_image("/missing.png", chrome).unwrap().is_none());
+}
@@ -0,0 +1,566 @@
+use crate::{AliasTarget, AssetIndex, AssetMetadata, Error};
+use bincode::Options;
+use serde::{de, de::DeserializeOwned, Deserialize};
+use std::collections::{BTreeMap, HashMap};
+use std::marker::PhantomData;
+
+/// Header of serialized index: magic bytes followed by a one-byte format version.
+/// Indexes written by kv-assets 0.2 have no header; they begin with the bincode map length,
+/// which can't collide with the magic bytes for any realistic number of entries.
+const INDEX_MAGIC: &[u8; 4] = b"KVAI";
+const INDEX_VERSION: u8 = 4;
+// version 1 entries have no alias; version 2 entries have no headers or extra fields;
+// version 3 entries have no deleted time
+const INDEX_VERSION_1: u8 = 1;
+const INDEX_VERSION_2: u8 = 2;
+const INDEX_VERSION_3: u8 = 3;
+
+/// Methods on `AssetIndex`
+pub trait AssetIndexExt {
+    /// Asset count, total size, largest assets, and sizes by file extension
+    fn stats(&self) -> crate::IndexStats;
+
+    /// Assets larger than limit, in bytes. Check against `MAX_VALUE_SIZE`
+    /// before uploading, unless values are chunked
+    fn oversized(&self, limit: u64) -> Vec<crate::OversizedAsset>;
+
+    /// Pretty-printed JSON, for inspection by other tools
+    fn to_json(&self) -> Result<String, Error>;
+
+    /// Parse index from JSON, as generated by `to_json` or another tool.
+    /// Only `path`, `modified`, and `size` are required for each entry
+    fn from_json(json: &str) -> Result<Self, Error>
+    where
+        Self: Sized;
+
+    /// JSON manifest for a javascript worker in the same project, so it can resolve
+    /// assets uploaded by this crate: an object of asset path to `key` (the KV key),
+    /// `size`, `modified`, `hash`, `contentType`, and `headers`.
+    /// Alias entries have `alias` (an asset path) or `redirect` (a url) instead.
+    fn to_js_manifest(&self) -> Result<String, Error>;
+
+    /// TypeScript module exporting the manifest from `to_js_manifest` as `manifest`
+    /// (also the default export), with an `AssetEntry` interface for its entries
+    fn to_ts_module(&self) -> Result<String, Error>;
+
+    /// Index from a Workers Sites asset manifest (`__STATIC_CONTENT_MANIFEST`), the JSON
+    /// object of asset path to hashed KV key that wrangler publishes with a site.
+    /// Assets already uploaded by wrangler are served from their existing KV keys, so a
+    /// site can move to this crate without re-uploading. The manifest has no sizes or
+    /// modification times: they are zero, and responses use the length of the value
+    fn from_sites_manifest(json: &str) -> Result<Self, Error>
+    where
+        Self: Sized;
+
+    /// Added, removed, and modified entries between two indexes
+    /// (`AssetIndex::diff(&old, &new)`), for incremental deploys, change logs,
+    /// and invalidating cached copies of only the changed paths
+    fn diff(old: &Self, new: &Self) -> crate::IndexDiff;
+
+    /// Mark the asset at path as removed at removed_at (UTC seconds since EPOCH),
+    /// instead of deleting its entry: the tombstone isn't served, and its KV value is
+    /// kept so the asset can be restored. Returns false if path isn't a live asset
+    fn tombstone(&mut self, path: &str, removed_at: u64) -> bool;
+
+    /// Restore a tombstoned asset. Returns false if path isn't a tombstone
+    fn restore(&mut self, path: &str) -> bool;
+
+    /// Add tombstones for assets in the previous index that aren't in this one,
+    /// and carry over the previous tombstones. Returns the paths newly removed
+    fn keep_removed(&mut self, previous: &Self, removed_at: u64) -> Vec<String>;
+
+    /// Paths of tombstones removed before cutoff (UTC seconds since EPOCH), sorted
+    fn expired_tombstones(&self, cutoff: u64) -> Vec<String>;
+}
+
+impl AssetIndexExt for AssetIndex {
+    fn stats(&self) -> crate::IndexStats {
+        crate::IndexStats::from_index(self)
+    }
+
+    fn oversized(&self, limit: u64) -> Vec<crate::OversizedAsset> {
+        crate::stats::oversized(self, limit)
+    }
+
+    fn to_json(&self) -> Result<String, Error> {
+        // sorted by path, so output is stable for diffs
+        let sorted: BTreeMap<&String, &AssetMetadata> = self.iter().collect();
+        serde_json::to_string_pretty(&sorted)
+            .map_err(|e| Error::Message(format!("asset index json: {}", e)))
+    }
+
+    fn from_json(json: &str) -> Result<Self, Error> {
+        serde_json::from_str(json).map_err(|e| Error::Message(format!("asset index json: {}", e)))
+    }
+
+    fn to_js_manifest(&self) -> Result<String, Error> {
+        crate::js_manifest::to_js_manifest(self)
+    }
+
+    fn to_ts_module(&self) -> Result<String, Error> {
+        crate::js_manifest::to_ts_module(self)
+    }
+
+    fn from_sites_manifest(json: &str) -> Result<Self, Error> {
+        let manifest: HashMap<String, String> = serde_json::from_str(json)
+            .map_err(|e| Error::Message(format!("sites manifest json: {}", e)))?;
+        Ok(manifest
+            .into_iter()
+            .map(|(path, key)| {
+                let md = AssetMetadata {
+                    path: key,
+                    ..Default::default()
+                };
+                (path, md)
+            })
+            .collect())
+    }
+
+    fn diff(old: &Self, new: &Self) -> crate::IndexDiff {
+        crate::IndexDiff::new(old, new)
+    }
+
+    fn tombstone(&mut self, path: &str, removed_at: u64) -> bool {
+        crate::tombstone::tombstone(self, path, removed_at)
+    }
+
+    fn restore(&mut self, path: &str) -> bool {
+        crate::tombstone::restore(self, path)
+    }
+
+    fn keep_removed(&mut self, previous: &Self, removed_at: u64) -> Vec<String> {
+        crate::tombstone::keep_removed(self, previous, removed_at)
+    }
+
+    fn expired_tombstones(&self, cutoff: u64) -> Vec<String> {
+        crate::tombstone::expired(self, cutoff)
+    }
+}
+
+/// Converts a serialized index (from `encode_index`) to JSON
+pub fn index_to_json(bytes: &[u8]) -> Result<String, Error> {
+    decode_index(bytes)?.to_json()
+}
+
+/// Converts a JSON index to the serialized form embedded in the worker
+pub fn index_from_json(json: &str) -> Result<Vec<u8>, Error> {
+    encode_index(&AssetIndex::from_json(json)?)
+}
+
+/// Converts a Workers Sites asset manifest (see `AssetIndexExt::from_sites_manifest`)
+/// to the serialized form embedded in the worker
+pub fn index_from_sites_manifest(json: &str) -> Result<Vec<u8>, Error> {
+    encode_index(&AssetIndex::from_sites_manifest(json)?)
+}
+
+/// Serializes the asset index, with a format header, for embedding in the worker
+pub fn encode_index(index: &AssetIndex) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::with_capacity(INDEX_MAGIC.len() + 1);
+    bytes.extend_from_slice(INDEX_MAGIC);
+    bytes.push(INDEX_VERSION);
+    bincode::serialize_into(&mut bytes, index)
+        .map_err(|e| Error::Message(format!("serializing asset index: {}", e)))?;
+    Ok(bytes)
+}
+
+/// Limits checked while decoding an index, so a corrupted or malicious index fails
+/// fast with `Error::IndexCorrupt` instead of exhausting worker memory.
+/// Nothing in the index can allocate more than the size of the serialized index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeLimits {
+    max_entries: usize,
+    max_path_bytes: usize,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            max_entries: 1_000_000,
+            max_path_bytes: 64 << 20,
+        }
+    }
+}
+
+impl DecodeLimits {
+    /// Default limits: one million entries, and 64MiB of index paths
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maximum number of index entries
+    pub fn with_max_entries(mut self, max: usize) -> Self {
+        self.max_entries = max;
+        self
+    }
+
+    /// Maximum total length of index paths, in bytes
+    pub fn with_max_path_bytes(mut self, max: usize) -> Self {
+        self.max_path_bytes = max;
+        self
+    }
+}
+
+/// Deserializes an index created by `encode_index`, or by kv-assets 0.2, with the
+/// default `DecodeLimits`. Zero bytes (the builder's default) is an empty index.
+pub fn decode_index(bytes: &[u8]) -> Result<AssetIndex, Error> {
+    decode_index_with_limits(bytes, &DecodeLimits::default())
+}
+
+/// Deserializes an index as `decode_index` does, with limits. Returns
+/// `Error::IndexCorrupt`, with the offset where decoding stopped, if the index
+/// is truncated, malformed, or exceeds the limits
+pub fn decode_index_with_limits(bytes: &[u8], limits: &DecodeLimits) -> Result<AssetIndex, Error> {
+    if bytes.is_empty() {
+        return Ok(AssetIndex::new());
+    }
+    let header = INDEX_MAGIC.len() + 1;
+    match bytes.strip_prefix(INDEX_MAGIC) {
+        Some([INDEX_VERSION, rest @ ..]) => decode_limited(rest, header, limits),
+        Some([INDEX_VERSION_3, rest @ ..]) => {
+            decode_legacy::<AssetMetadataV3>(rest, header, limits)
+        }
+        Some([INDEX_VERSION_2, rest @ ..]) => {
+            decode_legacy::<AssetMetadataV2>(rest, header, limits)
+        }
+        Some([INDEX_VERSION_1, rest @ ..]) => {
+            decode_legacy::<AssetMetadataV1>(rest, header, limits)
+        }
+        Some(_) => Err(Error::Message(
+            "Unsupported asset index version. Upgrade kv-assets".into(),
+        )),
+        None => decode_legacy::<AssetMetadataV0>(bytes, 0, limits),
+    }
+}
+
+// Deserialize index written in an older format, and convert entries to the current format
+fn decode_legacy<T: DeserializeOwned + Into<AssetMetadata>>(
+    bytes: &[u8],
+    offset: usize,
+    limits: &DecodeLimits,
+) -> Result<AssetIndex, Error> {
+    let legacy: HashMap<String, T> = decode_limited(bytes, offset, limits)?;
+    Ok(legacy.into_iter().map(|(k, md)| (k, md.into())).collect())
+}
+
+// Deserialize the bincode map at offset in the index, within limits. Same encoding as
+// `bincode::deserialize`, with allocations bounded by the length of bytes
+fn decode_limited<T: DeserializeOwned>(
+    bytes: &[u8],
+    offset: usize,
+    limits: &DecodeLimits,
+) -> Result<HashMap<String, T>, Error> {
+    let mut reader = CountingReader { bytes, read: 0 };
+    bincode::options()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(bytes.len() as u64)
+        .deserialize_from_seed(
+            LimitedMap {
+                limits,
+                _entry: PhantomData,
+            },
+            &mut reader,
+        )
+        .map_err(|e| Error::IndexCorrupt {
+            offset: offset + reader.read,
+            reason: e.to_string(),
+        })
+}
+
+// Reader that records how much of the index has been read, for error offsets
+struct CountingReader<'b> {
+    bytes: &'b [u8],
+    read: usize,
+}
+
+impl std::io::Read for CountingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = std::io::Read::read(&mut &self.bytes[self.read..], buf)?;
+        self.read += n;
+        Ok(n)
+    }
+}
+
+// Map of index path to entry, checking limits as entries are decoded
+struct LimitedMap<'l, T> {
+    limits: &'l DecodeLimits,
+    _entry: PhantomData<T>,
+}
+
+impl<'de, T: Deserialize<'de>> de::DeserializeSeed<'de> for LimitedMap<'_, T> {
+    type Value = HashMap<String, T>;
+
+    fn deserialize<D: de::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> de::Visitor<'de> for LimitedMap<'_, T> {
+    type Value = HashMap<String, T>;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("asset index")
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+        let len = access.size_hint().unwrap_or_default();
+        if len > self.limits.max_entries {
+            return Err(de::Error::custom(format!(
+                "{} entries exceeds limit of {}",
+                len, self.limits.max_entries
+            )));
+        }
+        let mut map = HashMap::with_capacity(len.min(4096));
+        let mut path_bytes = 0;
+        while let Some(path) = access.next_key::<String>()? {
+            path_bytes += path.len();
+            if path_bytes > self.limits.max_path_bytes {
+                return Err(de::Error::custom(format!(
+                    "index paths exceed limit of {} bytes",
+                    self.limits.max_path_bytes
+                )));
+            }
+            let entry = access.next_value()?;
+            map.insert(path, entry);
+        }
+        Ok(map)
+    }
+}
+
+/// Asset metadata in index format version 3, without deleted time
+#[derive(Deserialize)]
+struct AssetMetadataV3 {
+    path: String,
+    modified: u64,
+    size: u64,
+    hash: Option<String>,
+    alias: Option<AliasTarget>,
+    headers: BTreeMap<String, String>,
+    extra: BTreeMap<String, String>,
+}
+
+impl From<AssetMetadataV3> for AssetMetadata {
+    fn from(md: AssetMetadataV3) -> Self {
+        AssetMetadata {
+            path: md.path,
+            modified: md.modified,
+            size: md.size,
+            hash: md.hash,
+            alias: md.alias,
+            headers: md.headers,
+            extra: md.extra,
+            deleted: None,
+        }
+    }
+}
+
+/// Asset metadata in index format version 2, without headers or extra fields
+#[derive(Deserialize)]
+struct AssetMetadataV2 {
+    path: String,
+    modified: u64,
+    size: u64,
+    hash: Option<String>,
+    alias: Option<AliasTarget>,
+}
+
+impl From<AssetMetadataV2> for AssetMetadata {
+    fn from(md: AssetMetadataV2) -> Self {
+        AssetMetadataV3 {
+            path: md.path,
+            modified: md.modified,
+            size: md.size,
+            hash: md.hash,
+            alias: md.alias,
+            headers: BTreeMap::new(),
+            extra: BTreeMap::new(),
+        }
+        .into()
+    }
+}
+
+/// Asset metadata in index format version 1, without alias
+#[derive(Deserialize)]
+struct AssetMetadataV1 {
+    path: String,
+    modified: u64,
+    size: u64,
+    hash: Option<String>,
+}
+
+impl From<AssetMetadataV1> for AssetMetadata {
+    fn from(md: AssetMetadataV1) -> Self {
+        AssetMetadataV2 {
+            path: md.path,
+            modified: md.modified,
+            size: md.size,
+            hash: md.hash,
+            alias: None,
+        }
+        .into()
+    }
+}
+
+/// Asset metadata written by kv-assets 0.2, without a content hash
+#[derive(Deserialize)]
+struct AssetMetadataV0 {
+    path: String,
+    modified: u64,
+    size: u64,
+}
+
+impl From<AssetMetadataV0> for AssetMetadata {
+    fn from(md: AssetMetadataV0) -> Self {
+        AssetMetadataV1 {
+            path: md.path,
+            modified: md.modified,
+            size: md.size,
+            hash: None,
+        }
+        .into()
+    }
+}
+
+/// Tests JSON export and import
+#[test]
+fn test_index_json() {
+    let mut index = AssetIndex::new();
+    index.insert(
+        "a.txt".to_string(),
+        AssetMetadata {
+            path: "a.1.txt".to_string(),
+            modified: 10000,
+            size: 10,
+            hash: Some("abc".to_string()),
+            ..Default::default()
+        },
+    );
+    index.insert("b.txt".to_string(), AssetMetadata::alias("a.txt"));
+    let json = index.to_json().unwrap();
+    assert_eq!(AssetIndex::from_json(&json).unwrap(), index);
+    let bytes = index_from_json(&json).unwrap();
+    assert_eq!(index_to_json(&bytes).unwrap(), json);
+
+    // optional fields omitted
+    let generated =
+        AssetIndex::from_json(r#"{"c.txt": {"path": "c.2.txt", "modified": 1, "size": 2}}"#)
+            .unwrap();
+    assert_eq!(generated["c.txt"].hash, None);
+    assert!(AssetIndex::from_json(r#"{"c.txt": {"path": "c.2.txt"}}"#).is_err());
+}
+
+/// Tests importing a Workers Sites manifest
+#[test]
+fn test_sites_manifest() {
+    let manifest =
+        r#"{"index.html":"index.5eb63bbbe0.html","css/site.css":"css/site.d41d8cd98f.css"}"#;
+    let index = AssetIndex::from_sites_manifest(manifest).unwrap();
+    assert_eq!(index.len(), 2);
+    assert_eq!(index["css/site.css"].path, "css/site.d41d8cd98f.css");
+    assert_eq!(index["index.html"].size, 0);
+    let bytes = index_from_sites_manifest(manifest).unwrap();
+    assert_eq!(decode_index(&bytes).unwrap(), index);
+    assert!(AssetIndex::from_sites_manifest(r#"{"a": 1}"#).is_err());
+}
+
+/// Tests current and legacy index formats
+#[test]
+fn test_decode_index() {
+    let md = AssetMetadata {
+        path: "a.0123456789.txt".to_string(),
+        modified: 10000,
+        size: 10,
+        hash: Some("abc".to_string()),
+        ..Default::default()
+    };
+    let mut index = AssetIndex::new();
+    index.insert("a.txt".to_string(), md.clone());
+    let bytes = encode_index(&index).unwrap();
+    assert_eq!(decode_index(&bytes).unwrap(), index);
+
+    // index without header, as written by kv-assets 0.2
+    let mut legacy = HashMap::new();
+    legacy.insert("a.txt", (&md.path, md.modified, md.size));
+    let legacy_bytes = bincode::serialize(&legacy).unwrap();
+    let decoded = decode_index(&legacy_bytes).unwrap();
+    assert_eq!(decoded["a.txt"].path, md.path);
+    assert_eq!(decoded["a.txt"].hash, None);
+
+    // version 3, without deleted time
+    let mut v3 = HashMap::new();
+    v3.insert(
+        "a.txt",
+        (
+            &md.path,
+            md.modified,
+            md.size,
+            &md.hash,
+            &md.alias,
+            &md.headers,
+            &md.extra,
+        ),
+    );
+    let mut v3_bytes = INDEX_MAGIC.to_vec();
+    v3_bytes.push(INDEX_VERSION_3);
+    v3_bytes.extend(bincode::serialize(&v3).unwrap());
+    assert_eq!(decode_index(&v3_bytes).unwrap(), index);
+
+    // version 2, without headers or extra fields
+    let mut v2 = HashMap::new();
+    v2.insert(
+        "a.txt",
+        (&md.path, md.modified, md.size, &md.hash, &md.alias),
+    );
+    let mut v2_bytes = INDEX_MAGIC.to_vec();
+    v2_bytes.push(INDEX_VERSION_2);
+    v2_bytes.extend(bincode::serialize(&v2).unwrap());
+    assert_eq!(decode_index(&v2_bytes).unwrap(), index);
+
+    // version 1, without alias
+    let mut v1 = HashMap::new();
+    v1.insert("a.txt", (&md.path, md.modified, md.size, &md.hash));
+    let mut v1_bytes = INDEX_MAGIC.to_vec();
+    v1_bytes.push(INDEX_VERSION_1);
+    v1_bytes.extend(bincode::serialize(&v1).unwrap());
+    assert_eq!(decode_index(&v1_bytes).unwrap(), index);
+
+    assert!(decode_index(&[]).unwrap().is_empty());
+
+    let mut future = bytes.clone();
+    future[INDEX_MAGIC.len()] = INDEX_VERSION + 1;
+    assert!(decode_index(&future).is_err());
+}
+
+/// Tests decoding corrupt indexes and indexes exceeding limits
+#[test]
+fn test_decode_limits() {
+    let mut index = AssetIndex::new();
+    for path in ["a.txt", "b.txt"] {
+        index.insert(path.to_string(), AssetMetadata::default());
+    }
+    let bytes = encode_index(&index).unwrap();
+    let limits = DecodeLimits::new();
+    assert_eq!(decode_index_with_limits(&bytes, &limits).unwrap(), index);
+
+    let corrupt_at = |result: Result<AssetIndex, Error>| match result {
+        Err(Error::IndexCorrupt { offset, .. }) => offset,
+        other => panic!("expected IndexCorrupt, got {:?}", other),
+    };
+    let too_few = limits.with_max_entries(1);
+    assert_eq!(corrupt_at(decode_index_with_limits(&bytes, &too_few)), 13);
+    let too_short = limits.with_max_path_bytes(9);
+    assert!(corrupt_at(decode_index_with_limits(&bytes, &too_short)) > 13);
+
+    // entry count and string length far beyond the data don't allocate
+    let mut huge = INDEX_MAGIC.to_vec();
+    huge.push(INDEX_VERSION);
+    huge.extend(1_000u64.to_le_bytes());
+    huge.extend(u64::MAX.to_le_bytes());
+    // stops after the path length
+    assert_eq!(corrupt_at(decode_index(&huge)), 21);
+    let truncated = &bytes[..bytes.len() - 3];
+    assert!(corrupt_at(decode_index(truncated)) <= truncated.len());
+}
@@ -0,0 +1,126 @@
+use crate::{AliasTarget, AssetIndex, AssetMetadata, Error};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+// Declarations at the top of the module generated by `to_ts_module`
+const TS_HEADER: &str = "// Generated by kv-assets. Do not edit.
+export interface AssetEntry {
+  key?: string;
+  size?: number;
+  modified?: number;
+  hash?: string;
+  contentType?: string;
+  headers?: Record<string, string>;
+  alias?: string;
+  redirect?: string;
+}
+";
+
+// Manifest entry, with camelCase names for javascript
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsEntry<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    modified: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hash: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    headers: &'a BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alias: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    redirect: Option<&'a str>,
+}
+
+impl<'a> JsEntry<'a> {
+    fn new(path: &str, md: &'a AssetMetadata) -> Self {
+        let mut entry = JsEntry {
+            key: None,
+            size: None,
+            modified: None,
+            hash: None,
+            content_type: None,
+            headers: &md.headers,
+            alias: None,
+            redirect: None,
+        };
+        match &md.alias {
+            Some(AliasTarget::Path(target)) => entry.alias = Some(target),
+            Some(AliasTarget::Redirect(url)) => entry.redirect = Some(url),
+            None => {
+                entry.key = Some(&md.path);
+                entry.size = Some(md.size);
+                entry.modified = Some(md.modified);
+                entry.hash = md.hash.as_deref();
+                entry.content_type = Some(
+                    mime_guess::from_path(path)
+                        .first_or_octet_stream()
+                        .to_string(),
+                );
+            }
+        }
+        entry
+    }
+}
+
+/// JSON object of asset path to entry, sorted by path
+pub(crate) fn to_js_manifest(index: &AssetIndex) -> Result<String, Error> {
+    let sorted: BTreeMap<&str, JsEntry> = index
+        .iter()
+        .filter(|(_, md)| !md.is_deleted())
+        .map(|(path, md)| (path.as_str(), JsEntry::new(path, md)))
+        .collect();
+    serde_json::to_string_pretty(&sorted)
+        .map_err(|e| Error::Message(format!("js manifest json: {}", e)))
+}
+
+/// TypeScript module exporting the manifest
+pub(crate) fn to_ts_module(index: &AssetIndex) -> Result<String, Error> {
+    Ok(format!(
+        "{}\nexport const manifest: Record<string, AssetEntry> = {};\n\nexport default manifest;\n",
+        TS_HEADER,
+        to_js_manifest(index)?
+    ))
+}
+
+/// Tests JSON and TypeScript manifests
+#[test]
+fn test_js_manifest() {
+    use crate::AssetIndexExt;
+
+    let mut index = AssetIndex::new();
+    index.insert(
+        "css/site.css".to_string(),
+        AssetMetadata {
+            path: "css/site.0123456789.css".to_string(),
+            modified: 10000,
+            size: 5,
+            hash: Some("abc".to_string()),
+            ..Default::default()
+        },
+    );
+    index.insert("old.css".to_string(), AssetMetadata::alias("css/site.css"));
+    let json: serde_json::Value = serde_json::from_str(&index.to_js_manifest().unwrap()).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "css/site.css": {
+                "key": "css/site.0123456789.css",
+                "size": 5,
+                "modified": 10000,
+                "hash": "abc",
+                "contentType": "text/css",
+            },
+            "old.css": { "alias": "css/site.css" },
+        })
+    );
+    let module = index.to_ts_module().unwrap();
+    assert!(module.contains("export const manifest: Record<string, AssetEntry> = {\n"));
+    assert!(module.ends_with("export default manifest;\n"));
+}
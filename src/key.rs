@@ -0,0 +1,115 @@
+use crate::Error;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Deref;
+
+/// Maximum length of a KV key, in bytes
+pub const MAX_KEY_LENGTH: usize = 512;
+
+/// KV key (or asset path), validated against Cloudflare's constraints:
+/// not empty, at most 512 bytes, and not "." or "..".
+/// Methods taking `&str` keys validate them the same way, so an invalid key fails
+/// with `Error::InvalidKey` before any request, rather than with a confusing api error.
+/// Keys are percent-encoded in api urls, so characters such as '/', '?', and '#'
+/// address the intended key. `AssetKey` dereferences to `str`, so it can be passed
+/// wherever a key is expected.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct AssetKey(String);
+
+impl AssetKey {
+    /// Validate the key
+    pub fn new(key: impl Into<String>) -> Result<Self, Error> {
+        let key = key.into();
+        if key.is_empty() {
+            return Err(Error::EmptyKey);
+        }
+        if key.len() > MAX_KEY_LENGTH {
+            return Err(Error::InvalidKey(format!(
+                "key is {} bytes, the limit is {}",
+                key.len(),
+                MAX_KEY_LENGTH
+            )));
+        }
+        if key == "." || key == ".." {
+            return Err(Error::InvalidKey(format!("key can't be '{}'", key)));
+        }
+        Ok(Self(key))
+    }
+
+    /// The key
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The key, percent-encoded for use in an api url path
+    pub(crate) fn url_encoded(&self) -> String {
+        let mut encoded = String::with_capacity(self.0.len());
+        for b in self.0.bytes() {
+            match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    encoded.push(b as char)
+                }
+                _ => encoded.push_str(&format!("%{:02X}", b)),
+            }
+        }
+        encoded
+    }
+}
+
+impl TryFrom<String> for AssetKey {
+    type Error = Error;
+    fn try_from(key: String) -> Result<Self, Error> {
+        Self::new(key)
+    }
+}
+
+impl TryFrom<&str> for AssetKey {
+    type Error = Error;
+    fn try_from(key: &str) -> Result<Self, Error> {
+        Self::new(key)
+    }
+}
+
+impl From<AssetKey> for String {
+    fn from(key: AssetKey) -> String {
+        key.0
+    }
+}
+
+impl Deref for AssetKey {
+    type Target = str;
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for AssetKey {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for AssetKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Tests key validation and encoding
+#[test]
+fn test_asset_key() {
+    assert!(matches!(AssetKey::new(""), Err(Error::EmptyKey)));
+    assert!(matches!(AssetKey::new(".."), Err(Error::InvalidKey(_))));
+    assert!(matches!(
+        AssetKey::new("a".repeat(513)),
+        Err(Error::InvalidKey(_))
+    ));
+    assert!(AssetKey::new("a".repeat(512)).is_ok());
+
+    let key = AssetKey::try_from("css/a b?.css").unwrap();
+    assert_eq!(key.url_encoded(), "css%2Fa%20b%3F.css");
+    assert_eq!(&*key, "css/a b?.css");
+    assert!(serde_json::from_str::<AssetKey>(r#"".""#).is_err());
+}
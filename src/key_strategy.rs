@@ -0,0 +1,163 @@
+use crate::{AssetIndex, KVAssets, MAX_KEY_LENGTH};
+use sha2::{Digest, Sha256};
+
+/// Maps an asset's index path and content hash to its KV key. Install with
+/// `KVAssets::with_key_strategy`: the handler uses it to name the values it
+/// publishes (`publish_dir`, `stage_dir`, `AssetWatcher`), and to derive the keys of
+/// index entries that have none (an empty `AssetMetadata::path`), so a publisher and
+/// a worker configured with the same strategy always agree on keys.
+///
+/// Keys longer than `MAX_KEY_LENGTH` are shortened (see `shorten_key`), whatever the strategy.
+pub trait KeyStrategy: Send + Sync {
+    /// KV key of the asset at path (relative, with '/' separators), whose content
+    /// has hash (created by `HashAlgorithm::hash`)
+    fn key(&self, path: &str, hash: &str) -> String;
+}
+
+/// The index path is the KV key, so each deploy overwrites the previous values
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IdentityKeys;
+
+impl KeyStrategy for IdentityKeys {
+    fn key(&self, path: &str, _hash: &str) -> String {
+        path.to_string()
+    }
+}
+
+/// Keys include a hash of the content before the extension, as wrangler does
+/// (`css/site.css` -> `css/site.2cf24dba5f.css`), so new versions don't overwrite
+/// values used by a deployed worker. The default strategy
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ContentHashKeys;
+
+impl KeyStrategy for ContentHashKeys {
+    fn key(&self, path: &str, hash: &str) -> String {
+        crate::checksum::versioned_key(path, hash)
+    }
+}
+
+/// Keys are a prefix and the content hash (`assets/2cf24dba5f…`), so identical
+/// files share a value, and values of one site can be listed by prefix
+#[derive(Debug, Clone, Default)]
+pub struct PrefixedHashKeys {
+    prefix: String,
+}
+
+impl PrefixedHashKeys {
+    /// Keys begin with prefix, such as "assets/"
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+        }
+    }
+}
+
+impl KeyStrategy for PrefixedHashKeys {
+    fn key(&self, _path: &str, hash: &str) -> String {
+        let hash = hash.rsplit(':').next().unwrap_or(hash);
+        format!("{}{}", self.prefix, hash)
+    }
+}
+
+// Keys of another strategy under "{version}/", for versions staged side by side
+// (see `KVAssets::stage_version`). The prefix is part of the key that is shortened
+pub(crate) struct VersionedKeys<'k> {
+    pub(crate) version: &'k str,
+    pub(crate) keys: &'k dyn KeyStrategy,
+}
+
+impl KeyStrategy for VersionedKeys<'_> {
+    fn key(&self, path: &str, hash: &str) -> String {
+        format!("{}/{}", self.version, self.keys.key(path, hash))
+    }
+}
+
+/// Key shortened to fit `MAX_KEY_LENGTH`: keys that are too long are truncated, and
+/// end with '~' and the SHA-256 digest of the whole key, so distinct keys stay distinct
+pub fn shorten_key(key: String) -> String {
+    if key.len() <= MAX_KEY_LENGTH {
+        return key;
+    }
+    let digest = format!("{:x}", Sha256::digest(key.as_bytes()));
+    let mut end = MAX_KEY_LENGTH - digest.len() - 1;
+    while !key.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}~{}", &key[..end], digest)
+}
+
+// KV key of an asset with the strategy, shortened to fit
+pub(crate) fn key_with(strategy: &dyn KeyStrategy, path: &str, hash: &str) -> String {
+    shorten_key(strategy.key(path, hash))
+}
+
+impl<'ah> KVAssets<'ah> {
+    /// KV key of the asset at index path with content hash, from the handler's
+    /// key strategy (see `with_key_strategy`)
+    pub fn kv_key(&self, path: &str, hash: &str) -> String {
+        key_with(self.key_strategy(), path, hash)
+    }
+
+    // Derive the keys of index entries without one, from their path and hash
+    pub(crate) fn fill_keys(&self, index: &mut AssetIndex) {
+        for (path, md) in index.iter_mut() {
+            if md.path.is_empty() && md.alias.is_none() {
+                if let Some(hash) = &md.hash {
+                    md.path = self.kv_key(path, hash);
+                }
+            }
+        }
+    }
+}
+
+/// Tests key strategies and shortening of long keys
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn test_key_strategy() {
+    use crate::{AssetMetadata, HashAlgorithm};
+    use std::sync::Arc;
+
+    let hash = HashAlgorithm::Sha256.hash(b"hello");
+    assert_eq!(IdentityKeys.key("css/site.css", &hash), "css/site.css");
+    assert_eq!(
+        ContentHashKeys.key("css/site.css", &hash),
+        "css/site.2cf24dba5f.css"
+    );
+    assert_eq!(
+        PrefixedHashKeys::new("assets/").key("css/site.css", "blake3:abc"),
+        "assets/abc"
+    );
+
+    let long = "é".repeat(300);
+    let short = shorten_key(long.clone());
+    assert!(short.len() <= MAX_KEY_LENGTH);
+    assert!(short.starts_with("é") && short.contains('~'));
+    assert_ne!(short, shorten_key(format!("{}a", long)));
+    assert_eq!(shorten_key("a.txt".to_string()), "a.txt");
+
+    // publisher and reader derive the same keys
+    let dir = std::env::temp_dir().join(format!("kv-assets-keys-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("index.html"), "hello").unwrap();
+    let strategy = Arc::new(PrefixedHashKeys::new("site/"));
+    let publisher = KVAssets::init(&[], "acct", "ns", "token")
+        .with_hash_algorithm(HashAlgorithm::Sha256)
+        .with_key_strategy(strategy.clone());
+    let index = crate::scan::index_dir_keyed(&dir, None, HashAlgorithm::Sha256, strategy.as_ref());
+    std::fs::remove_dir_all(&dir).unwrap();
+    let key = index.unwrap()["index.html"].path.clone();
+    assert_eq!(key, format!("site/{}", hash));
+    assert_eq!(publisher.kv_key("index.html", &hash), key);
+
+    let mut index = AssetIndex::new();
+    index.insert(
+        "index.html".to_string(),
+        AssetMetadata {
+            hash: Some(hash),
+            ..Default::default()
+        },
+    );
+    let bytes = crate::encode_index(&index).unwrap();
+    let reader = KVAssets::init(&bytes, "acct", "ns", "token").with_key_strategy(strategy);
+    assert_eq!(reader.lookup_key("/index.html").unwrap().unwrap().path, key);
+}
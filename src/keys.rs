@@ -0,0 +1,120 @@
+use crate::{Error, KVAssets};
+use bytes::Bytes;
+use futures_util::stream::{self, Stream, TryStreamExt};
+use http::Method;
+use serde::Deserialize;
+
+/// A key in the KV namespace, as returned by `KVAssets::list_all_keys`
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct KeyInfo {
+    /// Key name
+    pub name: String,
+    /// Expiration time (seconds since epoch), if the key expires
+    #[serde(default)]
+    pub expiration: Option<u64>,
+    /// Metadata stored with the key, if any
+    #[serde(default)]
+    pub metadata: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct ListKeysResponse {
+    result: Vec<KeyInfo>,
+    result_info: Option<ResultInfo>,
+}
+
+#[derive(Deserialize)]
+struct ResultInfo {
+    cursor: Option<String>,
+}
+
+// Listing state: cursor of the next page, or Done after the last page
+enum Page {
+    First,
+    Next(String),
+    Done,
+}
+
+impl<'ah> KVAssets<'ah> {
+    /// Stream of all keys in the KV namespace, with their expiration and metadata.
+    /// Pages of up to 1000 keys are requested as the stream is consumed, so audit and
+    /// gc tools can process large namespaces without holding every key in memory.
+    /// The stream ends after the first error.
+    pub fn list_all_keys(&self) -> impl Stream<Item = Result<KeyInfo, Error>> + '_ {
+        stream::try_unfold(Page::First, move |page| async move {
+            let cursor = match page {
+                Page::First => None,
+                Page::Next(cursor) => Some(cursor),
+                Page::Done => return Ok::<_, Error>(None),
+            };
+            let (keys, cursor) = self.list_page(cursor.as_deref()).await?;
+            let next = match cursor {
+                Some(cursor) => Page::Next(cursor),
+                None => Page::Done,
+            };
+            Ok(Some((stream::iter(keys.into_iter().map(Ok)), next)))
+        })
+        .try_flatten()
+    }
+
+    /// Lists one page of keys, returning the keys and the cursor of the next page, if any
+    pub(crate) async fn list_page(
+        &self,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<KeyInfo>, Option<String>), Error> {
+        let url = self.namespace_url(&format!(
+            "keys?limit=1000{}",
+            match cursor {
+                Some(cursor) => format!("&cursor={}", cursor),
+                None => String::from(""),
+            }
+        ))?;
+        self.rate_limit().await;
+        let response = self
+            .send(self.request(Method::GET, &url, Bytes::new()))
+            .await?;
+        if !response.status().is_success() {
+            return Err(Error::Message(format!(
+                "listing keys: status={}",
+                response.status().as_u16()
+            )));
+        }
+        let page: ListKeysResponse = crate::assets::parse_json(response.body())?;
+        let cursor = page
+            .result_info
+            .and_then(|info| info.cursor)
+            .filter(|c| !c.is_empty());
+        Ok((page.result, cursor))
+    }
+}
+
+/// Tests lazy paging of keys, against the mock api
+#[cfg(all(test, feature = "reqwest"))]
+#[tokio::test]
+async fn test_list_all_keys() {
+    use crate::KvPutOptions;
+    use futures_util::StreamExt;
+
+    let mock = crate::testspace::MockCloudflare::start().unwrap();
+    let kv = mock.handler(&[], "ns");
+    let metadata = KvPutOptions::new().with_metadata(serde_json::json!({ "hash": "aa" }));
+    kv.put("a", Bytes::from("a"), &metadata).await.unwrap();
+    let expiring = KvPutOptions::new().with_expiration(4_000_000_000);
+    kv.put("b", Bytes::from("b"), &expiring).await.unwrap();
+    // a second page
+    for n in 0..1500 {
+        mock.insert("ns", &format!("c{:04}", n), "c");
+    }
+
+    let requests = mock.request_count();
+    let mut keys = Box::pin(kv.list_all_keys());
+    let first = keys.next().await.unwrap().unwrap();
+    assert_eq!(first.metadata.unwrap()["hash"], "aa");
+    assert_eq!(mock.request_count(), requests + 1);
+    let rest: Vec<KeyInfo> = keys.try_collect().await.unwrap();
+    assert_eq!(rest[0].expiration, Some(4_000_000_000));
+    assert_eq!(rest.len(), 1501);
+    assert_eq!(rest[1500].name, "c1499");
+    assert_eq!(mock.request_count(), requests + 2);
+    assert_eq!(kv.list_keys().await.unwrap().len(), 1502);
+}
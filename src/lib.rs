@@ -1,27 +1,240 @@
+mod access;
+#[cfg(feature = "actix")]
+pub mod actix;
 mod assets;
+#[cfg(feature = "axum")]
+pub mod axum;
+mod blocking;
+mod bluegreen;
+mod breaker;
+mod builder;
+mod bulk;
+mod cache;
+mod cancel;
+mod checkpoint;
+mod checksum;
+mod chunked;
+mod client;
+mod compress;
+mod copy;
+mod crypto;
+mod diff;
+mod edge_cache;
+mod embedded;
+mod error_kind;
+mod fallback;
+mod handle;
+mod i18n;
+mod image;
+mod index;
+mod js_manifest;
+mod key;
+mod key_strategy;
+mod keys;
+mod listing;
+mod local;
+mod metadata;
+mod metrics;
+mod mirror;
+mod mount;
+mod namespaces;
+mod observer;
+mod origin;
+mod outcome;
+mod overlay;
+mod path_mapping;
+mod preload;
+mod profiles;
+mod purge;
+mod put_options;
+mod ratelimit;
+mod refresh;
+mod report;
+mod rollback;
+mod scan;
+mod security;
+mod serve;
+mod service;
+mod signed_url;
+mod signing;
+mod sniff;
+mod source;
+mod sri;
+mod stage;
+mod stats;
+#[cfg(all(any(test, feature = "testspace"), feature = "reqwest"))]
+pub mod testspace;
+mod timing;
+mod token;
+mod tombstone;
+mod transform;
+mod ttl;
 mod upload;
+mod usage;
+mod value_info;
+mod verify;
+mod warm;
+#[cfg(feature = "warp")]
+pub mod warp;
+mod watch;
+#[cfg(feature = "wrangler-config")]
+pub mod wrangler_config;
 
-pub use assets::{AssetIndex, AssetMetadata, KVAssets};
+pub use access::{AccessFn, AccessRules, ACCESS_KEY};
+pub use assets::{
+    AliasTarget, AssetIndex, AssetMetadata, KVAssets, NamespaceTarget, ResolvedAsset,
+    MAX_VALUE_SIZE,
+};
+pub use bluegreen::{ACTIVE_VERSION_KEY, DEFAULT_ACTIVE_VERSION_TTL};
+pub use breaker::{BreakerState, CircuitBreaker};
+pub use builder::{
+    KVAssetsBuilder, ENV_ACCOUNT_ID, ENV_API_TOKEN, ENV_NAMESPACE_ID, ENV_PREVIEW_NAMESPACE_ID,
+};
+pub use bulk::BULK_KEY_MAX;
+pub use cache::{CacheOptions, CachePriority, ValueCache, CACHE_PRIORITY_KEY, DEFAULT_CACHE_TTL};
+pub use cancel::CancelToken;
+pub use checksum::HashAlgorithm;
+pub use client::{HttpClient, HttpFuture};
+pub use diff::IndexDiff;
+pub use edge_cache::{EdgeCache, DEFAULT_EDGE_CACHE_TTL};
+pub use embedded::EmbeddedAssets;
+pub use error_kind::ErrorKind;
+pub use fallback::{FallbackChain, FallbackHit, ServedBy, SOURCE_KEY};
+pub use handle::NamespaceHandle;
+pub use i18n::LocalizedAsset;
+pub use image::NegotiatedImage;
+pub use index::{
+    decode_index, decode_index_with_limits, encode_index, index_from_json,
+    index_from_sites_manifest, index_to_json, AssetIndexExt, DecodeLimits,
+};
+pub use key::{AssetKey, MAX_KEY_LENGTH};
+pub use key_strategy::{shorten_key, ContentHashKeys, IdentityKeys, KeyStrategy, PrefixedHashKeys};
+pub use keys::KeyInfo;
+pub use listing::DirEntry;
+pub use metadata::AssetMetadataBuilder;
+pub use metrics::Metrics;
+pub use mount::MountTable;
+pub use namespaces::{Namespace, NamespaceOrder, NamespacePage, NamespaceQuery};
+pub use observer::{DeployEvent, DeployObserver, UPLOAD_ATTEMPTS};
+pub use origin::OriginPull;
+pub use outcome::ServeOutcome;
+pub use overlay::WriteOverlay;
+pub use path_mapping::PathMapping;
+pub use preload::{PreloadRules, PRELOAD_KEY};
+#[cfg(feature = "profiles")]
+pub use profiles::{Profile, Profiles, DEFAULT_PROFILES_FILE, ENV_PROFILES_FILE};
+pub use purge::{CachePurge, PURGE_BATCH_SIZE};
+pub use put_options::KvPutOptions;
+pub use ratelimit::{RateLimiter, SleepFuture};
+pub use report::{DeployDurations, DeployReport};
+pub use rollback::{index_version_key, CURRENT_INDEX_KEY, INDEX_KEY_PREFIX};
+pub use security::SecurityHeaders;
+pub use source::{AssetFuture, AssetSource};
+pub use sri::{sri_hash, INTEGRITY_KEY};
+pub use stats::{ExtensionStats, IndexStats, OversizedAsset};
+pub use timing::ServeTiming;
+pub use token::TokenStatus;
+pub use ttl::TtlRules;
+pub use usage::{UsageBudget, UsageSnapshot};
+pub use value_info::ValueInfo;
+pub use verify::VerifyReport;
+
+#[cfg(all(feature = "reqwest", not(target_arch = "wasm32")))]
+pub use client::ConnectionOptions;
+#[cfg(feature = "reqwest")]
+pub use client::ReqwestClient;
+
+#[cfg(all(feature = "blocking", not(target_arch = "wasm32")))]
+pub use blocking::KVAssetsBlocking;
+
+#[cfg(feature = "compression")]
+pub use compress::{Compression, ContentEncoding};
+#[cfg(feature = "encryption")]
+pub use crypto::Encryption;
+
+#[cfg(feature = "signing")]
+pub use signed_url::UrlSigner;
+#[cfg(feature = "signing")]
+pub use signing::sign_index;
+
+#[cfg(feature = "tower")]
+pub use service::KvAssetsService;
+
+#[cfg(all(feature = "watch", not(target_arch = "wasm32")))]
+pub use watch::{AssetWatcher, WatchUpdate};
 
 // for non-wasm, export asset builders that depend on std::fs and wrangler libs
 #[cfg(not(target_arch = "wasm32"))]
-pub use upload::{sync_assets, SyncConfig};
+pub use checkpoint::{CheckpointStore, CHECKPOINT_INTERVAL, CHECKPOINT_KEY};
+#[cfg(not(target_arch = "wasm32"))]
+pub use local::LocalAssets;
+#[cfg(not(target_arch = "wasm32"))]
+pub use scan::{index_dir, index_dir_with};
+#[cfg(not(target_arch = "wasm32"))]
+pub use stage::StagedDeploy;
+#[cfg(not(target_arch = "wasm32"))]
+pub use transform::{TransformFn, Transforms};
+#[cfg(not(target_arch = "wasm32"))]
+pub use upload::{sync_assets, AnnotateFn, SyncConfig};
 
 use thiserror::Error as ThisError;
+/// Errors returned by this crate. More variants may be added in minor releases:
+/// use `Error::kind` to handle categories of errors, and `is_retryable` or
+/// `is_not_found` to decide what to do with a failed request.
 #[derive(Debug, ThisError)]
+#[non_exhaustive]
 pub enum Error {
     #[error("KV Api error {0}")]
-    KVHttp(reqwest::Error),
+    KVHttp(Box<dyn std::error::Error + Send + Sync>),
 
     #[error("KV Key {0} not found. status={1}")]
     KVKeyNotFound(String, u16),
 
+    #[error("Api error {code}: {message}")]
+    Api { code: u32, message: String },
+
     #[error("Deserializing assets:{0}")]
     DeserializeAssets(bincode::Error),
 
+    #[error("Corrupt asset index at byte {offset}: {reason}")]
+    IndexCorrupt { offset: usize, reason: String },
+
+    #[cfg(feature = "signing")]
+    #[error("Asset index signature does not match")]
+    IndexTampered,
+
+    #[cfg(feature = "signing")]
+    #[error("Invalid signed url: {0}")]
+    InvalidSignedUrl(String),
+
+    #[error("Circuit breaker open: KV api is failing")]
+    CircuitOpen,
+
+    #[error("Index out of date: KV key {key} not found")]
+    IndexOutOfDate { key: String },
+
+    #[error("Value too large for KV key {key}: {size} bytes, limit is {limit}")]
+    ValueTooLarge {
+        key: String,
+        size: usize,
+        limit: usize,
+    },
+
+    #[error("Value of KV key {key} too large to buffer: {size} bytes, limit is {limit}")]
+    TooLargeToBuffer { key: String, size: u64, limit: u64 },
+
+    #[error("Checksum mismatch for KV value {0}")]
+    ChecksumMismatch(String),
+
     #[error("Empty key passed to lookup")]
     EmptyKey,
 
+    #[error("Invalid key: {0}")]
+    InvalidKey(String),
+
+    #[error("Alias loop or chain too long at {0}")]
+    AliasLoop(String),
+
     #[cfg(not(target_arch = "wasm32"))]
     #[error("Wangler error: {0}")]
     Wrangler(String),
@@ -42,13 +255,48 @@ pub enum Error {
     #[error("Invalid asset path: not a directory: {0}")]
     InvalidAssetPath(String),
 
-    #[cfg(not(target_arch = "wasm32"))]
     #[error("Missing config file {0}")]
     MissingWranglerFile(String),
 
+    #[error("Invalid wrangler.toml: {0}")]
+    WranglerConfig(String),
+
+    #[cfg(feature = "encryption")]
+    #[error("Encryption error: {0}")]
+    Encryption(String),
+
+    #[cfg(feature = "profiles")]
+    #[error("Invalid profiles file: {0}")]
+    ProfileConfig(String),
+
+    #[cfg(feature = "profiles")]
+    #[error("Unknown profile {0}")]
+    UnknownProfile(String),
+
+    #[error("Missing configuration: {}", .0.join(", "))]
+    MissingConfig(Vec<String>),
+
+    #[error("Api token rejected: {0}")]
+    InvalidToken(String),
+
+    #[error("Assets too large: {}", .0.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", "))]
+    OversizedAssets(Vec<OversizedAsset>),
+
+    #[error("Values missing from KV: {}", .0.join(", "))]
+    MissingValues(Vec<String>),
+
+    #[error("Invalid asset metadata: {}", .0.join("; "))]
+    InvalidMetadata(Vec<String>),
+
+    #[error("Origin returned status {1} for {0}")]
+    OriginStatus(String, u16),
+
     #[error("TTL to short. Must be at least 60 seconds")]
     TTLTooShort,
 
+    #[error("Cancelled: {0}")]
+    Cancelled(String),
+
     // catch-all
     #[error("{0}")]
     Message(String),
@@ -0,0 +1,212 @@
+use crate::serve::{redirect_response, status_response};
+use crate::{Error, KVAssets};
+use bytes::Bytes;
+use http::{header, HeaderValue, Method, Request, Response, StatusCode};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Entry of a directory listing (see `KVAssets::with_auto_index`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DirEntry {
+    /// File name, or subdirectory name ending with '/'
+    pub name: String,
+    /// True for subdirectories
+    pub dir: bool,
+    /// Size in bytes (0 for subdirectories and aliases)
+    pub size: u64,
+    /// Last modified time, in UTC seconds since EPOCH (0 for subdirectories)
+    pub modified: u64,
+}
+
+impl<'ah> KVAssets<'ah> {
+    /// Entries of the index directly under the directory prefix ("" for the root,
+    /// otherwise an index path such as "/files/" or "files"; the path mapping isn't
+    /// applied), sorted by name: files, and
+    /// subdirectories for deeper paths. Tombstones are skipped.
+    /// Returns Ok(None) if there are no entries under prefix
+    pub fn list_dir(&self, prefix: &str) -> Result<Option<Vec<DirEntry>>, Error> {
+        let prefix = dir_prefix(prefix);
+        let entries = self.with_index(|index| {
+            let mut entries = BTreeMap::new();
+            for (path, md) in index.iter() {
+                let rest = match path.strip_prefix(prefix.as_str()) {
+                    Some(rest) if !rest.is_empty() && !md.is_deleted() => rest,
+                    _ => continue,
+                };
+                let entry = match rest.split_once('/') {
+                    Some((dir, _)) => DirEntry {
+                        name: format!("{}/", dir),
+                        dir: true,
+                        size: 0,
+                        modified: 0,
+                    },
+                    None => DirEntry {
+                        name: rest.to_string(),
+                        dir: false,
+                        size: md.size,
+                        modified: md.modified,
+                    },
+                };
+                entries.insert(entry.name.clone(), entry);
+            }
+            entries.into_values().collect::<Vec<_>>()
+        })?;
+        Ok(Some(entries).filter(|e| !e.is_empty()))
+    }
+
+    // Listing response for a GET or HEAD of a directory without index.html, if auto-index
+    // is enabled. Directories requested without the trailing '/' are redirected to it,
+    // so relative links in the listing work
+    pub(crate) fn dir_listing(&self, req: &Request<()>) -> Option<Response<Bytes>> {
+        if !self.auto_index() || !matches!(*req.method(), Method::GET | Method::HEAD) {
+            return None;
+        }
+        let path = req.uri().path();
+        let index_path = self.index_path(path)?;
+        let prefix = dir_prefix(&index_path);
+        let taken = self
+            .with_index(|index| {
+                let live = |key: &str| index.get(key).is_some_and(|md| !md.is_deleted());
+                live(&index_path) || live(&format!("{}index.html", prefix))
+            })
+            .ok()?;
+        if taken {
+            return None;
+        }
+        let entries = self.list_dir(&prefix).ok()??;
+        if !path.ends_with('/') {
+            return Some(redirect_response(&format!("{}/", path)));
+        }
+        let (content_type, body) = match wants_json(req) {
+            true => (
+                "application/json",
+                serde_json::to_string(&entries).unwrap_or_default(),
+            ),
+            false => ("text/html; charset=utf-8", listing_html(path, &entries)),
+        };
+        let mut response = status_response(StatusCode::OK);
+        let headers = response.headers_mut();
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from(body.len()));
+        if req.method() == Method::GET {
+            *response.body_mut() = Bytes::from(body);
+        }
+        Some(response)
+    }
+}
+
+// Index path prefix of a directory: without the leading '/', and with a trailing '/'
+// unless it is the root
+fn dir_prefix(path: &str) -> String {
+    match path.trim_matches('/') {
+        "" => String::new(),
+        dir => format!("{}/", dir),
+    }
+}
+
+// True if the request prefers a JSON listing
+fn wants_json(req: &Request<()>) -> bool {
+    req.headers()
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/json") && !accept.contains("text/html"))
+}
+
+fn listing_html(path: &str, entries: &[DirEntry]) -> String {
+    let title = format!("Index of {}", escape_html(path));
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{0}</title></head>\n\
+         <body><h1>{0}</h1>\n<ul>\n",
+        title
+    );
+    if path != "/" {
+        html.push_str("<li><a href=\"../\">../</a></li>\n");
+    }
+    for entry in entries {
+        let name = escape_html(&entry.name);
+        match entry.dir {
+            true => html.push_str(&format!("<li><a href=\"{0}\">{0}</a></li>\n", name)),
+            false => html.push_str(&format!(
+                "<li><a href=\"{0}\">{0}</a> {1} bytes</li>\n",
+                name, entry.size
+            )),
+        }
+    }
+    html.push_str("</ul></body></html>\n");
+    html
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Tests directory listings (does not invoke cloudflare api)
+#[cfg(test)]
+#[tokio::test]
+async fn test_dir_listing() {
+    use crate::{AssetIndex, AssetMetadata};
+
+    let mut index = AssetIndex::new();
+    for (path, size) in [
+        ("files/a.txt", 5),
+        ("files/b<1>.txt", 7),
+        ("files/sub/c.txt", 1),
+        ("site/index.html", 3),
+    ] {
+        index.insert(
+            path.to_string(),
+            AssetMetadata {
+                path: path.to_string(),
+                size,
+                ..Default::default()
+            },
+        );
+    }
+    let bytes = crate::encode_index(&index).unwrap();
+    let request = |path: &str, accept: &str| {
+        Request::get(path)
+            .header(header::ACCEPT, accept)
+            .body(())
+            .unwrap()
+    };
+
+    // off by default
+    let kv = KVAssets::init(&bytes, "acct", "ns", "token");
+    assert!(kv.dir_listing(&request("/files/", "*/*")).is_none());
+
+    let kv = kv.with_auto_index(true);
+    let names: Vec<_> = kv
+        .list_dir("/files/")
+        .unwrap()
+        .unwrap()
+        .into_iter()
+        .map(|e| e.name)
+        .collect();
+    assert_eq!(names, vec!["a.txt", "b<1>.txt", "sub/"]);
+    assert_eq!(kv.list_dir("").unwrap().unwrap().len(), 2);
+    assert_eq!(kv.list_dir("missing").unwrap(), None);
+
+    let response = kv.dir_listing(&request("/files/", "text/html")).unwrap();
+    let html = std::str::from_utf8(response.body()).unwrap();
+    assert!(html.contains("<a href=\"a.txt\">a.txt</a> 5 bytes"));
+    assert!(html.contains("b&lt;1&gt;.txt"));
+    assert!(html.contains("<a href=\"sub/\">"));
+
+    let response = kv.serve(&request("/files/sub/", "application/json")).await;
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+    let listed: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(listed[0]["name"], "c.txt");
+    assert_eq!(listed[0]["dir"], false);
+
+    let response = kv.dir_listing(&request("/files", "*/*")).unwrap();
+    assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(response.headers()[header::LOCATION], "/files/");
+    // directories with index.html, files, and unknown paths aren't listed
+    assert!(kv.dir_listing(&request("/site/", "*/*")).is_none());
+    assert!(kv.dir_listing(&request("/files/a.txt", "*/*")).is_none());
+    assert!(kv.dir_listing(&request("/nothing/", "*/*")).is_none());
+}
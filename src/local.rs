@@ -0,0 +1,111 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use crate::{AssetFuture, AssetMetadata, AssetSource, Error, ResolvedAsset};
+use bytes::Bytes;
+use std::path::{Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Serves assets straight from a source directory, for local development
+/// without a Cloudflare account. Implements `AssetSource`, like `KVAssets`, so handler
+/// code doesn't change between development and production.
+/// Files are read on every request, so edits are visible immediately.
+/// Hidden files (beginning with ".") are not served, and paths can't escape the directory.
+pub struct LocalAssets {
+    root: PathBuf,
+}
+
+impl LocalAssets {
+    /// Serve files under root
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    // File for the asset path, or None if the path is unsafe or hidden
+    fn file_path(&self, path: &str) -> Option<PathBuf> {
+        let rel = Path::new(path);
+        let safe = rel.components().all(|c| match c {
+            Component::Normal(name) => !name.to_string_lossy().starts_with('.'),
+            _ => false,
+        });
+        match safe {
+            true => Some(self.root.join(rel)),
+            false => None,
+        }
+    }
+}
+
+impl AssetSource for LocalAssets {
+    fn resolve_key(&self, path: &str) -> Result<Option<ResolvedAsset>, Error> {
+        let path = path.strip_prefix('/').unwrap_or(path);
+        if path.is_empty() {
+            return Err(Error::EmptyKey);
+        }
+        let file = match self.file_path(path).filter(|f| f.is_file()) {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+        let content = read(&file)?;
+        let modified = std::fs::metadata(&file)
+            .and_then(|md| md.modified())
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        Ok(Some(ResolvedAsset::Direct(AssetMetadata {
+            path: path.to_string(),
+            modified,
+            size: content.len() as u64,
+            // so the ETag changes when the file is edited
+            hash: Some(crate::checksum::content_hash(&content)),
+            ..Default::default()
+        })))
+    }
+
+    fn fetch_asset<'a>(&'a self, md: &'a AssetMetadata) -> AssetFuture<'a, Result<Bytes, Error>> {
+        Box::pin(async move {
+            match self.file_path(&md.path) {
+                Some(file) => read(&file).map(Bytes::from),
+                None => Err(Error::KVKeyNotFound(md.path.clone(), 404)),
+            }
+        })
+    }
+}
+
+fn read(file: &Path) -> Result<Vec<u8>, Error> {
+    std::fs::read(file).map_err(|e| Error::IO(format!("reading {}: {}", file.display(), e)))
+}
+
+/// Tests serving from a local directory
+#[cfg(test)]
+#[tokio::test]
+async fn test_local_assets() {
+    use http::{header, Request, StatusCode};
+
+    let dir = std::env::temp_dir().join(format!("kv-assets-local-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("css")).unwrap();
+    std::fs::write(dir.join("css/site.css"), "body{}").unwrap();
+    std::fs::write(dir.join(".env"), "secret").unwrap();
+    let local = LocalAssets::new(&dir);
+    let source: &dyn AssetSource = &local;
+
+    let md = source.lookup_key("/css/site.css").unwrap().unwrap();
+    assert_eq!(md.size, 6);
+    assert_eq!(
+        source.get_asset("css/site.css").await.unwrap().unwrap(),
+        "body{}"
+    );
+    assert!(source.lookup_key("/.env").unwrap().is_none());
+    assert!(source.lookup_key("/../etc/passwd").unwrap().is_none());
+    assert!(source.get_asset("/missing.css").await.unwrap().is_none());
+
+    let mut req = Request::new(());
+    *req.uri_mut() = "/css/site.css".parse().unwrap();
+    let resp = source.serve(&req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers()[header::CONTENT_TYPE], "text/css");
+    assert_eq!(resp.body(), "body{}");
+    let etag = resp.headers()[header::ETAG].clone();
+    req.headers_mut().insert(header::IF_NONE_MATCH, etag);
+    assert_eq!(source.serve(&req).await.status(), StatusCode::NOT_MODIFIED);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,202 @@
+use crate::{AssetKey, AssetMetadata, Error, HashAlgorithm};
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Builder for index entries created programmatically, with the checks `kv-sync`
+/// entries satisfy by construction. `build` reports every problem at once.
+/// ```
+/// # use kv_assets::AssetMetadata;
+/// let md = AssetMetadata::builder("/css/site.css")
+///     .content(b"body {}")
+///     .modified(1_600_000_000)
+///     .header("x-robots-tag", "noindex")
+///     .build()
+///     .unwrap();
+/// assert_eq!(md.path, "css/site.css");
+/// assert_eq!(md.size, 7);
+/// ```
+#[derive(Debug, Clone)]
+pub struct AssetMetadataBuilder {
+    path: String,
+    modified: Option<u64>,
+    size: Option<u64>,
+    content_size: Option<u64>,
+    hash: Option<String>,
+    integrity: Option<String>,
+    headers: BTreeMap<String, String>,
+    extra: BTreeMap<String, String>,
+}
+
+impl AssetMetadata {
+    /// Builder for an entry whose value is stored at KV key path
+    /// (see `AssetMetadataBuilder`)
+    pub fn builder(path: impl Into<String>) -> AssetMetadataBuilder {
+        AssetMetadataBuilder {
+            path: path.into(),
+            modified: None,
+            size: None,
+            content_size: None,
+            hash: None,
+            integrity: None,
+            headers: BTreeMap::new(),
+            extra: BTreeMap::new(),
+        }
+    }
+}
+
+impl AssetMetadataBuilder {
+    /// Last modified time, in UTC seconds since EPOCH. Required, and not 0
+    pub fn modified(mut self, modified: u64) -> Self {
+        self.modified = Some(modified);
+        self
+    }
+
+    /// Last modified time
+    pub fn modified_at(self, time: SystemTime) -> Self {
+        let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs());
+        self.modified(secs.unwrap_or_default())
+    }
+
+    /// Size of the value, in bytes. Required, unless set by `content`
+    pub fn size(mut self, size: u64) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    /// Content hash, created by `HashAlgorithm::hash`
+    pub fn hash(mut self, hash: impl Into<String>) -> Self {
+        self.hash = Some(hash.into());
+        self
+    }
+
+    /// Set the size and hash (with the default algorithm) from the value, and the
+    /// integrity hash of JavaScript and CSS assets (see `KVAssets::sri_for`)
+    pub fn content(mut self, content: &[u8]) -> Self {
+        self.content_size = Some(content.len() as u64);
+        self.hash = Some(HashAlgorithm::default().hash(content));
+        self.integrity = Some(crate::sri::sri_hash(content));
+        self
+    }
+
+    /// Response header added by `serve`
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers
+            .insert(name.to_ascii_lowercase(), value.to_string());
+        self
+    }
+
+    /// Application-defined field (see `AssetMetadata::extra`)
+    pub fn extra(mut self, key: &str, value: &str) -> Self {
+        self.extra.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Check the settings and create the entry. The path's leading '/' is removed,
+    /// as `lookup_key` does. Returns `Error::InvalidMetadata` listing every problem:
+    /// an invalid KV key, a missing or zero modified time, a missing size or one
+    /// that doesn't match `content`, a hash with an unsupported algorithm, or an
+    /// invalid header
+    pub fn build(self) -> Result<AssetMetadata, Error> {
+        let mut problems = Vec::new();
+        let path = self
+            .path
+            .strip_prefix('/')
+            .unwrap_or(&self.path)
+            .to_string();
+        if let Err(e) = AssetKey::new(path.as_str()) {
+            problems.push(format!("path '{}': {}", self.path, e));
+        }
+        match self.modified {
+            None => problems.push("modified time not set".to_string()),
+            Some(0) => problems.push("modified time is 0".to_string()),
+            Some(_) => {}
+        }
+        let size = match (self.size, self.content_size) {
+            (Some(size), Some(len)) if size != len => {
+                problems.push(format!(
+                    "size {} doesn't match content length {}",
+                    size, len
+                ));
+                size
+            }
+            (Some(size), _) | (None, Some(size)) => size,
+            (None, None) => {
+                problems.push("size not set".to_string());
+                0
+            }
+        };
+        if let Some(hash) = &self.hash {
+            let hex = hash.rsplit(':').next().unwrap_or_default();
+            if HashAlgorithm::of(hash).is_none()
+                || hex.is_empty()
+                || !hex.bytes().all(|b| b.is_ascii_hexdigit())
+            {
+                problems.push(format!("unsupported hash '{}'", hash));
+            }
+        }
+        for (name, value) in self.headers.iter() {
+            if http::HeaderName::from_bytes(name.as_bytes()).is_err()
+                || http::HeaderValue::from_str(value).is_err()
+            {
+                problems.push(format!("invalid header '{}: {}'", name, value));
+            }
+        }
+        if !problems.is_empty() {
+            return Err(Error::InvalidMetadata(problems));
+        }
+        let mut extra = self.extra;
+        if let Some(integrity) = self.integrity {
+            if crate::sri::wants_integrity(&path) {
+                extra.insert(crate::INTEGRITY_KEY.to_string(), integrity);
+            }
+        }
+        Ok(AssetMetadata {
+            path,
+            modified: self.modified.unwrap_or_default(),
+            size,
+            hash: self.hash,
+            headers: self.headers,
+            extra,
+            ..Default::default()
+        })
+    }
+}
+
+/// Tests building and validating index entries
+#[test]
+fn test_metadata_builder() {
+    let md = AssetMetadata::builder("/a.txt")
+        .size(5)
+        .hash(HashAlgorithm::Sha256.hash(b"hello"))
+        .modified(1000)
+        .extra("cache", "pinned")
+        .build()
+        .unwrap();
+    assert_eq!(md.path, "a.txt");
+    assert_eq!(md.extra["cache"], "pinned");
+    assert!(crate::checksum::matches(
+        b"hello",
+        md.hash.as_deref().unwrap()
+    ));
+
+    match AssetMetadata::builder("/")
+        .content(b"abc")
+        .size(4)
+        .hash("md5:abc")
+        .header("bad header", "x")
+        .build()
+    {
+        Err(Error::InvalidMetadata(problems)) => assert_eq!(problems.len(), 5, "{:?}", problems),
+        other => panic!("expected InvalidMetadata, got {:?}", other),
+    }
+    assert!(AssetMetadata::builder("b.txt").modified(1).build().is_err());
+    let md = AssetMetadata::builder("app.js")
+        .content(b"alert(1)")
+        .modified(1)
+        .build()
+        .unwrap();
+    assert_eq!(
+        md.integrity(),
+        Some(crate::sri::sri_hash(b"alert(1)").as_str())
+    );
+}
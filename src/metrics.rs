@@ -0,0 +1,131 @@
+/// Receives counters and measurements from KV operations,
+/// so they can be forwarded to prometheus, statsd, or another metrics backend.
+/// All methods have no-op defaults: implement only the ones you need.
+/// Install with `KVAssets::with_metrics`.
+pub trait Metrics: Send + Sync {
+    /// Asset index lookup, and whether the path was found
+    fn lookup(&self, _found: bool) {}
+
+    /// Response status of a KV GET. Status is 0 if the request failed without a response
+    fn kv_get(&self, _status: u16) {}
+
+    /// Response status of a KV PUT. Status is 0 if the request failed without a response
+    fn kv_put(&self, _status: u16) {}
+
+    /// Size of response body returned by `get_asset` or `serve`
+    fn bytes_served(&self, _bytes: u64) {}
+
+    /// Value found in a cache: cache is "value" (see `KVAssets::with_value_cache`)
+    /// or "edge" (see `KVAssets::with_edge_cache`)
+    fn cache_hit(&self, _cache: &str) {}
+
+    /// Value not found in a cache, and read from KV. Cache is "value" or "edge"
+    fn cache_miss(&self, _cache: &str) {}
+
+    /// Failed upload that will be tried again (see `DeployEvent::Retried`).
+    /// Attempt is the number of the failed attempt, starting at 1
+    fn retry(&self, _attempt: u32) {}
+
+    /// A usage budget was exceeded (see `KVAssets::with_usage_budget`): counter is
+    /// "reads", "writes", "lists", or "bytes". Called once per counter, until
+    /// `reset_usage`
+    fn usage_budget_exceeded(&self, _counter: &str, _used: u64, _budget: u64) {}
+}
+
+// Records cache and retry events, as "cache_hit:value", "retry:1", etc., for tests
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct RecordedMetrics(std::sync::Mutex<Vec<String>>);
+
+#[cfg(test)]
+impl RecordedMetrics {
+    pub(crate) fn events(&self) -> Vec<String> {
+        self.0.lock().unwrap().clone()
+    }
+
+    fn push(&self, event: String) {
+        self.0.lock().unwrap().push(event);
+    }
+}
+
+#[cfg(test)]
+impl Metrics for RecordedMetrics {
+    fn cache_hit(&self, cache: &str) {
+        self.push(format!("cache_hit:{}", cache));
+    }
+
+    fn cache_miss(&self, cache: &str) {
+        self.push(format!("cache_miss:{}", cache));
+    }
+
+    fn retry(&self, attempt: u32) {
+        self.push(format!("retry:{}", attempt));
+    }
+}
+
+/// Tests that metrics hooks are called on lookup
+#[test]
+fn test_metrics_lookup() {
+    use crate::{AssetIndex, AssetMetadata, KVAssets};
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    };
+
+    #[derive(Default)]
+    struct Counter {
+        hits: AtomicU32,
+        misses: AtomicU32,
+    }
+    impl Metrics for Counter {
+        fn lookup(&self, found: bool) {
+            match found {
+                true => self.hits.fetch_add(1, Ordering::Relaxed),
+                false => self.misses.fetch_add(1, Ordering::Relaxed),
+            };
+        }
+    }
+
+    let mut index = AssetIndex::new();
+    index.insert(
+        "a.txt".to_string(),
+        AssetMetadata {
+            path: "a.123.txt".to_string(),
+            modified: 10000,
+            size: 10,
+            hash: None,
+            ..Default::default()
+        },
+    );
+    let blob = crate::encode_index(&index).expect("serialize-index");
+    let counter = Arc::new(Counter::default());
+    let kv = KVAssets::init(&blob, "123", "namespace", "token").with_metrics(counter.clone());
+
+    assert!(kv.lookup_key("a.txt").unwrap().is_some());
+    assert!(kv.lookup_key("b.txt").unwrap().is_none());
+    assert!(kv.lookup_key("/a.txt").unwrap().is_some());
+    assert_eq!(counter.hits.load(Ordering::Relaxed), 2);
+    assert_eq!(counter.misses.load(Ordering::Relaxed), 1);
+}
+
+/// Tests that value cache lookups are reported, against the mock api
+#[cfg(all(test, feature = "reqwest"))]
+#[tokio::test]
+async fn test_metrics_value_cache() {
+    use std::sync::Arc;
+
+    let mock = crate::testspace::MockCloudflare::start().unwrap();
+    mock.insert("ns", "a", "hello");
+    let metrics = Arc::new(RecordedMetrics::default());
+    let kv = mock
+        .handler(&[], "ns")
+        .with_value_cache(Arc::new(crate::ValueCache::new(1024)))
+        .with_metrics(metrics.clone());
+    for _ in 0..2 {
+        assert_eq!(kv.get_kv_value("a").await.unwrap(), "hello");
+    }
+    assert_eq!(
+        metrics.events(),
+        vec!["cache_miss:value", "cache_hit:value"]
+    );
+}
@@ -0,0 +1,114 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use crate::{Error, KVAssets};
+use futures_util::stream::{self, StreamExt, TryStreamExt};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+impl<'ah> KVAssets<'ah> {
+    /// Download all assets to a local directory, for backups, local debugging,
+    /// or migrating between namespaces. Files are written at their asset paths,
+    /// with modification times from the index. Aliases and tombstones are skipped. If the index is empty, all keys
+    /// in the namespace are downloaded instead, named by KV key.
+    /// Values are downloaded in parallel (see `with_chunk_concurrency`), and written
+    /// with blocking `std::fs` calls, which don't need a particular runtime.
+    /// Returns the number of files written.
+    pub async fn mirror_to_dir(&self, dest: &Path) -> Result<usize, Error> {
+        let mut entries: Vec<(String, String, Option<u64>)> = self.with_index(|index| {
+            index
+                .iter()
+                .filter(|(_, md)| md.alias.is_none() && !md.is_deleted())
+                .map(|(path, md)| (path.clone(), md.path.clone(), Some(md.modified)))
+                .collect()
+        })?;
+        if entries.is_empty() {
+            entries = self
+                .list_keys()
+                .await?
+                .into_iter()
+                .map(|key| (key.clone(), key, None))
+                .collect();
+        }
+        let count = entries.len();
+        stream::iter(entries)
+            .map(|(path, key, modified)| async move {
+                self.check_cancelled("mirroring assets")?;
+                let file = local_path(dest, &path)?;
+                let value = self.get_kv_value(&key).await?;
+                write_file(&file, &value, modified)
+            })
+            .buffer_unordered(self.chunk_concurrency())
+            .try_collect::<Vec<()>>()
+            .await?;
+        Ok(count)
+    }
+}
+
+// Destination of asset path, rejecting paths that would escape dest
+fn local_path(dest: &Path, path: &str) -> Result<PathBuf, Error> {
+    let rel = Path::new(path.trim_start_matches('/'));
+    if rel
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(Error::IO(format!("unsafe asset path {}", path)));
+    }
+    Ok(dest.join(rel))
+}
+
+// Write file, creating parent directories, and set its modification time
+fn write_file(file: &Path, value: &[u8], modified: Option<u64>) -> Result<(), Error> {
+    let io_err = |e: std::io::Error| Error::IO(format!("writing {}: {}", file.display(), e));
+    if let Some(parent) = file.parent() {
+        std::fs::create_dir_all(parent).map_err(io_err)?;
+    }
+    std::fs::write(file, value).map_err(io_err)?;
+    if let Some(modified) = modified {
+        std::fs::File::options()
+            .write(true)
+            .open(file)
+            .and_then(|f| f.set_modified(UNIX_EPOCH + Duration::from_secs(modified)))
+            .map_err(io_err)?;
+    }
+    Ok(())
+}
+
+/// Tests mirroring assets to a directory, against the mock api
+#[cfg(all(test, feature = "reqwest"))]
+#[tokio::test]
+async fn test_mirror_to_dir() {
+    use crate::{AssetIndex, AssetMetadata};
+
+    let mock = crate::testspace::MockCloudflare::start().unwrap();
+    let mut index = AssetIndex::new();
+    for (path, key) in [("a.txt", "a.1.txt"), ("css/b.css", "css.b.2.css")] {
+        mock.insert("ns", key, key.to_string());
+        index.insert(
+            path.to_string(),
+            AssetMetadata {
+                path: key.to_string(),
+                modified: 1_600_000_000,
+                size: 7,
+                hash: None,
+                ..Default::default()
+            },
+        );
+    }
+    let blob = crate::encode_index(&index).unwrap();
+    let kv = mock.handler(&blob, "ns");
+
+    let dest = std::env::temp_dir().join(format!("kv-assets-mirror-{}", std::process::id()));
+    assert_eq!(kv.mirror_to_dir(&dest).await.unwrap(), 2);
+    assert_eq!(
+        std::fs::read(dest.join("css/b.css")).unwrap(),
+        b"css.b.2.css"
+    );
+    let modified = std::fs::metadata(dest.join("a.txt"))
+        .unwrap()
+        .modified()
+        .unwrap();
+    assert_eq!(modified, UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+    std::fs::remove_dir_all(&dest).unwrap();
+
+    assert!(local_path(&dest, "../etc/passwd").is_err());
+}
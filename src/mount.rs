@@ -0,0 +1,199 @@
+use crate::{AssetMetadata, Error, KVAssets};
+use bytes::Bytes;
+use http::{Request, Response, StatusCode};
+use std::sync::Arc;
+
+/// Routes requests to one of several asset handlers by URL prefix, for workers
+/// serving multiple sites or apps (e.g., `/docs` and `/app` from different namespaces,
+/// and everything else from `/`). The longest matching prefix wins; prefixes match
+/// whole path segments, so `/doc` doesn't match `/docs/a.html`.
+/// The prefix is removed before the path is looked up in the handler's index.
+///
+/// Handlers can also be mounted for a single host with `mount_host`
+/// (e.g., `docs.example.com` served from the docs namespace). Mounts for the
+/// request's host are tried first, then mounts for any host.
+#[derive(Default)]
+pub struct MountTable<'ah> {
+    // sorted by prefix length, longest first
+    mounts: Vec<Mount<'ah>>,
+}
+
+struct Mount<'ah> {
+    // lowercase host name, or None for any host
+    host: Option<String>,
+    prefix: String,
+    kv: Arc<KVAssets<'ah>>,
+}
+
+impl<'ah> MountTable<'ah> {
+    /// Create table with no mounts
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serve paths under prefix from the handler. Mounting the same prefix again
+    /// replaces the previous handler
+    pub fn mount(self, prefix: &str, kv: impl Into<Arc<KVAssets<'ah>>>) -> Self {
+        self.add(None, prefix, kv.into())
+    }
+
+    /// Serve paths under prefix from the handler, for requests to the host only.
+    /// Host names are compared case-insensitively, ignoring any port
+    pub fn mount_host(self, host: &str, prefix: &str, kv: impl Into<Arc<KVAssets<'ah>>>) -> Self {
+        self.add(Some(normalize_host(host)), prefix, kv.into())
+    }
+
+    fn add(mut self, host: Option<String>, prefix: &str, kv: Arc<KVAssets<'ah>>) -> Self {
+        let prefix = prefix.trim_end_matches('/').to_string();
+        self.mounts.retain(|m| m.host != host || m.prefix != prefix);
+        self.mounts.push(Mount { host, prefix, kv });
+        self.mounts
+            .sort_by_key(|m| std::cmp::Reverse(m.prefix.len()));
+        self
+    }
+
+    /// Returns the handler for the path, and the path with the mount prefix removed.
+    /// Only mounts for any host are considered
+    pub fn resolve<'p>(&self, path: &'p str) -> Option<(&KVAssets<'ah>, &'p str)> {
+        self.resolve_host(None, path)
+    }
+
+    /// Returns the handler for the host and path, and the path with the mount prefix removed
+    pub fn resolve_host<'p>(
+        &self,
+        host: Option<&str>,
+        path: &'p str,
+    ) -> Option<(&KVAssets<'ah>, &'p str)> {
+        let host = host.map(normalize_host);
+        let matching = |for_host: bool| {
+            self.mounts
+                .iter()
+                .filter(|m| match for_host {
+                    true => m.host.is_some() && m.host == host,
+                    false => m.host.is_none(),
+                })
+                .find_map(|m| {
+                    let rest = path.strip_prefix(m.prefix.as_str())?;
+                    match rest.is_empty() || rest.starts_with('/') {
+                        true => Some((m.kv.as_ref(), rest)),
+                        false => None,
+                    }
+                })
+        };
+        matching(true).or_else(|| matching(false))
+    }
+
+    /// Looks up the path in the index of the handler mounted at its prefix.
+    /// Returns Ok(None) if no handler is mounted for the path
+    pub fn lookup_key(&self, path: &str) -> Result<Option<AssetMetadata>, Error> {
+        match self.resolve(path) {
+            Some((kv, rest)) => kv.lookup_key(rest),
+            None => Ok(None),
+        }
+    }
+
+    /// Gets the asset from the handler mounted at the path's prefix
+    pub async fn get_asset(&self, path: &str) -> Result<Option<Bytes>, Error> {
+        match self.resolve(path) {
+            Some((kv, rest)) => kv.get_asset(rest).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Serve the request from the handler mounted for its host and path prefix
+    /// (see `KVAssets::serve`). The host is taken from the `Host` header, or the request uri.
+    /// Returns 404 if no handler is mounted for the path.
+    pub async fn serve(&self, req: &Request<()>) -> Response<Bytes> {
+        let host = req
+            .headers()
+            .get(http::header::HOST)
+            .and_then(|h| h.to_str().ok())
+            .or_else(|| req.uri().host());
+        let (kv, rest) = match self.resolve_host(host, req.uri().path()) {
+            Some(found) => found,
+            None => return crate::serve::status_response(StatusCode::NOT_FOUND),
+        };
+        let mut uri = rest.to_string();
+        if let Some(query) = req.uri().query() {
+            uri.push('?');
+            uri.push_str(query);
+        }
+        let mut inner = Request::new(());
+        *inner.method_mut() = req.method().clone();
+        *inner.headers_mut() = req.headers().clone();
+        match uri.parse() {
+            Ok(uri) => *inner.uri_mut() = uri,
+            Err(_) => return crate::serve::status_response(StatusCode::NOT_FOUND),
+        }
+        kv.serve(&inner).await
+    }
+}
+
+// Lowercase host name without port
+fn normalize_host(host: &str) -> String {
+    let name = match host.strip_prefix('[') {
+        // ipv6 literal
+        Some(rest) => rest.split(']').next().unwrap_or(rest),
+        None => host.split(':').next().unwrap_or(host),
+    };
+    name.to_ascii_lowercase()
+}
+
+/// Tests longest-prefix matching
+#[test]
+fn test_mount_table() {
+    use crate::AssetIndex;
+
+    fn handler(index: &[u8]) -> KVAssets<'_> {
+        KVAssets::init(index, "acct", "ns", "token")
+    }
+    let mut docs = AssetIndex::new();
+    docs.insert(
+        "guide.html".to_string(),
+        AssetMetadata {
+            path: "guide.1.html".to_string(),
+            modified: 10000,
+            size: 5,
+            hash: None,
+            ..Default::default()
+        },
+    );
+    let docs = crate::encode_index(&docs).unwrap();
+    let root = crate::encode_index(&AssetIndex::new()).unwrap();
+
+    let table = MountTable::new()
+        .mount("/", handler(&root))
+        .mount("/docs/", handler(&docs))
+        .mount("/docs/api", handler(&root));
+
+    let (_, rest) = table.resolve("/docs/guide.html").unwrap();
+    assert_eq!(rest, "/guide.html");
+    assert_eq!(table.resolve("/docs/api/x.html").unwrap().1, "/x.html");
+    assert_eq!(table.resolve("/docsx/a.html").unwrap().1, "/docsx/a.html");
+    assert!(table.lookup_key("/docs/guide.html").unwrap().is_some());
+    assert!(table.lookup_key("/guide.html").unwrap().is_none());
+    assert!(MountTable::new().resolve("/a").is_none());
+}
+
+/// Tests host-based routing
+#[test]
+fn test_mount_host() {
+    let index = crate::encode_index(&crate::AssetIndex::new()).unwrap();
+    let docs = Arc::new(KVAssets::init(&index, "acct", "docs", "token"));
+    let site = Arc::new(KVAssets::init(&index, "acct", "site", "token"));
+    let table = MountTable::new().mount("/", site.clone()).mount_host(
+        "Docs.Example.com",
+        "/",
+        docs.clone(),
+    );
+
+    let (kv, rest) = table
+        .resolve_host(Some("docs.example.com:443"), "/a.html")
+        .unwrap();
+    assert!(std::ptr::eq(kv, docs.as_ref()));
+    assert_eq!(rest, "/a.html");
+    let (kv, _) = table.resolve_host(Some("example.com"), "/a.html").unwrap();
+    assert!(std::ptr::eq(kv, site.as_ref()));
+    let (kv, _) = table.resolve("/a.html").unwrap();
+    assert!(std::ptr::eq(kv, site.as_ref()));
+}
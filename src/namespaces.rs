@@ -0,0 +1,285 @@
+use crate::{assets::parse_json, Error, KVAssets};
+use bytes::Bytes;
+use http::Method;
+use serde::Deserialize;
+
+/// A Workers KV namespace in the account
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Namespace {
+    /// Namespace id, used as `namespace_id` for KV operations
+    pub id: String,
+    /// Human-readable namespace title
+    pub title: String,
+    /// True if the namespace supports url-encoded keys in api urls
+    #[serde(default)]
+    pub supports_url_encoding: bool,
+}
+
+// Namespaces listed per page. Cloudflare's maximum is 100
+const NAMESPACES_PER_PAGE: u32 = 100;
+
+/// Field namespaces are sorted by in `list_namespaces_page`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NamespaceOrder {
+    /// Sort by namespace id
+    Id,
+    /// Sort by namespace title
+    Title,
+}
+
+/// Page, page size, and sort order for `list_namespaces_page`
+/// ```
+/// # use kv_assets::{NamespaceOrder, NamespaceQuery};
+/// let query = NamespaceQuery::new()
+///     .with_page(2)
+///     .with_per_page(20)
+///     .with_order(NamespaceOrder::Title, false);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamespaceQuery {
+    pub(crate) page: u32,
+    pub(crate) per_page: u32,
+    pub(crate) order: Option<(NamespaceOrder, bool)>,
+}
+
+impl Default for NamespaceQuery {
+    fn default() -> Self {
+        Self {
+            page: 1,
+            per_page: NAMESPACES_PER_PAGE,
+            order: None,
+        }
+    }
+}
+
+impl NamespaceQuery {
+    /// First page of 100 namespaces, in the api's default order
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Page number, starting at 1
+    pub fn with_page(mut self, page: u32) -> Self {
+        self.page = page.max(1);
+        self
+    }
+
+    /// Namespaces per page, from 5 to 100 (Cloudflare's limits)
+    pub fn with_per_page(mut self, per_page: u32) -> Self {
+        self.per_page = per_page.clamp(5, NAMESPACES_PER_PAGE);
+        self
+    }
+
+    /// Sort by field, in descending order if descending is true
+    pub fn with_order(mut self, order: NamespaceOrder, descending: bool) -> Self {
+        self.order = Some((order, descending));
+        self
+    }
+
+    // Query string of the list api url
+    fn query_string(&self) -> String {
+        let mut query = format!("page={}&per_page={}", self.page, self.per_page);
+        if let Some((order, descending)) = self.order {
+            let field = match order {
+                NamespaceOrder::Id => "id",
+                NamespaceOrder::Title => "title",
+            };
+            let direction = if descending { "desc" } else { "asc" };
+            query.push_str(&format!("&order={}&direction={}", field, direction));
+        }
+        query
+    }
+}
+
+/// A page of namespaces returned by `list_namespaces_page`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamespacePage {
+    /// Namespaces on this page
+    pub namespaces: Vec<Namespace>,
+    /// Page number, starting at 1
+    pub page: u32,
+    /// Number of pages, if reported by the api
+    pub total_pages: Option<u32>,
+    /// Number of namespaces in the account, if reported by the api
+    pub total_count: Option<u32>,
+}
+
+impl NamespacePage {
+    /// True if there are more pages after this one
+    pub fn has_more(&self, per_page: u32) -> bool {
+        match self.total_pages {
+            Some(total) => self.page < total,
+            None => self.namespaces.len() >= per_page as usize,
+        }
+    }
+}
+
+/// Namespace management. These methods use only the account id and api token,
+/// so a handler created to bootstrap a namespace can be initialized with an empty
+/// namespace id, then re-created with the id returned by `create_namespace`.
+impl<'ah> KVAssets<'ah> {
+    /// List all KV namespaces in the account
+    pub async fn list_namespaces(&self) -> Result<Vec<Namespace>, Error> {
+        let mut namespaces = Vec::new();
+        let mut query = NamespaceQuery::new();
+        loop {
+            let page = self.list_namespaces_page(&query).await?;
+            let more = page.has_more(query.per_page);
+            namespaces.extend(page.namespaces);
+            if !more {
+                break;
+            }
+            query.page += 1;
+        }
+        Ok(namespaces)
+    }
+
+    /// List one page of KV namespaces in the account, with the page size
+    /// and sort order of query
+    pub async fn list_namespaces_page(
+        &self,
+        query: &NamespaceQuery,
+    ) -> Result<NamespacePage, Error> {
+        let url = self.account_url(&format!("storage/kv/namespaces?{}", query.query_string()));
+        let response: ApiResponse<Vec<Namespace>> = self
+            .namespace_api(Method::GET, &url, Bytes::new(), "listing namespaces")
+            .await?;
+        let info = response.result_info;
+        Ok(NamespacePage {
+            namespaces: response.result.unwrap_or_default(),
+            page: query.page,
+            total_pages: info.as_ref().and_then(|i| i.total_pages),
+            total_count: info.as_ref().and_then(|i| i.total_count),
+        })
+    }
+
+    /// Returns the namespace with the title, or None if there isn't one.
+    /// Pages are listed until it is found
+    pub async fn find_namespace(&self, title: &str) -> Result<Option<Namespace>, Error> {
+        let mut query = NamespaceQuery::new();
+        loop {
+            let page = self.list_namespaces_page(&query).await?;
+            let more = page.has_more(query.per_page);
+            if let Some(ns) = page.namespaces.into_iter().find(|ns| ns.title == title) {
+                return Ok(Some(ns));
+            }
+            if !more {
+                return Ok(None);
+            }
+            query.page += 1;
+        }
+    }
+
+    /// Create a namespace, returning its id and title
+    pub async fn create_namespace(&self, title: &str) -> Result<Namespace, Error> {
+        let url = self.account_url("storage/kv/namespaces");
+        let response: ApiResponse<Namespace> = self
+            .namespace_api(Method::POST, &url, title_body(title), "creating namespace")
+            .await?;
+        response
+            .result
+            .ok_or_else(|| Error::Message(format!("creating namespace {}: no result", title)))
+    }
+
+    /// Change the title of a namespace
+    pub async fn rename_namespace(&self, namespace_id: &str, title: &str) -> Result<(), Error> {
+        let url = self.account_url(&format!("storage/kv/namespaces/{}", namespace_id));
+        let _: ApiResponse<serde_json::Value> = self
+            .namespace_api(Method::PUT, &url, title_body(title), "renaming namespace")
+            .await?;
+        Ok(())
+    }
+
+    /// Delete a namespace and all of its keys
+    pub async fn delete_namespace(&self, namespace_id: &str) -> Result<(), Error> {
+        let url = self.account_url(&format!("storage/kv/namespaces/{}", namespace_id));
+        let _: ApiResponse<serde_json::Value> = self
+            .namespace_api(Method::DELETE, &url, Bytes::new(), "deleting namespace")
+            .await?;
+        Ok(())
+    }
+
+    // Send json api request and check for success
+    pub(crate) async fn namespace_api<T: serde::de::DeserializeOwned>(
+        &self,
+        method: Method,
+        url: &str,
+        body: Bytes,
+        action: &str,
+    ) -> Result<ApiResponse<T>, Error> {
+        let mut request = self.request(method, url, body);
+        request.headers_mut().insert(
+            http::header::CONTENT_TYPE,
+            http::HeaderValue::from_static("application/json"),
+        );
+        let response = self.send(request).await?;
+        let status = response.status().as_u16();
+        let parsed: ApiResponse<T> = parse_json(response.body())?;
+        if parsed.success {
+            Ok(parsed)
+        } else {
+            Err(crate::error_kind::api_error(
+                &format!("{}: status={}", action, status),
+                &parsed.errors,
+            ))
+        }
+    }
+}
+
+// Json body {"title": ..}
+fn title_body(title: &str) -> Bytes {
+    Bytes::from(serde_json::json!({ "title": title }).to_string())
+}
+
+// Cloudflare api response envelope
+#[derive(Deserialize)]
+pub(crate) struct ApiResponse<T> {
+    pub success: bool,
+    #[serde(default)]
+    pub errors: Vec<serde_json::Value>,
+    pub result: Option<T>,
+    result_info: Option<PageInfo>,
+}
+
+#[derive(Deserialize)]
+struct PageInfo {
+    total_pages: Option<u32>,
+    total_count: Option<u32>,
+}
+
+/// Tests namespace management and paging, against the mock api
+#[cfg(all(test, feature = "reqwest"))]
+#[tokio::test]
+async fn test_namespaces() {
+    let mock = crate::testspace::MockCloudflare::start().unwrap();
+    let kv = mock.handler(&[], "");
+    assert!(kv.list_namespaces().await.unwrap().is_empty());
+    let a = kv.create_namespace("a").await.unwrap();
+    assert_eq!(a.title, "a");
+    assert!(a.supports_url_encoding);
+    kv.create_namespace("b").await.unwrap();
+    for n in 0..103 {
+        mock.insert(&format!("site{:03}", n), "key", "value");
+    }
+    let all = kv.list_namespaces().await.unwrap();
+    assert_eq!(all.len(), 105);
+    assert_eq!(kv.find_namespace("a").await.unwrap().unwrap().id, a.id);
+    assert_eq!(kv.find_namespace("z").await.unwrap(), None);
+
+    let query = NamespaceQuery::new()
+        .with_page(2)
+        .with_per_page(1000)
+        .with_order(NamespaceOrder::Title, true);
+    let page = kv.list_namespaces_page(&query).await.unwrap();
+    assert_eq!(page.total_pages, Some(2));
+    assert_eq!(page.total_count, Some(105));
+    assert!(!page.has_more(100));
+    let titles: Vec<_> = page.namespaces.iter().map(|ns| ns.title.as_str()).collect();
+    assert_eq!(titles, vec!["site002", "site001", "site000", "b", "a"]);
+
+    kv.rename_namespace(&a.id, "z").await.unwrap();
+    assert_eq!(kv.find_namespace("z").await.unwrap().unwrap().id, a.id);
+    assert!(kv.delete_namespace("missing").await.is_err());
+    kv.delete_namespace(&a.id).await.unwrap();
+    assert_eq!(kv.find_namespace("z").await.unwrap(), None);
+}
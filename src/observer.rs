@@ -0,0 +1,98 @@
+use serde::Serialize;
+
+/// Number of attempts `KVAssets::publish_dir` makes to upload each value
+pub const UPLOAD_ATTEMPTS: u32 = 3;
+
+/// Event reported to a `DeployObserver` during a deploy.
+/// Serializes to JSON with an `event` tag, e.g. `{"event":"skipped","path":"a.css"}`
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum DeployEvent {
+    /// Upload of an asset is starting
+    Started {
+        /// Asset path
+        path: String,
+        /// KV key
+        key: String,
+        /// Size of the value, in bytes
+        size: u64,
+    },
+    /// Asset was uploaded
+    Uploaded {
+        /// Asset path
+        path: String,
+        /// KV key
+        key: String,
+        /// Size of the value, in bytes
+        size: u64,
+    },
+    /// Asset was not uploaded, because its value is already in KV
+    Skipped {
+        /// Asset path
+        path: String,
+    },
+    /// Upload failed, and will be tried again
+    Retried {
+        /// Asset path
+        path: String,
+        /// Number of the failed attempt, starting at 1
+        attempt: u32,
+        /// Error from the failed attempt
+        error: String,
+    },
+    /// Upload failed. The deploy stops with this error
+    Failed {
+        /// Asset path
+        path: String,
+        /// Error message
+        error: String,
+    },
+    /// Unreferenced KV key was deleted (prune)
+    Deleted {
+        /// KV key
+        key: String,
+    },
+    /// The deploy was cancelled (see `CancelToken`). Running it again resumes it
+    Cancelled {
+        /// Number of assets uploaded or skipped before cancelling
+        completed: usize,
+        /// Number of assets not processed
+        remaining: usize,
+    },
+}
+
+impl DeployEvent {
+    /// Serialize to a single line of JSON, for JSON logs
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// Receives events from `sync_assets` (see `SyncConfig::observer`) and from
+/// `KVAssets::publish_dir` and `KVAssets::prune` (see `KVAssets::with_deploy_observer`),
+/// so tools can render progress, write JSON logs, or annotate CI jobs
+/// without parsing the console output. Closures taking `&DeployEvent` are observers.
+pub trait DeployObserver: Send + Sync {
+    /// Called for each event, in order
+    fn on_event(&self, event: &DeployEvent);
+}
+
+impl<F: Fn(&DeployEvent) + Send + Sync> DeployObserver for F {
+    fn on_event(&self, event: &DeployEvent) {
+        self(event)
+    }
+}
+
+/// Tests JSON serialization of events
+#[test]
+fn test_deploy_event_json() {
+    let event = DeployEvent::Retried {
+        path: "a.css".to_string(),
+        attempt: 1,
+        error: "timeout".to_string(),
+    };
+    assert_eq!(
+        event.to_json(),
+        r#"{"event":"retried","path":"a.css","attempt":1,"error":"timeout"}"#
+    );
+}
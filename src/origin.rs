@@ -0,0 +1,294 @@
+use crate::serve::{
+    body_response, error_response, head_response, is_not_modified, not_modified_response,
+    redirect_response, status_response,
+};
+use crate::{AssetFuture, AssetMetadata, AssetSource, Error, KVAssets, ResolvedAsset};
+use bytes::Bytes;
+use futures_util::future::try_join;
+use http::{header, Method, Request, Response, StatusCode};
+use std::sync::Arc;
+
+/// Pull-through cache in front of an origin server. Assets in the index are served
+/// from KV as usual. On a miss (the path isn't in the index, or its value isn't in KV),
+/// the asset is fetched from the origin, served, and written to KV with a TTL,
+/// so later requests are served from KV without pre-publishing everything.
+///
+/// Pulled assets are stored under `key_prefix` + path (default "origin/"), with their
+/// hash and modification time in KV metadata. Origin requests are sent with the handler's
+/// `HttpClient`, without the api token. A failure writing to KV doesn't fail the request.
+pub struct OriginPull<'ah> {
+    kv: Arc<KVAssets<'ah>>,
+    origin: String,
+    key_prefix: String,
+    ttl: Option<u64>,
+}
+
+impl<'ah> OriginPull<'ah> {
+    /// Pull missing assets from origin_url (e.g., "https://origin.example.com/static"),
+    /// caching them in kv's namespace for an hour
+    pub fn new(kv: impl Into<Arc<KVAssets<'ah>>>, origin_url: &str) -> Self {
+        Self {
+            kv: kv.into(),
+            origin: origin_url.trim_end_matches('/').to_string(),
+            key_prefix: "origin/".to_string(),
+            ttl: Some(3600),
+        }
+    }
+
+    /// Expiration of pulled values in KV, in seconds (at least 60), or None to keep them.
+    /// default: 3600
+    pub fn with_ttl(mut self, ttl: Option<u64>) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Prefix of KV keys for pulled assets. default: "origin/"
+    pub fn with_key_prefix(mut self, prefix: &str) -> Self {
+        self.key_prefix = prefix.to_string();
+        self
+    }
+
+    /// Returns the asset and its metadata from KV, or from the origin if it isn't in KV.
+    /// Returns Ok(None) if the origin doesn't have it either (404)
+    pub async fn pull(&self, path: &str) -> Result<Option<(AssetMetadata, Bytes)>, Error> {
+        let path = path.strip_prefix('/').unwrap_or(path);
+        check_origin_path(path)?;
+        let key = match self.kv.resolve_key(path)? {
+            Some(ResolvedAsset::Redirect(_)) => return Ok(None),
+            Some(ResolvedAsset::Direct(md)) | Some(ResolvedAsset::Alias { metadata: md, .. }) => {
+                match self.kv.fetch_asset(&md).await {
+                    Ok(body) => return Ok(Some((md, body))),
+                    Err(Error::IndexOutOfDate { key }) => key,
+                    Err(e) => return Err(e),
+                }
+            }
+            None => {
+                let key = format!("{}{}", self.key_prefix, path);
+                match try_join(self.kv.get_kv_value(&key), self.kv.get_kv_metadata(&key)).await {
+                    Ok((body, metadata)) => {
+                        let modified = metadata
+                            .as_ref()
+                            .and_then(|m| m.get("modified"))
+                            .and_then(|m| m.as_u64())
+                            .unwrap_or_default();
+                        return Ok(Some((
+                            pulled_metadata(&key, modified, &body, self.kv.hash_algorithm()),
+                            body,
+                        )));
+                    }
+                    Err(Error::KVKeyNotFound(_, 404)) => key,
+                    Err(e) => return Err(e),
+                }
+            }
+        };
+        let (modified, body) = match self.fetch_origin(path).await? {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+        let md = pulled_metadata(&key, modified, &body, self.kv.hash_algorithm());
+        let mut opts = crate::KvPutOptions::new()
+            .with_metadata(serde_json::json!({ "hash": md.hash, "modified": modified }));
+        opts.expiration_ttl = self.ttl;
+        if let Err(_e) = self.kv.put(&key, body.clone(), &opts).await {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(key = key.as_str(), error = %_e, "caching origin asset");
+        }
+        Ok(Some((md, body)))
+    }
+
+    // GET the path from the origin, returning its Last-Modified time (or 0) and content
+    async fn fetch_origin(&self, path: &str) -> Result<Option<(u64, Bytes)>, Error> {
+        let url = format!("{}/{}", self.origin, path);
+        let mut request = Request::new(Bytes::new());
+        *request.uri_mut() = url
+            .parse()
+            .map_err(|_| Error::Message(format!("invalid origin url {}", url)))?;
+        let response = self.kv.send(request).await?;
+        match response.status() {
+            StatusCode::OK => {}
+            StatusCode::NOT_FOUND => return Ok(None),
+            status => return Err(Error::OriginStatus(url, status.as_u16())),
+        }
+        let modified = response
+            .headers()
+            .get(header::LAST_MODIFIED)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| httpdate::parse_http_date(v).ok())
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        Ok(Some((modified, response.into_body())))
+    }
+}
+
+// Paths with "." or ".." segments (also percent-encoded) or backslashes are rejected:
+// on origins that don't normalize paths, they would fetch outside the origin url
+fn check_origin_path(path: &str) -> Result<(), Error> {
+    let unsafe_segment = path.split('/').any(|segment| {
+        let segment = segment.to_ascii_lowercase().replace("%2e", ".");
+        segment == "." || segment == ".." || segment.contains('\\') || segment.contains("%5c")
+    });
+    match unsafe_segment {
+        true => Err(Error::InvalidKey(path.to_string())),
+        false => Ok(()),
+    }
+}
+
+// Metadata of a pulled asset
+fn pulled_metadata(
+    key: &str,
+    modified: u64,
+    body: &[u8],
+    algorithm: crate::HashAlgorithm,
+) -> AssetMetadata {
+    AssetMetadata {
+        path: key.to_string(),
+        modified,
+        size: body.len() as u64,
+        hash: Some(algorithm.hash(body)),
+        ..Default::default()
+    }
+}
+
+impl<'ah> AssetSource for OriginPull<'ah> {
+    /// Paths not in the index resolve to their pull key, since the origin may have them
+    fn resolve_key(&self, path: &str) -> Result<Option<ResolvedAsset>, Error> {
+        let path = path.strip_prefix('/').unwrap_or(path);
+        match self.kv.resolve_key(path)? {
+            Some(resolved) => Ok(Some(resolved)),
+            None => Ok(Some(ResolvedAsset::Direct(AssetMetadata {
+                path: format!("{}{}", self.key_prefix, path),
+                ..Default::default()
+            }))),
+        }
+    }
+
+    fn fetch_asset<'a>(&'a self, md: &'a AssetMetadata) -> AssetFuture<'a, Result<Bytes, Error>> {
+        Box::pin(async move {
+            match md.path.strip_prefix(self.key_prefix.as_str()) {
+                Some(path) if md.size == 0 && md.hash.is_none() => match self.pull(path).await? {
+                    Some((_, body)) => Ok(body),
+                    None => Err(Error::KVKeyNotFound(md.path.clone(), 404)),
+                },
+                _ => self.kv.fetch_asset(md).await,
+            }
+        })
+    }
+
+    fn get_asset<'a>(&'a self, path: &'a str) -> AssetFuture<'a, Result<Option<Bytes>, Error>> {
+        Box::pin(async move { Ok(self.pull(path).await?.map(|(_, body)| body)) })
+    }
+
+    fn serve<'a>(&'a self, req: &'a Request<()>) -> AssetFuture<'a, Response<Bytes>> {
+        Box::pin(async move {
+            let path = req.uri().path();
+            match self.kv.resolve_key(path) {
+                Ok(Some(ResolvedAsset::Redirect(url))) => return redirect_response(&url),
+                Ok(Some(_)) => {
+                    let response = self.kv.serve(req).await;
+                    if response.status() != StatusCode::NOT_FOUND {
+                        return response;
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => return error_response(&e),
+            }
+            let (md, body) = match self.pull(path).await {
+                Ok(Some(found)) => found,
+                Ok(None) => return status_response(StatusCode::NOT_FOUND),
+                Err(e) => return error_response(&e),
+            };
+            if is_not_modified(req.headers(), &md) {
+                return not_modified_response(&md);
+            }
+            if req.method() == Method::HEAD {
+                return head_response(path, &md, body);
+            }
+            let response = body_response(req, &md, body);
+            #[cfg(feature = "compression")]
+            let response = match self.kv.compression() {
+                Some(compression) if response.status() == StatusCode::OK => {
+                    compression.apply(req.headers(), response)
+                }
+                _ => response,
+            };
+            response
+        })
+    }
+}
+
+/// Tests pulling a missing asset from origin and caching it in KV (does not invoke cloudflare api)
+#[cfg(test)]
+#[tokio::test]
+async fn test_origin_pull() {
+    use crate::{HttpClient, HttpFuture};
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    // origin and KV in one stub: KV values are stored by key
+    #[derive(Default)]
+    struct Stub {
+        kv: Mutex<HashMap<String, Bytes>>,
+        origin_requests: Mutex<Vec<String>>,
+    }
+    impl HttpClient for Stub {
+        fn send(&self, request: http::Request<Bytes>) -> HttpFuture<'_> {
+            let uri = request.uri().to_string();
+            let mut response = http::Response::new(Bytes::new());
+            if let Some(path) = uri.strip_prefix("https://origin.test/") {
+                assert!(request.headers().get(header::AUTHORIZATION).is_none());
+                self.origin_requests.lock().unwrap().push(path.to_string());
+                match path {
+                    "a.css" => *response.body_mut() = Bytes::from("body{}"),
+                    _ => *response.status_mut() = StatusCode::NOT_FOUND,
+                }
+            } else if let Some(key) = uri.split("/values/").nth(1) {
+                // keys are percent-encoded in the url
+                let key = key.split('?').next().unwrap().replace("%2F", "/");
+                let mut kv = self.kv.lock().unwrap();
+                if request.method() == Method::PUT {
+                    kv.insert(key, Bytes::from("body{}"));
+                    *response.body_mut() =
+                        Bytes::from(r#"{"success":true,"errors":[],"messages":[]}"#);
+                } else {
+                    match kv.get(&key) {
+                        Some(value) => *response.body_mut() = value.clone(),
+                        None => *response.status_mut() = StatusCode::NOT_FOUND,
+                    }
+                }
+            } else {
+                // metadata
+                *response.body_mut() = Bytes::from(r#"{"success":true,"result":null}"#);
+            }
+            Box::pin(async move { Ok(response) })
+        }
+    }
+
+    let stub = Arc::new(Stub::default());
+    let kv = KVAssets::init(&[], "acct", "ns", "token").with_http_client(stub.clone());
+    let pull = OriginPull::new(kv, "https://origin.test/");
+
+    let mut req = Request::new(());
+    *req.uri_mut() = "/a.css".parse().unwrap();
+    let resp = pull.serve(&req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.body(), "body{}");
+    assert_eq!(resp.headers()[header::CONTENT_TYPE], "text/css");
+    assert!(stub.kv.lock().unwrap().contains_key("origin/a.css"));
+
+    // second request is served from KV
+    assert_eq!(pull.get_asset("/a.css").await.unwrap().unwrap(), "body{}");
+    assert_eq!(*stub.origin_requests.lock().unwrap(), vec!["a.css"]);
+
+    *req.uri_mut() = "/missing.css".parse().unwrap();
+    assert_eq!(pull.serve(&req).await.status(), StatusCode::NOT_FOUND);
+
+    // paths can't escape the origin url
+    for path in ["/a/../../admin", "/a/%2E%2e/admin", "/./a.css", "/a\\..\\b"].iter() {
+        assert!(matches!(pull.pull(path).await, Err(Error::InvalidKey(_))));
+    }
+    assert_eq!(
+        *stub.origin_requests.lock().unwrap(),
+        vec!["a.css", "missing.css"]
+    );
+}
@@ -0,0 +1,205 @@
+use crate::serve::{
+    asset_response, is_read_method, method_not_allowed_response, not_modified_response,
+    redirect_response, status_response,
+};
+use crate::{AssetMetadata, Error, KVAssets, ResolvedAsset};
+use bytes::Bytes;
+use http::{HeaderMap, Method, Request, Response, StatusCode};
+
+/// Result of `KVAssets::serve_outcome`: what happened, with what's needed
+/// to build the response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServeOutcome {
+    /// Asset found and read from KV
+    Found {
+        /// Asset content
+        body: Bytes,
+        /// Asset metadata (of the alias target, for aliases)
+        metadata: AssetMetadata,
+    },
+    /// The client's copy is current (conditional request headers matched). KV was not read
+    NotModified {
+        /// Asset metadata, for validator headers
+        metadata: AssetMetadata,
+    },
+    /// The path is an alias to an external url
+    Redirect {
+        /// Redirect target
+        location: String,
+    },
+    /// The path is not in the index
+    NotFound,
+    /// The request's claims don't allow access to the path (see `AccessRules`)
+    Forbidden,
+    /// The request method isn't GET or HEAD (see `serve_request_outcome`)
+    MethodNotAllowed,
+    /// The index references a KV key that doesn't exist: the index is out of date,
+    /// or the value expired or was deleted
+    IndexStale {
+        /// Missing KV key
+        key: String,
+    },
+}
+
+impl ServeOutcome {
+    /// Default response for the outcome: 200 with asset headers, 304, 301,
+    /// 403, 405 with `Allow: GET, HEAD`, or 404 (for both NotFound and IndexStale)
+    pub fn into_response(self, path: &str) -> Response<Bytes> {
+        match self {
+            ServeOutcome::Found { body, metadata } => asset_response(path, &metadata, body),
+            ServeOutcome::NotModified { metadata } => not_modified_response(&metadata),
+            ServeOutcome::Redirect { location } => redirect_response(&location),
+            ServeOutcome::Forbidden => status_response(StatusCode::FORBIDDEN),
+            ServeOutcome::MethodNotAllowed => method_not_allowed_response(),
+            ServeOutcome::NotFound | ServeOutcome::IndexStale { .. } => {
+                status_response(StatusCode::NOT_FOUND)
+            }
+        }
+    }
+}
+
+impl<'ah> KVAssets<'ah> {
+    /// Looks up the path, evaluates conditional request headers (If-None-Match,
+    /// If-Modified-Since), and reads the asset from KV if needed.
+    /// Unlike `get_asset`, distinguishes not-modified, redirects, and a stale index
+    /// from not found. Other failures (api errors, checksum mismatch) are errors.
+    pub async fn serve_outcome(
+        &self,
+        path: &str,
+        request_headers: &HeaderMap,
+    ) -> Result<ServeOutcome, Error> {
+        self.outcome(path, request_headers, true).await
+    }
+
+    /// Outcome of the request, as `serve_outcome` for its path and headers, routed by
+    /// method: GET reads the asset, HEAD is answered from the index without reading KV
+    /// (`Found` has an empty body), and other methods are `MethodNotAllowed`, for
+    /// handlers mounted as a catch-all fallback
+    pub async fn serve_request_outcome(&self, req: &Request<()>) -> Result<ServeOutcome, Error> {
+        if !is_read_method(req.method()) {
+            return Ok(ServeOutcome::MethodNotAllowed);
+        }
+        let fetch = req.method() == Method::GET;
+        self.outcome(req.uri().path(), req.headers(), fetch).await
+    }
+
+    async fn outcome(
+        &self,
+        path: &str,
+        request_headers: &HeaderMap,
+        fetch: bool,
+    ) -> Result<ServeOutcome, Error> {
+        let metadata = match self.resolve_key(path)? {
+            Some(ResolvedAsset::Direct(md)) | Some(ResolvedAsset::Alias { metadata: md, .. }) => md,
+            Some(ResolvedAsset::Redirect(location)) => {
+                return Ok(ServeOutcome::Redirect { location })
+            }
+            None => return Ok(ServeOutcome::NotFound),
+        };
+        if crate::serve::is_not_modified(request_headers, &metadata) {
+            return Ok(ServeOutcome::NotModified { metadata });
+        }
+        if !fetch {
+            return Ok(ServeOutcome::Found {
+                body: Bytes::new(),
+                metadata,
+            });
+        }
+        match self.fetch_asset(&metadata).await {
+            Ok(body) => Ok(ServeOutcome::Found { body, metadata }),
+            Err(Error::IndexOutOfDate { key }) => Ok(ServeOutcome::IndexStale { key }),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Tests outcomes other than found (does not invoke cloudflare api)
+#[cfg(test)]
+#[tokio::test]
+async fn test_serve_outcome() {
+    use crate::{AssetIndex, HttpClient, HttpFuture};
+    use std::sync::Arc;
+
+    struct Missing;
+    impl HttpClient for Missing {
+        fn send(&self, _: http::Request<Bytes>) -> HttpFuture<'_> {
+            Box::pin(async {
+                let mut response = Response::new(Bytes::new());
+                *response.status_mut() = StatusCode::NOT_FOUND;
+                Ok(response)
+            })
+        }
+    }
+
+    let md = AssetMetadata {
+        path: "a.1.txt".to_string(),
+        modified: 1_600_000_000,
+        size: 5,
+        hash: Some("abc".to_string()),
+        ..Default::default()
+    };
+    let mut index = AssetIndex::new();
+    index.insert("a.txt".to_string(), md.clone());
+    index.insert(
+        "b".to_string(),
+        AssetMetadata::redirect("https://example.com/"),
+    );
+    let blob = crate::encode_index(&index).unwrap();
+    let kv = KVAssets::init(&blob, "acct", "ns", "token").with_http_client(Arc::new(Missing));
+
+    let none = HeaderMap::new();
+    assert_eq!(
+        kv.serve_outcome("/a.txt", &none).await.unwrap(),
+        ServeOutcome::IndexStale {
+            key: "a.1.txt".to_string()
+        }
+    );
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        http::header::IF_NONE_MATCH,
+        http::HeaderValue::from_static("\"abc\""),
+    );
+    assert_eq!(
+        kv.serve_outcome("/a.txt", &headers).await.unwrap(),
+        ServeOutcome::NotModified { metadata: md }
+    );
+    let redirect = kv.serve_outcome("/b", &none).await.unwrap();
+    assert_eq!(
+        redirect,
+        ServeOutcome::Redirect {
+            location: "https://example.com/".to_string()
+        }
+    );
+    assert_eq!(
+        redirect.into_response("/b").status(),
+        StatusCode::MOVED_PERMANENTLY
+    );
+    assert_eq!(
+        kv.serve_outcome("/c", &none).await.unwrap(),
+        ServeOutcome::NotFound
+    );
+
+    // routing by method
+    let request = |method: Method| Request::builder().method(method).uri("/a.txt").body(());
+    let outcome = kv
+        .serve_request_outcome(&request(Method::POST).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(outcome, ServeOutcome::MethodNotAllowed);
+    let response = outcome.into_response("/a.txt");
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(response.headers()[http::header::ALLOW], "GET, HEAD");
+    match kv
+        .serve_request_outcome(&request(Method::HEAD).unwrap())
+        .await
+        .unwrap()
+    {
+        ServeOutcome::Found { body, metadata } => {
+            assert!(body.is_empty());
+            assert_eq!(metadata.size, 5);
+        }
+        other => panic!("expected Found, got {:?}", other),
+    }
+    let response = kv.serve(&request(Method::DELETE).unwrap()).await;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+}
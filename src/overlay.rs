@@ -0,0 +1,104 @@
+use crate::Error;
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Read-your-writes overlay for an eventually consistent namespace.
+/// KV can take up to a minute to propagate writes, so a read right after
+/// `put_kv_value` may 404 or return the previous value. With an overlay installed
+/// (`KVAssets::with_write_overlay`), values written or deleted through the handler
+/// are served from memory for the `window` after the write, so deploy smoke tests
+/// and reads that immediately follow a publish see the new content.
+///
+/// Values are held in memory until the window elapses: use a short window
+/// (about a minute) and don't enable it on handlers that write large volumes.
+/// The clock defaults to the system clock; on wasm, supply one with `with_clock`.
+pub struct WriteOverlay {
+    window_ms: u64,
+    clock: fn() -> u64,
+    // key -> (time written, value, or None if deleted)
+    entries: Mutex<HashMap<String, (u64, Option<Bytes>)>>,
+}
+
+impl WriteOverlay {
+    /// Serve written values from memory for window after each write
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window_ms: window.as_millis() as u64,
+            clock: crate::breaker::system_millis,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Use a clock returning the current time in milliseconds
+    pub fn with_clock(mut self, clock: fn() -> u64) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Number of keys currently in the overlay
+    pub fn len(&self) -> usize {
+        let now = (self.clock)();
+        let mut entries = self.entries.lock().unwrap();
+        self.expire(&mut entries, now);
+        entries.len()
+    }
+
+    /// Returns true if the overlay holds no keys
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Record a write (Some) or delete (None) of the key
+    pub(crate) fn record(&self, key: &str, value: Option<Bytes>) {
+        let now = (self.clock)();
+        let mut entries = self.entries.lock().unwrap();
+        self.expire(&mut entries, now);
+        entries.insert(key.to_string(), (now, value));
+    }
+
+    /// Value recently written to the key: Some(Ok) if written, Some(Err(KVKeyNotFound))
+    /// if deleted, or None if the key wasn't written within the window
+    pub(crate) fn get(&self, key: &str) -> Option<Result<Bytes, Error>> {
+        let now = (self.clock)();
+        let entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some((written, value)) if now.saturating_sub(*written) < self.window_ms => Some(
+                value
+                    .clone()
+                    .ok_or_else(|| Error::KVKeyNotFound(key.to_string(), 404)),
+            ),
+            _ => None,
+        }
+    }
+
+    fn expire(&self, entries: &mut HashMap<String, (u64, Option<Bytes>)>, now: u64) {
+        entries.retain(|_, (written, _)| now.saturating_sub(*written) < self.window_ms);
+    }
+}
+
+/// Tests overlay window and deletes
+#[test]
+fn test_write_overlay() {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    static NOW: AtomicU64 = AtomicU64::new(1000);
+    fn clock() -> u64 {
+        NOW.load(Ordering::Relaxed)
+    }
+
+    let overlay = WriteOverlay::new(Duration::from_secs(60)).with_clock(clock);
+    overlay.record("a", Some(Bytes::from("new")));
+    overlay.record("b", None);
+    assert_eq!(overlay.get("a").unwrap().unwrap(), "new");
+    assert!(matches!(
+        overlay.get("b"),
+        Some(Err(Error::KVKeyNotFound(_, 404)))
+    ));
+    assert!(overlay.get("c").is_none());
+    assert_eq!(overlay.len(), 2);
+
+    NOW.fetch_add(60_000, Ordering::Relaxed);
+    assert!(overlay.get("a").is_none());
+    assert!(overlay.is_empty());
+}
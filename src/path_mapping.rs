@@ -0,0 +1,110 @@
+use std::borrow::Cow;
+
+/// Maps request paths (URL space) to index paths, for handlers whose index doesn't
+/// mirror the public URL structure. Install with `KVAssets::with_path_mapping`.
+/// The url prefix is removed, then the index prefix added, so with
+/// `PathMapping::new().strip_prefix("/static").add_prefix("v42")`,
+/// `/static/app.js` is looked up as `v42/app.js`. Paths outside the url prefix aren't
+/// found. Prefixes match whole path segments. Alias targets in the index are index
+/// paths, and aren't mapped.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PathMapping {
+    // without leading or trailing '/'
+    strip: String,
+    // with trailing '/', or empty
+    add: String,
+}
+
+impl PathMapping {
+    /// Mapping that leaves paths unchanged
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// URL prefix removed from request paths, such as "/static"
+    pub fn strip_prefix(mut self, prefix: &str) -> Self {
+        self.strip = prefix.trim_matches('/').to_string();
+        self
+    }
+
+    /// Index prefix added to request paths, such as a deploy version "v42"
+    pub fn add_prefix(mut self, prefix: &str) -> Self {
+        self.add = match prefix.trim_matches('/') {
+            "" => String::new(),
+            prefix => format!("{}/", prefix),
+        };
+        self
+    }
+
+    /// Index path for the request path (with or without its leading '/'),
+    /// or None if it is outside the url prefix
+    pub fn map<'p>(&self, path: &'p str) -> Option<Cow<'p, str>> {
+        let path = path.strip_prefix('/').unwrap_or(path);
+        let rest = match self.strip.as_str() {
+            "" => path,
+            strip => match path.strip_prefix(strip)? {
+                "" => "",
+                rest => rest.strip_prefix('/')?,
+            },
+        };
+        match self.add.as_str() {
+            "" => Some(Cow::Borrowed(rest)),
+            add => Some(Cow::Owned(format!("{}{}", add, rest))),
+        }
+    }
+}
+
+/// Tests mapping request paths to index paths
+#[test]
+fn test_path_mapping() {
+    let mapping = PathMapping::new().strip_prefix("/static/");
+    assert_eq!(mapping.map("/static/app.js").unwrap(), "app.js");
+    assert_eq!(mapping.map("/static").unwrap(), "");
+    assert_eq!(mapping.map("/staticfiles/app.js"), None);
+    assert_eq!(mapping.map("/app.js"), None);
+
+    let mapping = mapping.add_prefix("v42");
+    assert_eq!(mapping.map("/static/css/a.css").unwrap(), "v42/css/a.css");
+    let mapping = PathMapping::new().add_prefix("/v42/");
+    assert_eq!(mapping.map("app.js").unwrap(), "v42/app.js");
+    assert_eq!(PathMapping::new().map("/a/b").unwrap(), "a/b");
+}
+
+/// Tests serving mapped paths (does not invoke cloudflare api)
+#[cfg(test)]
+#[tokio::test]
+async fn test_serve_mapped() {
+    use crate::{AssetIndex, AssetMetadata, HttpClient, HttpFuture, KVAssets};
+    use bytes::Bytes;
+    use std::sync::Arc;
+
+    struct Stub;
+    impl HttpClient for Stub {
+        fn send(&self, request: http::Request<Bytes>) -> HttpFuture<'_> {
+            assert!(request.uri().path().ends_with("/values/v42%2Fapp.1.js"));
+            Box::pin(async { Ok(http::Response::new(Bytes::from("js"))) })
+        }
+    }
+
+    let mut index = AssetIndex::new();
+    index.insert(
+        "v42/app.js".to_string(),
+        AssetMetadata {
+            path: "v42/app.1.js".to_string(),
+            size: 2,
+            ..Default::default()
+        },
+    );
+    let bytes = crate::encode_index(&index).unwrap();
+    let kv = KVAssets::init(&bytes, "acct", "ns", "token")
+        .with_http_client(Arc::new(Stub))
+        .with_path_mapping(PathMapping::new().strip_prefix("static").add_prefix("v42"));
+    assert!(kv.lookup_key("/static/app.js").unwrap().is_some());
+    assert!(kv.lookup_key("/v42/app.js").unwrap().is_none());
+    assert!(kv.lookup_key("/static").unwrap().is_none());
+
+    let req = http::Request::get("/static/app.js").body(()).unwrap();
+    let response = kv.serve(&req).await;
+    assert_eq!(response.status(), http::StatusCode::OK);
+    assert_eq!(response.body().as_ref(), b"js");
+}
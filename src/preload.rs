@@ -0,0 +1,228 @@
+use crate::ttl::glob_match;
+use crate::{Error, KVAssets};
+use bytes::Bytes;
+use http::{header, HeaderMap, HeaderValue, Request, Response, StatusCode};
+use std::collections::BTreeMap;
+
+/// Key in `AssetMetadata::extra` listing the assets an HTML page preloads,
+/// separated by commas, such as "app.css, app.js"
+pub const PRELOAD_KEY: &str = "preload";
+
+/// Preload relationships of HTML pages, selected by glob patterns on the page path
+/// (as for `TtlRules`). Every matching rule applies. Install with
+/// `KVAssets::with_preload_rules`; pages can also list their preloads in the index
+/// (see `PRELOAD_KEY`). `serve` adds a `Link: <…>; rel=preload` header for each
+/// asset to HTML responses, and `KVAssets::early_hints` builds a 103 Early Hints
+/// response with them.
+///
+/// ```
+/// # use kv_assets::PreloadRules;
+/// let rules = PreloadRules::new()
+///     .preload("**/*.html", &["/css/site.css"])
+///     .preload("index.html", &["/app.js"]);
+/// assert_eq!(rules.preloads_for("/index.html"), vec!["/css/site.css", "/app.js"]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PreloadRules {
+    // page pattern, and url paths of the assets it preloads
+    rules: Vec<(String, Vec<String>)>,
+}
+
+impl PreloadRules {
+    /// No rules
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pages matching pattern preload the assets, by url path
+    pub fn preload(mut self, pattern: &str, assets: &[&str]) -> Self {
+        let assets = assets.iter().map(|a| url_path(a)).collect();
+        self.rules
+            .push((pattern.trim_start_matches('/').to_string(), assets));
+        self
+    }
+
+    /// Rules from a JSON object of page pattern to the asset paths it preloads,
+    /// such as `{"**/*.html": ["/css/site.css"], "index.html": ["/app.js"]}`
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let rules: BTreeMap<String, Vec<String>> = serde_json::from_str(json)
+            .map_err(|e| Error::Message(format!("invalid preload rules: {}", e)))?;
+        Ok(rules
+            .into_iter()
+            .fold(Self::new(), |acc, (pattern, assets)| {
+                let assets: Vec<&str> = assets.iter().map(String::as_str).collect();
+                acc.preload(&pattern, &assets)
+            }))
+    }
+
+    /// Returns true if there are no rules
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Url paths of the assets preloaded by the page at path, without duplicates
+    pub fn preloads_for(&self, path: &str) -> Vec<String> {
+        let path = path.trim_start_matches('/');
+        let mut preloads = Vec::new();
+        for (_, assets) in self
+            .rules
+            .iter()
+            .filter(|(pattern, _)| glob_match(pattern.as_bytes(), path.as_bytes()))
+        {
+            for asset in assets {
+                if !preloads.contains(asset) {
+                    preloads.push(asset.clone());
+                }
+            }
+        }
+        preloads
+    }
+}
+
+impl<'ah> KVAssets<'ah> {
+    /// Url paths of the assets preloaded by the page at path: from the preload rules
+    /// (see `with_preload_rules`), then from the page's index entry (see `PRELOAD_KEY`)
+    pub fn preloads_for(&self, path: &str) -> Result<Vec<String>, Error> {
+        let mut preloads = self
+            .preload_rules()
+            .map(|rules| rules.preloads_for(path))
+            .unwrap_or_default();
+        if let Some(md) = self.lookup_key(path)? {
+            let listed = md.extra.get(PRELOAD_KEY).map(String::as_str);
+            for asset in listed.unwrap_or_default().split(',') {
+                let asset = asset.trim();
+                if !asset.is_empty() && !preloads.iter().any(|p| p == &url_path(asset)) {
+                    preloads.push(url_path(asset));
+                }
+            }
+        }
+        Ok(preloads)
+    }
+
+    /// 103 Early Hints response for the request, with a `Link` header for each asset
+    /// the requested page preloads, for servers that can send informational responses
+    /// before the final response. Returns None if the page preloads nothing
+    pub fn early_hints(&self, req: &Request<()>) -> Option<Response<Bytes>> {
+        let preloads = self.preloads_for(req.uri().path()).ok()?;
+        if preloads.is_empty() {
+            return None;
+        }
+        let mut response = Response::new(Bytes::new());
+        // http 0.2 has no constant for 103
+        *response.status_mut() = StatusCode::from_u16(103).expect("valid status");
+        add_links(response.headers_mut(), &preloads);
+        Some(response)
+    }
+
+    // Add preload Link headers to an HTML response for the page at path
+    pub(crate) fn apply_preloads(&self, path: &str, response: &mut Response<Bytes>) {
+        if response.status() != StatusCode::OK || !is_html(response.headers()) {
+            return;
+        }
+        if let Ok(preloads) = self.preloads_for(path) {
+            add_links(response.headers_mut(), &preloads);
+        }
+    }
+}
+
+fn add_links(headers: &mut HeaderMap, preloads: &[String]) {
+    for asset in preloads {
+        if let Ok(value) = HeaderValue::from_str(&link_value(asset)) {
+            headers.append(header::LINK, value);
+        }
+    }
+}
+
+// Link header value preloading the asset, with the destination for its type
+fn link_value(asset: &str) -> String {
+    let ext = asset
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase())
+        .unwrap_or_default();
+    let destination = match ext.as_str() {
+        "css" => "style",
+        "js" | "mjs" => "script",
+        "woff" | "woff2" | "ttf" | "otf" => "font; crossorigin",
+        "png" | "jpg" | "jpeg" | "gif" | "webp" | "avif" | "svg" => "image",
+        _ => "fetch; crossorigin",
+    };
+    format!("<{}>; rel=preload; as={}", asset, destination)
+}
+
+fn url_path(asset: &str) -> String {
+    match asset.starts_with('/') {
+        true => asset.to_string(),
+        false => format!("/{}", asset),
+    }
+}
+
+fn is_html(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/html"))
+}
+
+/// Tests preload headers on HTML responses (does not invoke cloudflare api)
+#[cfg(test)]
+#[tokio::test]
+async fn test_preload() {
+    use crate::{AssetIndex, AssetMetadata, HttpClient, HttpFuture};
+    use std::sync::Arc;
+
+    struct Stub;
+    impl HttpClient for Stub {
+        fn send(&self, _: Request<Bytes>) -> HttpFuture<'_> {
+            Box::pin(async { Ok(Response::new(Bytes::from("<html></html>"))) })
+        }
+    }
+
+    let mut index = AssetIndex::new();
+    for path in ["index.html", "about.html", "app.js"] {
+        let mut md = AssetMetadata {
+            path: path.to_string(),
+            ..Default::default()
+        };
+        if path == "index.html" {
+            md.extra.insert(
+                PRELOAD_KEY.to_string(),
+                "app.js, /fonts/a.woff2".to_string(),
+            );
+        }
+        index.insert(path.to_string(), md);
+    }
+    let bytes = crate::encode_index(&index).unwrap();
+    let rules = PreloadRules::from_json(r#"{"**/*.html": ["/css/site.css", "app.js"]}"#).unwrap();
+    let kv = KVAssets::init(&bytes, "acct", "ns", "token")
+        .with_http_client(Arc::new(Stub))
+        .with_preload_rules(rules);
+    assert_eq!(
+        kv.preloads_for("/index.html").unwrap(),
+        vec!["/css/site.css", "/app.js", "/fonts/a.woff2"]
+    );
+
+    let get = |path: &str| Request::get(path).body(()).unwrap();
+    let response = kv.serve(&get("/index.html")).await;
+    let links: Vec<_> = response
+        .headers()
+        .get_all(header::LINK)
+        .iter()
+        .map(|v| v.to_str().unwrap())
+        .collect();
+    assert_eq!(
+        links,
+        vec![
+            "</css/site.css>; rel=preload; as=style",
+            "</app.js>; rel=preload; as=script",
+            "</fonts/a.woff2>; rel=preload; as=font; crossorigin",
+        ]
+    );
+    let response = kv.serve(&get("/app.js")).await;
+    assert!(response.headers().get(header::LINK).is_none());
+
+    let hints = kv.early_hints(&get("/about.html")).unwrap();
+    assert_eq!(hints.status().as_u16(), 103);
+    assert_eq!(hints.headers().get_all(header::LINK).iter().count(), 2);
+    assert!(kv.early_hints(&get("/app.js")).is_none());
+    assert!(PreloadRules::from_json("[]").is_err());
+}
@@ -0,0 +1,262 @@
+//! Named account/namespace/token settings (feature "profiles"), for deploy tooling
+//! managing several sites or environments (e.g., `staging`, `production`, and `docs`).
+//!
+//! Profiles are loaded from a TOML file with a table per profile:
+//!
+//! ```toml
+//! [production]
+//! account_id = "..."
+//! namespace_id = "..."
+//! preview_namespace_id = "..."   # optional
+//! auth_token_env = "PROD_CF_TOKEN" # or auth_token = "...", to keep tokens out of the file
+//! ```
+//!
+//! or from environment variables `KV_ASSETS_PROFILE_<NAME>_ACCOUNT_ID`,
+//! `..._NAMESPACE_ID`, `..._PREVIEW_NAMESPACE_ID`, and `..._AUTH_TOKEN`
+//! (profile names are lowercase).
+#![cfg(feature = "profiles")]
+
+use crate::{Error, KVAssets, KVAssetsBuilder};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Environment variable with the path of the profiles file, used by `Profiles::load_default`
+pub const ENV_PROFILES_FILE: &str = "KV_ASSETS_PROFILES";
+/// Profiles file used by `Profiles::load_default` if `KV_ASSETS_PROFILES` is not set
+pub const DEFAULT_PROFILES_FILE: &str = "kv-assets.toml";
+// Prefix of profile environment variables
+const ENV_PREFIX: &str = "KV_ASSETS_PROFILE_";
+
+/// Settings for one account and namespace
+#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq)]
+pub struct Profile {
+    /// Cloudflare account id
+    pub account_id: Option<String>,
+    /// KV namespace id
+    pub namespace_id: Option<String>,
+    /// Preview KV namespace id, paired with namespace_id
+    pub preview_namespace_id: Option<String>,
+    /// Cloudflare api token
+    pub auth_token: Option<String>,
+    /// Environment variable holding the api token, used if auth_token is not set
+    pub auth_token_env: Option<String>,
+}
+
+impl Profile {
+    /// Api token, from auth_token or the variable named by auth_token_env
+    pub fn auth_token(&self) -> Option<String> {
+        self.auth_token.clone().or_else(|| {
+            self.auth_token_env
+                .as_deref()
+                .and_then(|name| std::env::var(name).ok())
+                .filter(|v| !v.is_empty())
+        })
+    }
+
+    // Fill settings not set here from other
+    fn merge(&mut self, other: Profile) {
+        self.account_id = self.account_id.take().or(other.account_id);
+        self.namespace_id = self.namespace_id.take().or(other.namespace_id);
+        self.preview_namespace_id = self
+            .preview_namespace_id
+            .take()
+            .or(other.preview_namespace_id);
+        self.auth_token = self.auth_token.take().or(other.auth_token);
+        self.auth_token_env = self.auth_token_env.take().or(other.auth_token_env);
+    }
+}
+
+/// Named profiles
+#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Profiles {
+    profiles: BTreeMap<String, Profile>,
+}
+
+impl Profiles {
+    /// Create empty set of profiles
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load and parse a profiles file
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|_| Error::MissingWranglerFile(path.display().to_string()))?;
+        Self::parse(&text)
+    }
+
+    /// Parse profiles file contents
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        toml::from_str(text).map_err(|e| Error::ProfileConfig(e.to_string()))
+    }
+
+    /// Profiles from `KV_ASSETS_PROFILE_*` environment variables
+    pub fn from_env() -> Self {
+        Self::from_vars(std::env::vars())
+    }
+
+    fn from_vars(vars: impl Iterator<Item = (String, String)>) -> Self {
+        // longest suffix first: PREVIEW_NAMESPACE_ID also ends with NAMESPACE_ID
+        const FIELDS: [&str; 4] = [
+            "_PREVIEW_NAMESPACE_ID",
+            "_NAMESPACE_ID",
+            "_ACCOUNT_ID",
+            "_AUTH_TOKEN",
+        ];
+        let mut profiles = Self::new();
+        for (name, value) in vars.filter(|(_, v)| !v.is_empty()) {
+            let rest = match name.strip_prefix(ENV_PREFIX) {
+                Some(rest) => rest,
+                None => continue,
+            };
+            if let Some((field, profile)) = FIELDS.iter().find_map(|field| {
+                let profile = rest.strip_suffix(field).filter(|p| !p.is_empty())?;
+                Some((*field, profile.to_ascii_lowercase()))
+            }) {
+                let profile = profiles.profiles.entry(profile).or_default();
+                let value = Some(value);
+                match field {
+                    "_PREVIEW_NAMESPACE_ID" => profile.preview_namespace_id = value,
+                    "_NAMESPACE_ID" => profile.namespace_id = value,
+                    "_ACCOUNT_ID" => profile.account_id = value,
+                    _ => profile.auth_token = value,
+                }
+            }
+        }
+        profiles
+    }
+
+    /// Profiles from the file named by `KV_ASSETS_PROFILES`, or `kv-assets.toml` in the
+    /// current directory if it exists, with settings from environment variables
+    /// taking precedence
+    pub fn load_default() -> Result<Self, Error> {
+        let mut profiles = match std::env::var(ENV_PROFILES_FILE) {
+            Ok(path) if !path.is_empty() => Self::load(Path::new(&path))?,
+            _ if Path::new(DEFAULT_PROFILES_FILE).exists() => {
+                Self::load(Path::new(DEFAULT_PROFILES_FILE))?
+            }
+            _ => Self::new(),
+        };
+        profiles.merge(Self::from_env());
+        Ok(profiles)
+    }
+
+    /// Add settings from other. Where both set a value, other takes precedence
+    pub fn merge(&mut self, other: Profiles) {
+        for (name, profile) in other.profiles {
+            let mut profile = profile;
+            if let Some(existing) = self.profiles.remove(&name) {
+                profile.merge(existing);
+            }
+            self.profiles.insert(name, profile);
+        }
+    }
+
+    /// Add or replace a profile
+    pub fn insert(&mut self, name: impl Into<String>, profile: Profile) {
+        self.profiles.insert(name.into(), profile);
+    }
+
+    /// Returns the profile
+    pub fn get(&self, name: &str) -> Option<&Profile> {
+        self.profiles.get(name)
+    }
+
+    /// Profile names, in sorted order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(String::as_str)
+    }
+
+    /// Create a handler from the named profile. Returns `Error::UnknownProfile`
+    /// if there is no such profile, or `Error::MissingConfig` if it is incomplete
+    pub fn handler<'ah>(&self, index: &'ah [u8], name: &str) -> Result<KVAssets<'ah>, Error> {
+        let profile = self
+            .get(name)
+            .ok_or_else(|| Error::UnknownProfile(name.to_string()))?;
+        KVAssetsBuilder::new().index(index).profile(profile).build()
+    }
+}
+
+impl<'ah> KVAssetsBuilder<'ah> {
+    /// Take the account id, namespace ids, and api token from the profile
+    pub fn profile(mut self, profile: &Profile) -> Self {
+        if let Some(account_id) = &profile.account_id {
+            self = self.account_id(account_id.clone());
+        }
+        if let Some(namespace_id) = &profile.namespace_id {
+            self = self.namespace_id(namespace_id.clone());
+        }
+        if let Some(namespace_id) = &profile.preview_namespace_id {
+            self = self.preview_namespace_id(namespace_id.clone());
+        }
+        if let Some(auth_token) = profile.auth_token() {
+            self = self.auth_token(auth_token);
+        }
+        self
+    }
+}
+
+impl<'ah> KVAssets<'ah> {
+    /// Initialize handler from the named profile, loaded with `Profiles::load_default`
+    pub fn from_profile(index: &'ah [u8], name: &str) -> Result<Self, Error> {
+        Profiles::load_default()?.handler(index, name)
+    }
+}
+
+/// Tests parsing and merging of profiles
+#[test]
+fn test_profiles() {
+    let mut profiles = Profiles::parse(
+        r#"
+        [production]
+        account_id = "acct"
+        namespace_id = "prod-ns"
+        auth_token = "file-token"
+
+        [docs]
+        account_id = "acct"
+        namespace_id = "docs-ns"
+        "#,
+    )
+    .unwrap();
+    profiles.merge(Profiles::from_vars(
+        [
+            ("KV_ASSETS_PROFILE_PRODUCTION_AUTH_TOKEN", "env-token"),
+            (
+                "KV_ASSETS_PROFILE_PRODUCTION_PREVIEW_NAMESPACE_ID",
+                "prev-ns",
+            ),
+            ("KV_ASSETS_PROFILE_MY_SITE_NAMESPACE_ID", "site-ns"),
+            ("KV_ASSETS_PROFILE_MY_SITE_ACCOUNT_ID", "acct2"),
+            ("OTHER_VAR", "x"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string())),
+    ));
+    assert_eq!(
+        profiles.names().collect::<Vec<_>>(),
+        vec!["docs", "my_site", "production"]
+    );
+    let production = profiles.get("production").unwrap();
+    assert_eq!(production.auth_token.as_deref(), Some("env-token"));
+    assert_eq!(production.namespace_id.as_deref(), Some("prod-ns"));
+    assert_eq!(production.preview_namespace_id.as_deref(), Some("prev-ns"));
+    assert_eq!(
+        profiles.get("my_site").unwrap().account_id.as_deref(),
+        Some("acct2")
+    );
+
+    let kv = profiles.handler(&[], "production").unwrap();
+    assert_eq!(kv.namespace_id().unwrap(), "prod-ns");
+    assert!(matches!(
+        profiles.handler(&[], "docs"),
+        Err(Error::MissingConfig(_))
+    ));
+    assert!(matches!(
+        profiles.handler(&[], "missing"),
+        Err(Error::UnknownProfile(_))
+    ));
+    assert!(Profiles::parse("production = 1").is_err());
+}
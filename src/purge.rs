@@ -0,0 +1,109 @@
+use crate::{namespaces::ApiResponse, Error, IndexDiff, KVAssets};
+use bytes::Bytes;
+use http::Method;
+
+/// Maximum number of urls or prefixes in one purge request
+pub const PURGE_BATCH_SIZE: usize = 30;
+
+/// What `KVAssets::purge_cache` removes from the Cloudflare edge cache
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CachePurge {
+    /// Complete urls, such as `https://example.com/css/site.css`
+    Files(Vec<String>),
+    /// Url prefixes without the scheme, such as `example.com/css/`
+    /// (prefix purges require an Enterprise plan)
+    Prefixes(Vec<String>),
+    /// Everything cached for the zone
+    Everything,
+}
+
+impl CachePurge {
+    /// Urls of the paths changed between two indexes (see `IndexDiff::changed_paths`),
+    /// under base_url (e.g., `https://example.com`)
+    pub fn changed(base_url: &str, diff: &IndexDiff) -> Self {
+        let base_url = base_url.trim_end_matches('/');
+        CachePurge::Files(
+            diff.changed_paths()
+                .iter()
+                .map(|path| format!("{}/{}", base_url, path.trim_start_matches('/')))
+                .collect(),
+        )
+    }
+
+    // Request bodies, in batches of up to PURGE_BATCH_SIZE
+    fn bodies(&self) -> Vec<Bytes> {
+        let (field, items) = match self {
+            CachePurge::Files(urls) => ("files", urls),
+            CachePurge::Prefixes(prefixes) => ("prefixes", prefixes),
+            CachePurge::Everything => {
+                return vec![Bytes::from(r#"{"purge_everything":true}"#)];
+            }
+        };
+        items
+            .chunks(PURGE_BATCH_SIZE)
+            .map(|batch| Bytes::from(serde_json::json!({ field: batch }).to_string()))
+            .collect()
+    }
+}
+
+impl<'ah> KVAssets<'ah> {
+    /// Purge cached copies from the Cloudflare edge cache of the zone, so updated
+    /// assets are served after a deploy. Use `CachePurge::changed` to purge only the
+    /// paths changed by the deploy. Large purges are sent in batches of `PURGE_BATCH_SIZE`.
+    /// The api token needs the Zone "Cache Purge" permission.
+    pub async fn purge_cache(&self, zone_id: &str, purge: &CachePurge) -> Result<(), Error> {
+        let url = self.api_url(&format!("zones/{}/purge_cache", zone_id));
+        for body in purge.bodies() {
+            self.rate_limit().await;
+            let _: ApiResponse<serde_json::Value> = self
+                .namespace_api(Method::POST, &url, body, "purging cache")
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Tests purge request batching (does not invoke cloudflare api)
+#[cfg(test)]
+#[tokio::test]
+async fn test_purge_cache() {
+    use crate::{AssetIndex, AssetIndexExt, AssetMetadata, HttpClient, HttpFuture};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Stub {
+        bodies: Mutex<Vec<(String, serde_json::Value)>>,
+    }
+    impl HttpClient for Stub {
+        fn send(&self, request: http::Request<Bytes>) -> HttpFuture<'_> {
+            self.bodies.lock().unwrap().push((
+                request.uri().path().to_string(),
+                serde_json::from_slice(request.body()).unwrap(),
+            ));
+            Box::pin(async {
+                Ok(http::Response::new(Bytes::from(
+                    r#"{"success":true,"errors":[],"result":{"id":"z"}}"#,
+                )))
+            })
+        }
+    }
+
+    let mut new = AssetIndex::new();
+    for i in 0..31 {
+        new.insert(format!("{}.css", i), AssetMetadata::default());
+    }
+    let purge = CachePurge::changed(
+        "https://example.com/",
+        &AssetIndex::diff(&AssetIndex::new(), &new),
+    );
+    let stub = Arc::new(Stub::default());
+    let kv = KVAssets::init(&[], "acct", "ns", "token").with_http_client(stub.clone());
+    kv.purge_cache("zone1", &purge).await.unwrap();
+
+    let bodies = stub.bodies.lock().unwrap();
+    assert_eq!(bodies.len(), 2);
+    assert_eq!(bodies[0].0, "/client/v4/zones/zone1/purge_cache");
+    assert_eq!(bodies[0].1["files"][0], "https://example.com/0.css");
+    assert_eq!(bodies[0].1["files"].as_array().unwrap().len(), 30);
+    assert_eq!(bodies[1].1["files"].as_array().unwrap().len(), 1);
+}
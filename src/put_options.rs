@@ -0,0 +1,109 @@
+use crate::Error;
+
+/// Options for writing a KV value with `KVAssets::put_kv_value_opts`.
+/// For a value with at most a TTL, `put_kv_value` is simpler.
+///
+/// ```
+/// use kv_assets::KvPutOptions;
+/// let opts = KvPutOptions::new()
+///     .with_expiration_ttl(3600)
+///     .with_metadata(serde_json::json!({ "source": "upload" }))
+///     .with_content_type("image/png");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KvPutOptions {
+    pub(crate) expiration_ttl: Option<u64>,
+    pub(crate) expiration: Option<u64>,
+    pub(crate) metadata: Option<serde_json::Value>,
+    pub(crate) content_type: Option<String>,
+}
+
+impl KvPutOptions {
+    /// Options with no expiration, metadata, or content type
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delete the value this many seconds after it is written. Must be at least 60.
+    /// Replaces any absolute expiration
+    pub fn with_expiration_ttl(mut self, seconds: u64) -> Self {
+        self.expiration_ttl = Some(seconds);
+        self.expiration = None;
+        self
+    }
+
+    /// Delete the value at this time, in seconds since the unix epoch.
+    /// Must be at least 60 seconds in the future. Replaces any TTL
+    pub fn with_expiration(mut self, unix_secs: u64) -> Self {
+        self.expiration = Some(unix_secs);
+        self.expiration_ttl = None;
+        self
+    }
+
+    /// Store json metadata with the value (read it with `get_kv_metadata`)
+    pub fn with_metadata(mut self, metadata: serde_json::Value) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    /// Content-Type sent with the value
+    pub fn with_content_type(mut self, content_type: &str) -> Self {
+        self.content_type = Some(content_type.to_string());
+        self
+    }
+
+    /// Expiration TTL, in seconds
+    pub fn expiration_ttl(&self) -> Option<u64> {
+        self.expiration_ttl
+    }
+
+    /// Absolute expiration, in seconds since the unix epoch
+    pub fn expiration(&self) -> Option<u64> {
+        self.expiration
+    }
+
+    /// Metadata stored with the value
+    pub fn metadata(&self) -> Option<&serde_json::Value> {
+        self.metadata.as_ref()
+    }
+
+    /// Content-Type sent with the value
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    // Options for the parts of a chunked value: expiration only
+    pub(crate) fn expiration_only(&self) -> Self {
+        Self {
+            expiration_ttl: self.expiration_ttl,
+            expiration: self.expiration,
+            ..Default::default()
+        }
+    }
+
+    // Query string for the values api ("" or "?expiration_ttl=N")
+    pub(crate) fn query(&self) -> Result<String, Error> {
+        match (self.expiration_ttl, self.expiration) {
+            (Some(ttl), _) if ttl < 60 => Err(Error::TTLTooShort),
+            (Some(ttl), _) => Ok(format!("?expiration_ttl={}", ttl)),
+            (None, Some(at)) => Ok(format!("?expiration={}", at)),
+            (None, None) => Ok(String::new()),
+        }
+    }
+}
+
+/// Tests expiration query strings
+#[test]
+fn test_put_options_query() {
+    assert_eq!(KvPutOptions::new().query().unwrap(), "");
+    let opts = KvPutOptions::new()
+        .with_expiration(1_900_000_000)
+        .with_expiration_ttl(120);
+    assert_eq!(opts.query().unwrap(), "?expiration_ttl=120");
+    let opts = opts.with_expiration(1_900_000_000);
+    assert_eq!(opts.query().unwrap(), "?expiration=1900000000");
+    assert!(matches!(
+        KvPutOptions::new().with_expiration_ttl(10).query(),
+        Err(Error::TTLTooShort)
+    ));
+}
@@ -0,0 +1,134 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Future returned by a `RateLimiter` sleep function
+#[cfg(not(target_arch = "wasm32"))]
+pub type SleepFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Future returned by a `RateLimiter` sleep function
+#[cfg(target_arch = "wasm32")]
+pub type SleepFuture = Pin<Box<dyn Future<Output = ()>>>;
+
+/// Token-bucket rate limiter for Cloudflare api requests made by bulk operations
+/// (writes, deletes, and key listing), so large uploads stay under the api rate limit
+/// instead of failing partway through. The bucket holds up to `requests` tokens and
+/// refills at `requests` per `period`; each request takes one token, waiting if none is left.
+///
+/// Install with `KVAssets::with_rate_limiter`. Share one limiter (in an `Arc`) between
+/// handlers and concurrent tasks using the same account.
+/// The clock and sleep default to the system clock and a timer thread. On wasm, where
+/// they aren't available, supply them with `with_clock` and `with_sleep`.
+pub struct RateLimiter {
+    capacity: f64,
+    // tokens added per millisecond
+    rate: f64,
+    clock: fn() -> u64,
+    sleep: fn(Duration) -> SleepFuture,
+    // available tokens, and time of last refill
+    state: Mutex<(f64, u64)>,
+}
+
+impl RateLimiter {
+    /// Allow bursts of `requests`, and on average `requests` per `period`
+    pub fn new(requests: u32, period: Duration) -> Self {
+        let capacity = requests.max(1) as f64;
+        let clock = crate::breaker::system_millis;
+        Self {
+            capacity,
+            rate: capacity / (period.as_millis().max(1) as f64),
+            clock,
+            sleep: default_sleep,
+            state: Mutex::new((capacity, clock())),
+        }
+    }
+
+    /// Cloudflare's global api limit: 1200 requests per 5 minutes
+    pub fn cloudflare() -> Self {
+        Self::new(1200, Duration::from_secs(300))
+    }
+
+    /// Use a clock returning the current time in milliseconds
+    pub fn with_clock(mut self, clock: fn() -> u64) -> Self {
+        self.clock = clock;
+        self.state = Mutex::new((self.capacity, clock()));
+        self
+    }
+
+    /// Use a sleep function, e.g., `|d| Box::pin(tokio::time::sleep(d))`
+    pub fn with_sleep(mut self, sleep: fn(Duration) -> SleepFuture) -> Self {
+        self.sleep = sleep;
+        self
+    }
+
+    /// Take a token if one is available, without waiting
+    pub fn try_acquire(&self) -> bool {
+        self.take().is_none()
+    }
+
+    /// Take a token, waiting until one is available
+    pub async fn acquire(&self) {
+        while let Some(wait) = self.take() {
+            (self.sleep)(wait).await;
+        }
+    }
+
+    // Take a token, or return the time until one is available
+    fn take(&self) -> Option<Duration> {
+        let now = (self.clock)();
+        let mut state = self.state.lock().unwrap();
+        let (tokens, last) = *state;
+        let tokens = (tokens + now.saturating_sub(last) as f64 * self.rate).min(self.capacity);
+        if tokens >= 1.0 {
+            *state = (tokens - 1.0, now);
+            None
+        } else {
+            *state = (tokens, now);
+            Some(Duration::from_millis(
+                ((1.0 - tokens) / self.rate).ceil().max(1.0) as u64,
+            ))
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn default_sleep(duration: Duration) -> SleepFuture {
+    Box::pin(futures_timer::Delay::new(duration))
+}
+
+// No timer on wasm: doesn't wait, unless a sleep function is supplied
+#[cfg(target_arch = "wasm32")]
+fn default_sleep(_duration: Duration) -> SleepFuture {
+    Box::pin(async {})
+}
+
+/// Tests token refill and waiting
+#[cfg(test)]
+#[tokio::test]
+async fn test_rate_limiter() {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    static NOW: AtomicU64 = AtomicU64::new(1000);
+    fn clock() -> u64 {
+        NOW.load(Ordering::Relaxed)
+    }
+    // sleeping advances the clock
+    fn sleep(duration: Duration) -> SleepFuture {
+        NOW.fetch_add(duration.as_millis() as u64, Ordering::Relaxed);
+        Box::pin(async {})
+    }
+
+    let limiter = RateLimiter::new(2, Duration::from_secs(1))
+        .with_clock(clock)
+        .with_sleep(sleep);
+    assert!(limiter.try_acquire());
+    assert!(limiter.try_acquire());
+    assert!(!limiter.try_acquire());
+    NOW.fetch_add(500, Ordering::Relaxed);
+    assert!(limiter.try_acquire());
+    assert!(!limiter.try_acquire());
+
+    let start = clock();
+    limiter.acquire().await;
+    assert_eq!(clock() - start, 500);
+}
@@ -0,0 +1,119 @@
+use crate::{Error, HashAlgorithm, KVAssets};
+use bytes::Bytes;
+use http::{header, Request, StatusCode};
+
+impl<'ah> KVAssets<'ah> {
+    /// Load the index from url (such as an index published to object storage or a CDN),
+    /// and use it in this handler instead of the index it was initialized with.
+    /// The request is sent with the handler's `HttpClient`, without the api token.
+    /// Later calls send the ETag of the loaded index in `If-None-Match`, so an unchanged
+    /// index isn't downloaded again; if the server doesn't return ETags, an index whose
+    /// content hash is unchanged isn't decoded again. The index is verified if an index
+    /// key is set (`with_index_key`). Returns true if the index changed
+    pub async fn load_index_from_url(&self, url: &str) -> Result<bool, Error> {
+        let mut request = Request::new(Bytes::new());
+        *request.uri_mut() = url
+            .parse()
+            .map_err(|_| Error::Message(format!("invalid index url {}", url)))?;
+        if let Some(etag) = self.index_tag().and_then(|tag| etag_value(&tag)) {
+            request.headers_mut().insert(header::IF_NONE_MATCH, etag);
+        }
+        let response = self.send(request).await?;
+        match response.status() {
+            StatusCode::OK => {}
+            StatusCode::NOT_MODIFIED => return Ok(false),
+            status => return Err(Error::OriginStatus(url.to_string(), status.as_u16())),
+        }
+        let tag = match response.headers().get(header::ETAG) {
+            Some(etag) => format!("etag:{}", etag.to_str().unwrap_or_default()),
+            None => format!("sha256:{}", HashAlgorithm::Sha256.hash(response.body())),
+        };
+        if self.index_tag().as_deref() == Some(tag.as_str()) {
+            return Ok(false);
+        }
+        let index = self.decode_published(response.body())?;
+        self.replace_index(index);
+        self.set_index_tag(Some(tag));
+        Ok(true)
+    }
+}
+
+// If-None-Match value for a tag, if it is an etag
+fn etag_value(tag: &str) -> Option<http::HeaderValue> {
+    http::HeaderValue::from_str(tag.strip_prefix("etag:")?).ok()
+}
+
+/// Tests conditional loading of the index from a url (does not invoke cloudflare api)
+#[cfg(test)]
+#[tokio::test]
+async fn test_load_index_from_url() {
+    use crate::{AssetIndex, AssetMetadata, HttpClient, HttpFuture};
+    use std::sync::{Arc, Mutex};
+
+    // serves the index with an optional etag, recording If-None-Match headers
+    struct Stub {
+        index: Mutex<Bytes>,
+        etag: Option<&'static str>,
+        conditional: Mutex<Vec<String>>,
+    }
+    impl HttpClient for Stub {
+        fn send(&self, request: http::Request<Bytes>) -> HttpFuture<'_> {
+            assert!(request.headers().get(header::AUTHORIZATION).is_none());
+            let mut response = http::Response::new(self.index.lock().unwrap().clone());
+            if let Some(etag) = self.etag {
+                response
+                    .headers_mut()
+                    .insert(header::ETAG, http::HeaderValue::from_static(etag));
+            }
+            if let Some(tag) = request.headers().get(header::IF_NONE_MATCH) {
+                let tag = tag.to_str().unwrap().to_string();
+                if Some(tag.as_str()) == self.etag {
+                    *response.status_mut() = StatusCode::NOT_MODIFIED;
+                    *response.body_mut() = Bytes::new();
+                }
+                self.conditional.lock().unwrap().push(tag);
+            }
+            Box::pin(async move { Ok(response) })
+        }
+    }
+
+    let encoded = |key: &str| {
+        let mut index = AssetIndex::new();
+        index.insert(
+            "index.html".to_string(),
+            AssetMetadata {
+                path: key.to_string(),
+                ..Default::default()
+            },
+        );
+        Bytes::from(crate::encode_index(&index).unwrap())
+    };
+    let url = "https://cdn.example.com/assets.bin";
+    for etag in [Some("\"abc\""), None] {
+        let stub = Arc::new(Stub {
+            index: Mutex::new(encoded("index.1.html")),
+            etag,
+            conditional: Mutex::new(Vec::new()),
+        });
+        let kv = KVAssets::init(&[], "acct", "ns", "token").with_http_client(stub.clone());
+        assert!(kv.load_index_from_url(url).await.unwrap());
+        assert!(!kv.load_index_from_url(url).await.unwrap());
+        assert_eq!(
+            kv.lookup_key("index.html").unwrap().unwrap().path,
+            "index.1.html"
+        );
+        let sent = stub.conditional.lock().unwrap().clone();
+        match etag {
+            Some(etag) => assert_eq!(sent, vec![etag.to_string()]),
+            None => assert!(sent.is_empty()),
+        }
+        if etag.is_none() {
+            *stub.index.lock().unwrap() = encoded("index.2.html");
+            assert!(kv.load_index_from_url(url).await.unwrap());
+            assert_eq!(
+                kv.lookup_key("index.html").unwrap().unwrap().path,
+                "index.2.html"
+            );
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crate::{AssetIndex, Error, IndexStats};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Result of a deploy (`sync_assets` or `KVAssets::publish_dir`).
+/// Serializes to JSON with `to_json`, so CI jobs can post it as a summary
+/// or fail the build on thresholds; `Display` prints a one-line summary.
+#[derive(Debug, Default, Clone, Serialize, PartialEq, Eq)]
+pub struct DeployReport {
+    /// Asset paths uploaded that weren't in the previous index
+    pub added: Vec<String>,
+    /// Asset paths uploaded because their content changed
+    pub updated: Vec<String>,
+    /// KV keys deleted (with prune)
+    pub deleted: Vec<String>,
+    /// Asset paths removed by this deploy, and kept in the index as tombstones
+    /// (see `SyncConfig::tombstone_retention`)
+    pub tombstoned: Vec<String>,
+    /// Asset paths not uploaded, because their value is already in KV
+    pub skipped: Vec<String>,
+    /// Total size of uploaded values, in bytes
+    pub bytes_uploaded: u64,
+    /// Asset paths uploaded with an expiration (see `TtlRules`), and their TTL in seconds
+    pub expiring: BTreeMap<String, u64>,
+    /// Time spent in each phase
+    pub durations: DeployDurations,
+    /// Warnings, such as large assets, or deferred pruning
+    pub warnings: Vec<String>,
+    /// Summary of the new index
+    pub stats: IndexStats,
+}
+
+/// Time spent in each phase of a deploy, in milliseconds
+#[derive(Debug, Default, Clone, Serialize, PartialEq, Eq)]
+pub struct DeployDurations {
+    /// Scanning the asset directory and building the index
+    pub scan_ms: u64,
+    /// Uploading values
+    pub upload_ms: u64,
+    /// Checking that uploaded values are in KV, before publishing the index
+    pub verify_ms: u64,
+    /// Deleting unreferenced values
+    pub prune_ms: u64,
+    /// Whole deploy
+    pub total_ms: u64,
+}
+
+impl DeployReport {
+    /// Sorts the path lists, and records stats of the new index
+    pub(crate) fn finish(mut self, index: &AssetIndex) -> Self {
+        use crate::AssetIndexExt as _;
+        self.added.sort();
+        self.updated.sort();
+        self.deleted.sort();
+        self.skipped.sort();
+        self.stats = index.stats();
+        self
+    }
+
+    /// Number of values uploaded
+    pub fn uploaded(&self) -> usize {
+        self.added.len() + self.updated.len()
+    }
+
+    /// Report as pretty-printed JSON
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| Error::Message(format!("deploy report json: {}", e)))
+    }
+}
+
+impl std::fmt::Display for DeployReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} added, {} updated, {} deleted, {} unchanged; uploaded {} in {:.1}s",
+            self.added.len(),
+            self.updated.len(),
+            self.deleted.len(),
+            self.skipped.len(),
+            crate::stats::format_bytes(self.bytes_uploaded),
+            self.durations.total_ms as f64 / 1000.0,
+        )?;
+        if !self.warnings.is_empty() {
+            write!(f, " ({} warnings)", self.warnings.len())?;
+        }
+        Ok(())
+    }
+}
+
+/// Tests report summary and json
+#[test]
+fn test_deploy_report() {
+    let report = DeployReport {
+        added: vec!["b.css".into(), "a.html".into()],
+        skipped: vec!["c.js".into()],
+        bytes_uploaded: 2_500_000,
+        durations: DeployDurations {
+            total_ms: 1300,
+            ..Default::default()
+        },
+        warnings: vec!["Large asset: a.html".into()],
+        ..Default::default()
+    }
+    .finish(&AssetIndex::new());
+    assert_eq!(report.added, vec!["a.html", "b.css"]);
+    assert_eq!(report.uploaded(), 2);
+    assert_eq!(
+        report.to_string(),
+        "2 added, 0 updated, 0 deleted, 1 unchanged; uploaded 2.5 MB in 1.3s (1 warnings)"
+    );
+    let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+    assert_eq!(json["bytes_uploaded"], 2_500_000);
+    assert_eq!(json["durations"]["total_ms"], 1300);
+}
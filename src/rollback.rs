@@ -0,0 +1,195 @@
+use crate::{AssetIndex, Error, KVAssets};
+use bytes::Bytes;
+
+/// Prefix of KV keys used for published indexes. `verify` and `prune`
+/// ignore keys with this prefix
+pub const INDEX_KEY_PREFIX: &str = "__index_";
+
+/// KV key holding the version number of the current published index
+pub const CURRENT_INDEX_KEY: &str = "__index_current";
+
+/// KV key of a published index version: `__index_v{version}`
+pub fn index_version_key(version: u32) -> String {
+    format!("{}v{}", INDEX_KEY_PREFIX, version)
+}
+
+// Index tag of a published version
+fn version_tag(version: u32) -> String {
+    format!("v{}", version)
+}
+
+// Version number of a published index key
+fn parse_version_key(key: &str) -> Option<u32> {
+    key.strip_prefix(INDEX_KEY_PREFIX)?
+        .strip_prefix('v')?
+        .parse()
+        .ok()
+}
+
+impl<'ah> KVAssets<'ah> {
+    /// Store the index in KV under the next version key (`__index_v{n}`), and make it
+    /// the current version. Versions before the newest `keep` (at least 1) are deleted.
+    /// Since asset keys are content-addressed, values of older versions stay in KV
+    /// (unless pruned), so `rollback_to` can switch back to them without uploading.
+    /// If an index key is set (`with_index_key`), the stored index is signed.
+    /// Returns the new version number.
+    pub async fn publish_index(&self, index: &AssetIndex, keep: usize) -> Result<u32, Error> {
+        let versions = self.index_versions().await?;
+        let version = versions.last().map(|v| v + 1).unwrap_or(1);
+        self.put_kv_value(
+            &index_version_key(version),
+            self.encode_published(index)?,
+            None,
+        )
+        .await?;
+        self.set_current_version(version).await?;
+        let keep = keep.max(1) - 1;
+        for old in versions.iter().take(versions.len().saturating_sub(keep)) {
+            self.delete_kv_value(&index_version_key(*old)).await?;
+        }
+        Ok(version)
+    }
+
+    /// Versions of published indexes in KV, oldest first
+    pub async fn index_versions(&self) -> Result<Vec<u32>, Error> {
+        let mut versions: Vec<u32> = self
+            .list_keys()
+            .await?
+            .iter()
+            .filter_map(|key| parse_version_key(key))
+            .collect();
+        versions.sort_unstable();
+        Ok(versions)
+    }
+
+    /// Version of the current published index, or None if no index has been published
+    pub async fn current_index_version(&self) -> Result<Option<u32>, Error> {
+        match self.get_value(CURRENT_INDEX_KEY).await {
+            Ok(value) => std::str::from_utf8(&value)
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .map(Some)
+                .ok_or_else(|| Error::Message(format!("invalid {} value", CURRENT_INDEX_KEY))),
+            Err(Error::KVKeyNotFound(_, 404)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Make a previously published index version current again, for rolling back
+    /// a bad deploy, and use it in this handler. Workers pick it up the next time they
+    /// call `load_published_index`. Returns the index
+    pub async fn rollback_to(&self, version: u32) -> Result<AssetIndex, Error> {
+        let index = self.read_published(version).await?;
+        self.set_current_version(version).await?;
+        self.replace_index(index.clone());
+        self.set_index_tag(Some(version_tag(version)));
+        Ok(index)
+    }
+
+    /// Load the current published index from KV, and use it in this handler instead of
+    /// the index it was initialized with. Returns the version, or None if no index has
+    /// been published (the handler's index is unchanged). If the current version is the
+    /// one already loaded, only the version number is read, so refreshing an unchanged
+    /// index every minute doesn't download and decode it again
+    pub async fn load_published_index(&self) -> Result<Option<u32>, Error> {
+        let version = match self.current_index_version().await? {
+            Some(version) => version,
+            None => return Ok(None),
+        };
+        let tag = version_tag(version);
+        if self.index_tag().as_deref() == Some(tag.as_str()) {
+            return Ok(Some(version));
+        }
+        let index = self.read_published(version).await?;
+        self.replace_index(index);
+        self.set_index_tag(Some(tag));
+        Ok(Some(version))
+    }
+
+    async fn set_current_version(&self, version: u32) -> Result<(), Error> {
+        self.put_kv_value(CURRENT_INDEX_KEY, Bytes::from(version.to_string()), None)
+            .await
+    }
+
+    async fn read_published(&self, version: u32) -> Result<AssetIndex, Error> {
+        let value = self.read_kv_value(&index_version_key(version)).await?;
+        self.decode_published(&value)
+    }
+
+    // Decode a published index, verifying its signature if an index key is set
+    pub(crate) fn decode_published(&self, blob: &[u8]) -> Result<AssetIndex, Error> {
+        #[cfg(feature = "signing")]
+        let blob = match self.index_key() {
+            Some(key) => crate::signing::verify_index(blob, key)?,
+            None => blob,
+        };
+        let mut index = crate::decode_index_with_limits(blob, self.decode_limits())?;
+        self.fill_keys(&mut index);
+        Ok(index)
+    }
+
+    pub(crate) fn encode_published(&self, index: &AssetIndex) -> Result<Bytes, Error> {
+        let bytes = crate::encode_index(index)?;
+        #[cfg(feature = "signing")]
+        let bytes = match self.index_key() {
+            Some(key) => crate::sign_index(&bytes, key),
+            None => bytes,
+        };
+        Ok(Bytes::from(bytes))
+    }
+}
+
+/// Tests publishing index versions and rolling back, against the mock api
+#[cfg(all(test, feature = "reqwest"))]
+#[tokio::test]
+async fn test_rollback() {
+    use crate::AssetMetadata;
+
+    let index_with = |key: &str| {
+        let mut index = AssetIndex::new();
+        index.insert(
+            "index.html".to_string(),
+            AssetMetadata {
+                path: key.to_string(),
+                size: 5,
+                ..Default::default()
+            },
+        );
+        index
+    };
+    let mock = crate::testspace::MockCloudflare::start().unwrap();
+    let kv = mock.handler(&[], "ns");
+    assert_eq!(kv.current_index_version().await.unwrap(), None);
+    assert_eq!(kv.load_published_index().await.unwrap(), None);
+
+    for n in 1..=3 {
+        let index = index_with(&format!("index.{}.html", n));
+        assert_eq!(kv.publish_index(&index, 2).await.unwrap(), n);
+    }
+    assert_eq!(kv.index_versions().await.unwrap(), vec![2, 3]);
+    assert_eq!(kv.current_index_version().await.unwrap(), Some(3));
+
+    let worker = mock.handler(&[], "ns");
+    assert_eq!(worker.load_published_index().await.unwrap(), Some(3));
+    // unchanged version: the index isn't read again
+    mock.remove("ns", &index_version_key(3));
+    assert_eq!(worker.load_published_index().await.unwrap(), Some(3));
+    assert_eq!(
+        worker.lookup_key("index.html").unwrap().unwrap().path,
+        "index.3.html"
+    );
+
+    kv.rollback_to(2).await.unwrap();
+    assert_eq!(
+        kv.lookup_key("index.html").unwrap().unwrap().path,
+        "index.2.html"
+    );
+    assert_eq!(worker.load_published_index().await.unwrap(), Some(2));
+    assert_eq!(
+        worker.lookup_key("index.html").unwrap().unwrap().path,
+        "index.2.html"
+    );
+    assert!(kv.rollback_to(1).await.is_err());
+    assert_eq!(parse_version_key("__index_v12"), Some(12));
+    assert_eq!(parse_version_key(CURRENT_INDEX_KEY), None);
+}
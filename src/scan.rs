@@ -0,0 +1,405 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use crate::{
+    AssetIndex, AssetMetadata, DeployEvent, DeployReport, Error, HashAlgorithm, KVAssets,
+    KeyStrategy, Transforms, UPLOAD_ATTEMPTS,
+};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, UNIX_EPOCH};
+
+/// Builds an index of the files in asset_dir, without uploading them.
+/// Hidden files and directories (beginning with ".") are skipped.
+/// Each asset's KV key includes a hash of its content (`css/site.2cf24dba5f.css`),
+/// so new versions don't overwrite values used by a deployed worker.
+/// Content is hashed with the default `HashAlgorithm`.
+pub fn index_dir(asset_dir: &Path) -> Result<AssetIndex, Error> {
+    index_dir_with(asset_dir, HashAlgorithm::default())
+}
+
+/// Same as `index_dir`, hashing content with the algorithm
+pub fn index_dir_with(asset_dir: &Path, algorithm: HashAlgorithm) -> Result<AssetIndex, Error> {
+    index_dir_keyed(asset_dir, None, algorithm, &crate::ContentHashKeys)
+}
+
+// Same as `index_dir_with`, transforming content, and naming KV keys with the strategy
+pub(crate) fn index_dir_keyed(
+    asset_dir: &Path,
+    transforms: Option<&Transforms>,
+    algorithm: HashAlgorithm,
+    keys: &dyn KeyStrategy,
+) -> Result<AssetIndex, Error> {
+    let mut files = Vec::new();
+    collect_files(asset_dir, &mut files)?;
+    let mut index = AssetIndex::new();
+    for file in files {
+        if let Some(path) = asset_path(asset_dir, &file) {
+            let (md, _) = read_asset(&file, &path, transforms, algorithm, keys)?;
+            index.insert(path, md);
+        }
+    }
+    Ok(index)
+}
+
+impl<'ah> KVAssets<'ah> {
+    /// Indexes asset_dir (see `index_dir`), and uploads files whose keys
+    /// aren't already in KV. Content is transformed first, if the handler has
+    /// transforms (see `with_transforms`). Returns the new index and a report of the upload.
+    /// Uploaded assets are reported as updated if they are in this handler's
+    /// index, otherwise added. Failed uploads are tried up to `UPLOAD_ATTEMPTS` times,
+    /// and progress is reported to the deploy observer, if any. Assets matching
+    /// the handler's TTL rules (see `with_ttl_rules`) are uploaded with an expiration.
+    /// The index is not written: encode it with `encode_index` for the worker,
+    /// or use `stage_dir` and `commit_staged` to verify and publish it.
+    pub async fn publish_dir(&self, asset_dir: &Path) -> Result<(AssetIndex, DeployReport), Error> {
+        Ok(self.stage_dir(asset_dir).await?.into_parts())
+    }
+
+    /// First phase of an atomic deploy: same as `publish_dir`, but returns the
+    /// index and report as a `StagedDeploy`, to be verified and published
+    /// after all values are uploaded.
+    ///
+    /// With a checkpoint store (see `with_checkpoint`), progress is saved as values
+    /// are uploaded, and when the deploy fails or is cancelled. Deploying the same
+    /// content again skips the values recorded in the checkpoint, which may not
+    /// appear in KV key listings yet. The checkpoint is removed when the upload completes.
+    pub async fn stage_dir(&self, asset_dir: &Path) -> Result<crate::StagedDeploy, Error> {
+        self.stage(asset_dir, None).await
+    }
+
+    // Stage asset_dir, with index paths and KV keys under "{version}/", if set
+    pub(crate) async fn stage(
+        &self,
+        asset_dir: &Path,
+        version: Option<&str>,
+    ) -> Result<crate::StagedDeploy, Error> {
+        let start = Instant::now();
+        let mut report = DeployReport::default();
+        let versioned = version.map(|version| crate::key_strategy::VersionedKeys {
+            version,
+            keys: self.key_strategy(),
+        });
+        let keys = match &versioned {
+            Some(versioned) => versioned as &dyn KeyStrategy,
+            None => self.key_strategy(),
+        };
+        let mut index = index_dir_keyed(asset_dir, self.transforms(), self.hash_algorithm(), keys)?;
+        let existing: HashSet<String> = self.list_keys().await?.into_iter().collect();
+        let mut checkpoint = self.load_checkpoint(&index).await?;
+        report.durations.scan_ms = crate::upload::elapsed_ms(start);
+
+        let upload_start = Instant::now();
+        let uploaded = self
+            .upload_dir(asset_dir, &index, &existing, &mut checkpoint, &mut report)
+            .await;
+        if let Some(checkpoint) = checkpoint.as_mut() {
+            match uploaded {
+                Ok(()) => self.clear_checkpoint().await?,
+                Err(_) => self.save_checkpoint(checkpoint).await?,
+            }
+        }
+        uploaded?;
+        if let Some(version) = version {
+            index = index
+                .into_iter()
+                .map(|(path, md)| (format!("{}/{}", version, path), md))
+                .collect();
+        }
+        report.durations.upload_ms = crate::upload::elapsed_ms(upload_start);
+        report.durations.total_ms = crate::upload::elapsed_ms(start);
+        let report = report.finish(&index);
+        Ok(crate::StagedDeploy::new(index, report))
+    }
+}
+
+impl<'ah> KVAssets<'ah> {
+    // Upload the values of index not in KV or the checkpoint
+    async fn upload_dir(
+        &self,
+        asset_dir: &Path,
+        index: &AssetIndex,
+        existing: &HashSet<String>,
+        checkpoint: &mut Option<crate::checkpoint::Checkpoint>,
+        report: &mut DeployReport,
+    ) -> Result<(), Error> {
+        for (completed, (path, md)) in index.iter().enumerate() {
+            if let Err(e) = self.check_cancelled("publishing assets") {
+                let remaining = index.len() - completed;
+                self.observe(|| DeployEvent::Cancelled {
+                    completed,
+                    remaining,
+                });
+                return Err(e);
+            }
+            let checkpointed = checkpoint.as_ref().is_some_and(|c| c.contains(&md.path));
+            if existing.contains(&md.path) || checkpointed {
+                self.observe(|| DeployEvent::Skipped { path: path.clone() });
+                report.skipped.push(path.clone());
+                continue;
+            }
+            // upload the content the index entry was built from
+            let (read, content) = read_asset(
+                &asset_dir.join(path),
+                path,
+                self.transforms(),
+                self.hash_algorithm(),
+                self.key_strategy(),
+            )?;
+            if read.hash != md.hash {
+                return Err(Error::Message(format!("{} changed while publishing", path)));
+            }
+            let content = bytes::Bytes::from(content);
+            let ttl = self.ttl_for(path);
+            self.upload(path, md, content, ttl).await?;
+            if let Some(checkpoint) = checkpoint.as_mut() {
+                if checkpoint.record(&md.path) {
+                    self.save_checkpoint(checkpoint).await?;
+                }
+            }
+            if let Some(ttl) = ttl {
+                report.expiring.insert(path.clone(), ttl);
+            }
+            report.bytes_uploaded += md.size;
+            match self.with_index(|previous| previous.contains_key(path))? {
+                true => report.updated.push(path.clone()),
+                false => report.added.push(path.clone()),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'ah> KVAssets<'ah> {
+    // Upload asset value, retrying transient failures, and report events
+    async fn upload(
+        &self,
+        path: &str,
+        md: &AssetMetadata,
+        content: bytes::Bytes,
+        ttl: Option<u64>,
+    ) -> Result<(), Error> {
+        self.observe(|| DeployEvent::Started {
+            path: path.to_string(),
+            key: md.path.clone(),
+            size: md.size,
+        });
+        let mut attempt = 1;
+        loop {
+            match self.put_kv_value(&md.path, content.clone(), ttl).await {
+                Ok(()) => {
+                    self.observe(|| DeployEvent::Uploaded {
+                        path: path.to_string(),
+                        key: md.path.clone(),
+                        size: md.size,
+                    });
+                    return Ok(());
+                }
+                Err(e @ (Error::KVHttp(_) | Error::Api { .. } | Error::Message(_)))
+                    if attempt < UPLOAD_ATTEMPTS =>
+                {
+                    self.observe(|| DeployEvent::Retried {
+                        path: path.to_string(),
+                        attempt,
+                        error: e.to_string(),
+                    });
+                    if let Some(metrics) = self.metrics() {
+                        metrics.retry(attempt);
+                    }
+                    attempt += 1;
+                }
+                Err(e) => {
+                    self.observe(|| DeployEvent::Failed {
+                        path: path.to_string(),
+                        error: e.to_string(),
+                    });
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+/// All files under dir, recursively, skipping hidden files and directories
+pub(crate) fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|e| Error::IO(format!("reading {}: {}", dir.display(), e)))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| Error::IO(format!("reading {}: {}", dir.display(), e)))?;
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Index key (relative path with '/' separators) of a file in the asset dir.
+/// None for files outside the asset dir, and hidden files
+pub(crate) fn asset_path(asset_dir: &Path, file: &Path) -> Option<String> {
+    let rel = file.strip_prefix(asset_dir).ok()?;
+    let parts: Vec<String> = rel
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+    match parts.is_empty() || parts.iter().any(|p| p.starts_with('.')) {
+        true => None,
+        false => Some(parts.join("/")),
+    }
+}
+
+/// Reads and transforms file, returning its metadata (with KV key named by the strategy) and content
+pub(crate) fn read_asset(
+    file: &Path,
+    path: &str,
+    transforms: Option<&Transforms>,
+    algorithm: HashAlgorithm,
+    keys: &dyn KeyStrategy,
+) -> Result<(AssetMetadata, Vec<u8>), Error> {
+    let mut content = read_file(file)?;
+    if let Some(transforms) = transforms {
+        content = transforms.apply(path, content)?;
+    }
+    let hash = algorithm.hash(&content);
+    let modified = std::fs::metadata(file)
+        .and_then(|md| md.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let mut md = AssetMetadata {
+        path: crate::key_strategy::key_with(keys, path, &hash),
+        modified,
+        size: content.len() as u64,
+        hash: Some(hash),
+        ..Default::default()
+    };
+    crate::sri::add_integrity(path, &mut md, &content);
+    Ok((md, content))
+}
+
+fn read_file(file: &Path) -> Result<Vec<u8>, Error> {
+    std::fs::read(file).map_err(|e| Error::IO(format!("reading {}: {}", file.display(), e)))
+}
+
+/// Tests directory indexing
+#[test]
+fn test_index_dir() {
+    let dir = std::env::temp_dir().join(format!("kv-assets-scan-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("css")).unwrap();
+    std::fs::create_dir_all(dir.join(".git")).unwrap();
+    std::fs::write(dir.join("index.html"), "hello").unwrap();
+    std::fs::write(dir.join("css/site.css"), "body{}").unwrap();
+    std::fs::write(dir.join(".git/HEAD"), "x").unwrap();
+
+    let index = index_dir(&dir).unwrap();
+    let sha256 = index_dir_with(&dir, HashAlgorithm::Sha256).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(index.len(), 2);
+    let hash = HashAlgorithm::default().hash(b"hello");
+    assert_eq!(
+        index["index.html"].path,
+        crate::checksum::versioned_key("index.html", &hash)
+    );
+    assert_eq!(sha256["index.html"].path, "index.2cf24dba5f.html");
+    assert_eq!(index["index.html"].size, 5);
+    assert!(index.contains_key("css/site.css"));
+}
+
+/// Tests publish events, retries, and TTL rules (does not invoke cloudflare api)
+#[cfg(test)]
+#[tokio::test]
+async fn test_publish_observer() {
+    use crate::{HttpClient, HttpFuture};
+    use bytes::Bytes;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Stub {
+        puts: AtomicUsize,
+        uris: Mutex<Vec<String>>,
+    }
+    impl HttpClient for Stub {
+        fn send(&self, request: http::Request<Bytes>) -> HttpFuture<'_> {
+            self.uris.lock().unwrap().push(request.uri().to_string());
+            let body = match *request.method() {
+                // first put fails
+                http::Method::PUT => match self.puts.fetch_add(1, Ordering::Relaxed) {
+                    0 => r#"{"success":false,"errors":[],"messages":[]}"#,
+                    _ => r#"{"success":true,"errors":[],"messages":[]}"#,
+                },
+                _ => r#"{"success":true,"errors":[],"messages":[],"result":[]}"#,
+            };
+            Box::pin(async move { Ok(http::Response::new(Bytes::from(body))) })
+        }
+    }
+
+    let dir = std::env::temp_dir().join(format!("kv-assets-publish-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("index.html"), "hello").unwrap();
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let log = events.clone();
+    let stub = Arc::new(Stub::default());
+    let metrics = Arc::new(crate::metrics::RecordedMetrics::default());
+    let kv = KVAssets::init(&[], "acct", "ns", "token")
+        .with_http_client(stub.clone())
+        .with_metrics(metrics.clone())
+        .with_ttl_rules(Arc::new(
+            crate::TtlRules::new().expire("*.html", std::time::Duration::from_secs(3600)),
+        ))
+        .with_deploy_observer(Arc::new(move |e: &DeployEvent| {
+            log.lock().unwrap().push(e.clone())
+        }));
+    let (_, report) = kv.publish_dir(&dir).await.unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(report.added, vec!["index.html"]);
+    assert_eq!(report.expiring["index.html"], 3600);
+    let uris = stub.uris.lock().unwrap();
+    assert!(uris[1].ends_with("?expiration_ttl=3600"));
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 3);
+    assert!(matches!(&events[0], DeployEvent::Started { path, .. } if path == "index.html"));
+    assert!(matches!(
+        &events[1],
+        DeployEvent::Retried { attempt: 1, .. }
+    ));
+    assert!(matches!(&events[2], DeployEvent::Uploaded { size: 5, .. }));
+    assert_eq!(metrics.events(), vec!["retry:1"]);
+}
+
+/// Tests that published values, keys, and hashes come from transformed content
+#[cfg(all(test, feature = "reqwest"))]
+#[tokio::test]
+async fn test_publish_transforms() {
+    let dir = std::env::temp_dir().join(format!("kv-assets-scan-transform-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("site.css"), "a {\n  b: c;\n}\n").unwrap();
+    let mock = crate::testspace::MockCloudflare::start().unwrap();
+    let kv = mock
+        .handler(&[], "ns")
+        .with_hash_algorithm(HashAlgorithm::Sha256)
+        .with_transforms(Transforms::new().with("css", |_, css| {
+            let text = String::from_utf8_lossy(&css);
+            Ok(text.lines().map(str::trim).collect::<String>().into_bytes())
+        }));
+    let published = kv.publish_dir(&dir).await;
+    std::fs::remove_dir_all(&dir).unwrap();
+    let (index, _) = published.unwrap();
+    let md = &index["site.css"];
+    let minified = b"a {b: c;}";
+    assert_eq!(md.size, minified.len() as u64);
+    assert_eq!(
+        md.hash.as_deref(),
+        Some(HashAlgorithm::Sha256.hash(minified).as_str())
+    );
+    assert_eq!(md.path, kv.kv_key("site.css", md.hash.as_ref().unwrap()));
+    assert_eq!(mock.get("ns", &md.path).unwrap().as_ref(), minified);
+    assert_eq!(md.integrity(), Some(crate::sri_hash(minified).as_str()));
+}
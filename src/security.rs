@@ -0,0 +1,162 @@
+use http::header::{self, HeaderName};
+use http::{HeaderMap, HeaderValue};
+use std::time::Duration;
+
+/// Security headers added to responses from `serve` (install with
+/// `KVAssets::with_security_headers`). `new` starts with defaults suitable for
+/// static sites; `Content-Security-Policy` is not set by default, since a useful
+/// policy depends on the site. Headers already in the response, such as headers
+/// from the asset's index entry, are not replaced.
+///
+/// Different paths can use different policies: `with_prefix` sets the policy
+/// for paths under a prefix. Prefixes match whole path segments, and the longest
+/// matching prefix applies.
+#[derive(Debug, Clone)]
+pub struct SecurityHeaders {
+    headers: Vec<(HeaderName, HeaderValue)>,
+    // sorted by prefix length, longest first
+    overrides: Vec<(String, SecurityHeaders)>,
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SecurityHeaders {
+    /// Default policy:
+    /// - `Strict-Transport-Security: max-age=31536000; includeSubDomains`
+    /// - `X-Content-Type-Options: nosniff`
+    /// - `X-Frame-Options: DENY`
+    /// - `Referrer-Policy: strict-origin-when-cross-origin`
+    pub fn new() -> Self {
+        Self::empty()
+            .with_hsts(Duration::from_secs(365 * 24 * 3600), true)
+            .with_header(header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+            .with_frame_options("DENY")
+            .with_referrer_policy("strict-origin-when-cross-origin")
+    }
+
+    /// Policy with no headers
+    pub fn empty() -> Self {
+        Self {
+            headers: Vec::new(),
+            overrides: Vec::new(),
+        }
+    }
+
+    /// Set `Content-Security-Policy`, e.g., "default-src 'self'"
+    pub fn with_content_security_policy(self, policy: &str) -> Self {
+        self.with_header(header::CONTENT_SECURITY_POLICY, policy)
+    }
+
+    /// Set `Strict-Transport-Security`
+    pub fn with_hsts(self, max_age: Duration, include_subdomains: bool) -> Self {
+        let mut value = format!("max-age={}", max_age.as_secs());
+        if include_subdomains {
+            value.push_str("; includeSubDomains");
+        }
+        self.with_header(header::STRICT_TRANSPORT_SECURITY, &value)
+    }
+
+    /// Set `X-Frame-Options` ("DENY" or "SAMEORIGIN")
+    pub fn with_frame_options(self, value: &str) -> Self {
+        self.with_header(header::X_FRAME_OPTIONS, value)
+    }
+
+    /// Set `Referrer-Policy`
+    pub fn with_referrer_policy(self, value: &str) -> Self {
+        self.with_header(header::REFERRER_POLICY, value)
+    }
+
+    /// Set any header. Invalid values are ignored
+    pub fn with_header(mut self, name: HeaderName, value: &str) -> Self {
+        if let Ok(value) = HeaderValue::from_str(value) {
+            self.headers.retain(|(n, _)| *n != name);
+            self.headers.push((name, value));
+        }
+        self
+    }
+
+    /// Remove a header from the policy
+    pub fn without(mut self, name: HeaderName) -> Self {
+        self.headers.retain(|(n, _)| n != name);
+        self
+    }
+
+    /// Use a different policy for paths under prefix (e.g., to allow an embeddable
+    /// widget to be framed). Setting the same prefix again replaces the policy
+    pub fn with_prefix(mut self, prefix: &str, policy: SecurityHeaders) -> Self {
+        let prefix = format!("/{}", prefix.trim_matches('/'));
+        self.overrides.retain(|(p, _)| *p != prefix);
+        self.overrides.push((prefix, policy));
+        self.overrides
+            .sort_by_key(|(p, _)| std::cmp::Reverse(p.len()));
+        self
+    }
+
+    /// Headers of the policy that applies to the path
+    pub fn headers_for(&self, path: &str) -> &[(HeaderName, HeaderValue)] {
+        let path = format!("/{}", path.trim_start_matches('/'));
+        let policy = self.overrides.iter().find(|(prefix, _)| {
+            prefix == "/"
+                || path
+                    .strip_prefix(prefix.as_str())
+                    .map(|rest| rest.is_empty() || rest.starts_with('/'))
+                    .unwrap_or(false)
+        });
+        match policy {
+            Some((_, policy)) => &policy.headers,
+            None => &self.headers,
+        }
+    }
+
+    /// Add the headers for the path that aren't already set
+    pub fn apply(&self, path: &str, headers: &mut HeaderMap) {
+        for (name, value) in self.headers_for(path) {
+            if !headers.contains_key(name) {
+                headers.insert(name.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// Tests default policy and prefix overrides
+#[test]
+fn test_security_headers() {
+    let policy = SecurityHeaders::new()
+        .with_content_security_policy("default-src 'self'")
+        .with_prefix(
+            "/embed/",
+            SecurityHeaders::new().with_frame_options("SAMEORIGIN"),
+        );
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::REFERRER_POLICY,
+        HeaderValue::from_static("no-referrer"),
+    );
+    policy.apply("/index.html", &mut headers);
+    assert_eq!(headers[header::X_FRAME_OPTIONS], "DENY");
+    assert_eq!(
+        headers[header::CONTENT_SECURITY_POLICY],
+        "default-src 'self'"
+    );
+    assert_eq!(headers[header::REFERRER_POLICY], "no-referrer");
+    assert_eq!(
+        headers[header::STRICT_TRANSPORT_SECURITY],
+        "max-age=31536000; includeSubDomains"
+    );
+
+    let mut headers = HeaderMap::new();
+    policy.apply("embed/widget.html", &mut headers);
+    assert_eq!(headers[header::X_FRAME_OPTIONS], "SAMEORIGIN");
+    assert!(!headers.contains_key(header::CONTENT_SECURITY_POLICY));
+    let mut headers = HeaderMap::new();
+    policy.apply("/embedded.html", &mut headers);
+    assert_eq!(headers[header::X_FRAME_OPTIONS], "DENY");
+
+    let policy = SecurityHeaders::new().without(header::STRICT_TRANSPORT_SECURITY);
+    assert_eq!(policy.headers_for("/").len(), 3);
+}
@@ -0,0 +1,555 @@
+use crate::{AssetMetadata, AssetSource, Error, KVAssets, ResolvedAsset};
+use bytes::Bytes;
+use http::{header, HeaderMap, HeaderValue, Method, Request, Response, StatusCode};
+use std::time::{Duration, UNIX_EPOCH};
+
+impl<'ah> KVAssets<'ah> {
+    /// Serve an asset request: looks up the request path in the index,
+    /// fetches the value from KV, and generates response headers from the asset metadata.
+    /// Conditional requests (If-None-Match, If-Modified-Since) are answered with
+    /// 304-Not-Modified from the index, without reading KV.
+    /// HEAD requests are also answered from the index alone: headers are generated
+    /// from metadata, and the body is not fetched (except for entries of unknown size,
+    /// such as those imported from a Workers Sites manifest).
+    /// Requests with a single byte range get a 206-Partial Content response
+    /// (or 416 if the range can't be satisfied); multiple ranges are served as the full asset.
+    /// Only the request head is needed; adapters can discard the body with `req.map(|_| ())`.
+    /// Failures are converted to error responses (404 if not found, 502 for KV api errors).
+    /// Aliases are served with the content of their target; aliases to external
+    /// urls are answered with 301 redirects.
+    /// With the `compression` feature, full responses can be compressed on the fly
+    /// (see `with_compression`). Security headers are added to every response,
+    /// if a policy is installed (see `with_security_headers`).
+    /// Directories without `index.html` are listed if auto-index is enabled
+    /// (see `with_auto_index`).
+    /// Methods other than GET and HEAD are answered with 405 Method Not Allowed.
+    /// Use `serve_timed` to also get the timing of the lookup and KV read.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(method = %req.method(), path = req.uri().path()))
+    )]
+    pub async fn serve(&self, req: &Request<()>) -> Response<Bytes> {
+        self.serve_timed(req).await.0
+    }
+
+    /// Serve an asset request as `serve` does, with per-request cache behavior,
+    /// such as always reading preview or admin requests from KV (see `CacheOptions`)
+    pub async fn serve_with(
+        &self,
+        req: &Request<()>,
+        options: &crate::CacheOptions,
+    ) -> Response<Bytes> {
+        self.serve_timed_with(req, options).await.0
+    }
+}
+
+/// Serve an asset request from any source: resolve the path, answer conditional
+/// and HEAD requests from metadata, then fetch the content
+pub(crate) async fn serve_source<S: AssetSource + ?Sized>(
+    source: &S,
+    req: &Request<()>,
+) -> Response<Bytes> {
+    if !is_read_method(req.method()) {
+        return method_not_allowed_response();
+    }
+    let md = match source.resolve_key(req.uri().path()) {
+        Ok(Some(ResolvedAsset::Direct(md)))
+        | Ok(Some(ResolvedAsset::Alias { metadata: md, .. })) => md,
+        Ok(Some(ResolvedAsset::Redirect(url))) => return redirect_response(&url),
+        Ok(None) => return status_response(StatusCode::NOT_FOUND),
+        Err(e) => return error_response(&e),
+    };
+    if is_not_modified(req.headers(), &md) {
+        return not_modified_response(&md);
+    }
+    if req.method() == Method::HEAD && md.size != 0 {
+        return asset_response(req.uri().path(), &md, Bytes::new());
+    }
+    let body = match source.fetch_asset(&md).await {
+        Ok(body) => body,
+        Err(e) => return error_response(&e),
+    };
+    if req.method() == Method::HEAD {
+        // unknown (zero) size, such as entries imported from a Workers Sites manifest:
+        // headers are generated from the content
+        return head_response(req.uri().path(), &md, body);
+    }
+    body_response(req, &md, body)
+}
+
+/// Builds a 200, 206, or 416 response for the asset content, depending on the Range header
+pub(crate) fn body_response(req: &Request<()>, md: &AssetMetadata, body: Bytes) -> Response<Bytes> {
+    match byte_range(req.headers(), md, body.len() as u64) {
+        ByteRange::Full => asset_response(req.uri().path(), md, body),
+        ByteRange::Partial(start, end) => range_response(req.uri().path(), md, body, start, end),
+        ByteRange::Unsatisfiable => unsatisfiable_response(body.len() as u64),
+    }
+}
+
+/// Builds a 200 response with headers derived from asset metadata.
+/// Content-Length is the asset size from the index, so for HEAD responses, body may be empty.
+/// For entries with unknown (zero) size, such as those imported from a Workers Sites
+/// manifest, it is the length of the body.
+/// Content-Type is guessed from the path's extension or, if the extension isn't
+/// recognized, from the content, if body is the whole asset; a content-type header
+/// in the metadata overrides both.
+pub(crate) fn asset_response(path: &str, md: &AssetMetadata, body: Bytes) -> Response<Bytes> {
+    let len = match md.size {
+        0 => body.len() as u64,
+        size => size,
+    };
+    let complete = body.len() as u64 == len;
+    let content_type = content_type(path, Some(&body[..]).filter(|_| complete));
+    metadata_response(md, content_type, len, body)
+}
+
+/// Builds a 200 response to a HEAD request for the asset content: the headers
+/// `asset_response` generates, without the body
+pub(crate) fn head_response(path: &str, md: &AssetMetadata, content: Bytes) -> Response<Bytes> {
+    let mut response = asset_response(path, md, content);
+    *response.body_mut() = Bytes::new();
+    response
+}
+
+// Content-Type from the path's extension or, if it isn't recognized, sniffed from
+// the content. None if neither is known
+fn content_type(path: &str, content: Option<&[u8]>) -> Option<HeaderValue> {
+    match mime_guess::from_path(path).first() {
+        Some(mime) => HeaderValue::from_str(mime.as_ref()).ok(),
+        None => content.map(|content| {
+            HeaderValue::from_static(
+                crate::sniff::sniff_content_type(content).unwrap_or("application/octet-stream"),
+            )
+        }),
+    }
+}
+
+// Response with the body, and headers from the metadata
+fn metadata_response(
+    md: &AssetMetadata,
+    content_type: Option<HeaderValue>,
+    len: u64,
+    body: Bytes,
+) -> Response<Bytes> {
+    let mut response = Response::new(body);
+    let headers = response.headers_mut();
+    if let Some(val) = content_type {
+        headers.insert(header::CONTENT_TYPE, val);
+    }
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(len));
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    if let Ok(val) = HeaderValue::from_str(&last_modified(md)) {
+        headers.insert(header::LAST_MODIFIED, val);
+    }
+    if let Ok(val) = HeaderValue::from_str(&etag(md)) {
+        headers.insert(header::ETAG, val);
+    }
+    custom_headers(headers, md);
+    response
+}
+
+/// Result of evaluating the Range header against the asset
+#[derive(Debug, PartialEq)]
+pub(crate) enum ByteRange {
+    /// No range, or range should be ignored: serve full content
+    Full,
+    /// First and last byte positions, inclusive
+    Partial(u64, u64),
+    /// Range can't be satisfied (416)
+    Unsatisfiable,
+}
+
+/// Parses a single-range Range header (RFC 7233). Malformed headers, multiple ranges,
+/// and If-Range validators that don't match the current asset result in full content.
+pub(crate) fn byte_range(headers: &HeaderMap, md: &AssetMetadata, len: u64) -> ByteRange {
+    let spec = match headers
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().strip_prefix("bytes="))
+    {
+        Some(spec) if !spec.contains(',') => spec.trim(),
+        _ => return ByteRange::Full,
+    };
+    if let Some(val) = headers.get(header::IF_RANGE) {
+        if val.to_str().ok() != Some(&etag(md)) && val.to_str().ok() != Some(&last_modified(md)) {
+            return ByteRange::Full;
+        }
+    }
+    let (first, last) = match spec.split_once('-') {
+        Some(parts) => parts,
+        None => return ByteRange::Full,
+    };
+    if first.is_empty() {
+        // suffix range: last N bytes
+        return match last.parse::<u64>() {
+            Ok(0) => ByteRange::Unsatisfiable,
+            Ok(n) if len > 0 => ByteRange::Partial(len.saturating_sub(n), len - 1),
+            Ok(_) => ByteRange::Unsatisfiable,
+            Err(_) => ByteRange::Full,
+        };
+    }
+    let start = match first.parse::<u64>() {
+        Ok(start) => start,
+        Err(_) => return ByteRange::Full,
+    };
+    let end = if last.is_empty() {
+        len.saturating_sub(1)
+    } else {
+        match last.parse::<u64>() {
+            Ok(end) if end >= start => end.min(len.saturating_sub(1)),
+            _ => return ByteRange::Full,
+        }
+    };
+    if start >= len {
+        return ByteRange::Unsatisfiable;
+    }
+    ByteRange::Partial(start, end)
+}
+
+/// Builds a 206 response for the byte range [start, end] of body
+pub(crate) fn range_response(
+    path: &str,
+    md: &AssetMetadata,
+    body: Bytes,
+    start: u64,
+    end: u64,
+) -> Response<Bytes> {
+    let total = body.len();
+    // sniffed from the whole asset, not the range
+    let content_type = content_type(path, Some(&body[..]));
+    let range = body.slice(start as usize..=end as usize);
+    let mut response = metadata_response(md, content_type, end - start + 1, range);
+    *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+    let headers = response.headers_mut();
+    if let Ok(val) = HeaderValue::from_str(&format!("bytes {}-{}/{}", start, end, total)) {
+        headers.insert(header::CONTENT_RANGE, val);
+    }
+    response
+}
+
+/// Builds a 416 response, with the actual length in Content-Range
+pub(crate) fn unsatisfiable_response(len: u64) -> Response<Bytes> {
+    let mut response = status_response(StatusCode::RANGE_NOT_SATISFIABLE);
+    if let Ok(val) = HeaderValue::from_str(&format!("bytes */{}", len)) {
+        response.headers_mut().insert(header::CONTENT_RANGE, val);
+    }
+    response
+}
+
+/// Returns true if the request's conditional headers indicate the client copy is current.
+/// If-None-Match takes precedence over If-Modified-Since (RFC 7232, section 3.3).
+/// If-Modified-Since is ignored for assets with an unknown (zero) modification time
+pub(crate) fn is_not_modified(headers: &HeaderMap, md: &AssetMetadata) -> bool {
+    if let Some(val) = headers.get(header::IF_NONE_MATCH) {
+        let etag = etag(md);
+        return match val.to_str() {
+            Ok(tags) => tags
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag),
+            Err(_) => false,
+        };
+    }
+    if let Some(val) = headers
+        .get(header::IF_MODIFIED_SINCE)
+        .filter(|_| md.modified != 0)
+    {
+        if let Some(since) = val
+            .to_str()
+            .ok()
+            .and_then(|s| httpdate::parse_http_date(s).ok())
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        {
+            return md.modified <= since.as_secs();
+        }
+    }
+    false
+}
+
+/// Methods that read assets. Others are answered with 405 Method Not Allowed
+pub(crate) fn is_read_method(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD)
+}
+
+/// Builds a 405 response, with the methods assets can be requested with
+pub(crate) fn method_not_allowed_response() -> Response<Bytes> {
+    let mut response = status_response(StatusCode::METHOD_NOT_ALLOWED);
+    response
+        .headers_mut()
+        .insert(header::ALLOW, HeaderValue::from_static("GET, HEAD"));
+    response
+}
+
+/// Builds a 304 response, with validator headers but no body
+pub(crate) fn not_modified_response(md: &AssetMetadata) -> Response<Bytes> {
+    let mut response = status_response(StatusCode::NOT_MODIFIED);
+    let headers = response.headers_mut();
+    if let Ok(val) = HeaderValue::from_str(&last_modified(md)) {
+        headers.insert(header::LAST_MODIFIED, val);
+    }
+    if let Ok(val) = HeaderValue::from_str(&etag(md)) {
+        headers.insert(header::ETAG, val);
+    }
+    custom_headers(headers, md);
+    response
+}
+
+/// Adds headers recorded in the asset metadata. Invalid names or values are skipped
+pub(crate) fn custom_headers(headers: &mut HeaderMap, md: &AssetMetadata) {
+    for (name, value) in md.headers.iter() {
+        if let (Ok(name), Ok(value)) = (
+            header::HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            headers.insert(name, value);
+        }
+    }
+}
+
+/// Last-Modified header value, in http date format
+pub(crate) fn last_modified(md: &AssetMetadata) -> String {
+    httpdate::fmt_http_date(UNIX_EPOCH + Duration::from_secs(md.modified))
+}
+
+/// Strong ETag for the asset: the content hash, if it's in the index.
+/// Otherwise the KV key, which also includes a checksum of the file contents,
+/// so it changes whenever the content does.
+pub(crate) fn etag(md: &AssetMetadata) -> String {
+    match &md.hash {
+        Some(hash) => format!("\"{}\"", hash),
+        None => format!("\"{}\"", md.path.replace('"', "")),
+    }
+}
+
+/// Response with empty body and the given status
+pub(crate) fn status_response(status: StatusCode) -> Response<Bytes> {
+    let mut response = Response::new(Bytes::new());
+    *response.status_mut() = status;
+    response
+}
+
+/// Builds a 301 redirect to the url
+pub(crate) fn redirect_response(url: &str) -> Response<Bytes> {
+    let mut response = status_response(StatusCode::MOVED_PERMANENTLY);
+    if let Ok(val) = HeaderValue::from_str(url) {
+        response.headers_mut().insert(header::LOCATION, val);
+    }
+    response
+}
+
+/// Map error to http status response
+pub(crate) fn error_response(e: &Error) -> Response<Bytes> {
+    status_response(match e {
+        Error::EmptyKey
+        | Error::InvalidKey(_)
+        | Error::KVKeyNotFound(_, 404)
+        | Error::IndexOutOfDate { .. } => StatusCode::NOT_FOUND,
+        Error::KVHttp(_)
+        | Error::KVKeyNotFound(_, _)
+        | Error::ChecksumMismatch(_)
+        | Error::Api { .. }
+        | Error::OriginStatus(_, _) => StatusCode::BAD_GATEWAY,
+        Error::CircuitOpen => StatusCode::SERVICE_UNAVAILABLE,
+        Error::ValueTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+        #[cfg(feature = "signing")]
+        Error::InvalidSignedUrl(_) => StatusCode::FORBIDDEN,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    })
+}
+
+/// Tests header generation from metadata
+#[test]
+fn test_asset_response() {
+    let md = AssetMetadata {
+        path: "css/site.0123456789.css".to_string(),
+        modified: 1_600_000_000,
+        size: 5,
+        hash: None,
+        ..Default::default()
+    };
+    let resp = asset_response("/css/site.css", &md, Bytes::from("body{"));
+    assert_eq!(resp.status(), StatusCode::OK);
+    let headers = resp.headers();
+    assert_eq!(headers[header::CONTENT_TYPE], "text/css");
+    assert_eq!(headers[header::CONTENT_LENGTH], "5");
+    assert_eq!(
+        headers[header::LAST_MODIFIED],
+        "Sun, 13 Sep 2020 12:26:40 GMT"
+    );
+    assert_eq!(headers[header::ETAG], "\"css/site.0123456789.css\"");
+
+    let mut md = md;
+    md.headers
+        .insert("x-robots-tag".to_string(), "noindex".to_string());
+    md.headers.insert("bad name".to_string(), "x".to_string());
+    let resp = asset_response("/css/site.css", &md, Bytes::from("body{"));
+    assert_eq!(resp.headers()["x-robots-tag"], "noindex");
+    assert_eq!(resp.headers().len(), 6);
+
+    assert_eq!(
+        error_response(&Error::EmptyKey).status(),
+        StatusCode::NOT_FOUND
+    );
+}
+
+/// Tests evaluation of conditional request headers
+#[test]
+fn test_not_modified() {
+    let md = AssetMetadata {
+        path: "index.0123456789.html".to_string(),
+        modified: 1_600_000_000,
+        size: 100,
+        hash: None,
+        ..Default::default()
+    };
+    let mut headers = HeaderMap::new();
+    assert!(!is_not_modified(&headers, &md));
+
+    headers.insert(
+        header::IF_MODIFIED_SINCE,
+        HeaderValue::from_static("Sun, 13 Sep 2020 12:26:40 GMT"),
+    );
+    assert!(is_not_modified(&headers, &md));
+    headers.insert(
+        header::IF_MODIFIED_SINCE,
+        HeaderValue::from_static("Sat, 12 Sep 2020 12:26:40 GMT"),
+    );
+    assert!(!is_not_modified(&headers, &md));
+
+    // etag match wins over stale date
+    headers.insert(
+        header::IF_NONE_MATCH,
+        HeaderValue::from_static("\"abc\", W/\"index.0123456789.html\""),
+    );
+    assert!(is_not_modified(&headers, &md));
+    headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("\"abc\""));
+    assert!(!is_not_modified(&headers, &md));
+
+    let resp = not_modified_response(&md);
+    assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+    assert!(resp.body().is_empty());
+}
+
+/// Tests that HEAD is answered from the index (does not invoke cloudflare api)
+#[cfg(test)]
+#[tokio::test]
+async fn test_head() {
+    let md = AssetMetadata {
+        path: "app.0123456789.js".to_string(),
+        modified: 1_600_000_000,
+        size: 1234,
+        hash: None,
+        ..Default::default()
+    };
+    let mut index = crate::AssetIndex::new();
+    index.insert("app.js".to_string(), md);
+    index.insert("old.js".to_string(), AssetMetadata::alias("app.js"));
+    let mut license = AssetMetadata {
+        path: "LICENSE".to_string(),
+        size: 10,
+        ..Default::default()
+    };
+    index.insert("LICENSE".to_string(), license.clone());
+    license
+        .headers
+        .insert("content-type".to_string(), "text/plain".to_string());
+    index.insert("NOTICE".to_string(), license);
+    index.insert(
+        "elsewhere".to_string(),
+        AssetMetadata::redirect("https://example.com/"),
+    );
+    let blob = crate::encode_index(&index).expect("serialize-index");
+    // unreachable credentials: any KV access would fail
+    let kv = KVAssets::init(&blob, "123", "namespace", "token");
+
+    let mut req = Request::new(());
+    *req.method_mut() = Method::HEAD;
+    *req.uri_mut() = "/app.js".parse().unwrap();
+    let resp = kv.serve(&req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers()[header::CONTENT_LENGTH], "1234");
+    assert_eq!(resp.headers()[header::CONTENT_TYPE], "text/javascript");
+    assert!(resp.body().is_empty());
+
+    *req.uri_mut() = "/old.js".parse().unwrap();
+    let resp = kv.serve(&req).await;
+    assert_eq!(resp.headers()[header::CONTENT_LENGTH], "1234");
+
+    // content isn't sniffed without the body
+    *req.uri_mut() = "/LICENSE".parse().unwrap();
+    let resp = kv.serve(&req).await;
+    assert!(resp.headers().get(header::CONTENT_TYPE).is_none());
+    *req.uri_mut() = "/NOTICE".parse().unwrap();
+    let resp = kv.serve(&req).await;
+    assert_eq!(resp.headers()[header::CONTENT_TYPE], "text/plain");
+
+    *req.uri_mut() = "/elsewhere".parse().unwrap();
+    let resp = kv.serve(&req).await;
+    assert_eq!(resp.status(), StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(resp.headers()[header::LOCATION], "https://example.com/");
+
+    *req.uri_mut() = "/missing.js".parse().unwrap();
+    assert_eq!(kv.serve(&req).await.status(), StatusCode::NOT_FOUND);
+}
+
+/// Tests HEAD of entries with unknown size, from a Workers Sites manifest, against the mock api
+#[cfg(all(test, feature = "reqwest"))]
+#[tokio::test]
+async fn test_head_sites_manifest() {
+    let manifest = r#"{"index.html":"index.5eb63bbbe0.html"}"#;
+    let blob = crate::index_from_sites_manifest(manifest).unwrap();
+    let mock = crate::testspace::MockCloudflare::start().unwrap();
+    mock.insert("ns", "index.5eb63bbbe0.html", "<p>hello</p>");
+    let kv = mock.handler(&blob, "ns");
+
+    let mut req = Request::new(());
+    *req.method_mut() = Method::HEAD;
+    *req.uri_mut() = "/index.html".parse().unwrap();
+    let resp = kv.serve(&req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers()[header::CONTENT_LENGTH], "12");
+    assert!(resp.body().is_empty());
+}
+
+/// Tests Range header parsing and partial responses
+#[test]
+fn test_byte_range() {
+    let md = AssetMetadata {
+        path: "video.0123456789.mp4".to_string(),
+        modified: 1_600_000_000,
+        size: 100,
+        hash: None,
+        ..Default::default()
+    };
+    let range = |val: &'static str| {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::RANGE, HeaderValue::from_static(val));
+        byte_range(&headers, &md, 100)
+    };
+    assert_eq!(byte_range(&HeaderMap::new(), &md, 100), ByteRange::Full);
+    assert_eq!(range("bytes=0-9"), ByteRange::Partial(0, 9));
+    assert_eq!(range("bytes=90-"), ByteRange::Partial(90, 99));
+    assert_eq!(range("bytes=-10"), ByteRange::Partial(90, 99));
+    assert_eq!(range("bytes=50-500"), ByteRange::Partial(50, 99));
+    assert_eq!(range("bytes=100-"), ByteRange::Unsatisfiable);
+    assert_eq!(range("bytes=0-1,5-6"), ByteRange::Full);
+    assert_eq!(range("items=0-1"), ByteRange::Full);
+    assert_eq!(range("bytes=9-1"), ByteRange::Full);
+
+    let body = Bytes::from(vec![7u8; 100]);
+    let resp = range_response("video.mp4", &md, body, 90, 99);
+    assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(resp.headers()[header::CONTENT_RANGE], "bytes 90-99/100");
+    assert_eq!(resp.headers()[header::CONTENT_LENGTH], "10");
+    assert_eq!(resp.body().len(), 10);
+    assert_eq!(resp.headers()[header::CONTENT_TYPE], "video/mp4");
+    // sniffed from the whole asset, not the range
+    let mut body = b"\x89PNG\r\n\x1a\n".to_vec();
+    body.resize(100, 0);
+    let resp = range_response("image", &md, Bytes::from(body), 90, 99);
+    assert_eq!(resp.headers()[header::CONTENT_TYPE], "image/png");
+    assert_eq!(
+        unsatisfiable_response(100).headers()[header::CONTENT_RANGE],
+        "bytes */100"
+    );
+}
@@ -0,0 +1,50 @@
+#![cfg(feature = "tower")]
+
+use crate::KVAssets;
+use bytes::Bytes;
+use http::{Request, Response};
+use http_body::Full;
+use std::{
+    convert::Infallible,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+/// tower `Service` that serves static assets from Workers KV.
+/// Can be used as a fallback service in axum, hyper, or any other tower-based stack.
+/// Errors are returned as http error responses, so the service never fails.
+#[derive(Clone)]
+pub struct KvAssetsService {
+    kv: Arc<KVAssets<'static>>,
+}
+
+impl KvAssetsService {
+    /// Create service
+    pub fn new(kv: KVAssets<'static>) -> Self {
+        Self { kv: Arc::new(kv) }
+    }
+
+    /// Create service from shared handler
+    pub fn from_arc(kv: Arc<KVAssets<'static>>) -> Self {
+        Self { kv }
+    }
+}
+
+impl<B> tower_service::Service<Request<B>> for KvAssetsService {
+    type Response = Response<Full<Bytes>>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        let kv = self.kv.clone();
+        let (parts, _body) = req.into_parts();
+        let head = Request::from_parts(parts, ());
+        Box::pin(async move { Ok(kv.serve(&head).await.map(Full::new)) })
+    }
+}
@@ -0,0 +1,171 @@
+#![cfg(feature = "signing")]
+
+use crate::{Error, KVAssets};
+use bytes::Bytes;
+use hmac::{Hmac, Mac};
+use http::{Request, Response, StatusCode};
+use sha2::Sha256;
+use std::time::Duration;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Generates and verifies signed asset urls (path, expiry time, and HMAC-SHA256
+/// signature in the query string), so a worker can serve private assets
+/// only to holders of a link it issued, like S3 presigned urls.
+///
+/// Signed urls look like `/private/report.pdf?expires=1700000000&signature=3f1c...`.
+/// The signature covers the path and expiry; other query parameters are ignored.
+/// Signatures are compared in constant time. Expiry allows for clock skew
+/// between the signer and the verifier (default 30 seconds).
+pub struct UrlSigner {
+    key: Vec<u8>,
+    skew_secs: u64,
+    clock: fn() -> u64,
+}
+
+impl UrlSigner {
+    /// Sign and verify with the secret key
+    pub fn new(key: &[u8]) -> Self {
+        Self {
+            key: key.to_vec(),
+            skew_secs: 30,
+            clock: crate::breaker::system_millis,
+        }
+    }
+
+    /// Accept urls up to `skew` after their expiry time. default: 30 seconds
+    pub fn with_clock_skew(mut self, skew: Duration) -> Self {
+        self.skew_secs = skew.as_secs();
+        self
+    }
+
+    /// Use a clock returning the current time in milliseconds
+    pub fn with_clock(mut self, clock: fn() -> u64) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Signed url for the path, valid until `expires` (unix time, in seconds)
+    pub fn sign(&self, path: &str, expires: u64) -> String {
+        let path = normalize(path);
+        format!(
+            "{}?expires={}&signature={}",
+            path,
+            expires,
+            to_hex(&self.mac(&path, expires).finalize().into_bytes())
+        )
+    }
+
+    /// Signed url for the path, valid for `ttl` from now
+    pub fn sign_for(&self, path: &str, ttl: Duration) -> String {
+        self.sign(path, (self.clock)() / 1000 + ttl.as_secs())
+    }
+
+    /// Verifies the signature and expiry in the query string for the path.
+    /// Returns `Error::InvalidSignedUrl` if either is missing, invalid, or expired
+    pub fn verify(&self, path: &str, query: Option<&str>) -> Result<(), Error> {
+        let invalid = |reason: &str| Error::InvalidSignedUrl(reason.to_string());
+        let mut expires = None;
+        let mut signature = None;
+        for (name, value) in query
+            .unwrap_or_default()
+            .split('&')
+            .filter_map(|p| p.split_once('='))
+        {
+            match name {
+                "expires" => expires = value.parse::<u64>().ok(),
+                "signature" => signature = from_hex(value),
+                _ => {}
+            }
+        }
+        let (expires, signature) = match (expires, signature) {
+            (Some(expires), Some(signature)) => (expires, signature),
+            _ => return Err(invalid("missing expires or signature")),
+        };
+        self.mac(&normalize(path), expires)
+            .verify_slice(&signature)
+            .map_err(|_| invalid("signature mismatch"))?;
+        if (self.clock)() / 1000 > expires.saturating_add(self.skew_secs) {
+            return Err(invalid("expired"));
+        }
+        Ok(())
+    }
+
+    /// Verifies the request's path and query string (see `verify`)
+    pub fn verify_request<B>(&self, req: &Request<B>) -> Result<(), Error> {
+        self.verify(req.uri().path(), req.uri().query())
+    }
+
+    fn mac(&self, path: &str, expires: u64) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("hmac accepts any key length");
+        mac.update(path.as_bytes());
+        mac.update(b"\n");
+        mac.update(expires.to_string().as_bytes());
+        mac
+    }
+}
+
+impl<'ah> KVAssets<'ah> {
+    /// Serve the request (see `serve`) only if its url was signed by signer;
+    /// otherwise responds with 403 Forbidden
+    pub async fn serve_signed(&self, req: &Request<()>, signer: &UrlSigner) -> Response<Bytes> {
+        match signer.verify_request(req) {
+            Ok(()) => self.serve(req).await,
+            Err(_) => crate::serve::status_response(StatusCode::FORBIDDEN),
+        }
+    }
+}
+
+// Path with a leading '/'
+fn normalize(path: &str) -> String {
+    format!("/{}", path.trim_start_matches('/'))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Tests signing and verifying urls
+#[test]
+fn test_signed_url() {
+    fn clock() -> u64 {
+        1_700_000_000_000
+    }
+    let signer = UrlSigner::new(b"secret").with_clock(clock);
+    let url = signer.sign_for("private/a.pdf", Duration::from_secs(60));
+    let (path, query) = url.split_once('?').unwrap();
+    assert_eq!(path, "/private/a.pdf");
+    assert!(query.starts_with("expires=1700000060&signature="));
+    assert!(signer.verify(path, Some(query)).is_ok());
+    assert!(signer
+        .verify("private/a.pdf", Some(&format!("{}&x=1", query)))
+        .is_ok());
+
+    let check = |path: &str, query: &str| signer.verify(path, Some(query)).is_err();
+    assert!(check("/private/b.pdf", query));
+    assert!(check(path, &query.replace("1700000060", "1700000061")));
+    assert!(check(path, "expires=1700000060"));
+    assert!(signer.verify(path, None).is_err());
+    assert!(UrlSigner::new(b"other").verify(path, Some(query)).is_err());
+
+    // expired, within and beyond skew
+    let expired = signer.sign(path, 1_699_999_980);
+    let (_, query) = expired.split_once('?').unwrap();
+    assert!(signer.verify(path, Some(query)).is_ok());
+    let expired = signer.sign(path, 1_699_999_960);
+    let (_, query) = expired.split_once('?').unwrap();
+    assert!(matches!(
+        signer.verify(path, Some(query)),
+        Err(Error::InvalidSignedUrl(_))
+    ));
+}
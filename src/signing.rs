@@ -0,0 +1,65 @@
+#![cfg(feature = "signing")]
+
+use crate::Error;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+const TAG_LEN: usize = 32;
+
+/// Signs a serialized index with HMAC-SHA256, returning the index with the MAC appended.
+/// Use `KVAssets::with_index_key` with the same key to verify it when it's loaded.
+pub fn sign_index(index: &[u8], key: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("hmac accepts any key length");
+    mac.update(index);
+    let mut signed = Vec::with_capacity(index.len() + TAG_LEN);
+    signed.extend_from_slice(index);
+    signed.extend_from_slice(&mac.finalize().into_bytes());
+    signed
+}
+
+/// Verifies a signed index, returning the serialized index without the MAC
+pub(crate) fn verify_index<'a>(signed: &'a [u8], key: &[u8]) -> Result<&'a [u8], Error> {
+    if signed.len() < TAG_LEN {
+        return Err(Error::IndexTampered);
+    }
+    let (index, tag) = signed.split_at(signed.len() - TAG_LEN);
+    let mut mac = HmacSha256::new_from_slice(key).expect("hmac accepts any key length");
+    mac.update(index);
+    mac.verify_slice(tag).map_err(|_| Error::IndexTampered)?;
+    Ok(index)
+}
+
+/// Tests index signing and verification
+#[test]
+fn test_signed_index() {
+    use crate::{AssetIndex, AssetMetadata, KVAssets};
+
+    let mut index = AssetIndex::new();
+    index.insert(
+        "a.txt".to_string(),
+        AssetMetadata {
+            path: "a.123.txt".to_string(),
+            modified: 10000,
+            size: 10,
+            hash: None,
+            ..Default::default()
+        },
+    );
+    let blob = crate::encode_index(&index).expect("serialize-index");
+    let signed = sign_index(&blob, b"secret");
+    assert_eq!(verify_index(&signed, b"secret").unwrap(), &blob[..]);
+
+    let kv = KVAssets::init(&signed, "123", "namespace", "token").with_index_key(b"secret");
+    assert!(kv.lookup_key("a.txt").unwrap().is_some());
+
+    let kv = KVAssets::init(&signed, "123", "namespace", "token").with_index_key(b"wrong");
+    assert!(matches!(kv.lookup_key("a.txt"), Err(Error::IndexTampered)));
+
+    let mut tampered = signed.clone();
+    tampered[0] ^= 1;
+    assert!(matches!(
+        verify_index(&tampered, b"secret"),
+        Err(Error::IndexTampered)
+    ));
+}
@@ -0,0 +1,109 @@
+// Number of bytes examined when sniffing content
+const SNIFF_LEN: usize = 512;
+
+// Leading bytes of common binary formats, and their content types
+const SIGNATURES: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"\x00\x00\x01\x00", "image/x-icon"),
+    (b"BM", "image/bmp"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"\x00asm", "application/wasm"),
+    (b"wOFF", "font/woff"),
+    (b"wOF2", "font/woff2"),
+    (b"OTTO", "font/otf"),
+    (b"\x00\x01\x00\x00", "font/ttf"),
+    (b"\x1a\x45\xdf\xa3", "video/webm"),
+    (b"OggS", "audio/ogg"),
+    (b"ID3", "audio/mpeg"),
+];
+
+/// Guess the content type of an asset from its first bytes, for assets without
+/// a recognizable extension: magic numbers of common image, font, archive,
+/// and media formats, then html, svg, and utf-8 text.
+/// Returns None if the content isn't recognized
+pub(crate) fn sniff_content_type(content: &[u8]) -> Option<&'static str> {
+    let head = &content[..content.len().min(SNIFF_LEN)];
+    if let Some((_, content_type)) = SIGNATURES.iter().find(|(sig, _)| head.starts_with(sig)) {
+        return Some(content_type);
+    }
+    // RIFF container and ISO base media (ftyp box) formats
+    match (head.get(..4), head.get(4..8), head.get(8..12)) {
+        (Some(b"RIFF"), _, Some(b"WEBP")) => return Some("image/webp"),
+        (Some(b"RIFF"), _, Some(b"WAVE")) => return Some("audio/wav"),
+        (_, Some(b"ftyp"), Some(b"avif")) => return Some("image/avif"),
+        (_, Some(b"ftyp"), Some(_)) => return Some("video/mp4"),
+        _ => {}
+    }
+    let text = utf8_prefix(head)?;
+    let start = text
+        .trim_start_matches('\u{feff}')
+        .trim_start()
+        .to_ascii_lowercase();
+    if start.starts_with("<!doctype html") || start.starts_with("<html") {
+        Some("text/html; charset=utf-8")
+    } else if start.starts_with("<svg") || (start.starts_with("<?xml") && start.contains("<svg")) {
+        Some("image/svg+xml")
+    } else if start.starts_with("<?xml") {
+        Some("application/xml")
+    } else {
+        Some("text/plain; charset=utf-8")
+    }
+}
+
+// The content as text, if it is utf-8 without control characters other than whitespace.
+// A character truncated at the end of the sniffed bytes is ignored
+fn utf8_prefix(head: &[u8]) -> Option<&str> {
+    let text = match std::str::from_utf8(head) {
+        Ok(text) => text,
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&head[..e.valid_up_to()]).ok()?,
+        Err(_) => return None,
+    };
+    match text
+        .chars()
+        .any(|c| c.is_control() && !c.is_ascii_whitespace())
+    {
+        true => None,
+        false => Some(text),
+    }
+}
+
+/// Tests content type sniffing
+#[test]
+fn test_sniff_content_type() {
+    assert_eq!(
+        sniff_content_type(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+        Some("image/png")
+    );
+    assert_eq!(sniff_content_type(b"wOF2\0\x01\0\0"), Some("font/woff2"));
+    assert_eq!(
+        sniff_content_type(b"RIFF\x24\0\0\0WEBPVP8 "),
+        Some("image/webp")
+    );
+    assert_eq!(
+        sniff_content_type(b"\0\0\0\x1cftypavif\0\0\0\0"),
+        Some("image/avif")
+    );
+    assert_eq!(
+        sniff_content_type(b"\n<!DOCTYPE html><title>x</title>"),
+        Some("text/html; charset=utf-8")
+    );
+    assert_eq!(
+        sniff_content_type(b"<?xml version=\"1.0\"?>\n<svg xmlns=\"\"></svg>"),
+        Some("image/svg+xml")
+    );
+    assert_eq!(
+        sniff_content_type("caf\u{e9} au lait".as_bytes()),
+        Some("text/plain; charset=utf-8")
+    );
+    // multi-byte character cut off at the sniff limit
+    let mut text = vec![b'a'; SNIFF_LEN - 1];
+    text.extend_from_slice("\u{e9}".as_bytes());
+    assert_eq!(sniff_content_type(&text), Some("text/plain; charset=utf-8"));
+    assert_eq!(sniff_content_type(b"\x01\x02\x03binary"), None);
+    assert_eq!(sniff_content_type(b"\xfe\xfe\xfe"), None);
+}
@@ -0,0 +1,70 @@
+use crate::{AssetMetadata, Error, KVAssets, ResolvedAsset};
+use bytes::Bytes;
+use http::{Request, Response};
+use std::{future::Future, pin::Pin};
+
+/// Future returned by `AssetSource` methods
+#[cfg(not(target_arch = "wasm32"))]
+pub type AssetFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Future returned by `AssetSource` methods
+#[cfg(target_arch = "wasm32")]
+pub type AssetFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// Serving api shared by asset backends: `KVAssets` in production,
+/// and `LocalAssets` (a source directory) for local development.
+/// Handlers written against `&dyn AssetSource` can switch backends with configuration.
+pub trait AssetSource: Send + Sync {
+    /// Finds the path, resolving aliases. Returns Ok(None) if not found
+    fn resolve_key(&self, path: &str) -> Result<Option<ResolvedAsset>, Error>;
+
+    /// Reads the content of an asset returned by `resolve_key` or `lookup_key`
+    fn fetch_asset<'a>(&'a self, md: &'a AssetMetadata) -> AssetFuture<'a, Result<Bytes, Error>>;
+
+    /// Finds the path, returning the metadata of the asset (or of its alias target).
+    /// Returns Ok(None) if not found
+    fn lookup_key(&self, path: &str) -> Result<Option<AssetMetadata>, Error> {
+        Ok(self.resolve_key(path)?.map(|resolved| match resolved {
+            ResolvedAsset::Direct(md) | ResolvedAsset::Alias { metadata: md, .. } => md,
+            ResolvedAsset::Redirect(url) => AssetMetadata::redirect(url),
+        }))
+    }
+
+    /// Looks up and reads the asset. Returns Ok(None) if not found
+    fn get_asset<'a>(&'a self, path: &'a str) -> AssetFuture<'a, Result<Option<Bytes>, Error>> {
+        Box::pin(async move {
+            match self.lookup_key(path)? {
+                Some(md) => Ok(Some(self.fetch_asset(&md).await?)),
+                None => Ok(None),
+            }
+        })
+    }
+
+    /// Serve an asset request, with the same headers and conditional request
+    /// handling as `KVAssets::serve`
+    fn serve<'a>(&'a self, req: &'a Request<()>) -> AssetFuture<'a, Response<Bytes>> {
+        Box::pin(crate::serve::serve_source(self, req))
+    }
+}
+
+impl<'ah> AssetSource for KVAssets<'ah> {
+    fn resolve_key(&self, path: &str) -> Result<Option<ResolvedAsset>, Error> {
+        KVAssets::resolve_key(self, path)
+    }
+
+    fn fetch_asset<'a>(&'a self, md: &'a AssetMetadata) -> AssetFuture<'a, Result<Bytes, Error>> {
+        Box::pin(KVAssets::fetch_asset(self, md))
+    }
+
+    fn lookup_key(&self, path: &str) -> Result<Option<AssetMetadata>, Error> {
+        KVAssets::lookup_key(self, path)
+    }
+
+    fn get_asset<'a>(&'a self, path: &'a str) -> AssetFuture<'a, Result<Option<Bytes>, Error>> {
+        Box::pin(KVAssets::get_asset(self, path))
+    }
+
+    fn serve<'a>(&'a self, req: &'a Request<()>) -> AssetFuture<'a, Response<Bytes>> {
+        Box::pin(KVAssets::serve(self, req))
+    }
+}
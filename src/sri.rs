@@ -0,0 +1,83 @@
+use crate::{AssetMetadata, Error, KVAssets};
+use sha2::{Digest, Sha384};
+
+/// Key in `AssetMetadata::extra` holding the Subresource Integrity hash
+/// (`sha384-…`) of JavaScript and CSS assets, computed when the index is built
+pub const INTEGRITY_KEY: &str = "integrity";
+
+/// Subresource Integrity hash of content, for `integrity` attributes: `sha384-` and the
+/// base64-encoded SHA-384 digest
+pub fn sri_hash(content: &[u8]) -> String {
+    format!("sha384-{}", base64::encode(Sha384::digest(content)))
+}
+
+// Scripts and stylesheets, the assets integrity attributes apply to
+pub(crate) fn wants_integrity(path: &str) -> bool {
+    let ext = path
+        .rsplit_once('.')
+        .map(|(_, ext)| ext)
+        .unwrap_or_default();
+    matches!(
+        ext.to_ascii_lowercase().as_str(),
+        "js" | "mjs" | "cjs" | "css"
+    )
+}
+
+// Record the integrity hash of content in md, if the asset at path is a script or stylesheet
+pub(crate) fn add_integrity(path: &str, md: &mut AssetMetadata, content: &[u8]) {
+    if wants_integrity(path) {
+        md.extra
+            .insert(INTEGRITY_KEY.to_string(), sri_hash(content));
+    }
+}
+
+impl AssetMetadata {
+    /// Subresource Integrity hash of the value, for JavaScript and CSS assets
+    /// in indexes built by this version of kv-assets
+    pub fn integrity(&self) -> Option<&str> {
+        self.extra.get(INTEGRITY_KEY).map(String::as_str)
+    }
+}
+
+impl<'ah> KVAssets<'ah> {
+    /// Subresource Integrity hash (`sha384-…`) of the JavaScript or CSS asset at path,
+    /// for the `integrity` attribute of `<script>` and `<link>` tags generated by the
+    /// worker. The hash is computed from the value uploaded to KV when the index is
+    /// built, so it always matches what is served. Returns Ok(None) if the path isn't
+    /// in the index, or has no integrity hash
+    pub fn sri_for(&self, path: &str) -> Result<Option<String>, Error> {
+        Ok(self
+            .lookup_key(path)?
+            .and_then(|md| md.integrity().map(str::to_string)))
+    }
+}
+
+/// Tests integrity hashes of indexed scripts and stylesheets
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn test_sri_for() {
+    assert_eq!(
+        sri_hash(b""),
+        "sha384-OLBgp1GsljhM2TJ+sbHjaiH9txEUvgdDTAzHv2P24donTt6/529l+9Ua0vFImLlb"
+    );
+    let dir = std::env::temp_dir().join(format!("kv-assets-sri-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("css")).unwrap();
+    std::fs::write(dir.join("app.js"), "alert(1)").unwrap();
+    std::fs::write(dir.join("css/site.CSS"), "body{}").unwrap();
+    std::fs::write(dir.join("index.html"), "<html>").unwrap();
+    let index = crate::index_dir(&dir).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let bytes = crate::encode_index(&index).unwrap();
+    let kv = KVAssets::init(&bytes, "acct", "ns", "token");
+    assert_eq!(
+        kv.sri_for("/app.js").unwrap().unwrap(),
+        sri_hash(b"alert(1)")
+    );
+    assert_eq!(
+        kv.sri_for("css/site.CSS").unwrap().unwrap(),
+        sri_hash(b"body{}")
+    );
+    assert_eq!(kv.sri_for("/index.html").unwrap(), None);
+    assert_eq!(kv.sri_for("/missing.js").unwrap(), None);
+}
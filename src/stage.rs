@@ -0,0 +1,163 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use crate::{AssetIndex, DeployReport, Error, KVAssets};
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::Instant;
+
+/// A deploy whose values are uploaded, but whose index isn't published yet,
+/// returned by `KVAssets::stage_dir`. Workers keep using the previous index
+/// until it is committed, so they never see an index that references values
+/// that aren't in KV yet.
+///
+/// Deploys have three phases:
+/// 1. `stage_dir` uploads new and changed values
+/// 2. `verify_staged` checks that every value referenced by the index is in KV.
+///    Callers can run their own checks (such as smoke tests against a preview
+///    namespace) after this
+/// 3. `commit_staged` publishes the index to KV (see `publish_index`), or
+///    `commit_staged_to_file` writes it for embedding in the worker.
+///    Both verify first if `verify_staged` hasn't succeeded
+#[derive(Debug, Clone)]
+pub struct StagedDeploy {
+    index: AssetIndex,
+    report: DeployReport,
+    verified: bool,
+}
+
+impl StagedDeploy {
+    pub(crate) fn new(index: AssetIndex, report: DeployReport) -> Self {
+        Self {
+            index,
+            report,
+            verified: false,
+        }
+    }
+
+    /// The index to be published
+    pub fn index(&self) -> &AssetIndex {
+        &self.index
+    }
+
+    /// Report of the upload phase
+    pub fn report(&self) -> &DeployReport {
+        &self.report
+    }
+
+    /// Returns true if `verify_staged` succeeded
+    pub fn is_verified(&self) -> bool {
+        self.verified
+    }
+
+    /// The index and report, without publishing the index
+    pub fn into_parts(self) -> (AssetIndex, DeployReport) {
+        (self.index, self.report)
+    }
+}
+
+impl<'ah> KVAssets<'ah> {
+    /// Second phase of an atomic deploy: checks that the value of every asset in the
+    /// staged index is in KV, using the key listing api. Returns `Error::MissingValues`
+    /// with the missing keys, if any
+    pub async fn verify_staged(&self, staged: &mut StagedDeploy) -> Result<(), Error> {
+        let start = Instant::now();
+        let kv_keys: HashSet<String> = self.list_keys().await?.into_iter().collect();
+        let mut missing: Vec<String> = staged
+            .index
+            .values()
+            .filter(|md| md.alias.is_none() && !md.is_deleted())
+            .filter(|md| !kv_keys.contains(&md.path))
+            .map(|md| md.path.clone())
+            .collect();
+        staged.report.durations.verify_ms = crate::upload::elapsed_ms(start);
+        if !missing.is_empty() {
+            missing.sort();
+            missing.dedup();
+            return Err(Error::MissingValues(missing));
+        }
+        staged.verified = true;
+        Ok(())
+    }
+
+    /// Last phase of an atomic deploy: publishes the staged index to KV as a new
+    /// version, keeping the newest `keep` versions (see `publish_index`).
+    /// Returns the version, and the deploy report
+    pub async fn commit_staged(
+        &self,
+        mut staged: StagedDeploy,
+        keep: usize,
+    ) -> Result<(u32, DeployReport), Error> {
+        if !staged.verified {
+            self.verify_staged(&mut staged).await?;
+        }
+        let version = self.publish_index(&staged.index, keep).await?;
+        Ok((version, staged.report))
+    }
+
+    /// Last phase of an atomic deploy, for indexes embedded in the worker: writes the
+    /// staged index to path (signed, if an index key is set). Deploy the worker after this
+    pub async fn commit_staged_to_file(
+        &self,
+        mut staged: StagedDeploy,
+        path: &Path,
+    ) -> Result<DeployReport, Error> {
+        if !staged.verified {
+            self.verify_staged(&mut staged).await?;
+        }
+        let bytes = self.encode_published(&staged.index)?;
+        std::fs::write(path, &bytes)
+            .map_err(|e| Error::IO(format!("writing {}: {}", path.display(), e)))?;
+        Ok(staged.report)
+    }
+
+    /// Stage, verify, and commit asset_dir in one call (see `StagedDeploy`).
+    /// Returns the published index version, and the deploy report
+    pub async fn deploy_dir(
+        &self,
+        asset_dir: &Path,
+        keep: usize,
+    ) -> Result<(u32, DeployReport), Error> {
+        let staged = self.stage_dir(asset_dir).await?;
+        self.commit_staged(staged, keep).await
+    }
+}
+
+/// Tests that the index is published only after its values are verified,
+/// against the mock api
+#[cfg(all(test, feature = "reqwest"))]
+#[tokio::test]
+async fn test_staged_deploy() {
+    use crate::AssetMetadata;
+
+    let mut index = AssetIndex::new();
+    index.insert(
+        "index.html".to_string(),
+        AssetMetadata {
+            path: "index.1.html".to_string(),
+            ..Default::default()
+        },
+    );
+    index.insert("home.html".to_string(), AssetMetadata::alias("index.html"));
+    let mock = crate::testspace::MockCloudflare::start().unwrap();
+    let kv = mock.handler(&[], "ns");
+
+    // value not in KV: nothing is published
+    let staged = StagedDeploy::new(index.clone(), DeployReport::default());
+    match kv.commit_staged(staged, 3).await {
+        Err(Error::MissingValues(keys)) => assert_eq!(keys, vec!["index.1.html"]),
+        other => panic!("expected MissingValues, got {:?}", other.map(|r| r.0)),
+    }
+    assert!(mock.keys("ns").is_empty());
+
+    mock.insert("ns", "index.1.html", "hello");
+    let mut staged = StagedDeploy::new(index, DeployReport::default());
+    kv.verify_staged(&mut staged).await.unwrap();
+    assert!(staged.is_verified());
+    let (version, _) = kv.commit_staged(staged, 3).await.unwrap();
+    assert_eq!(version, 1);
+    assert_eq!(
+        mock.keys("ns"),
+        vec![crate::CURRENT_INDEX_KEY, "__index_v1", "index.1.html"]
+    );
+    assert_eq!(mock.get("ns", crate::CURRENT_INDEX_KEY).unwrap(), "1");
+}
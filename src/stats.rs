@@ -0,0 +1,189 @@
+use crate::AssetIndex;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+// Number of assets listed in `IndexStats::largest`
+const LARGEST_COUNT: usize = 10;
+
+/// Summary of an asset index, returned by `AssetIndexExt::stats`.
+/// Serializable for deploy reports; `Display` prints a one-line summary.
+#[derive(Debug, Default, Clone, Serialize, PartialEq, Eq)]
+pub struct IndexStats {
+    /// Number of assets, not including aliases
+    pub count: usize,
+    /// Number of alias entries
+    pub aliases: usize,
+    /// Total size of assets, in bytes
+    pub total_bytes: u64,
+    /// Largest assets (path, size), largest first
+    pub largest: Vec<(String, u64)>,
+    /// Count and size of assets by lowercase file extension ("" for none)
+    pub by_extension: BTreeMap<String, ExtensionStats>,
+}
+
+/// Count and total size of assets with one file extension
+#[derive(Debug, Default, Clone, Serialize, PartialEq, Eq)]
+pub struct ExtensionStats {
+    /// Number of assets
+    pub count: usize,
+    /// Total size, in bytes
+    pub bytes: u64,
+}
+
+/// Asset larger than a size limit, returned by `AssetIndexExt::oversized`
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct OversizedAsset {
+    /// Asset path
+    pub path: String,
+    /// Asset size, in bytes
+    pub size: u64,
+    /// Limit that was exceeded, in bytes
+    pub limit: u64,
+}
+
+impl std::fmt::Display for OversizedAsset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({}, limit {})",
+            self.path,
+            format_bytes(self.size),
+            format_bytes(self.limit)
+        )
+    }
+}
+
+/// Assets larger than limit, sorted by path
+pub(crate) fn oversized(index: &AssetIndex, limit: u64) -> Vec<OversizedAsset> {
+    let mut found: Vec<OversizedAsset> = index
+        .iter()
+        .filter(|(_, md)| md.alias.is_none() && !md.is_deleted() && md.size > limit)
+        .map(|(path, md)| OversizedAsset {
+            path: path.clone(),
+            size: md.size,
+            limit,
+        })
+        .collect();
+    found.sort_by(|a, b| a.path.cmp(&b.path));
+    found
+}
+
+impl IndexStats {
+    pub(crate) fn from_index(index: &AssetIndex) -> Self {
+        let mut stats = IndexStats::default();
+        let mut sizes = Vec::new();
+        for (path, md) in index.iter().filter(|(_, md)| !md.is_deleted()) {
+            if md.alias.is_some() {
+                stats.aliases += 1;
+                continue;
+            }
+            stats.count += 1;
+            stats.total_bytes += md.size;
+            sizes.push((path.clone(), md.size));
+            let ext = std::path::Path::new(path)
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+                .unwrap_or_default();
+            let by_ext = stats.by_extension.entry(ext).or_default();
+            by_ext.count += 1;
+            by_ext.bytes += md.size;
+        }
+        // largest first, then by path so the report is deterministic
+        sizes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        sizes.truncate(LARGEST_COUNT);
+        stats.largest = sizes;
+        stats
+    }
+}
+
+impl std::fmt::Display for IndexStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} files, {}",
+            self.count,
+            format_bytes(self.total_bytes)
+        )?;
+        if let Some((path, _)) = self.largest.first() {
+            write!(f, ", largest: {}", path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats byte count with a decimal unit, e.g. "18.3 MB"
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1000 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1000.0;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Tests index statistics
+#[test]
+fn test_index_stats() {
+    use crate::{AssetIndexExt, AssetMetadata};
+
+    let mut index = AssetIndex::new();
+    for (path, size) in [
+        ("index.html", 1_000),
+        ("about.HTML", 2_000),
+        ("video/intro.mp4", 18_000_000),
+        ("LICENSE", 300),
+    ] {
+        index.insert(
+            path.to_string(),
+            AssetMetadata {
+                path: path.to_string(),
+                size,
+                ..Default::default()
+            },
+        );
+    }
+    index.insert("old.html".to_string(), AssetMetadata::alias("index.html"));
+
+    let stats = index.stats();
+    assert_eq!(stats.count, 4);
+    assert_eq!(stats.aliases, 1);
+    assert_eq!(stats.total_bytes, 18_003_300);
+    assert_eq!(
+        stats.largest[0],
+        ("video/intro.mp4".to_string(), 18_000_000)
+    );
+    assert_eq!(stats.largest.len(), 4);
+    assert_eq!(
+        stats.by_extension["html"],
+        ExtensionStats {
+            count: 2,
+            bytes: 3_000
+        }
+    );
+    assert_eq!(stats.by_extension[""].count, 1);
+    assert_eq!(
+        stats.to_string(),
+        "4 files, 18.0 MB, largest: video/intro.mp4"
+    );
+    assert_eq!(format_bytes(999), "999 B");
+
+    let oversized = index.oversized(crate::MAX_VALUE_SIZE as u64);
+    assert!(oversized.is_empty());
+    let oversized = index.oversized(1_500);
+    assert_eq!(
+        oversized
+            .iter()
+            .map(|a| a.path.as_str())
+            .collect::<Vec<_>>(),
+        vec!["about.HTML", "video/intro.mp4"]
+    );
+    assert_eq!(
+        oversized[0].to_string(),
+        "about.HTML (2.0 KB, limit 1.5 KB)"
+    );
+}
@@ -0,0 +1,671 @@
+#![cfg(not(target_arch = "wasm32"))]
+//! In-process mock of the Cloudflare KV api, for hermetic tests of code using
+//! `KVAssets` (feature "testspace").
+//!
+//! `MockCloudflare::start` serves the KV endpoints this crate uses (values, metadata,
+//! bulk writes and deletes, key listings, and namespace management) on a local
+//! port, from in-memory namespaces, and `MockCloudflare::handler` returns a `KVAssets` that sends its
+//! requests there. Reads, writes, uploads, syncs, and serving run over real HTTP,
+//! without Cloudflare credentials or network access.
+//!
+//! ```
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! let mock = kv_assets::testspace::MockCloudflare::start().unwrap();
+//! let kv = mock.handler(&[], "ns");
+//! kv.put_kv_value("a.txt", "hello", None).await.unwrap();
+//! assert_eq!(mock.get("ns", "a.txt").unwrap(), "hello");
+//! # }
+//! ```
+
+use crate::{Error, KVAssets};
+use bytes::Bytes;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Account id of handlers created by `MockCloudflare::handler`
+pub const MOCK_ACCOUNT_ID: &str = "mock-account";
+
+/// Api token of handlers created by `MockCloudflare::handler`
+pub const MOCK_AUTH_TOKEN: &str = "mock-token";
+
+// Maximum keys in a page of a key listing, as in the Cloudflare api
+const LIST_LIMIT_MAX: usize = 1000;
+
+// Value stored in a mock namespace
+#[derive(Debug, Clone)]
+struct Entry {
+    value: Bytes,
+    metadata: Option<Value>,
+    // seconds since epoch
+    expiration: Option<u64>,
+}
+
+impl Entry {
+    fn is_live(&self, now: u64) -> bool {
+        self.expiration.is_none_or(|at| at > now)
+    }
+}
+
+// Mock namespace: its title, and key -> entry
+#[derive(Debug, Clone, Default)]
+struct MockNamespace {
+    title: String,
+    values: BTreeMap<String, Entry>,
+}
+
+// namespace id -> namespace
+type Namespaces = BTreeMap<String, MockNamespace>;
+
+// Namespace with the id, created with the id as title on first use
+fn namespace<'n>(namespaces: &'n mut Namespaces, id: &str) -> &'n mut MockNamespace {
+    namespaces
+        .entry(id.to_string())
+        .or_insert_with(|| MockNamespace {
+            title: id.to_string(),
+            values: BTreeMap::new(),
+        })
+}
+
+#[derive(Default)]
+struct State {
+    namespaces: Mutex<Namespaces>,
+    requests: AtomicU64,
+    stopped: AtomicBool,
+}
+
+/// Mock Cloudflare KV api, served on a local port from in-memory namespaces.
+/// Namespaces are created on first use. The server stops when this is dropped
+pub struct MockCloudflare {
+    addr: SocketAddr,
+    state: Arc<State>,
+}
+
+impl MockCloudflare {
+    /// Start the server on a free port of 127.0.0.1
+    pub fn start() -> Result<Self, Error> {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .map_err(|e| Error::IO(format!("starting mock api: {}", e)))?;
+        let addr = listener
+            .local_addr()
+            .map_err(|e| Error::IO(format!("starting mock api: {}", e)))?;
+        let state = Arc::new(State::default());
+        let server = state.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if server.stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                if let Ok(stream) = stream {
+                    let state = server.clone();
+                    std::thread::spawn(move || handle_connection(stream, &state));
+                }
+            }
+        });
+        Ok(Self { addr, state })
+    }
+
+    /// Api endpoint of the server, for `KVAssets::with_api_endpoint`
+    pub fn endpoint(&self) -> String {
+        format!("http://{}/client/v4", self.addr)
+    }
+
+    /// Handler for the namespace, sending its requests to this server
+    pub fn handler<'a>(&self, index: &'a [u8], namespace_id: &str) -> KVAssets<'a> {
+        KVAssets::from_parts(
+            index,
+            MOCK_ACCOUNT_ID.into(),
+            namespace_id.to_string().into(),
+            MOCK_AUTH_TOKEN.into(),
+        )
+        .with_api_endpoint(&self.endpoint())
+        .with_http_client(Arc::new(crate::ReqwestClient::new(
+            // a proxy configured in the environment can't reach the local port
+            reqwest::Client::builder()
+                .no_proxy()
+                .build()
+                .expect("reqwest client"),
+        )))
+    }
+
+    /// Value of the key in the namespace, if it exists and hasn't expired
+    pub fn get(&self, namespace_id: &str, key: &str) -> Option<Bytes> {
+        let now = now();
+        self.lock()
+            .get(namespace_id)
+            .and_then(|ns| ns.values.get(key))
+            .filter(|entry| entry.is_live(now))
+            .map(|entry| entry.value.clone())
+    }
+
+    /// Metadata of the key in the namespace, if it has any
+    pub fn metadata(&self, namespace_id: &str, key: &str) -> Option<Value> {
+        self.lock()
+            .get(namespace_id)
+            .and_then(|ns| ns.values.get(key))
+            .and_then(|entry| entry.metadata.clone())
+    }
+
+    /// Store a value in the namespace, as test setup
+    pub fn insert(&self, namespace_id: &str, key: &str, value: impl Into<Bytes>) {
+        namespace(&mut self.lock(), namespace_id).values.insert(
+            key.to_string(),
+            Entry {
+                value: value.into(),
+                metadata: None,
+                expiration: None,
+            },
+        );
+    }
+
+    /// Remove a key from the namespace, as test setup
+    pub fn remove(&self, namespace_id: &str, key: &str) {
+        if let Some(ns) = self.lock().get_mut(namespace_id) {
+            ns.values.remove(key);
+        }
+    }
+
+    /// Keys in the namespace, sorted
+    pub fn keys(&self, namespace_id: &str) -> Vec<String> {
+        let now = now();
+        self.lock()
+            .get(namespace_id)
+            .map(|ns| {
+                ns.values
+                    .iter()
+                    .filter(|(_, entry)| entry.is_live(now))
+                    .map(|(key, _)| key.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Number of api requests the server has answered
+    pub fn request_count(&self) -> u64 {
+        self.state.requests.load(Ordering::SeqCst)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Namespaces> {
+        self.state.namespaces.lock().unwrap()
+    }
+}
+
+impl Drop for MockCloudflare {
+    fn drop(&mut self) {
+        self.state.stopped.store(true, Ordering::SeqCst);
+        // wake the accept loop
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+// Parsed http request
+struct MockRequest {
+    method: String,
+    path: String,
+    query: BTreeMap<String, String>,
+    content_type: Option<String>,
+    body: Vec<u8>,
+}
+
+// Response status, extra headers, and body
+type MockResponse = (u16, Vec<(&'static str, String)>, Vec<u8>);
+
+fn handle_connection(stream: TcpStream, state: &State) {
+    let mut reader = BufReader::new(match stream.try_clone() {
+        Ok(stream) => stream,
+        Err(_) => return,
+    });
+    let mut writer = stream;
+    // serve requests until the client closes the connection
+    while let Some(request) = read_request(&mut reader) {
+        state.requests.fetch_add(1, Ordering::SeqCst);
+        let (status, headers, body) = route(&request, &mut state.namespaces.lock().unwrap());
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Length: {}\r\n",
+            status,
+            http::StatusCode::from_u16(status)
+                .ok()
+                .and_then(|s| s.canonical_reason())
+                .unwrap_or(""),
+            body.len()
+        );
+        for (name, value) in headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+        if writer.write_all(head.as_bytes()).is_err()
+            || writer.write_all(&body).is_err()
+            || writer.flush().is_err()
+        {
+            return;
+        }
+    }
+}
+
+fn read_request(reader: &mut BufReader<TcpStream>) -> Option<MockRequest> {
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let target = parts.next()?.to_string();
+    let mut content_length = 0;
+    let mut content_type = None;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).ok()?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            match name.trim().to_ascii_lowercase().as_str() {
+                "content-length" => content_length = value.trim().parse().ok()?,
+                "content-type" => content_type = Some(value.trim().to_string()),
+                _ => {}
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).ok()?;
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    let query = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(k, v)| (percent_decode(k), percent_decode(v)))
+        .collect();
+    Some(MockRequest {
+        method,
+        path: path.to_string(),
+        query,
+        content_type,
+        body,
+    })
+}
+
+fn route(req: &MockRequest, namespaces: &mut Namespaces) -> MockResponse {
+    // /client/v4/accounts/{account}/storage/kv/namespaces[/{namespace}[/{rest}]]
+    let path = req
+        .path
+        .strip_prefix("/client/v4/accounts/")
+        .and_then(|p| p.split_once("/storage/kv/namespaces"))
+        .map(|(_, p)| p);
+    let (id, rest) = match path {
+        Some("") => return namespace_api(req, namespaces, None),
+        Some(p) => match p[1..].split_once('/') {
+            Some(found) => found,
+            None => return namespace_api(req, namespaces, Some(&p[1..])),
+        },
+        None => return api_error(404, "unsupported endpoint"),
+    };
+    let ns = &mut namespace(namespaces, id).values;
+    let now = now();
+    ns.retain(|_, entry| entry.is_live(now));
+    let (endpoint, key) = rest.split_once('/').unwrap_or((rest, ""));
+    let key = percent_decode(key);
+    match (req.method.as_str(), endpoint) {
+        ("GET", "values") => match ns.get(&key) {
+            Some(entry) => {
+                let headers = entry
+                    .expiration
+                    .map(|at| vec![("expiration", at.to_string())])
+                    .unwrap_or_default();
+                (200, headers, entry.value.to_vec())
+            }
+            None => api_error(404, "key not found"),
+        },
+        ("GET", "metadata") => match ns.get(&key) {
+            Some(entry) => api_result(json!(entry.metadata)),
+            None => api_error(404, "key not found"),
+        },
+        ("PUT", "values") => {
+            let (value, metadata) = match req
+                .content_type
+                .as_deref()
+                .and_then(|ct| ct.split_once("boundary="))
+            {
+                Some((_, boundary)) => parse_form(&req.body, boundary),
+                None => (req.body.clone(), None),
+            };
+            let expiration = expiration(
+                req.query.get("expiration").and_then(|v| v.parse().ok()),
+                req.query.get("expiration_ttl").and_then(|v| v.parse().ok()),
+            );
+            ns.insert(
+                key,
+                Entry {
+                    value: Bytes::from(value),
+                    metadata,
+                    expiration,
+                },
+            );
+            api_result(Value::Null)
+        }
+        ("DELETE", "values") => {
+            ns.remove(&key);
+            api_result(Value::Null)
+        }
+        ("PUT", "bulk") => {
+            let pairs: Vec<BulkPair> = match serde_json::from_slice(&req.body) {
+                Ok(pairs) => pairs,
+                Err(e) => return api_error(400, &e.to_string()),
+            };
+            for pair in pairs {
+                let value = match pair.base64 {
+                    true => match base64::decode(&pair.value) {
+                        Ok(value) => value,
+                        Err(e) => return api_error(400, &e.to_string()),
+                    },
+                    false => pair.value.into_bytes(),
+                };
+                ns.insert(
+                    pair.key,
+                    Entry {
+                        value: Bytes::from(value),
+                        metadata: pair.metadata,
+                        expiration: expiration(pair.expiration, pair.expiration_ttl),
+                    },
+                );
+            }
+            api_result(Value::Null)
+        }
+        ("DELETE", "bulk") => {
+            let keys: Vec<String> = match serde_json::from_slice(&req.body) {
+                Ok(keys) => keys,
+                Err(e) => return api_error(400, &e.to_string()),
+            };
+            for key in keys {
+                ns.remove(&key);
+            }
+            api_result(Value::Null)
+        }
+        ("GET", "keys") => list_keys(req, ns),
+        _ => api_error(405, "unsupported method"),
+    }
+}
+
+// List, create, rename, or delete namespaces: id is None for the namespaces url
+fn namespace_api(req: &MockRequest, namespaces: &mut Namespaces, id: Option<&str>) -> MockResponse {
+    #[derive(Deserialize)]
+    struct Title {
+        title: String,
+    }
+    let title = || serde_json::from_slice::<Title>(&req.body).map(|t| t.title);
+    match (req.method.as_str(), id) {
+        ("GET", None) => list_namespaces(req, namespaces),
+        ("POST", None) => match title() {
+            Ok(title) => {
+                let id = (1..)
+                    .map(|n| format!("ns{}", n))
+                    .find(|id| !namespaces.contains_key(id))
+                    .expect("free namespace id");
+                namespace(namespaces, &id).title = title.clone();
+                api_result(json!({ "id": id, "title": title, "supports_url_encoding": true }))
+            }
+            Err(e) => api_error(400, &e.to_string()),
+        },
+        ("PUT", Some(id)) => match (namespaces.get_mut(id), title()) {
+            (Some(ns), Ok(title)) => {
+                ns.title = title;
+                api_result(Value::Null)
+            }
+            (None, _) => namespace_not_found(),
+            (_, Err(e)) => api_error(400, &e.to_string()),
+        },
+        ("DELETE", Some(id)) => match namespaces.remove(id) {
+            Some(_) => api_result(Value::Null),
+            None => namespace_not_found(),
+        },
+        _ => api_error(405, "unsupported method"),
+    }
+}
+
+// Page of namespaces, sorted as requested
+fn list_namespaces(req: &MockRequest, namespaces: &Namespaces) -> MockResponse {
+    let param = |name: &str, default: usize| {
+        req.query
+            .get(name)
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default)
+    };
+    let page = param("page", 1).max(1);
+    let per_page = param("per_page", 20).clamp(5, 100);
+    let mut all: Vec<(&String, &String)> =
+        namespaces.iter().map(|(id, ns)| (id, &ns.title)).collect();
+    if req.query.get("order").map(String::as_str) == Some("title") {
+        all.sort_by_key(|(_, title)| *title);
+    }
+    if req.query.get("direction").map(String::as_str) == Some("desc") {
+        all.reverse();
+    }
+    let result: Vec<Value> = all
+        .iter()
+        .skip((page - 1) * per_page)
+        .take(per_page)
+        .map(|(id, title)| json!({ "id": id, "title": title, "supports_url_encoding": true }))
+        .collect();
+    let body = json!({
+        "success": true, "errors": [], "messages": [], "result": result,
+        "result_info": {
+            "page": page, "per_page": per_page, "count": result.len(),
+            "total_count": all.len(), "total_pages": all.len().div_ceil(per_page).max(1),
+        },
+    });
+    (200, json_header(), body.to_string().into_bytes())
+}
+
+fn namespace_not_found() -> MockResponse {
+    let body = json!({
+        "success": false, "errors": [{ "code": 10013, "message": "namespace not found" }],
+        "messages": [], "result": null,
+    });
+    (404, json_header(), body.to_string().into_bytes())
+}
+
+// Key-value pair in the body of a bulk write request
+#[derive(Deserialize)]
+struct BulkPair {
+    key: String,
+    value: String,
+    #[serde(default)]
+    base64: bool,
+    expiration: Option<u64>,
+    expiration_ttl: Option<u64>,
+    metadata: Option<Value>,
+}
+
+// Page of keys, starting after the cursor (the index of the first key)
+fn list_keys(req: &MockRequest, ns: &BTreeMap<String, Entry>) -> MockResponse {
+    let prefix = req.query.get("prefix").map(String::as_str).unwrap_or("");
+    let limit = req
+        .query
+        .get("limit")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(LIST_LIMIT_MAX)
+        .clamp(1, LIST_LIMIT_MAX);
+    let start: usize = req
+        .query
+        .get("cursor")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    let matching: Vec<_> = ns.iter().filter(|(k, _)| k.starts_with(prefix)).collect();
+    let page: Vec<Value> = matching
+        .iter()
+        .skip(start)
+        .take(limit)
+        .map(|(name, entry)| {
+            let mut key = json!({ "name": name });
+            if let Some(at) = entry.expiration {
+                key["expiration"] = json!(at);
+            }
+            if let Some(metadata) = &entry.metadata {
+                key["metadata"] = metadata.clone();
+            }
+            key
+        })
+        .collect();
+    let cursor = match start + page.len() < matching.len() {
+        true => (start + page.len()).to_string(),
+        false => String::new(),
+    };
+    let body = json!({
+        "success": true, "errors": [], "messages": [], "result": page,
+        "result_info": { "count": page.len(), "cursor": cursor },
+    });
+    (200, json_header(), body.to_string().into_bytes())
+}
+
+// Value and metadata fields of a multipart/form-data body
+fn parse_form(body: &[u8], boundary: &str) -> (Vec<u8>, Option<Value>) {
+    let delimiter = format!("--{}", boundary.trim_matches('"'));
+    let mut value = Vec::new();
+    let mut metadata = None;
+    for part in split_bytes(body, delimiter.as_bytes()) {
+        let split = match find(part, b"\r\n\r\n") {
+            Some(split) => split,
+            None => continue,
+        };
+        let headers = String::from_utf8_lossy(&part[..split]);
+        let content = &part[split + 4..];
+        let content = content.strip_suffix(b"\r\n").unwrap_or(content);
+        if headers.contains("name=\"value\"") {
+            value = content.to_vec();
+        } else if headers.contains("name=\"metadata\"") {
+            metadata = serde_json::from_slice(content).ok();
+        }
+    }
+    (value, metadata)
+}
+
+fn split_bytes<'b>(data: &'b [u8], delimiter: &[u8]) -> Vec<&'b [u8]> {
+    let mut parts = Vec::new();
+    let mut rest = data;
+    while let Some(pos) = find(rest, delimiter) {
+        parts.push(&rest[..pos]);
+        rest = &rest[pos + delimiter.len()..];
+    }
+    parts.push(rest);
+    parts
+}
+
+fn find(data: &[u8], needle: &[u8]) -> Option<usize> {
+    data.windows(needle.len()).position(|w| w == needle)
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                decoded.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn expiration(at: Option<u64>, ttl: Option<u64>) -> Option<u64> {
+    at.or_else(|| ttl.map(|ttl| now() + ttl))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn json_header() -> Vec<(&'static str, String)> {
+    vec![("Content-Type", "application/json".to_string())]
+}
+
+fn api_result(result: Value) -> MockResponse {
+    let body = json!({ "success": true, "errors": [], "messages": [], "result": result });
+    (200, json_header(), body.to_string().into_bytes())
+}
+
+fn api_error(status: u16, message: &str) -> MockResponse {
+    let body = json!({
+        "success": false, "errors": [{ "code": 10000 + status, "message": message }],
+        "messages": [], "result": null,
+    });
+    (status, json_header(), body.to_string().into_bytes())
+}
+
+/// Tests get, put, bulk, list, and publish flows over http against the mock api
+#[cfg(test)]
+#[tokio::test]
+async fn test_mock_cloudflare() {
+    use crate::KvPutOptions;
+
+    let mock = MockCloudflare::start().unwrap();
+    let kv = mock.handler(&[], "ns");
+    kv.put_kv_value("dir/a b.txt", "hello", None).await.unwrap();
+    assert_eq!(kv.get_kv_value("dir/a b.txt").await.unwrap(), "hello");
+    assert_eq!(mock.get("ns", "dir/a b.txt").unwrap(), "hello");
+    assert!(matches!(
+        kv.get_kv_value("missing").await,
+        Err(Error::KVKeyNotFound(_, 404))
+    ));
+
+    let opts = KvPutOptions::new()
+        .with_metadata(json!({ "hash": "abc" }))
+        .with_expiration_ttl(3600);
+    kv.put("b.bin", Bytes::from_static(&[0, 159, 146, 150]), &opts)
+        .await
+        .unwrap();
+    assert_eq!(
+        kv.get_kv_value("b.bin").await.unwrap().as_ref(),
+        &[0, 159, 146, 150]
+    );
+    assert_eq!(
+        kv.get_kv_metadata("b.bin").await.unwrap(),
+        Some(json!({ "hash": "abc" }))
+    );
+    let info = kv.get_kv_value_with_info("b.bin").await.unwrap();
+    assert!(info.expiration.is_some());
+
+    let values: Vec<(String, Bytes)> = (0..3)
+        .map(|i| (format!("bulk/{}", i), Bytes::from(vec![i as u8, 255])))
+        .collect();
+    kv.put_kv_values_bulk(&values, &KvPutOptions::new())
+        .await
+        .unwrap();
+    assert_eq!(mock.get("ns", "bulk/2").unwrap().as_ref(), &[2, 255]);
+    assert_eq!(kv.list_keys().await.unwrap().len(), 5);
+    kv.delete_kv_value("dir/a b.txt").await.unwrap();
+    assert_eq!(mock.keys("ns"), vec!["b.bin", "bulk/0", "bulk/1", "bulk/2"]);
+
+    // publish a directory, then serve it with the new index
+    let dir = std::env::temp_dir().join(format!("kv-assets-testspace-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("index.html"), "<h1>hi</h1>").unwrap();
+    let publisher = mock.handler(&[], "site");
+    let published = publisher.publish_dir(&dir).await;
+    std::fs::remove_dir_all(&dir).unwrap();
+    let bytes = crate::encode_index(&published.unwrap().0).unwrap();
+    let worker = mock.handler(&bytes, "site");
+    let response = worker
+        .serve(&http::Request::get("/index.html").body(()).unwrap())
+        .await;
+    assert_eq!(response.status(), http::StatusCode::OK);
+    assert_eq!(response.body().as_ref(), b"<h1>hi</h1>");
+    assert!(mock.request_count() > 10);
+    assert!(mock.keys("other").is_empty());
+}
@@ -0,0 +1,206 @@
+use crate::{
+    AssetFuture, AssetMetadata, AssetSource, CacheOptions, Error, KVAssets, ResolvedAsset,
+};
+use bytes::Bytes;
+use http::{header, HeaderMap, HeaderValue, Request, Response};
+use std::sync::Mutex;
+
+/// Timing breakdown of a request answered by `KVAssets::serve_timed`, for
+/// `Server-Timing` headers or request logs.
+///
+/// Durations are measured with `std::time::Instant`, which isn't available on wasm:
+/// there, they are always 0 (Workers only advance clocks on I/O anyway).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServeTiming {
+    /// Time to resolve the path in the index, in microseconds. Includes
+    /// deserializing the index, on the first lookup
+    pub lookup_us: u64,
+    /// Time to read the value, in milliseconds, or None if it wasn't read
+    /// (such as for 304 Not Modified, HEAD, and not-found responses)
+    pub fetch_ms: Option<u64>,
+    /// Size of the response body, in bytes
+    pub bytes: u64,
+    /// True if the value was read from the edge cache, value cache,
+    /// or write overlay, without reading KV
+    pub cache_hit: bool,
+}
+
+impl ServeTiming {
+    /// `Server-Timing` header value, such as `lookup;dur=0.012, kv;dur=3;desc="miss"`
+    pub fn header_value(&self) -> String {
+        let mut value = format!("lookup;dur={:.3}", self.lookup_us as f64 / 1000.0);
+        if let Some(ms) = self.fetch_ms {
+            let desc = if self.cache_hit { "hit" } else { "miss" };
+            value.push_str(&format!(", kv;dur={};desc=\"{}\"", ms, desc));
+        }
+        value
+    }
+
+    /// Add the `Server-Timing` header to response headers
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        if let Ok(value) = HeaderValue::from_str(&self.header_value()) {
+            headers.insert(header::HeaderName::from_static("server-timing"), value);
+        }
+    }
+}
+
+impl<'ah> KVAssets<'ah> {
+    /// Serve an asset request as `serve` does, and also return how long the lookup
+    /// and value read took, so slow requests are visible individually rather than only
+    /// in aggregate metrics. The `Server-Timing` header isn't added: call
+    /// `ServeTiming::apply` to expose it
+    pub async fn serve_timed(&self, req: &Request<()>) -> (Response<Bytes>, ServeTiming) {
+        self.serve_timed_with(req, &CacheOptions::default()).await
+    }
+
+    /// Serve an asset request as `serve_timed` does, with per-request cache behavior
+    /// (see `CacheOptions`)
+    pub async fn serve_timed_with(
+        &self,
+        req: &Request<()>,
+        options: &CacheOptions,
+    ) -> (Response<Bytes>, ServeTiming) {
+        let timed = Timed {
+            kv: self,
+            options,
+            timing: Mutex::new(ServeTiming::default()),
+        };
+        let response = match self.dir_listing(req) {
+            Some(listing) => listing,
+            None => crate::serve::serve_source(&timed, req).await,
+        };
+        let response = match self.embedded_fallback() {
+            Some(embedded) if crate::embedded::is_failure(response.status()) => {
+                embedded.response(req).unwrap_or(response)
+            }
+            _ => response,
+        };
+        #[cfg(feature = "compression")]
+        let response = match self.compression() {
+            Some(compression) if response.status() == http::StatusCode::OK => {
+                compression.apply(req.headers(), response)
+            }
+            _ => response,
+        };
+        let mut response = response;
+        if let Some(policy) = self.security_headers() {
+            policy.apply(req.uri().path(), response.headers_mut());
+        }
+        self.apply_preloads(req.uri().path(), &mut response);
+        if let Some(metrics) = self.metrics() {
+            if !response.body().is_empty() {
+                metrics.bytes_served(response.body().len() as u64);
+            }
+        }
+        let mut timing = timed.timing.into_inner().unwrap();
+        timing.bytes = response.body().len() as u64;
+        (response, timing)
+    }
+}
+
+// Source that records the timing of lookups and reads from kv
+struct Timed<'a, 'ah> {
+    kv: &'a KVAssets<'ah>,
+    options: &'a CacheOptions,
+    timing: Mutex<ServeTiming>,
+}
+
+impl AssetSource for Timed<'_, '_> {
+    fn resolve_key(&self, path: &str) -> Result<Option<ResolvedAsset>, Error> {
+        let start = Stopwatch::start();
+        let resolved = self.kv.resolve_key(path);
+        self.timing.lock().unwrap().lookup_us = start.elapsed_us();
+        resolved
+    }
+
+    fn fetch_asset<'a>(&'a self, md: &'a AssetMetadata) -> AssetFuture<'a, Result<Bytes, Error>> {
+        Box::pin(async move {
+            let start = Stopwatch::start();
+            let result = self.kv.fetch_asset_traced(md, self.options).await;
+            let mut timing = self.timing.lock().unwrap();
+            timing.fetch_ms = Some(start.elapsed_us() / 1000);
+            let (body, cache_hit) = result?;
+            timing.cache_hit = cache_hit;
+            Ok(body)
+        })
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+struct Stopwatch(std::time::Instant);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Stopwatch {
+    fn start() -> Self {
+        Self(std::time::Instant::now())
+    }
+
+    fn elapsed_us(&self) -> u64 {
+        self.0.elapsed().as_micros() as u64
+    }
+}
+
+// No monotonic clock on wasm
+#[cfg(target_arch = "wasm32")]
+struct Stopwatch;
+
+#[cfg(target_arch = "wasm32")]
+impl Stopwatch {
+    fn start() -> Self {
+        Self
+    }
+
+    fn elapsed_us(&self) -> u64 {
+        0
+    }
+}
+
+/// Tests timing of served requests (does not invoke cloudflare api)
+#[cfg(test)]
+#[tokio::test]
+async fn test_serve_timed() {
+    use crate::{AssetIndex, HttpClient, HttpFuture, ValueCache};
+    use std::sync::Arc;
+
+    struct Stub;
+    impl HttpClient for Stub {
+        fn send(&self, _: Request<Bytes>) -> HttpFuture<'_> {
+            Box::pin(async { Ok(Response::new(Bytes::from("hello"))) })
+        }
+    }
+
+    let mut index = AssetIndex::new();
+    index.insert(
+        "a.txt".to_string(),
+        AssetMetadata {
+            path: "a.1.txt".to_string(),
+            size: 5,
+            ..Default::default()
+        },
+    );
+    let bytes = crate::encode_index(&index).unwrap();
+    let kv = KVAssets::init(&bytes, "acct", "ns", "token")
+        .with_http_client(Arc::new(Stub))
+        .with_value_cache(Arc::new(ValueCache::new(1 << 20)));
+    let req = Request::get("/a.txt").body(()).unwrap();
+
+    let (response, timing) = kv.serve_timed(&req).await;
+    assert_eq!(response.body().as_ref(), b"hello");
+    assert_eq!(timing.bytes, 5);
+    assert!(timing.fetch_ms.is_some());
+    assert!(!timing.cache_hit);
+    assert!(timing.header_value().ends_with("desc=\"miss\""));
+
+    let (_, timing) = kv.serve_timed(&req).await;
+    assert!(timing.cache_hit);
+
+    let head = Request::head("/a.txt").body(()).unwrap();
+    let (mut response, timing) = kv.serve_timed(&head).await;
+    assert_eq!(timing.fetch_ms, None);
+    assert_eq!(timing.bytes, 0);
+    timing.apply(response.headers_mut());
+    assert!(response.headers()["server-timing"]
+        .to_str()
+        .unwrap()
+        .starts_with("lookup;dur="));
+}
@@ -0,0 +1,87 @@
+use crate::{assets::parse_json, namespaces::ApiResponse, Error, KVAssets};
+use bytes::Bytes;
+use http::Method;
+use serde::Deserialize;
+
+/// Status of the configured api token, returned by `verify_token`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct TokenStatus {
+    /// Token id
+    pub id: String,
+    /// Token status: "active", "disabled", or "expired"
+    pub status: String,
+    /// Expiration time (RFC 3339), if the token expires
+    #[serde(default)]
+    pub expires_on: Option<String>,
+    /// Time (RFC 3339) before which the token can't be used, if any
+    #[serde(default)]
+    pub not_before: Option<String>,
+}
+
+impl TokenStatus {
+    /// Returns true if the token can currently be used
+    pub fn is_active(&self) -> bool {
+        self.status == "active"
+    }
+}
+
+impl<'ah> KVAssets<'ah> {
+    /// Verify the api token with Cloudflare, so deploy tools can fail fast
+    /// with a clear message instead of a 403 partway through an upload.
+    /// Returns `Error::InvalidToken` if Cloudflare doesn't recognize the token.
+    /// A recognized token may still be inactive: check `TokenStatus::is_active`.
+    /// Cloudflare doesn't report a token's permissions here, so a token that
+    /// lacks KV permissions is only detected on the first KV request.
+    pub async fn verify_token(&self) -> Result<TokenStatus, Error> {
+        let url = self.api_url("user/tokens/verify");
+        let response = self
+            .send(self.request(Method::GET, &url, Bytes::new()))
+            .await?;
+        let status = response.status().as_u16();
+        let parsed: ApiResponse<TokenStatus> = parse_json(response.body())?;
+        match parsed.result {
+            Some(token) if parsed.success => Ok(token),
+            _ => Err(Error::InvalidToken(format!(
+                "status={} errors:{:?}",
+                status, parsed.errors
+            ))),
+        }
+    }
+}
+
+/// Tests token verification responses (does not invoke cloudflare api)
+#[cfg(test)]
+#[tokio::test]
+async fn test_verify_token() {
+    use crate::{HttpClient, HttpFuture};
+    use std::sync::Arc;
+
+    struct Stub(&'static str);
+    impl HttpClient for Stub {
+        fn send(&self, request: http::Request<Bytes>) -> HttpFuture<'_> {
+            assert_eq!(
+                request.uri(),
+                "https://api.cloudflare.com/client/v4/user/tokens/verify"
+            );
+            Box::pin(async move { Ok(http::Response::new(Bytes::from(self.0))) })
+        }
+    }
+
+    let kv = KVAssets::init(&[], "acct", "ns", "token").with_http_client(Arc::new(Stub(
+        r#"{"success":true,"errors":[],"result":{"id":"t1","status":"active"}}"#,
+    )));
+    assert!(kv.verify_token().await.unwrap().is_active());
+
+    let kv = KVAssets::init(&[], "acct", "ns", "token").with_http_client(Arc::new(Stub(
+        r#"{"success":true,"errors":[],"result":{"id":"t1","status":"expired","expires_on":"2020-01-01T00:00:00Z"}}"#,
+    )));
+    assert!(!kv.verify_token().await.unwrap().is_active());
+
+    let kv = KVAssets::init(&[], "acct", "ns", "token").with_http_client(Arc::new(Stub(
+        r#"{"success":false,"errors":[{"code":1000,"message":"Invalid API Token"}],"result":null}"#,
+    )));
+    assert!(matches!(
+        kv.verify_token().await,
+        Err(Error::InvalidToken(_))
+    ));
+}
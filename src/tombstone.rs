@@ -0,0 +1,161 @@
+use crate::{AssetIndex, Error, KVAssets};
+use std::collections::{HashMap, HashSet};
+
+// Mark the live asset at path removed
+pub(crate) fn tombstone(index: &mut AssetIndex, path: &str, removed_at: u64) -> bool {
+    match index.get_mut(path.trim_start_matches('/')) {
+        Some(md) if md.alias.is_none() && !md.is_deleted() => {
+            md.deleted = Some(removed_at);
+            true
+        }
+        _ => false,
+    }
+}
+
+// Clear the deleted time of a tombstone
+pub(crate) fn restore(index: &mut AssetIndex, path: &str) -> bool {
+    match index.get_mut(path.trim_start_matches('/')) {
+        Some(md) if md.is_deleted() => {
+            md.deleted = None;
+            true
+        }
+        _ => false,
+    }
+}
+
+// Add tombstones for assets (and tombstones) in previous that aren't in index
+pub(crate) fn keep_removed(
+    index: &mut AssetIndex,
+    previous: &AssetIndex,
+    removed_at: u64,
+) -> Vec<String> {
+    let mut removed = Vec::new();
+    for (path, md) in previous.iter() {
+        if md.alias.is_some() || index.contains_key(path) {
+            continue;
+        }
+        let mut md = md.clone();
+        if md.deleted.is_none() {
+            md.deleted = Some(removed_at);
+            removed.push(path.clone());
+        }
+        index.insert(path.clone(), md);
+    }
+    removed.sort();
+    removed
+}
+
+// Tombstones removed before cutoff, sorted by path
+pub(crate) fn expired(index: &AssetIndex, cutoff: u64) -> Vec<String> {
+    let mut paths: Vec<String> = index
+        .iter()
+        .filter(|(_, md)| matches!(md.deleted, Some(t) if t < cutoff))
+        .map(|(path, _)| path.clone())
+        .collect();
+    paths.sort();
+    paths
+}
+
+impl<'ah> KVAssets<'ah> {
+    /// Deletes tombstones removed before cutoff (UTC seconds since EPOCH; e.g., now
+    /// minus the retention window) from the index, and deletes their values from KV,
+    /// unless another entry in the index uses the same key, and the parts of chunked
+    /// values (see `with_chunking`). Deletions are reported
+    /// to the deploy observer, if any. Returns the deleted KV keys. If cancelled
+    /// (see `with_cancel_token`), tombstones not yet deleted stay in the index.
+    /// Encode and deploy the updated index before running this against the
+    /// namespace a worker is reading from.
+    pub async fn gc_tombstones(
+        &self,
+        index: &mut AssetIndex,
+        cutoff: u64,
+    ) -> Result<Vec<String>, Error> {
+        let expired = expired(index, cutoff);
+        let in_use: HashSet<String> = index
+            .iter()
+            .filter(|(path, _)| expired.binary_search(path).is_err())
+            .map(|(_, md)| md.path.clone())
+            .collect();
+        // parts of chunked values, by the value's key
+        let mut parts: HashMap<String, Vec<String>> = HashMap::new();
+        if !expired.is_empty() {
+            for key in self.list_keys().await? {
+                if let Some(value_key) = crate::chunked::chunked_key(&key).map(String::from) {
+                    parts.entry(value_key).or_default().push(key);
+                }
+            }
+        }
+        let mut keys = Vec::new();
+        for path in expired {
+            self.check_cancelled("deleting tombstones")?;
+            let key = index[&path].path.clone();
+            if !in_use.contains(&key) && !keys.contains(&key) {
+                let value_parts = parts.remove(&key).unwrap_or_default();
+                for key in std::iter::once(key).chain(value_parts) {
+                    match self.delete_kv_value(&key).await {
+                        Ok(()) | Err(Error::KVKeyNotFound(_, 404)) => {}
+                        Err(e) => return Err(e),
+                    }
+                    self.observe(|| crate::DeployEvent::Deleted { key: key.clone() });
+                    keys.push(key);
+                }
+            }
+            index.remove(&path);
+        }
+        keys.sort();
+        Ok(keys)
+    }
+}
+
+/// Tests tombstones, restoring, and garbage collection, against the mock api
+#[cfg(all(test, feature = "reqwest"))]
+#[tokio::test]
+async fn test_tombstones() {
+    use crate::{AssetIndexExt, AssetMetadata};
+
+    let entry = |key: &str| AssetMetadata {
+        path: key.to_string(),
+        size: 1,
+        ..Default::default()
+    };
+    let mut previous = AssetIndex::new();
+    previous.insert("a.css".to_string(), entry("a.1.css"));
+    previous.insert("b.css".to_string(), entry("b.1.css"));
+    previous.insert("c.css".to_string(), entry("shared.1.css"));
+    previous.insert("old.css".to_string(), AssetMetadata::alias("a.css"));
+
+    let mut index = AssetIndex::new();
+    index.insert("a.css".to_string(), entry("a.2.css"));
+    index.insert("d.css".to_string(), entry("shared.1.css"));
+    assert_eq!(
+        index.keep_removed(&previous, 1000),
+        vec!["b.css".to_string(), "c.css".to_string()]
+    );
+    assert_eq!(index["b.css"].deleted, Some(1000));
+    assert!(!index.contains_key("old.css"));
+    assert!(!index.tombstone("old.css", 2000));
+    assert!(index.tombstone("a.css", 2000));
+    assert!(index.restore("a.css"));
+    assert!(!index.restore("a.css"));
+    assert_eq!(index.expired_tombstones(1001).len(), 2);
+
+    let mock = crate::testspace::MockCloudflare::start().unwrap();
+    for key in ["a.2.css", "b.1.css", "b.1.css.part0", "shared.1.css"].iter() {
+        mock.insert("ns", key, "x");
+    }
+    let kv = mock.handler(&[], "ns");
+    // tombstones aren't served
+    let bytes = crate::encode_index(&index).unwrap();
+    let reader = KVAssets::init(&bytes, "acct", "ns", "token");
+    assert!(reader.lookup_key("b.css").unwrap().is_none());
+    assert!(reader.lookup_key("a.css").unwrap().is_some());
+
+    assert!(kv.gc_tombstones(&mut index, 1000).await.unwrap().is_empty());
+    // shared.1.css is still used by d.css. Parts of chunked values are deleted too
+    assert_eq!(
+        kv.gc_tombstones(&mut index, 1001).await.unwrap(),
+        vec!["b.1.css".to_string(), "b.1.css.part0".to_string()]
+    );
+    assert_eq!(mock.keys("ns"), vec!["a.2.css", "shared.1.css"]);
+    assert_eq!(index.len(), 2);
+}
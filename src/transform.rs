@@ -0,0 +1,85 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use crate::Error;
+use std::collections::HashMap;
+
+/// Transform of an asset's content at upload time: (asset path, content) -> new content
+pub type TransformFn = dyn Fn(&str, Vec<u8>) -> Result<Vec<u8>, Error> + Send + Sync;
+
+/// Transforms applied to asset content before upload, selected by file extension,
+/// for simple optimizations (minifying CSS or JS, optimizing SVGs) without a separate
+/// build step. The index records the size and hash of the transformed content.
+///
+/// ```
+/// # use kv_assets::Transforms;
+/// let transforms = Transforms::new().with("css", |_path, css| {
+///     let text = String::from_utf8_lossy(&css);
+///     Ok(text.lines().map(str::trim).collect::<String>().into_bytes())
+/// });
+/// ```
+#[derive(Default)]
+pub struct Transforms {
+    // lowercase extension -> transform
+    by_ext: HashMap<String, Box<TransformFn>>,
+}
+
+impl Transforms {
+    /// No transforms
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply transform to assets with the file extension (case-insensitive, without
+    /// the dot). Adding a transform for the same extension replaces the previous one
+    pub fn with(
+        mut self,
+        ext: &str,
+        transform: impl Fn(&str, Vec<u8>) -> Result<Vec<u8>, Error> + Send + Sync + 'static,
+    ) -> Self {
+        self.by_ext.insert(
+            ext.trim_start_matches('.').to_ascii_lowercase(),
+            Box::new(transform),
+        );
+        self
+    }
+
+    /// Returns true if no transforms are configured
+    pub fn is_empty(&self) -> bool {
+        self.by_ext.is_empty()
+    }
+
+    /// Transform the content of the asset at path, if a transform is configured
+    /// for its extension. Otherwise the content is returned unchanged
+    pub fn apply(&self, path: &str, content: Vec<u8>) -> Result<Vec<u8>, Error> {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        let ext = match name.rfind('.') {
+            Some(dot) if dot > 0 => name[dot + 1..].to_ascii_lowercase(),
+            _ => return Ok(content),
+        };
+        match self.by_ext.get(&ext) {
+            Some(transform) => transform(path, content),
+            None => Ok(content),
+        }
+    }
+}
+
+/// Tests transform selection by extension
+#[test]
+fn test_transforms() {
+    let transforms = Transforms::new()
+        .with(".CSS", |_, css| {
+            Ok(css.into_iter().filter(|b| *b != b' ').collect())
+        })
+        .with("svg", |path, _| {
+            Err(Error::Message(format!("bad {}", path)))
+        });
+
+    assert_eq!(
+        transforms.apply("css/a.css", b"a { b }".to_vec()).unwrap(),
+        b"a{b}"
+    );
+    assert_eq!(transforms.apply("A.Css", b" ".to_vec()).unwrap(), b"");
+    assert_eq!(transforms.apply("a.js", b"x y".to_vec()).unwrap(), b"x y");
+    assert_eq!(transforms.apply(".css", b"x y".to_vec()).unwrap(), b"x y");
+    assert!(transforms.apply("i.svg", Vec::new()).is_err());
+}
@@ -0,0 +1,102 @@
+use std::time::Duration;
+
+/// Expiration TTLs for assets uploaded by `sync_assets` or `KVAssets::publish_dir`,
+/// selected by glob patterns on the asset path: `*` matches within a path segment,
+/// `**` matches any number of segments, and `?` matches one character.
+/// The first matching rule applies; assets that match no rule are permanent.
+/// Expiring assets are listed in the deploy report.
+///
+/// ```
+/// # use kv_assets::TtlRules;
+/// # use std::time::Duration;
+/// let rules = TtlRules::new()
+///     .permanent("previews/keep/**")
+///     .expire("previews/**", Duration::from_secs(7 * 24 * 3600));
+/// assert_eq!(rules.ttl_for("previews/pr-12/index.html"), Some(604800));
+/// assert_eq!(rules.ttl_for("index.html"), None);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TtlRules {
+    // pattern, and TTL in seconds (None for permanent)
+    rules: Vec<(String, Option<u64>)>,
+}
+
+impl TtlRules {
+    /// No rules: all assets are permanent
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Assets matching pattern expire ttl after upload. KV's minimum TTL is
+    /// 60 seconds: shorter TTLs are rounded up
+    pub fn expire(mut self, pattern: &str, ttl: Duration) -> Self {
+        self.rules
+            .push((normalize(pattern), Some(ttl.as_secs().max(60))));
+        self
+    }
+
+    /// Assets matching pattern are permanent, for exceptions to later rules
+    pub fn permanent(mut self, pattern: &str) -> Self {
+        self.rules.push((normalize(pattern), None));
+        self
+    }
+
+    /// Returns true if there are no rules
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// TTL in seconds for the asset path, or None if it is permanent
+    pub fn ttl_for(&self, path: &str) -> Option<u64> {
+        let path = path.trim_start_matches('/');
+        self.rules
+            .iter()
+            .find(|(pattern, _)| glob_match(pattern.as_bytes(), path.as_bytes()))
+            .and_then(|(_, ttl)| *ttl)
+    }
+}
+
+fn normalize(pattern: &str) -> String {
+    pattern.trim_start_matches('/').to_string()
+}
+
+// Match path against glob pattern: `**` matches anything, `*` anything but '/',
+// and `?` any character but '/'. A `**/` prefix also matches zero segments
+pub(crate) fn glob_match(pattern: &[u8], path: &[u8]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        [b'*', b'*', b'/', rest @ ..] => {
+            glob_match(rest, path)
+                || (0..path.len()).any(|n| path[n] == b'/' && glob_match(rest, &path[n + 1..]))
+        }
+        [b'*', b'*', rest @ ..] => (0..=path.len()).any(|n| glob_match(rest, &path[n..])),
+        [b'*', rest @ ..] => {
+            let segment = path.iter().position(|c| *c == b'/').unwrap_or(path.len());
+            (0..=segment).any(|n| glob_match(rest, &path[n..]))
+        }
+        [b'?', rest @ ..] => matches!(path, [c, tail @ ..] if *c != b'/' && glob_match(rest, tail)),
+        [c, rest @ ..] => matches!(path, [p, tail @ ..] if p == c && glob_match(rest, tail)),
+    }
+}
+
+/// Tests glob patterns and rule order
+#[test]
+fn test_ttl_rules() {
+    assert!(glob_match(b"previews/**", b"previews/a/b.html"));
+    assert!(!glob_match(b"previews/**", b"preview.html"));
+    assert!(glob_match(b"*.html", b"index.html"));
+    assert!(!glob_match(b"*.html", b"docs/index.html"));
+    assert!(glob_match(b"**/*.html", b"index.html"));
+    assert!(glob_match(b"**/*.html", b"docs/a/index.html"));
+    assert!(glob_match(b"img/?.png", b"img/a.png"));
+    assert!(!glob_match(b"img/?.png", b"img/ab.png"));
+
+    let rules = TtlRules::new()
+        .permanent("/previews/keep/**")
+        .expire("previews/**", Duration::from_secs(3600))
+        .expire("tmp/*", Duration::from_secs(10));
+    assert_eq!(rules.ttl_for("/previews/pr-1/a.css"), Some(3600));
+    assert_eq!(rules.ttl_for("previews/keep/a.css"), None);
+    assert_eq!(rules.ttl_for("tmp/a"), Some(60));
+    assert_eq!(rules.ttl_for("index.html"), None);
+}
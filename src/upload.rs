@@ -1,8 +1,13 @@
 #![cfg(not(target_arch = "wasm32"))]
 
-use crate::{AssetIndex, AssetMetadata, Error};
+use crate::{
+    AliasTarget, AssetIndex, AssetIndexExt, AssetMetadata, DeployEvent, DeployObserver,
+    DeployReport, Error, Transforms,
+};
 use indicatif::{ProgressBar, ProgressStyle};
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
+use std::time::Instant;
 use wrangler::{
     kv::bulk,
     settings::{global_user::GlobalUser, toml::Manifest},
@@ -13,6 +18,9 @@ use wrangler::{
 const UPLOAD_PROGRESS_TEMPLATE: &str = "{wide_bar} {pos}/{len}\n{msg}";
 const DELETE_PROGRESS_TEMPLATE: &str = "{wide_bar} {pos}/{len}\n{msg}";
 
+/// Callback for setting custom metadata on index entries: (asset path, metadata)
+pub type AnnotateFn = dyn Fn(&str, &mut AssetMetadata);
+
 pub struct SyncConfig<'sync> {
     /// Path to wrangler.toml. defaults to "wrangler.toml"
     pub wrangler_path: &'sync Path,
@@ -24,6 +32,41 @@ pub struct SyncConfig<'sync> {
     pub prune: bool,
     /// True if using a preview environment. default=false
     pub preview_env: bool,
+    /// Alias entries added to the index: (path, target). default: none
+    pub aliases: &'sync [(String, AliasTarget)],
+    /// Sync fails before uploading if any file is larger than this, in bytes.
+    /// default: MAX_VALUE_SIZE (the KV limit)
+    pub max_value_size: u64,
+    /// Print a warning for files larger than this, in bytes. default: None
+    pub warn_value_size: Option<u64>,
+    /// Called with each asset path and its metadata as the index is built,
+    /// to set custom response `headers` or `extra` fields. default: None
+    pub annotate: Option<&'sync AnnotateFn>,
+    /// Transforms applied to asset content before upload, such as minifiers.
+    /// KV keys are derived from the source content (by wrangler), so changing only a
+    /// transform, such as upgrading a minifier, doesn't upload new values: change the
+    /// source, or publish with `KVAssets::with_transforms`, whose keys are derived from
+    /// the transformed content. The index records the size and hash of the transformed
+    /// content. default: None
+    pub transforms: Option<&'sync Transforms>,
+    /// Receives upload, skip, and delete events. Uploads are made in bulk, so each
+    /// asset's Started and Uploaded events are reported together, after the bulk
+    /// upload; if it fails, a Failed event is reported for each asset. default: None
+    pub observer: Option<&'sync dyn DeployObserver>,
+    /// If set, assets removed from asset_dir are kept in the index as tombstones
+    /// (see `AssetMetadata::deleted`) for this long, and their values aren't pruned,
+    /// so a rollback can restore them. default: None (removed assets are dropped)
+    pub tombstone_retention: Option<std::time::Duration>,
+    /// If set, sync stops with `Error::Cancelled` before uploading or pruning once the
+    /// token is cancelled. The index isn't written unless the upload completes. default: None
+    pub cancel: Option<&'sync crate::CancelToken>,
+    /// Expiration TTLs for uploaded assets, by path pattern. default: None (permanent)
+    pub ttl_rules: Option<&'sync crate::TtlRules>,
+    /// Algorithm for content hashes in the index. default: `HashAlgorithm::default()`
+    pub hash_algorithm: crate::HashAlgorithm,
+    /// If set, the generated index is signed with this HMAC key. default: None
+    #[cfg(feature = "signing")]
+    pub index_key: Option<&'sync [u8]>,
 }
 
 impl<'sync> Default for SyncConfig<'sync> {
@@ -34,6 +77,18 @@ impl<'sync> Default for SyncConfig<'sync> {
             output_path: Path::new("data"),
             prune: false,
             preview_env: false,
+            aliases: &[],
+            max_value_size: crate::MAX_VALUE_SIZE as u64,
+            warn_value_size: None,
+            annotate: None,
+            transforms: None,
+            observer: None,
+            tombstone_retention: None,
+            cancel: None,
+            ttl_rules: None,
+            hash_algorithm: crate::HashAlgorithm::default(),
+            #[cfg(feature = "signing")]
+            index_key: None,
         }
     }
 }
@@ -48,12 +103,17 @@ enum Update {
 /// Sync files
 /// - scan the asset folder to determine which files need to be uploaded to KV storage;
 /// - upload new files
-/// - generate the manifest
+/// - write the index, after all uploads succeed
 /// - if the prune option is set, remove unreferenced files in the KV namespace
-/// All the file system scanning and kv uploading is performed by wrangler library
-pub fn sync_assets(args: SyncConfig) -> Result<(), Error> {
+///
+/// All the file system scanning and kv uploading is performed by wrangler library.
+/// Returns a report of the files uploaded, deleted, and skipped. Assets are
+/// reported as added or updated by comparison with the index previously at output_path.
+pub fn sync_assets(args: SyncConfig) -> Result<DeployReport, Error> {
+    let start = Instant::now();
+    let mut report = DeployReport::default();
     // validate parameters
-    match std::fs::metadata(&args.asset_dir) {
+    match std::fs::metadata(args.asset_dir) {
         Ok(md) if md.is_dir() => {}
         _ => {
             return Err(Error::InvalidAssetPath(
@@ -61,7 +121,7 @@ pub fn sync_assets(args: SyncConfig) -> Result<(), Error> {
             ))
         }
     }
-    match std::fs::metadata(&args.wrangler_path) {
+    match std::fs::metadata(args.wrangler_path) {
         Ok(md) if md.is_file() => {}
         _ => {
             return Err(Error::MissingWranglerFile(
@@ -74,36 +134,158 @@ pub fn sync_assets(args: SyncConfig) -> Result<(), Error> {
     // create parent of output dir
     mkdir_bin_parent(args.output_path)?;
 
+    let previous = std::fs::read(args.output_path)
+        .ok()
+        .and_then(|bytes| crate::decode_index(&bytes).ok())
+        .unwrap_or_default();
     let manifest = Manifest::new(args.wrangler_path)?;
     let mut target = manifest.get_target(None, args.preview_env)?;
     let user = GlobalUser::new()?;
 
     let site_namespace = add_namespace(&user, &mut target, false)?;
-    let (to_upload, to_delete, asset_manifest) =
-        wrangler::sites::sync(&target, &user, &site_namespace.id, &args.asset_dir)?;
+    let (mut to_upload, mut to_delete, asset_manifest) =
+        wrangler::sites::sync(&target, &user, &site_namespace.id, args.asset_dir)?;
 
-    let index = make_index(&args.asset_dir, asset_manifest)?;
-    write_index(&args, index)?;
+    let paths: HashMap<&str, &str> = asset_manifest
+        .iter()
+        .map(|(path, key)| (key.as_str(), path.as_str()))
+        .collect();
+    if let Some(transforms) = args.transforms.filter(|t| !t.is_empty()) {
+        for kv in to_upload.iter_mut() {
+            if let Some(path) = paths.get(kv.key.as_str()) {
+                let content = read_asset_file(&args.asset_dir.join(path))?;
+                kv.value = base64::encode(transforms.apply(path, content)?);
+                kv.base64 = Some(true);
+            }
+        }
+    }
+    if let Some(rules) = args.ttl_rules.filter(|r| !r.is_empty()) {
+        for kv in to_upload.iter_mut() {
+            let path = paths.get(kv.key.as_str());
+            if let Some((path, ttl)) = path.and_then(|p| Some((p, rules.ttl_for(p)?))) {
+                kv.expiration_ttl = Some(ttl as i64);
+                report.expiring.insert(path.to_string(), ttl);
+            }
+        }
+    }
+    let mut index = make_index(
+        args.asset_dir,
+        asset_manifest,
+        args.transforms,
+        args.hash_algorithm,
+    )?;
+    if let Some(annotate) = args.annotate {
+        for (path, md) in index.iter_mut() {
+            annotate(path, md);
+        }
+    }
+    for (path, target) in args.aliases {
+        let path = path.trim_start_matches('/').to_string();
+        let md = match target {
+            AliasTarget::Path(target) => AssetMetadata::alias(target.trim_start_matches('/')),
+            AliasTarget::Redirect(url) => AssetMetadata::redirect(url.as_str()),
+        };
+        index.insert(path, md);
+    }
+    if let Some(retention) = args.tombstone_retention {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        report.tombstoned = index.keep_removed(&previous, now);
+        for path in index.expired_tombstones(now.saturating_sub(retention.as_secs())) {
+            index.remove(&path);
+        }
+        let kept: HashSet<&str> = index.values().map(|md| md.path.as_str()).collect();
+        to_delete.retain(|key| !kept.contains(key.as_str()));
+    }
+    let oversized = index.oversized(args.max_value_size);
+    if !oversized.is_empty() {
+        return Err(Error::OversizedAssets(oversized));
+    }
+    if let Some(limit) = args.warn_value_size {
+        for asset in index.oversized(limit) {
+            let warning = format!("Large asset: {}", asset);
+            StdErr::warn(&warning);
+            report.warnings.push(warning);
+        }
+    }
+    let observe = |event: DeployEvent| {
+        if let Some(observer) = args.observer {
+            observer.on_event(&event);
+        }
+    };
+    let uploading: HashSet<&str> = to_upload.iter().map(|kv| kv.key.as_str()).collect();
+    let mut uploaded = Vec::new();
+    for (path, md) in index
+        .iter()
+        .filter(|(_, md)| md.alias.is_none() && !md.is_deleted())
+    {
+        if !uploading.contains(md.path.as_str()) {
+            observe(DeployEvent::Skipped { path: path.clone() });
+            report.skipped.push(path.clone());
+            continue;
+        }
+        uploaded.push((path.clone(), md.path.clone(), md.size));
+        report.bytes_uploaded += md.size;
+        match matches!(previous.get(path), Some(md) if !md.is_deleted()) {
+            true => report.updated.push(path.clone()),
+            false => report.added.push(path.clone()),
+        }
+    }
+    StdErr::info(&format!("Assets: {}", index.stats()));
+    report.durations.scan_ms = elapsed_ms(start);
 
+    let check_cancelled = |operation: &str| match args.cancel {
+        Some(token) => token.check(operation),
+        None => Ok(()),
+    };
     // First, upload all existing files in asset_dir directory
+    check_cancelled("uploading assets")?;
+    let upload_start = Instant::now();
     StdErr::working("Uploading site files");
     let upload_progress_bar = make_progress_bar(to_upload.len(), UPLOAD_PROGRESS_TEMPLATE);
-    bulk::put(
+    if let Err(e) = bulk::put(
         &target,
         &user,
         &site_namespace.id,
         to_upload,
         &upload_progress_bar,
-    )?;
+    ) {
+        let error = Error::from(e);
+        for (path, _, _) in uploaded {
+            observe(DeployEvent::Failed {
+                path,
+                error: error.to_string(),
+            });
+        }
+        return Err(error);
+    }
+    for (path, key, size) in uploaded {
+        observe(DeployEvent::Started {
+            path: path.clone(),
+            key: key.clone(),
+            size,
+        });
+        observe(DeployEvent::Uploaded { path, key, size });
+    }
 
     if let Some(pb) = upload_progress_bar {
         pb.finish_with_message("Done Uploading");
     }
+    report.durations.upload_ms = elapsed_ms(upload_start);
+
+    // Write the index only after its values are uploaded, so a worker built
+    // from it never references missing values
+    write_index(&args, &index)?;
 
     // Finally, remove any stale files
     if !to_delete.is_empty() {
         if args.prune {
+            check_cancelled("pruning")?;
             StdErr::info("Pruning stale files...");
+            let prune_start = Instant::now();
+            report.deleted = to_delete.clone();
             let delete_progress_bar = make_progress_bar(to_delete.len(), DELETE_PROGRESS_TEMPLATE);
             bulk::delete(
                 &target,
@@ -116,18 +298,34 @@ pub fn sync_assets(args: SyncConfig) -> Result<(), Error> {
             if let Some(pb) = delete_progress_bar {
                 pb.finish_with_message("Done deleting");
             }
+            for key in report.deleted.iter() {
+                observe(DeployEvent::Deleted { key: key.clone() });
+            }
+            report.durations.prune_ms = elapsed_ms(prune_start);
         } else {
-            StdErr::message(&format!(
+            let warning = format!(
                 "Deferred pruning [{}] stale files. Run with '--prune' later to remove them.",
                 to_delete.len()
-            ));
+            );
+            StdErr::message(&warning);
+            report.warnings.push(warning);
         }
     }
-    Ok(())
+    report.durations.total_ms = elapsed_ms(start);
+    Ok(report.finish(&index))
+}
+
+pub(crate) fn elapsed_ms(start: Instant) -> u64 {
+    start.elapsed().as_millis() as u64
 }
 
 /// Generates the asset manifest
-fn make_index(asset_dir: &Path, asset_manifest: AssetManifest) -> Result<AssetIndex, Error> {
+fn make_index(
+    asset_dir: &Path,
+    asset_manifest: AssetManifest,
+    transforms: Option<&Transforms>,
+    algorithm: crate::HashAlgorithm,
+) -> Result<AssetIndex, Error> {
     use std::time::SystemTime;
 
     let mut index: AssetIndex = AssetIndex::new();
@@ -137,7 +335,7 @@ fn make_index(asset_dir: &Path, asset_manifest: AssetManifest) -> Result<AssetIn
             Error::IO(format!(
                 "failed reading asset file {}: {}",
                 &asset_path.display(),
-                e.to_string()
+                e
             ))
         })?;
         let modified = md
@@ -151,24 +349,43 @@ fn make_index(asset_dir: &Path, asset_manifest: AssetManifest) -> Result<AssetIn
             .duration_since(SystemTime::UNIX_EPOCH)
             .unwrap_or_else(|_| panic!("Invalid timestamp for file {}", &asset_path.display()))
             .as_secs();
-        index.insert(
-            k,
-            AssetMetadata {
-                path: v,
-                size: md.len(),
-                modified,
-            },
-        );
+        let mut content = read_asset_file(&asset_path)?;
+        if let Some(transforms) = transforms {
+            content = transforms.apply(&k, content)?;
+        }
+        let mut md = AssetMetadata {
+            path: v,
+            size: content.len() as u64,
+            modified,
+            hash: Some(algorithm.hash(&content)),
+            ..Default::default()
+        };
+        crate::sri::add_integrity(&k, &mut md, &content);
+        index.insert(k, md);
     }
     Ok(index)
 }
 
+fn read_asset_file(path: &Path) -> Result<Vec<u8>, Error> {
+    std::fs::read(path).map_err(|e| {
+        Error::IO(format!(
+            "failed reading asset file {}: {}",
+            path.display(),
+            e
+        ))
+    })
+}
+
 /// Serializes the asset manifest. Before writing it to a file, loads the previous file
 /// to determine whether any changes are required. This lets us generate a friendlier and more
 /// specific console message, and avoiding an unnecessary file write may shorten the next build time.
-fn write_index(args: &SyncConfig, asset_index: AssetIndex) -> Result<(), Error> {
-    let bytes = bincode::serialize(&asset_index)
-        .map_err(|e| Error::IO(format!("serialization error: {}", e.to_string())))?;
+fn write_index(args: &SyncConfig, asset_index: &AssetIndex) -> Result<(), Error> {
+    let bytes = crate::encode_index(asset_index)?;
+    #[cfg(feature = "signing")]
+    let bytes = match args.index_key {
+        Some(key) => crate::sign_index(&bytes, key),
+        None => bytes,
+    };
 
     let update = match std::fs::read(args.output_path) {
         Ok(existing_bytes) => {
@@ -182,13 +399,8 @@ fn write_index(args: &SyncConfig, asset_index: AssetIndex) -> Result<(), Error>
     };
     match update {
         Update::New | Update::Updated => {
-            std::fs::write(args.output_path, &bytes).map_err(|e| {
-                Error::IO(format!(
-                    "writing {}: {}",
-                    args.output_path.display(),
-                    e.to_string()
-                ))
-            })?;
+            std::fs::write(args.output_path, &bytes)
+                .map_err(|e| Error::IO(format!("writing {}: {}", args.output_path.display(), e)))?;
         }
         _ => {}
     }
@@ -221,7 +433,7 @@ fn mkdir_bin_parent(output_path: &Path) -> Result<(), Error> {
         Error::IO(format!(
             "creating output directory {} for assets: {}",
             output_path.display(),
-            e.to_string()
+            e
         ))
     })?;
     Ok(())
@@ -0,0 +1,269 @@
+use crate::{KVAssets, Metrics};
+use http::Method;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// Cumulative Cloudflare api usage of a handler, returned by `KVAssets::usage`.
+/// Handlers are bound to one namespace, so this is the usage of the namespace
+/// through this handler (`KVAssets::namespace` handlers count separately).
+/// Bulk writes and deletes count as one call.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct UsageSnapshot {
+    /// Value and metadata reads
+    pub reads: u64,
+    /// Value writes, including bulk writes
+    pub writes: u64,
+    /// Value deletes, including bulk deletes
+    pub deletes: u64,
+    /// Key listings
+    pub lists: u64,
+    /// Other requests: namespace management, token checks, cache purges,
+    /// and indexes loaded from urls
+    pub other: u64,
+    /// Request bodies sent, in bytes
+    pub bytes_sent: u64,
+    /// Response bodies received, in bytes
+    pub bytes_received: u64,
+}
+
+impl UsageSnapshot {
+    /// Total number of api calls
+    pub fn calls(&self) -> u64 {
+        self.reads + self.writes + self.deletes + self.lists + self.other
+    }
+}
+
+/// Soft limits on a handler's api usage (see `KVAssets::with_usage_budget`).
+/// Exceeding a limit doesn't fail requests: it is reported once to
+/// `Metrics::usage_budget_exceeded` (and logged with the `tracing` feature)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UsageBudget {
+    reads: Option<u64>,
+    writes: Option<u64>,
+    lists: Option<u64>,
+    bytes: Option<u64>,
+}
+
+impl UsageBudget {
+    /// Budget with no limits
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit on reads
+    pub fn with_reads(mut self, max: u64) -> Self {
+        self.reads = Some(max);
+        self
+    }
+
+    /// Limit on writes and deletes
+    pub fn with_writes(mut self, max: u64) -> Self {
+        self.writes = Some(max);
+        self
+    }
+
+    /// Limit on key listings
+    pub fn with_lists(mut self, max: u64) -> Self {
+        self.lists = Some(max);
+        self
+    }
+
+    /// Limit on bytes sent and received
+    pub fn with_bytes(mut self, max: u64) -> Self {
+        self.bytes = Some(max);
+        self
+    }
+}
+
+// Kind of api call, from its method and url path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Operation {
+    Read,
+    Write,
+    Delete,
+    List,
+    Other,
+}
+
+pub(crate) fn classify(method: &Method, path: &str) -> Operation {
+    let kv = path
+        .split_once("/storage/kv/namespaces/")
+        .and_then(|(_, rest)| rest.split_once('/'))
+        .map(|(_, rest)| rest);
+    match (method, kv) {
+        (&Method::GET, Some(rest))
+            if rest.starts_with("values/") || rest.starts_with("metadata/") =>
+        {
+            Operation::Read
+        }
+        (&Method::GET, Some("keys")) => Operation::List,
+        (&Method::PUT, Some(rest)) if rest.starts_with("values/") || rest == "bulk" => {
+            Operation::Write
+        }
+        (&Method::DELETE, Some(rest)) if rest.starts_with("values/") || rest == "bulk" => {
+            Operation::Delete
+        }
+        _ => Operation::Other,
+    }
+}
+
+// Usage counters of a handler
+#[derive(Debug, Default)]
+pub(crate) struct Usage {
+    reads: AtomicU64,
+    writes: AtomicU64,
+    deletes: AtomicU64,
+    lists: AtomicU64,
+    other: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    budget: UsageBudget,
+    // reads, writes, lists, bytes: set once the budget is exceeded
+    warned: [AtomicBool; 4],
+}
+
+impl Usage {
+    pub(crate) fn set_budget(&mut self, budget: UsageBudget) {
+        self.budget = budget;
+    }
+
+    // Count an api call, and report budgets it exceeds
+    pub(crate) fn record(
+        &self,
+        op: Operation,
+        sent: u64,
+        received: u64,
+        metrics: Option<&dyn Metrics>,
+    ) {
+        let counter = match op {
+            Operation::Read => &self.reads,
+            Operation::Write => &self.writes,
+            Operation::Delete => &self.deletes,
+            Operation::List => &self.lists,
+            Operation::Other => &self.other,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(sent, Ordering::Relaxed);
+        self.bytes_received.fetch_add(received, Ordering::Relaxed);
+
+        let usage = self.snapshot();
+        let checks = [
+            ("reads", usage.reads, self.budget.reads),
+            ("writes", usage.writes + usage.deletes, self.budget.writes),
+            ("lists", usage.lists, self.budget.lists),
+            (
+                "bytes",
+                usage.bytes_sent + usage.bytes_received,
+                self.budget.bytes,
+            ),
+        ];
+        for (&(name, used, budget), warned) in checks.iter().zip(self.warned.iter()) {
+            match budget {
+                Some(budget) if used > budget && !warned.swap(true, Ordering::Relaxed) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(counter = name, used, budget, "usage budget exceeded");
+                    if let Some(metrics) = metrics {
+                        metrics.usage_budget_exceeded(name, used, budget);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> UsageSnapshot {
+        UsageSnapshot {
+            reads: self.reads.load(Ordering::Relaxed),
+            writes: self.writes.load(Ordering::Relaxed),
+            deletes: self.deletes.load(Ordering::Relaxed),
+            lists: self.lists.load(Ordering::Relaxed),
+            other: self.other.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        for counter in [
+            &self.reads,
+            &self.writes,
+            &self.deletes,
+            &self.lists,
+            &self.other,
+            &self.bytes_sent,
+            &self.bytes_received,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+        for warned in self.warned.iter() {
+            warned.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+impl<'ah> KVAssets<'ah> {
+    /// Cumulative api calls and bytes transferred by this handler, by operation,
+    /// for visibility into what KV is costing
+    pub fn usage(&self) -> UsageSnapshot {
+        self.usage_counters().snapshot()
+    }
+
+    /// Reset usage counters to 0, such as at the start of a billing period.
+    /// Budgets can be exceeded (and reported) again
+    pub fn reset_usage(&self) {
+        self.usage_counters().reset()
+    }
+}
+
+/// Tests usage accounting and budget warnings, against the mock api
+#[cfg(all(test, feature = "reqwest"))]
+#[tokio::test]
+async fn test_usage() {
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Warnings(Mutex<Vec<(String, u64, u64)>>);
+    impl Metrics for Warnings {
+        fn usage_budget_exceeded(&self, counter: &str, used: u64, budget: u64) {
+            self.0
+                .lock()
+                .unwrap()
+                .push((counter.to_string(), used, budget));
+        }
+    }
+
+    let mock = crate::testspace::MockCloudflare::start().unwrap();
+    mock.insert("ns", "a", "hello");
+    let warnings = Arc::new(Warnings::default());
+    let kv = mock
+        .handler(&[], "ns")
+        .with_metrics(warnings.clone())
+        .with_usage_budget(UsageBudget::new().with_reads(1).with_lists(5));
+    for _ in 0..3 {
+        kv.get_kv_value("a").await.unwrap();
+    }
+    kv.put_kv_value("b", "data", None).await.unwrap();
+    kv.delete_kv_value("b").await.unwrap();
+    kv.list_keys().await.unwrap();
+
+    let usage = kv.usage();
+    assert_eq!(
+        (usage.reads, usage.writes, usage.deletes, usage.lists),
+        (3, 1, 1, 1)
+    );
+    assert_eq!(usage.calls(), 6);
+    assert_eq!(usage.bytes_sent, 4);
+    assert!(usage.bytes_received > 15);
+    // reported once
+    assert_eq!(
+        *warnings.0.lock().unwrap(),
+        vec![("reads".to_string(), 2, 1)]
+    );
+
+    kv.reset_usage();
+    assert_eq!(kv.usage(), UsageSnapshot::default());
+    assert_eq!(
+        classify(&Method::GET, "/client/v4/accounts/a/storage/kv/namespaces"),
+        Operation::Other
+    );
+}
@@ -0,0 +1,60 @@
+use crate::{Error, KVAssets};
+use bytes::Bytes;
+
+/// KV value with details of the stored key, returned by `KVAssets::get_kv_value_with_info`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueInfo {
+    /// The value (reassembled and decrypted, if chunking or encryption is enabled)
+    pub body: Bytes,
+    /// Length of the value, in bytes
+    pub content_length: u64,
+    /// Expiration time (seconds since epoch), if the key expires
+    pub expiration: Option<u64>,
+    /// Metadata stored with the key, if any
+    pub metadata: Option<serde_json::Value>,
+}
+
+impl<'ah> KVAssets<'ah> {
+    /// Same as `get_kv_value`, but also returns the key's expiration and metadata.
+    /// The expiration comes from the value response; the api returns metadata
+    /// from a separate endpoint, which is read concurrently with the value.
+    /// Always reads KV: the write overlay and value cache are not consulted.
+    pub async fn get_kv_value_with_info(&self, key: &str) -> Result<ValueInfo, Error> {
+        let (value, metadata) =
+            futures_util::join!(self.get_value_with_headers(key), self.get_kv_metadata(key));
+        let (headers, mut body) = value?;
+        if self.chunk_size().is_some() {
+            if let Some(manifest) = crate::chunked::ChunkManifest::decode(&body) {
+                body = self.get_chunks(key, &manifest).await?;
+            }
+        }
+        let expiration = headers
+            .get("expiration")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse().ok());
+        Ok(ValueInfo {
+            content_length: body.len() as u64,
+            body,
+            expiration,
+            // a key deleted between the two requests has no metadata
+            metadata: metadata.unwrap_or_default(),
+        })
+    }
+}
+
+/// Tests value details, against the mock api
+#[cfg(all(test, feature = "reqwest"))]
+#[tokio::test]
+async fn test_get_kv_value_with_info() {
+    let mock = crate::testspace::MockCloudflare::start().unwrap();
+    let kv = mock.handler(&[], "ns");
+    let opts = crate::KvPutOptions::new()
+        .with_expiration(4_000_000_000)
+        .with_metadata(serde_json::json!({ "hash": "abc" }));
+    kv.put_kv_value_opts("a", "hello", &opts).await.unwrap();
+    let info = kv.get_kv_value_with_info("a").await.unwrap();
+    assert_eq!(info.body, "hello");
+    assert_eq!(info.content_length, 5);
+    assert_eq!(info.expiration, Some(4_000_000_000));
+    assert_eq!(info.metadata.unwrap()["hash"], "abc");
+}
@@ -0,0 +1,203 @@
+use crate::{AssetIndex, Error, KVAssets};
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// Results of reconciling the asset index against KV contents.
+/// All lists contain KV keys (`AssetMetadata::path`), sorted.
+#[derive(Debug, Default, Serialize, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// Keys referenced by the index that are not in KV
+    pub missing: Vec<String>,
+    /// Keys in KV that are not referenced by the index (stale or foreign values)
+    pub extra: Vec<String>,
+    /// Keys whose KV value doesn't match the size or hash in the index.
+    /// Only checked by `verify_content`
+    pub mismatched: Vec<String>,
+}
+
+impl VerifyReport {
+    /// Returns true if every index entry is present (and matches, if checked)
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.mismatched.is_empty()
+    }
+}
+
+impl<'ah> KVAssets<'ah> {
+    /// Checks that every key referenced by the index exists in KV,
+    /// and reports keys in KV that aren't referenced by the index.
+    /// Uses only the key listing api, so values are not downloaded.
+    pub async fn verify(&self) -> Result<VerifyReport, Error> {
+        let kv_keys = self.list_keys().await?;
+        self.with_index(|index| reconcile(index, kv_keys))
+    }
+
+    /// Deletes keys in KV that aren't referenced by the index (`VerifyReport::extra`),
+    /// such as values of previous deploys. Returns the deleted keys.
+    /// Only run this after the index is deployed: workers using an older index
+    /// may still read the deleted values. Deletes are reported to the deploy observer, if any.
+    pub async fn prune(&self) -> Result<Vec<String>, Error> {
+        let extra = self.verify().await?.extra;
+        for key in extra.iter() {
+            self.check_cancelled("pruning")?;
+            self.delete_kv_value(key).await?;
+            self.observe(|| crate::DeployEvent::Deleted { key: key.clone() });
+        }
+        Ok(extra)
+    }
+
+    /// Same as `verify`, but also downloads each value referenced by the index
+    /// to check its size, and hash if the index has one.
+    pub async fn verify_content(&self) -> Result<VerifyReport, Error> {
+        let mut report = self.verify().await?;
+        let entries = self.with_index(|index| {
+            index
+                .values()
+                .filter(|md| md.alias.is_none() && !md.is_deleted())
+                .cloned()
+                .collect::<Vec<_>>()
+        })?;
+        for md in entries {
+            if report.missing.binary_search(&md.path).is_ok() {
+                continue;
+            }
+            let body = match self.get_kv_value(&md.path).await {
+                Ok(body) => body,
+                Err(Error::KVKeyNotFound(_, 404)) => {
+                    report.missing.push(md.path);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            let hash_ok = match &md.hash {
+                Some(hash) => crate::checksum::matches(&body, hash),
+                None => true,
+            };
+            if body.len() as u64 != md.size || !hash_ok {
+                report.mismatched.push(md.path);
+            }
+        }
+        report.missing.sort();
+        report.mismatched.sort();
+        Ok(report)
+    }
+}
+
+/// Compares index entries with the list of keys in KV. Values of tombstones
+/// may be present, but aren't required. Published indexes aren't assets, and
+/// parts of chunked values (see `with_chunking`) belong to their value's key
+fn reconcile(index: &AssetIndex, kv_keys: Vec<String>) -> VerifyReport {
+    let referenced: HashSet<&str> = index
+        .values()
+        .filter(|md| md.alias.is_none())
+        .map(|md| md.path.as_str())
+        .collect();
+    let live: HashSet<&str> = index
+        .values()
+        .filter(|md| md.alias.is_none() && !md.is_deleted())
+        .map(|md| md.path.as_str())
+        .collect();
+    let kv_keys: HashSet<String> = kv_keys
+        .into_iter()
+        .filter(|k| !k.starts_with(crate::INDEX_KEY_PREFIX))
+        .collect();
+    let mut missing: Vec<String> = live
+        .iter()
+        .filter(|k| !kv_keys.contains(**k))
+        .map(|k| k.to_string())
+        .collect();
+    let mut extra: Vec<String> = kv_keys
+        .into_iter()
+        .filter(|k| {
+            !referenced.contains(k.as_str())
+                && !crate::chunked::chunked_key(k).is_some_and(|key| referenced.contains(key))
+        })
+        .collect();
+    missing.sort();
+    extra.sort();
+    VerifyReport {
+        missing,
+        extra,
+        mismatched: Vec::new(),
+    }
+}
+
+/// Tests reconciliation of index and KV keys
+#[test]
+fn test_reconcile() {
+    use crate::AssetMetadata;
+
+    let mut index = AssetIndex::new();
+    for (name, path) in &[("a", "a.111"), ("b", "b.222"), ("c", "c.333")] {
+        index.insert(
+            name.to_string(),
+            AssetMetadata {
+                path: path.to_string(),
+                modified: 0,
+                size: 0,
+                hash: None,
+                ..Default::default()
+            },
+        );
+    }
+    let kv_keys = vec![
+        "a.111".to_string(),
+        "c.000".to_string(),
+        "c.333".to_string(),
+    ];
+    let report = reconcile(&index, kv_keys);
+    assert_eq!(report.missing, vec!["b.222"]);
+    assert_eq!(report.extra, vec!["c.000"]);
+    assert!(!report.is_ok());
+
+    // parts of chunked values aren't extra, unless their value is
+    let kv_keys = vec!["a.111".into(), "a.111.part0".into(), "c.000.part0".into()];
+    assert_eq!(reconcile(&index, kv_keys).extra, vec!["c.000.part0"]);
+
+    let report = reconcile(&index, vec!["a.111".into(), "b.222".into(), "c.333".into()]);
+    assert_eq!(report, VerifyReport::default());
+    assert!(report.is_ok());
+}
+
+/// Tests that pruning keeps the parts of chunked values, against the mock api
+#[cfg(all(test, feature = "reqwest"))]
+#[tokio::test]
+async fn test_prune_chunked() {
+    use crate::AssetMetadata;
+
+    let mut index = AssetIndex::new();
+    index.insert(
+        "big.bin".to_string(),
+        AssetMetadata {
+            path: "big.1.bin".to_string(),
+            size: 10,
+            ..Default::default()
+        },
+    );
+    let bytes = crate::encode_index(&index).unwrap();
+    let mock = crate::testspace::MockCloudflare::start().unwrap();
+    let kv = mock.handler(&bytes, "ns").with_chunking(4);
+    for key in ["big.1.bin", "big.0.bin"].iter() {
+        kv.put_kv_value(key, "0123456789", None).await.unwrap();
+    }
+
+    let deleted = kv.prune().await.unwrap();
+    assert_eq!(
+        deleted,
+        vec![
+            "big.0.bin",
+            "big.0.bin.part0",
+            "big.0.bin.part1",
+            "big.0.bin.part2"
+        ]
+    );
+    assert_eq!(
+        mock.keys("ns"),
+        vec![
+            "big.1.bin",
+            "big.1.bin.part0",
+            "big.1.bin.part1",
+            "big.1.bin.part2"
+        ]
+    );
+    assert_eq!(kv.get_kv_value("big.1.bin").await.unwrap(), "0123456789");
+}
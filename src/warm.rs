@@ -0,0 +1,54 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use crate::{Error, KVAssets};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+impl KVAssets<'static> {
+    /// Initialize handler (see `init`), and start deserializing the index on a background
+    /// thread, so it is usually ready before the first request. A request that arrives
+    /// sooner waits for the thread to finish rather than decoding the index again.
+    /// If decoding fails, the error is returned by the first request that uses the index.
+    pub fn init_eager(
+        index: &'static [u8],
+        account_id: &'static str,
+        namespace_id: &'static str,
+        auth_token: &'static str,
+    ) -> Arc<Self> {
+        let kv = Arc::new(Self::init(index, account_id, namespace_id, auth_token));
+        kv.spawn_warm_index();
+        kv
+    }
+
+    /// Deserialize the index on a background thread (see `warm_index`).
+    /// Join the returned handle to get the number of entries, or the decoding error.
+    pub fn spawn_warm_index(self: &Arc<Self>) -> JoinHandle<Result<usize, Error>> {
+        let kv = self.clone();
+        std::thread::spawn(move || kv.warm_index())
+    }
+}
+
+/// Tests background index deserialization
+#[test]
+fn test_init_eager() {
+    use crate::{AssetIndex, AssetMetadata};
+
+    let mut index = AssetIndex::new();
+    index.insert(
+        "a.txt".to_string(),
+        AssetMetadata {
+            path: "a.1.txt".to_string(),
+            modified: 10000,
+            size: 5,
+            hash: None,
+            ..Default::default()
+        },
+    );
+    let blob: &'static [u8] = Box::leak(crate::encode_index(&index).unwrap().into_boxed_slice());
+    let kv = KVAssets::init_eager(blob, "acct", "ns", "token");
+    assert_eq!(kv.spawn_warm_index().join().unwrap().unwrap(), 1);
+    assert!(kv.lookup_key("/a.txt").unwrap().is_some());
+
+    let kv = Arc::new(KVAssets::init(b"not an index", "acct", "ns", "token"));
+    assert!(kv.spawn_warm_index().join().unwrap().is_err());
+}
@@ -0,0 +1,44 @@
+//! warp integration (feature "warp")
+//!
+//! ```ignore
+//! let routes = api_routes.or(kv_assets::warp::assets(kv));
+//! warp::serve(routes).run(([127, 0, 0, 1], 3030)).await;
+//! ```
+
+use crate::KVAssets;
+use ::warp::{
+    filters::path::FullPath, http::HeaderMap, hyper::Body, reply::Response, Filter, Rejection,
+};
+use std::sync::Arc;
+
+/// Filter that serves GET and HEAD requests from KV.
+/// Rejects with `not_found` if the path is not an asset,
+/// so it can be combined with other filters using `or`.
+pub fn assets(
+    kv: impl Into<Arc<KVAssets<'static>>>,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
+    let kv = kv.into();
+    ::warp::get()
+        .or(::warp::head())
+        .unify()
+        .and(::warp::method())
+        .and(::warp::path::full())
+        .and(::warp::header::headers_cloned())
+        .and_then(move |method, path: FullPath, headers: HeaderMap| {
+            let kv = kv.clone();
+            async move {
+                let mut head = http::Request::new(());
+                *head.method_mut() = method;
+                *head.uri_mut() = path
+                    .as_str()
+                    .parse()
+                    .map_err(|_| ::warp::reject::not_found())?;
+                *head.headers_mut() = headers;
+                let response = kv.serve(&head).await;
+                if response.status() == http::StatusCode::NOT_FOUND {
+                    return Err(::warp::reject::not_found());
+                }
+                Ok(response.map(Body::from))
+            }
+        })
+}
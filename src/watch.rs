@@ -0,0 +1,209 @@
+#![cfg(all(feature = "watch", not(target_arch = "wasm32")))]
+
+use crate::{AssetIndex, Error, KVAssets, Transforms};
+use futures_channel::mpsc;
+use futures_util::StreamExt;
+use notify::{RecursiveMode, Watcher as _};
+use std::path::PathBuf;
+
+/// Result of applying a batch of file changes
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct WatchUpdate {
+    /// Asset paths uploaded, because they are new or their content changed
+    pub uploaded: Vec<String>,
+    /// Asset paths removed from the index
+    pub removed: Vec<String>,
+}
+
+impl WatchUpdate {
+    /// Returns true if the index changed
+    pub fn is_empty(&self) -> bool {
+        self.uploaded.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Keeps KV and an index file in sync with a source directory, for a live preview
+/// workflow: edit a file, and it's uploaded and the index republished
+/// (so, e.g., `wrangler dev` rebuilds the worker) before the browser is refreshed.
+/// Only files whose content changed are uploaded. Values are stored under
+/// versioned keys (`site.2cf24dba5f.css`), so a worker using the previous index
+/// keeps working until it reloads.
+pub struct AssetWatcher<'kv, 'ah> {
+    kv: &'kv KVAssets<'ah>,
+    asset_dir: PathBuf,
+    output_path: PathBuf,
+    index: AssetIndex,
+    transforms: Option<Transforms>,
+}
+
+impl<'kv, 'ah> AssetWatcher<'kv, 'ah> {
+    /// Watch asset_dir, uploading with kv and writing the serialized index to output_path.
+    /// The index initially contains the entries of kv's index, so unchanged files
+    /// aren't uploaded again.
+    pub fn new(
+        kv: &'kv KVAssets<'ah>,
+        asset_dir: impl Into<PathBuf>,
+        output_path: impl Into<PathBuf>,
+    ) -> Result<Self, Error> {
+        let index = kv.with_index(|index| index.clone())?;
+        Ok(Self {
+            kv,
+            asset_dir: asset_dir.into(),
+            output_path: output_path.into(),
+            index,
+            transforms: None,
+        })
+    }
+
+    /// Transform file content before upload, e.g., to minify CSS and JS
+    pub fn with_transforms(mut self, transforms: Transforms) -> Self {
+        self.transforms = Some(transforms);
+        self
+    }
+
+    /// Current index
+    pub fn index(&self) -> &AssetIndex {
+        &self.index
+    }
+
+    /// Scan the whole directory, upload new and changed files,
+    /// remove entries for deleted files, and write the index
+    pub async fn build(&mut self) -> Result<WatchUpdate, Error> {
+        let mut paths = Vec::new();
+        crate::scan::collect_files(&self.asset_dir, &mut paths)?;
+        let mut update = self.update(&paths).await?;
+        let removed: Vec<String> = self
+            .index
+            .iter()
+            .filter(|(path, md)| md.alias.is_none() && !self.asset_dir.join(path).is_file())
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in removed {
+            self.index.remove(&path);
+            update.removed.push(path);
+        }
+        self.write_index()?;
+        Ok(update)
+    }
+
+    /// Apply changes to the files (created, modified, or removed), and write
+    /// the index if anything changed. Paths outside the asset directory are ignored
+    pub async fn update(&mut self, paths: &[PathBuf]) -> Result<WatchUpdate, Error> {
+        let mut update = WatchUpdate::default();
+        for file in paths {
+            let path = match crate::scan::asset_path(&self.asset_dir, file) {
+                Some(path) => path,
+                None => continue,
+            };
+            if !file.is_file() {
+                if self.index.remove(&path).is_some() {
+                    update.removed.push(path);
+                }
+                continue;
+            }
+            let (md, content) = crate::scan::read_asset(
+                file,
+                &path,
+                self.transforms.as_ref(),
+                self.kv.hash_algorithm(),
+                self.kv.key_strategy(),
+            )?;
+            if self.index.get(&path).and_then(|e| e.hash.as_ref()) == md.hash.as_ref() {
+                continue;
+            }
+            self.kv.put_kv_value(&md.path, content, None).await?;
+            self.index.insert(path.clone(), md);
+            update.uploaded.push(path);
+        }
+        if !update.is_empty() {
+            self.write_index()?;
+        }
+        Ok(update)
+    }
+
+    /// Build, then watch the directory until an error occurs,
+    /// calling on_update after each batch of changes is applied
+    pub async fn run(mut self, mut on_update: impl FnMut(&WatchUpdate)) -> Result<(), Error> {
+        let update = self.build().await?;
+        on_update(&update);
+
+        let (tx, mut rx) = mpsc::unbounded();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = tx.unbounded_send(event);
+        })
+        .map_err(watch_error)?;
+        watcher
+            .watch(&self.asset_dir, RecursiveMode::Recursive)
+            .map_err(watch_error)?;
+
+        while let Some(event) = rx.next().await {
+            // editors often generate several events per save: apply them as one batch
+            let mut paths = event.map_err(watch_error)?.paths;
+            while let Ok(event) = rx.try_recv() {
+                paths.extend(event.map_err(watch_error)?.paths);
+            }
+            paths.sort();
+            paths.dedup();
+            let update = self.update(&paths).await?;
+            if !update.is_empty() {
+                on_update(&update);
+            }
+        }
+        Ok(())
+    }
+
+    // Write serialized index, signed with the handler's index key, if any
+    fn write_index(&self) -> Result<(), Error> {
+        let bytes = crate::encode_index(&self.index)?;
+        #[cfg(feature = "signing")]
+        let bytes = match self.kv.index_key() {
+            Some(key) => crate::sign_index(&bytes, key),
+            None => bytes,
+        };
+        std::fs::write(&self.output_path, bytes)
+            .map_err(|e| Error::IO(format!("writing {}: {}", self.output_path.display(), e)))
+    }
+}
+
+fn watch_error(e: notify::Error) -> Error {
+    Error::IO(format!("watching files: {}", e))
+}
+
+/// Tests incremental updates, against the mock api
+#[cfg(all(test, feature = "reqwest"))]
+#[tokio::test]
+async fn test_watch_update() {
+    let root = std::env::temp_dir().join(format!("kv-assets-watch-{}", std::process::id()));
+    let dir = root.join("public");
+    std::fs::create_dir_all(dir.join("css")).unwrap();
+    std::fs::write(dir.join("index.html"), "hello").unwrap();
+    std::fs::write(dir.join("css/site.css"), "body{}").unwrap();
+    std::fs::write(dir.join(".hidden"), "x").unwrap();
+
+    let mock = crate::testspace::MockCloudflare::start().unwrap();
+    let kv = mock
+        .handler(&[], "ns")
+        .with_hash_algorithm(crate::HashAlgorithm::Sha256);
+    let mut watcher = AssetWatcher::new(&kv, &dir, root.join("assets.bin")).unwrap();
+    let update = watcher.build().await.unwrap();
+    assert_eq!(update.uploaded.len(), 2);
+    assert_eq!(watcher.index()["index.html"].path, "index.2cf24dba5f.html");
+    let written = crate::decode_index(&std::fs::read(root.join("assets.bin")).unwrap()).unwrap();
+    assert_eq!(&written, watcher.index());
+
+    // unchanged content is not uploaded again
+    let update = watcher.update(&[dir.join("index.html")]).await.unwrap();
+    assert!(update.is_empty());
+
+    std::fs::write(dir.join("index.html"), "changed").unwrap();
+    std::fs::remove_file(dir.join("css/site.css")).unwrap();
+    let update = watcher
+        .update(&[dir.join("index.html"), dir.join("css/site.css")])
+        .await
+        .unwrap();
+    assert_eq!(update.uploaded, vec!["index.html"]);
+    assert_eq!(update.removed, vec!["css/site.css"]);
+    assert_eq!(mock.get("ns", "index.2cf24dba5f.html").unwrap(), "hello");
+    assert_eq!(mock.keys("ns").len(), 3);
+    std::fs::remove_dir_all(&root).unwrap();
+}
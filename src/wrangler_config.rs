@@ -0,0 +1,131 @@
+//! Loader for `wrangler.toml` (feature "wrangler-config"), so deploy tooling can discover
+//! the account and KV namespace ids from the same file Wrangler uses.
+
+use crate::Error;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Settings from wrangler.toml relevant to KV assets
+#[derive(Debug, Default, Deserialize)]
+pub struct WranglerConfig {
+    /// Worker name
+    pub name: Option<String>,
+    /// Cloudflare account id
+    pub account_id: Option<String>,
+    /// KV namespace bindings for the top-level (production) environment
+    #[serde(default)]
+    pub kv_namespaces: Vec<KvNamespaceBinding>,
+    /// Named environments, from `[env.<name>]` sections
+    #[serde(default)]
+    pub env: HashMap<String, EnvConfig>,
+}
+
+/// Settings from an `[env.<name>]` section
+#[derive(Debug, Default, Deserialize)]
+pub struct EnvConfig {
+    /// Account id override for the environment
+    pub account_id: Option<String>,
+    /// KV namespace bindings for the environment. As in Wrangler,
+    /// these are not inherited from the top level
+    #[serde(default)]
+    pub kv_namespaces: Vec<KvNamespaceBinding>,
+}
+
+/// A `kv_namespaces` entry
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct KvNamespaceBinding {
+    /// Name of the binding in the worker
+    pub binding: String,
+    /// Namespace id
+    pub id: Option<String>,
+    /// Namespace id used by `wrangler dev` and preview deployments
+    pub preview_id: Option<String>,
+}
+
+impl WranglerConfig {
+    /// Load and parse wrangler.toml
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|_| Error::MissingWranglerFile(path.display().to_string()))?;
+        Self::parse(&text)
+    }
+
+    /// Parse wrangler.toml contents
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        toml::from_str(text).map_err(|e| Error::WranglerConfig(e.to_string()))
+    }
+
+    /// Account id for the environment (None for top-level), falling back to the top-level id
+    pub fn account_id(&self, env: Option<&str>) -> Option<&str> {
+        env.and_then(|name| self.env.get(name))
+            .and_then(|env| env.account_id.as_deref())
+            .or(self.account_id.as_deref())
+    }
+
+    /// KV namespace bindings for the environment (None for top-level)
+    pub fn namespaces(&self, env: Option<&str>) -> &[KvNamespaceBinding] {
+        match env {
+            Some(name) => self
+                .env
+                .get(name)
+                .map(|env| env.kv_namespaces.as_slice())
+                .unwrap_or_default(),
+            None => &self.kv_namespaces,
+        }
+    }
+
+    /// Namespace id for the binding in the environment.
+    /// If preview is true, returns the binding's preview_id.
+    pub fn namespace_id(&self, binding: &str, env: Option<&str>, preview: bool) -> Option<&str> {
+        let ns = self
+            .namespaces(env)
+            .iter()
+            .find(|ns| ns.binding == binding)?;
+        match preview {
+            true => ns.preview_id.as_deref(),
+            false => ns.id.as_deref(),
+        }
+    }
+}
+
+/// Tests parsing of environments and namespace bindings
+#[test]
+fn test_wrangler_config() {
+    let config = WranglerConfig::parse(
+        r#"
+        name = "site"
+        account_id = "acct"
+
+        kv_namespaces = [
+            { binding = "ASSETS", id = "prod-id", preview_id = "preview-id" }
+        ]
+
+        [env.staging]
+        account_id = "staging-acct"
+        kv_namespaces = [
+            { binding = "ASSETS", id = "staging-id" }
+        ]
+
+        [env.dev]
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.name.as_deref(), Some("site"));
+    assert_eq!(config.account_id(None), Some("acct"));
+    assert_eq!(config.account_id(Some("staging")), Some("staging-acct"));
+    assert_eq!(config.account_id(Some("dev")), Some("acct"));
+    assert_eq!(config.namespace_id("ASSETS", None, false), Some("prod-id"));
+    assert_eq!(
+        config.namespace_id("ASSETS", None, true),
+        Some("preview-id")
+    );
+    assert_eq!(
+        config.namespace_id("ASSETS", Some("staging"), false),
+        Some("staging-id")
+    );
+    assert_eq!(config.namespace_id("ASSETS", Some("dev"), false), None);
+    assert_eq!(config.namespace_id("OTHER", None, false), None);
+
+    assert!(WranglerConfig::parse("name = [").is_err());
+}